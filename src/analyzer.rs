@@ -1,7 +1,10 @@
-use crate::ast::Stmt;
-use crate::environment::Environment;
+use crate::ast::{free_names, Expr, Stmt};
+use crate::environment::{Environment, VarStore};
 use crate::error::{msg, Msg, RangeReporter, Result};
+use crate::host::{Native, Natives, NO_NATIVES};
+use crate::operators::Builtin;
 use crate::parser::Parser;
+use crate::types::Type;
 use std::collections::HashMap;
 
 /// struct for typechecking or interpreting Wabbit AST
@@ -19,11 +22,13 @@ where
     pub(crate) constants: HashMap<&'a String, T>,
     /// map of functions to types or values
     pub(crate) functions: HashMap<&'a String, &'a Stmt>,
+    /// functions provided by the application embedding Wabbit
+    pub natives: &'a Natives,
     /// record of IO (print statements)
     pub output: Vec<T>,
     /// current depth of function calls
     pub(crate) call_depth: usize,
-    /// current depth of while loops
+    /// current depth of loops
     pub(crate) loop_depth: usize,
     /// map of expressions/statemts to source indices, borrowed from a parser
     pub(crate) ranges: &'a HashMap<usize, (usize, usize)>,
@@ -52,6 +57,7 @@ where
             env: Environment::new(),
             constants: HashMap::new(),
             functions: HashMap::new(),
+            natives: &NO_NATIVES,
             output: Vec::new(),
             statements,
             call_depth: 0,
@@ -69,6 +75,11 @@ where
         }
     }
 
+    /// whether a name refers to a variable or constant, which are found before functions
+    pub(crate) fn is_variable(&self, name: &String) -> bool {
+        self.constants.contains_key(name) || self.env.get(name).is_some()
+    }
+
     /// check if a name is already used by a constant
     pub(crate) fn check_constant(&self, name: &String, id: usize) -> Result<()> {
         if self.constants.contains_key(name) {
@@ -80,12 +91,72 @@ where
 
     /// check if a name is already used by a function
     pub(crate) fn check_function(&self, name: &String, id: usize) -> Result<()> {
-        if self.functions.contains_key(name) {
+        if self.functions.contains_key(name)
+            || self.natives.get(name).is_some()
+            || Builtin::from_name(name).is_some()
+        {
             msg!(Msg::RedeclareFunc, (self.ranges, id), name)
         } else {
             Ok(())
         }
     }
+
+    /// the local variables that a function expression captures, in the order it uses them,
+    /// which must be initialized
+    pub(crate) fn captures(
+        &self,
+        params: &'a [(String, Type)],
+        body: &'a Stmt,
+        id: usize,
+    ) -> Result<Vec<(&'a String, T)>> {
+        let mut captures = Vec::new();
+        for name in free_names(params, body) {
+            match self.env.get_local(name) {
+                Some(VarStore::Init(value) | VarStore::Captured(value)) => {
+                    captures.push((name, value));
+                }
+                Some(VarStore::UnInit(_)) => {
+                    return msg!(Msg::AccessUninit, (self.ranges, id), name);
+                }
+                None => {}
+            }
+        }
+        Ok(captures)
+    }
+
+    /// check that a built-in function is called without arguments
+    pub(crate) fn check_builtin_args(&self, name: &String, args: &[Expr], id: usize) -> Result<()> {
+        if args.is_empty() {
+            Ok(())
+        } else {
+            msg!(Msg::FuncAirty, (self.ranges, id), name, 0, args.len())
+        }
+    }
+
+    /// check the types of arguments to a native function
+    pub(crate) fn check_native_args(
+        &self,
+        name: &String,
+        native: &Native,
+        arg_types: &[Type],
+        id: usize,
+    ) -> Result<()> {
+        if native.params.len() != arg_types.len() {
+            return msg!(
+                Msg::FuncAirty,
+                (self.ranges, id),
+                name,
+                native.params.len(),
+                arg_types.len()
+            );
+        }
+        for (i, (param, arg)) in std::iter::zip(&native.params, arg_types).enumerate() {
+            if param != arg {
+                return msg!(Msg::ParamType, (self.ranges, id), i + 1, param, arg);
+            }
+        }
+        Ok(())
+    }
 }
 
 impl<'a, T> From<&'a Parser<'a>> for Analyzer<'a, T>
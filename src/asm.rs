@@ -0,0 +1,941 @@
+//! generate x86-64 assembly
+//!
+//! the generated code is deliberately simple, working from the crate's IR one instruction at a
+//! time rather than allocating registers
+
+use crate::ir::{
+    function_symbol, global_symbol, Function, Instr, Module, Operand, Reg, Terminator,
+};
+use crate::lower::Lowering;
+use crate::operators::{BinaryOp, UnaryOp};
+use crate::parser::Parser;
+use crate::types::Type;
+use crate::WabbitType;
+use std::collections::HashMap;
+
+/// the print runtime, which formats values the same way as the interpreter
+///
+/// see [`WabbitType::output`], floats are printed with the shortest `%.*e` digits that read back
+/// as the same value, then written out with `%.*f` or, for integers, as digits followed by zeros.
+/// Values are read with `scanf` and `getchar`, as in the LLVM runtime.
+const RUNTIME: &str = r#"
+	.section .rodata
+.Lwb_fmt_int:
+	.string "%d\n"
+.Lwb_fmt_int64:
+	.string "%ld\n"
+.Lwb_fmt_exp:
+	.string "%.*e"
+.Lwb_fmt_fixed:
+	.string "%.*f\n"
+.Lwb_fmt_digits:
+	.string "%.*s%.*s%.*d\n"
+.Lwb_fmt_inf:
+	.string "%f\n"
+.Lwb_true:
+	.string "true"
+.Lwb_false:
+	.string "false"
+.Lwb_nan:
+	.string "NaN"
+.Lwb_scan_int:
+	.string "%d"
+.Lwb_scan_float:
+	.string "%lf"
+
+	.text
+wb_rt_print_int:
+	subq $8, %rsp
+	movl %edi, %esi
+	leaq .Lwb_fmt_int(%rip), %rdi
+	xorl %eax, %eax
+	call printf@PLT
+	addq $8, %rsp
+	ret
+
+wb_rt_print_int64:
+	subq $8, %rsp
+	movq %rdi, %rsi
+	leaq .Lwb_fmt_int64(%rip), %rdi
+	xorl %eax, %eax
+	call printf@PLT
+	addq $8, %rsp
+	ret
+
+wb_rt_print_bool:
+	subq $8, %rsp
+	testl %edi, %edi
+	leaq .Lwb_true(%rip), %rdi
+	leaq .Lwb_false(%rip), %rax
+	cmovz %rax, %rdi
+	call puts@PLT
+	addq $8, %rsp
+	ret
+
+wb_rt_print_char:
+	subq $8, %rsp
+	movzbl %dil, %edi
+	call putchar@PLT
+	addq $8, %rsp
+	ret
+
+# a float32 is printed with the fewest digits that read back as the same float32
+wb_rt_print_float32:
+	cvtss2sd %xmm0, %xmm0
+	movl $1, %edi
+	jmp .Lwb_print_decimal
+
+wb_rt_print_float:
+	xorl %edi, %edi
+.Lwb_print_decimal:
+	pushq %rbx
+	pushq %r12
+	subq $56, %rsp
+	movsd %xmm0, (%rsp)
+	movl %edi, 8(%rsp)
+	ucomisd %xmm0, %xmm0
+	jp .Lwb_float_nan
+	movapd %xmm0, %xmm1
+	subsd %xmm0, %xmm1
+	ucomisd %xmm1, %xmm1
+	jp .Lwb_float_inf
+	xorl %ebx, %ebx
+.Lwb_float_loop:
+	leaq 16(%rsp), %rdi
+	movl $32, %esi
+	leaq .Lwb_fmt_exp(%rip), %rdx
+	movl %ebx, %ecx
+	movsd (%rsp), %xmm0
+	movl $1, %eax
+	call snprintf@PLT
+	cmpl $16, %ebx
+	jae .Lwb_float_found
+	leaq 16(%rsp), %rdi
+	xorl %esi, %esi
+	cmpl $0, 8(%rsp)
+	jne .Lwb_float_single
+	call strtod@PLT
+	jmp .Lwb_float_compare
+.Lwb_float_single:
+	call strtof@PLT
+	cvtss2sd %xmm0, %xmm0
+.Lwb_float_compare:
+	ucomisd (%rsp), %xmm0
+	jp .Lwb_float_next
+	je .Lwb_float_found
+.Lwb_float_next:
+	incl %ebx
+	jmp .Lwb_float_loop
+.Lwb_float_found:
+	leaq 16(%rsp), %rdi
+	movl $101, %esi
+	call strchr@PLT
+	leaq 1(%rax), %rdi
+	call atoi@PLT
+	movl %eax, %r12d
+	cmpl %ebx, %r12d
+	jge .Lwb_float_digits
+	leaq .Lwb_fmt_fixed(%rip), %rdi
+	movl %ebx, %esi
+	subl %r12d, %esi
+	movsd (%rsp), %xmm0
+	movl $1, %eax
+	call printf@PLT
+	jmp .Lwb_float_done
+.Lwb_float_digits:
+	movl $1, %esi
+	cmpb $45, 16(%rsp)
+	jne .Lwb_float_head
+	movl $2, %esi
+.Lwb_float_head:
+	leaq 16(%rsp), %rdx
+	movl %ebx, %ecx
+	movslq %esi, %rax
+	leaq 17(%rsp,%rax), %r8
+	movl %r12d, %r9d
+	subl %ebx, %r9d
+	leaq .Lwb_fmt_digits(%rip), %rdi
+	subq $16, %rsp
+	movq $0, (%rsp)
+	xorl %eax, %eax
+	call printf@PLT
+	addq $16, %rsp
+	jmp .Lwb_float_done
+.Lwb_float_inf:
+	leaq .Lwb_fmt_inf(%rip), %rdi
+	movl $1, %eax
+	call printf@PLT
+	jmp .Lwb_float_done
+.Lwb_float_nan:
+	leaq .Lwb_nan(%rip), %rdi
+	call puts@PLT
+.Lwb_float_done:
+	addq $56, %rsp
+	popq %r12
+	popq %rbx
+	ret
+
+# saturating conversion, which matches Rust's `as`
+wb_rt_fptosi:
+	xorl %eax, %eax
+	ucomisd %xmm0, %xmm0
+	jp .Lwb_fptosi_done
+	movl $2147483647, %eax
+	movabsq $0x41DFFFFFFFC00000, %rcx
+	movq %rcx, %xmm1
+	ucomisd %xmm1, %xmm0
+	jae .Lwb_fptosi_done
+	movl $-2147483648, %eax
+	movabsq $0xC1E0000000000000, %rcx
+	movq %rcx, %xmm1
+	ucomisd %xmm1, %xmm0
+	jbe .Lwb_fptosi_done
+	cvttsd2si %xmm0, %eax
+.Lwb_fptosi_done:
+	ret
+
+wb_rt_fptosi64:
+	xorl %eax, %eax
+	ucomisd %xmm0, %xmm0
+	jp .Lwb_fptosi64_done
+	movabsq $0x7FFFFFFFFFFFFFFF, %rax
+	movabsq $0x43E0000000000000, %rcx
+	movq %rcx, %xmm1
+	ucomisd %xmm1, %xmm0
+	jae .Lwb_fptosi64_done
+	# below the minimum, this gives the minimum
+	cvttsd2si %xmm0, %rax
+.Lwb_fptosi64_done:
+	ret
+
+wb_rt_fptoui8:
+	xorl %eax, %eax
+	ucomisd %xmm0, %xmm0
+	jp .Lwb_fptoui8_done
+	xorpd %xmm1, %xmm1
+	ucomisd %xmm1, %xmm0
+	jbe .Lwb_fptoui8_done
+	movl $255, %eax
+	movabsq $0x406FE00000000000, %rcx
+	movq %rcx, %xmm1
+	ucomisd %xmm1, %xmm0
+	jae .Lwb_fptoui8_done
+	cvttsd2si %xmm0, %eax
+.Lwb_fptoui8_done:
+	ret
+
+# reads exit with status 1 if the input is exhausted or invalid
+wb_rt_read_int:
+	subq $24, %rsp
+	leaq 12(%rsp), %rsi
+	leaq .Lwb_scan_int(%rip), %rdi
+	xorl %eax, %eax
+	call scanf@PLT
+	cmpl $1, %eax
+	jne .Lwb_read_fail
+	movl 12(%rsp), %eax
+	addq $24, %rsp
+	ret
+
+wb_rt_read_float:
+	subq $24, %rsp
+	leaq 8(%rsp), %rsi
+	leaq .Lwb_scan_float(%rip), %rdi
+	xorl %eax, %eax
+	call scanf@PLT
+	cmpl $1, %eax
+	jne .Lwb_read_fail
+	movsd 8(%rsp), %xmm0
+	addq $24, %rsp
+	ret
+
+wb_rt_read_char:
+	subq $8, %rsp
+	call getchar@PLT
+	testl %eax, %eax
+	js .Lwb_read_fail
+	movzbl %al, %eax
+	addq $8, %rsp
+	ret
+
+.Lwb_read_fail:
+	movl $1, %edi
+	call exit@PLT
+"#;
+
+/// System V registers for integer arguments
+const INT_ARGS: [&str; 6] = ["%edi", "%esi", "%edx", "%ecx", "%r8d", "%r9d"];
+
+/// System V registers for float arguments
+const FLOAT_ARGS: usize = 8;
+
+/// Struct for generating x86-64 assembly (GNU as syntax), by way of the crate's own IR
+pub struct CodegenAsm<'a> {
+    /// lowers the AST into IR
+    lowering: Lowering<'a>,
+}
+
+impl<'a> From<&'a Parser<'a>> for CodegenAsm<'a> {
+    fn from(parser: &'a Parser) -> Self {
+        Self {
+            lowering: Lowering::from(parser),
+        }
+    }
+}
+
+/// whether a value of this type is passed in an SSE register
+fn is_float(dtype: &Type) -> bool {
+    dtype.is_float()
+}
+
+/// the instruction that moves a value of this type to or from memory
+fn mov(dtype: &Type) -> &'static str {
+    match dtype {
+        Type::Float => "movsd",
+        Type::Float32 => "movss",
+        Type::Int64 | Type::Func(_) => "movq",
+        _ => "movl",
+    }
+}
+
+/// the register holding an integer value of this type, from the name of its low 32 bits
+fn int_register(reg: &str, dtype: &Type) -> String {
+    match (dtype, reg.strip_suffix('d')) {
+        (Type::Int64 | Type::Func(_), Some(numbered)) => numbered.to_string(),
+        (Type::Int64 | Type::Func(_), None) => reg.replacen("%e", "%r", 1),
+        _ => reg.to_string(),
+    }
+}
+
+/// assign each parameter to a register or, for the rest, a stack position in order
+fn classify_args(types: &[Type]) -> Vec<ArgLocation> {
+    let (mut ints, mut floats, mut stack) = (0, 0, 0);
+    types
+        .iter()
+        .map(|dtype| {
+            if is_float(dtype) && floats < FLOAT_ARGS {
+                floats += 1;
+                ArgLocation::Float(floats - 1)
+            } else if !is_float(dtype) && ints < INT_ARGS.len() {
+                ints += 1;
+                ArgLocation::Int(ints - 1)
+            } else {
+                stack += 1;
+                ArgLocation::Stack(stack - 1)
+            }
+        })
+        .collect()
+}
+
+/// where an argument is passed
+#[derive(Clone, Copy)]
+enum ArgLocation {
+    Int(usize),
+    Float(usize),
+    Stack(usize),
+}
+
+/// code generation state for a single function
+struct AsmFunction<'m> {
+    func: &'m Function,
+    /// stack offset below %rbp of each register, since every register gets its own slot
+    slots: HashMap<Reg, usize>,
+    /// phi copies to perform when leaving a block: (phi register, type, incoming value)
+    phi_copies: HashMap<&'m String, Vec<(Reg, &'m Type, &'m Operand)>>,
+    lines: Vec<String>,
+}
+
+impl<'a> CodegenAsm<'a> {
+    /// transform AST into assembly
+    pub fn asm_codegen(&mut self) -> String {
+        let module = self.lowering.lower();
+        Self::asm_module(&module)
+    }
+
+    /// transform a module of the crate's IR into assembly
+    pub fn asm_module(module: &Module) -> String {
+        let mut lines = vec![RUNTIME.to_string()];
+
+        if !module.globals.is_empty() {
+            lines.push("\t.bss".to_string());
+            lines.push("\t.align 8".to_string());
+            for global in &module.globals {
+                lines.push(format!("{}:\n\t.zero 8", global_symbol(&global.name)));
+            }
+        }
+
+        lines.push("\t.text".to_string());
+        for func in &module.functions {
+            lines.push(AsmFunction::new(func).generate());
+        }
+
+        lines.push("\t.section .note.GNU-stack,\"\",@progbits\n".to_string());
+        lines.join("\n")
+    }
+}
+
+impl<'m> AsmFunction<'m> {
+    fn new(func: &'m Function) -> Self {
+        let mut slots = HashMap::new();
+        let mut phi_copies: HashMap<&String, Vec<_>> = HashMap::new();
+
+        let mut assign = |reg: Reg| {
+            let offset = 8 * (slots.len() + 1);
+            slots.entry(reg).or_insert(offset);
+        };
+
+        for (reg, _) in &func.params {
+            assign(*reg);
+        }
+        for block in &func.blocks {
+            for instr in &block.instrs {
+                if let Some(dest) = instr.dest() {
+                    assign(dest);
+                }
+                if let Instr::Phi {
+                    dest,
+                    dtype,
+                    incoming,
+                } = instr
+                {
+                    for (value, label) in incoming {
+                        phi_copies
+                            .entry(label)
+                            .or_default()
+                            .push((*dest, dtype, value));
+                    }
+                }
+            }
+        }
+
+        Self {
+            func,
+            slots,
+            phi_copies,
+            lines: Vec::new(),
+        }
+    }
+
+    fn emit(&mut self, line: &str) {
+        self.lines.push(format!("\t{line}"));
+    }
+
+    fn label(&self, label: &str) -> String {
+        format!(".L{}_{label}", self.func.name)
+    }
+
+    /// the stack slot of a register
+    fn slot(&self, reg: Reg) -> String {
+        format!("-{}(%rbp)", self.slots[&reg])
+    }
+
+    /// the memory referred to by an address operand (a global or an alloca)
+    fn address(&self, ptr: &Operand) -> String {
+        match ptr {
+            Operand::Reg(reg) => self.slot(*reg),
+            Operand::Global(name) => format!("{}(%rip)", global_symbol(name)),
+            Operand::Const(..) => panic!("constant used as an address"),
+        }
+    }
+
+    /// load a value into `int_reg` or, for floats, `float_reg` (clobbers %rax for float constants)
+    ///
+    /// `int_reg` is the name of the low 32 bits, widened for int64s. Values only pass through
+    /// %eax/%ecx (%rax/%rcx) or %xmm0/%xmm1 while an instruction is computed, and ints, bools,
+    /// chars and uint8s are all stored as 32-bit values, with bools as 0/1 and chars and uint8s
+    /// zero extended
+    fn load(&mut self, value: &Operand, dtype: &Type, int_reg: &str, float_reg: &str) {
+        let int_reg = int_register(int_reg, dtype);
+        match (value, is_float(dtype)) {
+            (Operand::Reg(reg), false) => {
+                self.emit(&format!("{} {}, {int_reg}", mov(dtype), self.slot(*reg)));
+            }
+            (Operand::Reg(reg), true) => {
+                self.emit(&format!("{} {}, {float_reg}", mov(dtype), self.slot(*reg)));
+            }
+            (Operand::Const(WabbitType::Float(val)), true) => {
+                self.emit(&format!("movabsq ${:#X}, %rax", val.to_bits()));
+                self.emit(&format!("movq %rax, {float_reg}"));
+            }
+            (Operand::Const(WabbitType::Float32(val)), true) => {
+                self.emit(&format!("movl ${:#X}, %eax", val.to_bits()));
+                self.emit(&format!("movd %eax, {float_reg}"));
+            }
+            (Operand::Const(WabbitType::Int64(val)), false) => {
+                self.emit(&format!("movabsq ${val}, {int_reg}"));
+            }
+            (Operand::Const(value), false) => {
+                let value = match value {
+                    WabbitType::Int(val) => *val,
+                    WabbitType::Uint8(val) => i32::from(*val),
+                    WabbitType::Bool(val) => i32::from(*val),
+                    WabbitType::Char(val) => i32::from(*val as u8),
+                    _ => panic!("typecheck failure"),
+                };
+                self.emit(&format!("movl ${value}, {int_reg}"));
+            }
+            _ => panic!("typecheck failure"),
+        }
+    }
+
+    /// store %eax, %rax or %xmm0 into the slot of a register
+    fn store(&mut self, dest: Reg, dtype: &Type) {
+        let value = if is_float(dtype) {
+            "%xmm0".to_string()
+        } else {
+            int_register("%eax", dtype)
+        };
+        self.emit(&format!("{} {value}, {}", mov(dtype), self.slot(dest)));
+    }
+
+    /// set %eax to 0 or 1 from the flags, using the given condition code
+    fn set_bool(&mut self, condition: &str) {
+        self.emit(&format!("set{condition} %al"));
+        self.emit("movzbl %al, %eax");
+    }
+
+    fn generate(mut self) -> String {
+        let symbol = function_symbol(&self.func.name);
+        self.lines.push(format!("\n\t.globl {symbol}"));
+        self.lines.push(format!("\t.type {symbol}, @function"));
+        self.lines.push(format!("{symbol}:"));
+
+        // keep %rsp 16-byte aligned for calls
+        let frame = 8 * (self.slots.len() + self.slots.len() % 2);
+        self.emit("pushq %rbp");
+        self.emit("movq %rsp, %rbp");
+        if frame > 0 {
+            self.emit(&format!("subq ${frame}, %rsp"));
+        }
+
+        let func = self.func;
+        let types: Vec<Type> = func.params.iter().map(|(_, dtype)| dtype.clone()).collect();
+        for ((reg, dtype), location) in func.params.iter().zip(classify_args(&types)) {
+            let slot = self.slot(*reg);
+            match location {
+                ArgLocation::Int(i) => {
+                    let arg = int_register(INT_ARGS[i], dtype);
+                    self.emit(&format!("{} {arg}, {slot}", mov(dtype)));
+                }
+                ArgLocation::Float(i) => self.emit(&format!("{} %xmm{i}, {slot}", mov(dtype))),
+                ArgLocation::Stack(i) => {
+                    let arg = format!("{}(%rbp)", 16 + 8 * i);
+                    self.load_arg(&arg, dtype);
+                    self.store(*reg, dtype);
+                }
+            }
+        }
+
+        for block in &func.blocks {
+            self.lines.push(format!("{}:", self.label(&block.label)));
+            for instr in &block.instrs {
+                self.instr(instr);
+            }
+            if let Some(copies) = self.phi_copies.get(&block.label).cloned() {
+                for (dest, dtype, value) in copies {
+                    self.load(value, dtype, "%eax", "%xmm0");
+                    self.store(dest, dtype);
+                }
+            }
+            let term = block.term.as_ref().expect("IR block without a terminator");
+            self.terminator(&block.label, term);
+        }
+
+        self.lines.join("\n")
+    }
+
+    /// load a stack argument into %eax, %rax or %xmm0
+    fn load_arg(&mut self, arg: &str, dtype: &Type) {
+        let value = if is_float(dtype) {
+            "%xmm0".to_string()
+        } else {
+            int_register("%eax", dtype)
+        };
+        self.emit(&format!("{} {arg}, {value}", mov(dtype)));
+    }
+
+    /// generate assembly for a single instruction
+    fn instr(&mut self, instr: &Instr) {
+        match instr {
+            // storage is the register's own slot, and phis are copied into by predecessors
+            Instr::Alloca { .. } | Instr::Phi { .. } => (),
+            Instr::Load { dest, dtype, ptr } => {
+                let address = self.address(ptr);
+                self.load_arg(&address, dtype);
+                self.store(*dest, dtype);
+            }
+            Instr::Store { dtype, value, ptr } => {
+                self.load(value, dtype, "%eax", "%xmm0");
+                let address = self.address(ptr);
+                let value = if is_float(dtype) {
+                    "%xmm0".to_string()
+                } else {
+                    int_register("%eax", dtype)
+                };
+                self.emit(&format!("{} {value}, {address}", mov(dtype)));
+            }
+            Instr::Binary {
+                dest,
+                op,
+                dtype,
+                lhs,
+                rhs,
+            } => {
+                self.load(rhs, dtype, "%ecx", "%xmm1");
+                self.load(lhs, dtype, "%eax", "%xmm0");
+                let result = self.binary(op, dtype);
+                self.store(*dest, &result);
+            }
+            Instr::Unary {
+                dest,
+                op,
+                dtype,
+                operand,
+            } => {
+                self.load(operand, dtype, "%eax", "%xmm0");
+                match (dtype, op) {
+                    (Type::Int, UnaryOp::Minus) => self.emit("negl %eax"),
+                    (Type::Int64, UnaryOp::Minus) => self.emit("negq %rax"),
+                    (Type::Float, UnaryOp::Minus) => {
+                        self.emit("movabsq $0x8000000000000000, %rax");
+                        self.emit("movq %rax, %xmm1");
+                        self.emit("xorpd %xmm1, %xmm0");
+                    }
+                    (Type::Float32, UnaryOp::Minus) => {
+                        self.emit("movl $0x80000000, %eax");
+                        self.emit("movd %eax, %xmm1");
+                        self.emit("xorps %xmm1, %xmm0");
+                    }
+                    (Type::Bool, UnaryOp::LogicalNot) => self.emit("xorl $1, %eax"),
+                    _ => panic!("typecheck failure"),
+                }
+                self.store(*dest, dtype);
+            }
+            Instr::Convert {
+                dest,
+                from,
+                to,
+                value,
+            } => {
+                self.load(value, from, "%eax", "%xmm0");
+                self.convert(from, to);
+                self.store(*dest, to);
+            }
+            Instr::Call {
+                dest,
+                ret,
+                func,
+                args,
+            } => {
+                let pushed = self.call_args(args);
+                self.emit(&format!("call {}", function_symbol(func)));
+                if pushed > 0 {
+                    self.emit(&format!("addq ${pushed}, %rsp"));
+                }
+                self.store(*dest, ret);
+            }
+            Instr::Closure {
+                dest,
+                func,
+                captures,
+                ..
+            } => {
+                // a function value is a heap record of the function, then 8 bytes per capture
+                self.emit(&format!("movl ${}, %edi", 8 * (captures.len() + 1)));
+                self.emit("call malloc@PLT");
+                self.emit(&format!("movq %rax, {}", self.slot(*dest)));
+                self.emit(&format!("leaq {}(%rip), %rcx", function_symbol(func)));
+                self.emit("movq %rcx, (%rax)");
+                for (i, (dtype, value)) in captures.iter().enumerate() {
+                    self.load(value, dtype, "%ecx", "%xmm0");
+                    self.emit(&format!("movq {}, %rdx", self.slot(*dest)));
+                    let value = if is_float(dtype) {
+                        "%xmm0".to_string()
+                    } else {
+                        int_register("%ecx", dtype)
+                    };
+                    self.emit(&format!("{} {value}, {}(%rdx)", mov(dtype), 8 * (i + 1)));
+                }
+            }
+            Instr::Capture { dest, dtype, index } => {
+                // the function value is the first parameter
+                let (closure, _) = self.func.params[0];
+                self.emit(&format!("movq {}, %rdx", self.slot(closure)));
+                self.load_arg(&format!("{}(%rdx)", 8 * (index + 1)), dtype);
+                self.store(*dest, dtype);
+            }
+            Instr::CallValue {
+                dest,
+                ret,
+                callee,
+                args,
+            } => {
+                // the function value is passed first, so it can read its captured values
+                let types = args.iter().map(|(dtype, _)| dtype.clone()).collect();
+                let dtype = Type::func(types, ret.clone());
+                let args: Vec<(Type, Operand)> = std::iter::once((dtype.clone(), callee.clone()))
+                    .chain(args.iter().cloned())
+                    .collect();
+                let pushed = self.call_args(&args);
+                self.load(callee, &dtype, "%r11d", "%xmm0");
+                self.emit("movq (%r11), %r11");
+                self.emit("call *%r11");
+                if pushed > 0 {
+                    self.emit(&format!("addq ${pushed}, %rsp"));
+                }
+                self.store(*dest, ret);
+            }
+            Instr::Print { dtype, value } => {
+                self.load(value, dtype, "%edi", "%xmm0");
+                let runtime = match dtype {
+                    Type::Int | Type::Uint8 => "wb_rt_print_int",
+                    Type::Int64 => "wb_rt_print_int64",
+                    Type::Float => "wb_rt_print_float",
+                    Type::Float32 => "wb_rt_print_float32",
+                    Type::Char => "wb_rt_print_char",
+                    Type::Bool => "wb_rt_print_bool",
+                    Type::Func(_) => panic!("typecheck failure"),
+                };
+                self.emit(&format!("call {runtime}"));
+            }
+            Instr::Read { dest, dtype } => {
+                let runtime = match dtype {
+                    Type::Int => "wb_rt_read_int",
+                    Type::Float => "wb_rt_read_float",
+                    Type::Char => "wb_rt_read_char",
+                    _ => panic!("typecheck failure"),
+                };
+                self.emit(&format!("call {runtime}"));
+                self.store(*dest, dtype);
+            }
+        }
+    }
+
+    /// pass the arguments of a call, returning the bytes pushed onto the stack for it
+    fn call_args(&mut self, args: &[(Type, Operand)]) -> usize {
+        let types: Vec<Type> = args.iter().map(|(dtype, _)| dtype.clone()).collect();
+        let locations = classify_args(&types);
+
+        // stack arguments are pushed last to first, keeping %rsp 16-byte aligned
+        let stack: Vec<_> = std::iter::zip(args, &locations)
+            .filter(|(_, location)| matches!(location, ArgLocation::Stack(_)))
+            .collect();
+        let padding = if stack.len() % 2 == 1 { 8 } else { 0 };
+        if padding > 0 {
+            self.emit("subq $8, %rsp");
+        }
+        for ((dtype, value), _) in stack.iter().rev() {
+            self.load(value, dtype, "%eax", "%xmm0");
+            if is_float(dtype) {
+                self.emit("movq %xmm0, %rax");
+            }
+            self.emit("pushq %rax");
+        }
+
+        for ((dtype, value), location) in std::iter::zip(args, &locations) {
+            match location {
+                ArgLocation::Int(i) => self.load(value, dtype, INT_ARGS[*i], "%xmm0"),
+                ArgLocation::Float(i) => {
+                    self.load(value, dtype, "%eax", &format!("%xmm{i}"));
+                }
+                ArgLocation::Stack(_) => (),
+            }
+        }
+        8 * stack.len() + padding
+    }
+
+    /// compute %eax/%rax/%xmm0 `op` %ecx/%rcx/%xmm1, returning the type of the result
+    fn binary(&mut self, op: &BinaryOp, dtype: &Type) -> Type {
+        if is_float(dtype) {
+            return self.float_binary(op, dtype);
+        }
+        // uint8s are computed as 32-bit values, then truncated
+        let unsigned = *dtype == Type::Uint8;
+        let q = if *dtype == Type::Int64 { "q" } else { "l" };
+        let (a, c) = (int_register("%eax", dtype), int_register("%ecx", dtype));
+        match op {
+            BinaryOp::Plus => self.emit(&format!("add{q} {c}, {a}")),
+            BinaryOp::Minus => self.emit(&format!("sub{q} {c}, {a}")),
+            BinaryOp::Times => self.emit(&format!("imul{q} {c}, {a}")),
+            BinaryOp::Divide | BinaryOp::Modulo => {
+                if unsigned {
+                    self.emit("xorl %edx, %edx");
+                    self.emit("divl %ecx");
+                } else {
+                    self.emit(if *dtype == Type::Int64 {
+                        "cqto"
+                    } else {
+                        "cltd"
+                    });
+                    self.emit(&format!("idiv{q} {c}"));
+                }
+                if matches!(op, BinaryOp::Modulo) {
+                    let d = int_register("%edx", dtype);
+                    self.emit(&format!("mov{q} {d}, {a}"));
+                }
+            }
+            BinaryOp::BitAnd => self.emit(&format!("and{q} {c}, {a}")),
+            BinaryOp::BitOr => self.emit(&format!("or{q} {c}, {a}")),
+            BinaryOp::BitXor => self.emit(&format!("xor{q} {c}, {a}")),
+            BinaryOp::ShiftLeft => self.emit(&format!("sal{q} %cl, {a}")),
+            BinaryOp::ShiftRight if unsigned => self.emit("shrl %cl, %eax"),
+            BinaryOp::ShiftRight => self.emit(&format!("sar{q} %cl, {a}")),
+            _ => {
+                // chars and bools are zero extended, so signed comparisons are also unsigned
+                let condition = match (op, unsigned) {
+                    (BinaryOp::EqualEqual, _) => "e",
+                    (BinaryOp::NotEqual, _) => "ne",
+                    (BinaryOp::Less, false) => "l",
+                    (BinaryOp::LessEqual, false) => "le",
+                    (BinaryOp::Greater, false) => "g",
+                    (BinaryOp::GreaterEqual, false) => "ge",
+                    (BinaryOp::Less, true) => "b",
+                    (BinaryOp::LessEqual, true) => "be",
+                    (BinaryOp::Greater, true) => "a",
+                    (BinaryOp::GreaterEqual, true) => "ae",
+                    _ => panic!("typecheck failure"),
+                };
+                self.emit(&format!("cmp{q} {c}, {a}"));
+                self.set_bool(condition);
+                return Type::Bool;
+            }
+        }
+        if unsigned {
+            self.emit("movzbl %al, %eax");
+        }
+        dtype.clone()
+    }
+
+    /// compute %xmm0 `op` %xmm1 for a float or float32, returning the type of the result
+    fn float_binary(&mut self, op: &BinaryOp, dtype: &Type) -> Type {
+        let s = if *dtype == Type::Float32 { "ss" } else { "sd" };
+        match op {
+            BinaryOp::Plus => self.emit(&format!("add{s} %xmm1, %xmm0")),
+            BinaryOp::Minus => self.emit(&format!("sub{s} %xmm1, %xmm0")),
+            BinaryOp::Times => self.emit(&format!("mul{s} %xmm1, %xmm0")),
+            BinaryOp::Divide => self.emit(&format!("div{s} %xmm1, %xmm0")),
+            // unordered (NaN) comparisons set ZF, PF and CF, so only `!=` is true
+            BinaryOp::EqualEqual => {
+                self.emit(&format!("ucomi{s} %xmm1, %xmm0"));
+                self.emit("sete %al");
+                self.emit("setnp %cl");
+                self.emit("andb %cl, %al");
+                self.emit("movzbl %al, %eax");
+                return Type::Bool;
+            }
+            BinaryOp::NotEqual => {
+                self.emit(&format!("ucomi{s} %xmm1, %xmm0"));
+                self.emit("setne %al");
+                self.emit("setp %cl");
+                self.emit("orb %cl, %al");
+                self.emit("movzbl %al, %eax");
+                return Type::Bool;
+            }
+            BinaryOp::Greater | BinaryOp::GreaterEqual => {
+                self.emit(&format!("ucomi{s} %xmm1, %xmm0"));
+                self.set_bool(if matches!(op, BinaryOp::Greater) {
+                    "a"
+                } else {
+                    "ae"
+                });
+                return Type::Bool;
+            }
+            BinaryOp::Less | BinaryOp::LessEqual => {
+                self.emit(&format!("ucomi{s} %xmm0, %xmm1"));
+                self.set_bool(if matches!(op, BinaryOp::Less) {
+                    "a"
+                } else {
+                    "ae"
+                });
+                return Type::Bool;
+            }
+            _ => panic!("typecheck failure"),
+        }
+        dtype.clone()
+    }
+
+    /// convert %eax/%rax/%xmm0 from one type to another, in place
+    fn convert(&mut self, from: &Type, to: &Type) {
+        // float32s are widened first, which is exact
+        if *from == Type::Float32 && *to != Type::Float32 {
+            self.emit("cvtss2sd %xmm0, %xmm0");
+        }
+        match (to, from) {
+            (Type::Float32, Type::Float) => self.emit("cvtsd2ss %xmm0, %xmm0"),
+            (Type::Float, Type::Float32) => (),
+            // saturating, which matches the interpreter's `as`
+            (Type::Int, Type::Float | Type::Float32) => self.emit("call wb_rt_fptosi"),
+            (Type::Int64, Type::Float | Type::Float32) => self.emit("call wb_rt_fptosi64"),
+            (Type::Uint8, Type::Float | Type::Float32) => self.emit("call wb_rt_fptoui8"),
+            (Type::Float, Type::Int64) => self.emit("cvtsi2sdq %rax, %xmm0"),
+            (Type::Float32, Type::Int64) => self.emit("cvtsi2ssq %rax, %xmm0"),
+            // other integers are 32 bits, with uint8s zero extended
+            (Type::Float, _) => self.emit("cvtsi2sdl %eax, %xmm0"),
+            (Type::Float32, _) => self.emit("cvtsi2ssl %eax, %xmm0"),
+            // only exactly 1 is true, as in the interpreter
+            (Type::Bool, _) => {
+                let a = int_register("%eax", from);
+                let q = if *from == Type::Int64 { "q" } else { "l" };
+                self.emit(&format!("cmp{q} $1, {a}"));
+                self.set_bool("e");
+            }
+            (Type::Int64, Type::Int) => self.emit("movslq %eax, %rax"),
+            (Type::Char | Type::Uint8, _) => self.emit("movzbl %al, %eax"),
+            // chars, bools and uint8s are already zero extended, and an int is the low 32 bits of
+            // an int64
+            (Type::Int | Type::Int64, _) => (),
+            (Type::Func(_), _) => panic!("typecheck failure"),
+        }
+    }
+
+    /// generate assembly for a block terminator
+    fn terminator(&mut self, label: &str, term: &Terminator) {
+        match term {
+            Terminator::Jump(label) => self.emit(&format!("jmp {}", self.label(label))),
+            Terminator::Branch {
+                cond,
+                then,
+                otherwise,
+            } => {
+                self.load(cond, &Type::Bool, "%eax", "%xmm0");
+                self.emit("testl %eax, %eax");
+                self.emit(&format!("jne {}", self.label(then)));
+                self.emit(&format!("jmp {}", self.label(otherwise)));
+            }
+            Terminator::Switch {
+                dtype,
+                value,
+                cases,
+                default,
+            } => {
+                self.load(value, dtype, "%eax", "%xmm0");
+                for (case, label) in cases {
+                    self.load(&Operand::Const(case.clone()), dtype, "%ecx", "%xmm1");
+                    self.emit("cmpl %ecx, %eax");
+                    self.emit(&format!("je {}", self.label(label)));
+                }
+                self.emit(&format!("jmp {}", self.label(default)));
+            }
+            Terminator::Return(dtype, value) => {
+                self.load(value, dtype, "%eax", "%xmm0");
+                self.emit("leave");
+                self.emit("ret");
+            }
+            Terminator::Unreachable => self.emit("ud2"),
+            Terminator::Fail(message) => {
+                let message = format!("error: {message}\n");
+                let symbol = format!("{}_message", self.label(label));
+                self.emit(".pushsection .rodata");
+                self.lines.push(format!("{symbol}:"));
+                self.emit(&format!(".ascii {message:?}"));
+                self.emit(".popsection");
+                self.emit("movl $2, %edi");
+                self.emit(&format!("leaq {symbol}(%rip), %rsi"));
+                self.emit(&format!("movq ${}, %rdx", message.len()));
+                self.emit("call write@PLT");
+                self.emit("movl $1, %edi");
+                self.emit("call exit@PLT");
+            }
+        }
+    }
+}
@@ -10,6 +10,12 @@ pub enum Stmt {
         value: Expr,
         id: usize,
     },
+    Assert {
+        value: Expr,
+        /// the source of `value`, for the error when it is false
+        text: String,
+        id: usize,
+    },
     VarDef {
         name: String,
         maybe_type: Option<Type>,
@@ -35,11 +41,28 @@ pub enum Stmt {
         maybe_else_block: Option<Box<Stmt>>,
         id: usize,
     },
+    Match {
+        value: Expr,
+        /// the literal pattern of each arm, with the arm's block
+        arms: Vec<(Expr, Stmt)>,
+        /// the block of the `_` arm, run when no pattern matches
+        maybe_default: Option<Box<Stmt>>,
+        id: usize,
+    },
     While {
         condition: Expr,
         body: Box<Stmt>,
         id: usize,
     },
+    For {
+        /// a variable definition or assignment, run once before the loop
+        init: Box<Stmt>,
+        condition: Expr,
+        /// an assignment, run after each iteration, including those ended by `continue`
+        step: Box<Stmt>,
+        body: Box<Stmt>,
+        id: usize,
+    },
     LoopControl {
         control: LoopControl,
         id: usize,
@@ -50,6 +73,8 @@ pub enum Stmt {
     },
     Assign {
         name: String,
+        /// the operator of a compound assignment such as `+=`, applied to the variable and `value`
+        op: Option<BinaryOp>,
         value: Expr,
         id: usize,
     },
@@ -67,6 +92,19 @@ pub enum Expr {
         params: Vec<Expr>,
         id: usize,
     },
+    /// a call of a function value that is not a name, such as `make_adder(1)(2)`
+    CallValue {
+        callee: Box<Expr>,
+        params: Vec<Expr>,
+        id: usize,
+    },
+    /// an anonymous function, such as `func(x int) int { return x + n; }`
+    Function {
+        params: Vec<(String, Type)>,
+        return_type: Type,
+        body: Box<Stmt>,
+        id: usize,
+    },
     TypeConversion {
         dtype: Type,
         params: Vec<Expr>,
@@ -106,3 +144,186 @@ pub enum Expr {
         id: usize,
     },
 }
+
+impl Stmt {
+    /// the id of the statement's source range
+    pub fn id(&self) -> usize {
+        match self {
+            Stmt::Print { id, .. }
+            | Stmt::Assert { id, .. }
+            | Stmt::VarDef { id, .. }
+            | Stmt::ConstDef { id, .. }
+            | Stmt::FuncDef { id, .. }
+            | Stmt::If { id, .. }
+            | Stmt::Match { id, .. }
+            | Stmt::While { id, .. }
+            | Stmt::For { id, .. }
+            | Stmt::LoopControl { id, .. }
+            | Stmt::Return { id, .. }
+            | Stmt::Assign { id, .. }
+            | Stmt::Block { id, .. } => *id,
+            Stmt::Expr(e) => e.id(),
+        }
+    }
+}
+
+impl Expr {
+    /// the id of the expression's source range
+    pub fn id(&self) -> usize {
+        match self {
+            Expr::Call { id, .. }
+            | Expr::CallValue { id, .. }
+            | Expr::Function { id, .. }
+            | Expr::TypeConversion { id, .. }
+            | Expr::Logical { id, .. }
+            | Expr::VarName { id, .. }
+            | Expr::TypeName { id, .. }
+            | Expr::Grouping { id, .. }
+            | Expr::Binary { id, .. }
+            | Expr::Unary { id, .. }
+            | Expr::Literal { id, .. } => *id,
+        }
+    }
+}
+
+/// the names that a function expression uses without defining them itself, in the order they are
+/// first used, which includes the variables it captures
+pub(crate) fn free_names<'a>(params: &'a [(String, Type)], body: &'a Stmt) -> Vec<&'a String> {
+    let mut scopes = vec![params.iter().map(|(name, _)| name).collect()];
+    let mut free = Vec::new();
+    free_names_stmt(body, &mut scopes, &mut free);
+    free
+}
+
+fn use_name<'a>(name: &'a String, scopes: &[Vec<&'a String>], free: &mut Vec<&'a String>) {
+    if !scopes.iter().any(|scope| scope.contains(&name)) && !free.contains(&name) {
+        free.push(name);
+    }
+}
+
+fn free_names_block<'a>(
+    block: &'a Stmt,
+    scopes: &mut Vec<Vec<&'a String>>,
+    free: &mut Vec<&'a String>,
+) {
+    scopes.push(Vec::new());
+    free_names_stmt(block, scopes, free);
+    scopes.pop();
+}
+
+fn free_names_stmt<'a>(
+    stmt: &'a Stmt,
+    scopes: &mut Vec<Vec<&'a String>>,
+    free: &mut Vec<&'a String>,
+) {
+    match stmt {
+        Stmt::Print { value, .. }
+        | Stmt::Assert { value, .. }
+        | Stmt::Return { value, .. }
+        | Stmt::Expr(value) => free_names_expr(value, scopes, free),
+        Stmt::VarDef {
+            name, maybe_value, ..
+        } => {
+            if let Some(value) = maybe_value {
+                free_names_expr(value, scopes, free);
+            }
+            scopes.last_mut().unwrap().push(name);
+        }
+        Stmt::ConstDef { name, value, .. } => {
+            free_names_expr(value, scopes, free);
+            scopes.last_mut().unwrap().push(name);
+        }
+        Stmt::FuncDef { .. } | Stmt::LoopControl { .. } => {}
+        Stmt::If {
+            condition,
+            then_block,
+            maybe_else_block,
+            ..
+        } => {
+            free_names_expr(condition, scopes, free);
+            free_names_block(then_block, scopes, free);
+            if let Some(else_block) = maybe_else_block {
+                free_names_block(else_block, scopes, free);
+            }
+        }
+        Stmt::Match {
+            value,
+            arms,
+            maybe_default,
+            ..
+        } => {
+            free_names_expr(value, scopes, free);
+            for (_, arm) in arms {
+                free_names_block(arm, scopes, free);
+            }
+            if let Some(default) = maybe_default {
+                free_names_block(default, scopes, free);
+            }
+        }
+        Stmt::While {
+            condition, body, ..
+        } => {
+            free_names_expr(condition, scopes, free);
+            free_names_block(body, scopes, free);
+        }
+        Stmt::For {
+            init,
+            condition,
+            step,
+            body,
+            ..
+        } => {
+            scopes.push(Vec::new());
+            free_names_stmt(init, scopes, free);
+            free_names_expr(condition, scopes, free);
+            free_names_block(body, scopes, free);
+            free_names_stmt(step, scopes, free);
+            scopes.pop();
+        }
+        Stmt::Assign { name, value, .. } => {
+            use_name(name, scopes, free);
+            free_names_expr(value, scopes, free);
+        }
+        Stmt::Block { statements, .. } => {
+            for statement in statements {
+                free_names_stmt(statement, scopes, free);
+            }
+        }
+    }
+}
+
+fn free_names_expr<'a>(e: &'a Expr, scopes: &mut Vec<Vec<&'a String>>, free: &mut Vec<&'a String>) {
+    match e {
+        Expr::Call { name, params, .. } => {
+            use_name(name, scopes, free);
+            for param in params {
+                free_names_expr(param, scopes, free);
+            }
+        }
+        Expr::CallValue { callee, params, .. } => {
+            free_names_expr(callee, scopes, free);
+            for param in params {
+                free_names_expr(param, scopes, free);
+            }
+        }
+        Expr::TypeConversion { params, .. } => {
+            for param in params {
+                free_names_expr(param, scopes, free);
+            }
+        }
+        Expr::Function { params, body, .. } => {
+            scopes.push(params.iter().map(|(name, _)| name).collect());
+            free_names_stmt(body, scopes, free);
+            scopes.pop();
+        }
+        Expr::VarName { name, .. } => use_name(name, scopes, free),
+        Expr::Logical { lhs, rhs, .. } | Expr::Binary { lhs, rhs, .. } => {
+            free_names_expr(lhs, scopes, free);
+            free_names_expr(rhs, scopes, free);
+        }
+        Expr::Grouping { e: operand, .. } | Expr::Unary { operand, .. } => {
+            free_names_expr(operand, scopes, free);
+        }
+        Expr::TypeName { .. } | Expr::Literal { .. } => {}
+    }
+}
@@ -1,122 +1,726 @@
-use std::path::PathBuf;
-use std::process::Command;
+use std::fmt::Display;
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use clap::Parser as CliParser;
-use main_error::MainError;
+use clap::error::ErrorKind;
+use clap::{Args, CommandFactory, Parser as CliParser, Subcommand, ValueEnum};
 
-use wabbit::{CodegenLLVM, Interpreter, Parser, Scanner, Typechecker, WabbitErrorReporter};
+use wabbit::coverage::Coverage;
+use wabbit::debugger::{Console, Debugger};
+use wabbit::host::Limits;
+use wabbit::llvm::{Overflow, Target};
+use wabbit::native::{BuildError, Emit, Toolchain};
+use wabbit::profile::Profile;
+use wabbit::testing::{self, Outcome};
+use wabbit::trace::Trace;
+use wabbit::{
+    CodegenAsm, CodegenC, CodegenLLVM, CodegenWasm, Interpreter, Lowering, Parser, Scanner,
+    Typechecker, WabbitError, WabbitErrorReporter,
+};
 
-/// command-line options
-#[allow(clippy::struct_excessive_bools)]
+/// a Wabbit interpreter and compiler
+///
+/// exits with 1 for an error in the program, 2 for invalid arguments, 3 when the program fails
+/// while running, and 4 when reading files or running external tools fails
 #[derive(CliParser, Debug)]
 pub struct Cli {
-    /// option to print tokens
-    #[arg(short, long)]
-    tokens: bool,
+    /// print the time taken by each stage to stderr
+    #[arg(long, global = true)]
+    time_passes: bool,
 
-    /// option to print AST
-    #[arg(short, long)]
-    ast: bool,
+    #[command(subcommand)]
+    command: Commands,
+}
 
-    /// option to print minimized code
-    #[arg(short, long)]
-    minimize: bool,
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// run a program, by default with the interpreter
+    Run(Run),
+    /// check a program for errors without running it
+    Check(Source),
+    /// compile a program with LLVM, by default to a native executable
+    Build(Build),
+    /// print a program in the formatter's compact form
+    Fmt(Source),
+    /// print a stage of compilation
+    Dump(Dump),
+    /// run a program with the interpreter, pausing at breakpoints to inspect it
+    Debug(Debug),
+    /// run the `test_*` functions of a program, by default with the interpreter
+    Test(Test),
+}
+
+/// a Wabbit program, for subcommands that need nothing else
+#[derive(Args, Debug)]
+struct Source {
+    /// path to Wabbit program, or `-` for stdin
+    path: PathBuf,
+}
 
-    /// option to skip typechecking
+/// how `run` executes a program
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Backend {
+    Interpreter,
+    /// LLVM IR, compiled with clang
+    Llvm,
+    /// x86-64 assembly, assembled with cc
+    Asm,
+    /// C source, compiled with cc
+    C,
+}
+
+/// options for the `run` subcommand
+#[derive(Args, Debug)]
+#[allow(clippy::struct_excessive_bools)]
+struct Run {
+    /// path to Wabbit program, or `-` for stdin
+    path: PathBuf,
+
+    /// how to run the program
+    #[arg(long, value_enum, default_value_t = Backend::Interpreter)]
+    backend: Backend,
+
+    /// skip typechecking, leaving errors to the interpreter (compiled backends always typecheck)
     #[arg(long)]
     skip_typecheck: bool,
 
-    /// option to print LLVM IR
+    /// path to the compiler, by default `clang` for LLVM IR and `cc` otherwise
+    #[arg(long)]
+    cc: Option<PathBuf>,
+
+    /// int overflow with `--backend llvm`: `trap` to exit with an error, as the interpreter
+    /// does, or `wrap`
+    #[arg(long, default_value = "trap")]
+    overflow: Overflow,
+
+    /// stop the interpreter after this many statements
     #[arg(long)]
-    llvm_print: bool,
+    fuel: Option<u64>,
+
+    /// the deepest the interpreter may nest function calls
+    #[arg(long, default_value_t = MAX_CALL_DEPTH)]
+    max_call_depth: usize,
+
+    /// stop the interpreter after this many seconds
+    #[arg(long, value_parser = seconds)]
+    timeout: Option<Duration>,
+
+    /// print the statements the interpreter ran in each function and loop to stderr
+    #[arg(long)]
+    profile: bool,
+
+    /// write the statements the interpreter ran in each stack of calls to this file, as folded
+    /// stacks for flamegraph tools
+    #[arg(long, value_name = "PATH")]
+    folded: Option<PathBuf>,
+
+    /// print each statement to stderr as the interpreter runs it, with the values it assigns
+    #[arg(long)]
+    trace: bool,
+
+    /// print how many lines, branches and functions the interpreter ran to stderr, and the
+    /// lines it missed
+    #[arg(long)]
+    coverage: bool,
+
+    /// write the lines, branches and functions the interpreter ran to this file, as LCOV
+    #[arg(long, value_name = "PATH")]
+    lcov: Option<PathBuf>,
+}
+
+/// options for the `debug` subcommand
+#[derive(Args, Debug)]
+struct Debug {
+    /// path to Wabbit program, or `-` for stdin; with `--dap`, used if the client does not name one
+    #[arg(required_unless_present = "dap")]
+    path: Option<PathBuf>,
+
+    /// serve the Debug Adapter Protocol on stdin and stdout, for debugging in an editor
+    #[arg(long)]
+    dap: bool,
+
+    /// pause at this line, instead of before the first statement (repeatable)
+    #[arg(short, long = "break", value_name = "LINE")]
+    breakpoints: Vec<usize>,
+
+    /// the deepest the interpreter may nest function calls
+    #[arg(long, default_value_t = MAX_CALL_DEPTH)]
+    max_call_depth: usize,
+}
+
+/// the default for `--max-call-depth`, which fits in `INTERPRETER_STACK` with plenty to spare
+const MAX_CALL_DEPTH: usize = 10_000;
 
-    /// option to compile and execute LLVM IR (using clang)
+/// the stack size of the interpreter thread, since the interpreter recurses on the Rust stack
+const INTERPRETER_STACK: usize = 1 << 30;
+
+fn seconds(arg: &str) -> Result<Duration, String> {
+    let seconds: f64 = arg.parse().map_err(|e| format!("{e}"))?;
+    Duration::try_from_secs_f64(seconds).map_err(|e| format!("{e}"))
+}
+
+/// options for the `test` subcommand
+#[derive(Args, Debug)]
+struct Test {
+    /// path to Wabbit program, or `-` for stdin
+    path: PathBuf,
+
+    /// how to run the tests
+    #[arg(long, value_enum, default_value_t = Backend::Interpreter)]
+    backend: Backend,
+
+    /// path to the compiler, by default `clang` for LLVM IR and `cc` otherwise
     #[arg(long)]
-    llvm_exec: bool,
+    cc: Option<PathBuf>,
+
+    /// int overflow with `--backend llvm`: `trap` to exit with an error, as the interpreter
+    /// does, or `wrap`
+    #[arg(long, default_value = "trap")]
+    overflow: Overflow,
+
+    /// the deepest the interpreter may nest function calls
+    #[arg(long, default_value_t = MAX_CALL_DEPTH)]
+    max_call_depth: usize,
+
+    /// stop the interpreter after this many seconds in each test
+    #[arg(long, value_parser = seconds)]
+    timeout: Option<Duration>,
+}
+
+/// options for the `build` subcommand
+#[derive(Args, Debug)]
+struct Build {
+    /// path to Wabbit program, or `-` for stdin
+    path: PathBuf,
 
-    /// option to use interpreter
+    /// output path, by default the program's name with the extension for `--emit`
     #[arg(short, long)]
-    interpret: bool,
+    output: Option<PathBuf>,
+
+    /// what to write: `llvm-ir`, `asm`, `obj` or `exe`
+    #[arg(long, default_value = "exe")]
+    emit: Emit,
+
+    /// optimization level passed to the compiler, as in `-O2`
+    #[arg(short = 'O', default_value = "0")]
+    opt_level: String,
+
+    /// path to clang, or another compiler that accepts LLVM IR
+    #[arg(long, default_value = "clang")]
+    cc: PathBuf,
+
+    /// target for LLVM IR: `generic` (the host), `x86_64` or `aarch64`
+    #[arg(long, default_value = "generic")]
+    llvm_target: Target,
+
+    /// int overflow in LLVM IR: `trap` to exit with an error, as the interpreter does, or `wrap`
+    #[arg(long, default_value = "trap")]
+    overflow: Overflow,
+}
+
+/// what `dump` prints
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Stage {
+    Tokens,
+    Ast,
+    /// the intermediate representation
+    Ir,
+    /// LLVM IR
+    Llvm,
+    /// x86-64 assembly
+    Asm,
+    /// C source
+    C,
+    /// WebAssembly text
+    Wat,
+    /// a WebAssembly binary module
+    Wasm,
+}
+
+/// options for the `dump` subcommand
+#[derive(Args, Debug)]
+struct Dump {
+    /// what to print
+    #[arg(value_enum)]
+    stage: Stage,
+
+    /// path to Wabbit program, or `-` for stdin
+    path: PathBuf,
+
+    /// target for LLVM IR: `generic` (the host), `x86_64` or `aarch64`
+    #[arg(long, default_value = "generic")]
+    llvm_target: Target,
+
+    /// int overflow in LLVM IR: `trap` to exit with an error, as the interpreter does, or `wrap`
+    #[arg(long, default_value = "trap")]
+    overflow: Overflow,
+}
+
+/// why a subcommand failed, which decides the exit code
+enum Failure {
+    /// an error found in the program before running it
+    Compile(String),
+    /// an error while running the program
+    Runtime(String),
+    /// an error reading files or running external tools
+    Io(String),
+}
+
+impl Failure {
+    fn exit_code(&self) -> u8 {
+        match self {
+            Self::Compile(_) => 1,
+            Self::Runtime(_) => 3,
+            Self::Io(_) => 4,
+        }
+    }
+
+    /// an error reading or writing `path`
+    fn file(action: &str, path: &Path, e: &std::io::Error) -> Self {
+        Self::Io(format!("cannot {action} {}: {e}", path.display()))
+    }
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Compile(report) | Self::Runtime(report) => write!(f, "{report}"),
+            Self::Io(e) => write!(f, "Error: {e}"),
+        }
+    }
+}
+
+impl From<std::io::Error> for Failure {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e.to_string())
+    }
+}
+
+impl From<BuildError> for Failure {
+    fn from(e: BuildError) -> Self {
+        Self::Io(e.to_string())
+    }
+}
+
+/// times each stage, printing to stderr under `--time-passes`
+struct Passes {
+    enabled: bool,
+}
+
+impl Passes {
+    fn time<T>(&self, stage: &str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        if self.enabled {
+            eprintln!("{stage:>16}: {:.3?}", start.elapsed());
+        }
+        result
+    }
+}
 
-    /// path to Wabbit program
+/// the source of a Wabbit program
+struct Program {
+    /// path used in error reports
     path: PathBuf,
+    source: String,
+}
+
+impl Program {
+    /// read a program from a file, or stdin for `-`
+    fn read(path: &Path) -> Result<Self, Failure> {
+        if path == Path::new("-") {
+            let mut source = String::new();
+            std::io::stdin().read_to_string(&mut source)?;
+            Ok(Self {
+                path: PathBuf::from("<stdin>"),
+                source,
+            })
+        } else {
+            Ok(Self {
+                path: path.to_path_buf(),
+                source: std::fs::read_to_string(path)
+                    .map_err(|e| Failure::file("read", path, &e))?,
+            })
+        }
+    }
+
+    /// render errors from a stage in the context of the source
+    fn report(&self, errors: Vec<WabbitError>, title: &str) -> String {
+        WabbitErrorReporter::new(errors, self.path.clone(), self.source.clone(), title).to_string()
+    }
+
+    fn scan(&self, passes: &Passes) -> Result<Scanner, Failure> {
+        let mut scanner = Scanner::new(&self.source);
+        passes
+            .time("scan", || scanner.scan())
+            .map_err(|errs| Failure::Compile(self.report(errs, "Scanner")))?;
+        Ok(scanner)
+    }
+
+    fn parse<'a>(&self, scanner: &'a Scanner, passes: &Passes) -> Result<Parser<'a>, Failure> {
+        let mut parser = Parser::from(scanner);
+        passes
+            .time("parse", || parser.parse())
+            .map_err(|err| Failure::Compile(self.report(vec![err], "Parser")))?;
+        Ok(parser)
+    }
+
+    fn typecheck(&self, parser: &Parser, passes: &Passes) -> Result<(), Failure> {
+        passes
+            .time("typecheck", || Typechecker::from(parser).typecheck())
+            .map_err(|err| Failure::Compile(self.report(vec![err], "Typechecking")))
+    }
 }
 
 // Potential improvements:
 //  Scanner: handler errors for floats like 10.a with better error messages
 //  Parser: handle multiple errors
 
-fn main() -> Result<(), MainError> {
+fn main() -> ExitCode {
     let args = Cli::parse();
-    let source = std::fs::read_to_string(&args.path)?;
-    let mut scanner = Scanner::new(&source);
+    let passes = Passes {
+        enabled: args.time_passes,
+    };
 
-    if let Err(errs) = scanner.scan() {
-        let error_report = WabbitErrorReporter::new(errs, args.path, source, "Scanner");
-        return Err(error_report.into());
-    }
+    let result = match args.command {
+        Commands::Run(args) => run(&args, &passes),
+        Commands::Check(args) => check(&args, &passes),
+        Commands::Build(args) => build(args, &passes),
+        Commands::Fmt(args) => fmt(&args, &passes),
+        Commands::Dump(args) => dump(&args, &passes),
+        Commands::Debug(args) => debug(&args, &passes),
+        Commands::Test(args) => test(&args, &passes),
+    };
 
-    if args.tokens {
-        println!("Tokens: \n\n{:#?}\n", scanner.borrow_tokens());
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(failure) => {
+            eprintln!("{failure}");
+            ExitCode::from(failure.exit_code())
+        }
     }
+}
 
-    let mut parser = Parser::from(&scanner);
+fn run(args: &Run, passes: &Passes) -> Result<(), Failure> {
+    let program = Program::read(&args.path)?;
+    let scanner = program.scan(passes)?;
+    let parser = program.parse(&scanner, passes)?;
 
-    if let Err(err) = parser.parse() {
-        let error_report = WabbitErrorReporter::new(vec![err], args.path, source, "Parser");
-        return Err(error_report.into());
+    let profiling = args.profile || args.folded.is_some();
+    let measuring = args.coverage || args.lcov.is_some();
+    if args.backend != Backend::Interpreter && (profiling || measuring || args.trace) {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--profile, --folded, --trace, --coverage and --lcov need the interpreter backend",
+            )
+            .exit();
     }
 
-    if args.ast {
-        println!("Statements: \n\n{:#?}", parser.borrow_statements());
+    if !(args.skip_typecheck && args.backend == Backend::Interpreter) {
+        program.typecheck(&parser, passes)?;
     }
 
-    if args.minimize {
-        for stmt in parser.borrow_statements() {
-            println!("{stmt}");
+    match args.backend {
+        Backend::Interpreter => {
+            let limits = Limits {
+                fuel: args.fuel,
+                call_depth: Some(args.max_call_depth),
+                timeout: args.timeout,
+            };
+            let mut profile = Profile::default();
+            let mut coverage = Coverage::default();
+            let interpret = || {
+                let mut trace = Trace::new(&program.source, BufWriter::new(std::io::stderr()));
+                let mut interpreter = Interpreter::from(&parser).with_limits(limits);
+                if profiling {
+                    interpreter = interpreter.with_profile(&mut profile);
+                }
+                if args.trace {
+                    interpreter = interpreter.with_trace(&mut trace);
+                }
+                if measuring {
+                    interpreter = interpreter.with_coverage(&mut coverage);
+                }
+                interpreter.interpret()
+            };
+            let result = passes.time("interpret", || on_interpreter_stack(interpret));
+            if args.profile {
+                eprint!("{}", profile.report(&program.source));
+            }
+            if let Some(path) = &args.folded {
+                std::fs::write(path, profile.folded())
+                    .map_err(|e| Failure::file("write", path, &e))?;
+            }
+            if measuring {
+                let report = coverage.report(&parser, &program.source);
+                if args.coverage {
+                    eprint!("{}", report.summary());
+                }
+                if let Some(path) = &args.lcov {
+                    std::fs::write(path, report.lcov(&program.path.to_string_lossy()))
+                        .map_err(|e| Failure::file("write", path, &e))?;
+                }
+            }
+            result.map_err(|err| Failure::Runtime(program.report(vec![err], "Interpreter")))
+        }
+        backend => {
+            let compiled = Compiled::new(backend, &parser, args.overflow, passes);
+            let status = passes.time("compile and run", || {
+                compiled.toolchain(args.cc.clone()).run(
+                    &compiled.source,
+                    compiled.file_name,
+                    compiled.cc_args,
+                )
+            })?;
+            if status.success() {
+                Ok(())
+            } else {
+                Err(Failure::Runtime(format!(
+                    "Error: program exited with {status}"
+                )))
+            }
         }
     }
+}
 
-    if !args.skip_typecheck {
-        let mut typechecker = Typechecker::from(&parser);
+/// a program generated by a compiled backend, ready for its compiler
+struct Compiled {
+    source: String,
+    file_name: &'static str,
+    cc_args: &'static [&'static str],
+    /// the compiler used unless `--cc` is given
+    default_cc: &'static str,
+}
 
-        if let Err(err) = typechecker.typecheck() {
-            let error_report =
-                WabbitErrorReporter::new(vec![err], args.path, source, "Typechecking");
-            return Err(error_report.into());
+impl Compiled {
+    fn new(backend: Backend, parser: &Parser, overflow: Overflow, passes: &Passes) -> Self {
+        let (source, file_name, cc_args, default_cc) = match backend {
+            Backend::Interpreter => unreachable!("the interpreter is not compiled"),
+            Backend::Llvm => {
+                let mut codegen = CodegenLLVM::from(parser);
+                codegen.overflow = overflow;
+                let llvm = passes.time("codegen", || codegen.llvm_codegen());
+                (llvm, "out.ll", &[][..], "clang")
+            }
+            Backend::Asm => {
+                let asm = passes.time("codegen", || CodegenAsm::from(parser).asm_codegen());
+                (asm, "out.s", &[][..], "cc")
+            }
+            Backend::C => {
+                let c = passes.time("codegen", || CodegenC::from(parser).c_codegen());
+                (c, "out.c", &["-std=c99"][..], "cc")
+            }
+        };
+        Self {
+            source,
+            file_name,
+            cc_args,
+            default_cc,
         }
     }
 
-    if args.llvm_print || args.llvm_exec {
-        let mut codegen = CodegenLLVM::from(&parser);
-        let llvm = codegen.llvm_codegen();
+    fn toolchain(&self, cc: Option<PathBuf>) -> Toolchain {
+        Toolchain::new(cc.unwrap_or_else(|| PathBuf::from(self.default_cc)))
+    }
+}
+
+/// run `f` on a thread with `INTERPRETER_STACK` bytes of stack
+fn on_interpreter_stack<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    thread::scope(|scope| {
+        let thread = thread::Builder::new()
+            .stack_size(INTERPRETER_STACK)
+            .spawn_scoped(scope, f)
+            .expect("failed to start the interpreter thread");
+        thread
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+fn debug(args: &Debug, passes: &Passes) -> Result<(), Failure> {
+    let limits = Limits {
+        call_depth: Some(args.max_call_depth),
+        ..Limits::default()
+    };
+    if args.dap {
+        let path = args.path.as_deref();
+        let serve = || wabbit::dap::serve(std::io::stdin().lock(), std::io::stdout(), path, limits);
+        return Ok(on_interpreter_stack(serve)?);
+    }
+
+    let program = Program::read(args.path.as_deref().unwrap_or(Path::new("-")))?;
+    let scanner = program.scan(passes)?;
+    let parser = program.parse(&scanner, passes)?;
+    program.typecheck(&parser, passes)?;
 
-        if args.llvm_print {
-            println!("{llvm}\n");
+    let interpret = || {
+        let console = Console::new(std::io::stdin().lock(), std::io::stdout());
+        let mut debugger = Debugger::new(&program.source, console);
+        debugger.stop_on_entry = args.breakpoints.is_empty();
+        debugger.breakpoints.extend(&args.breakpoints);
+        let result = Interpreter::from(&parser)
+            .with_limits(limits)
+            .with_debugger(&mut debugger)
+            .interpret();
+        match result {
+            Err(_) if debugger.stopped() => Ok(()),
+            result => result,
         }
-        if args.llvm_exec {
-            std::fs::write("./out.ll", llvm)?;
-            let clang = Command::new("clang").args(["./out.ll"]).status()?;
-
-            if clang.success() {
-                let output = Command::new("./a.out").output()?;
-                print!("{}", std::str::from_utf8(&output.stdout)?);
-                std::fs::remove_file("./a.out")?;
+    };
+    on_interpreter_stack(interpret)
+        .map_err(|err| Failure::Runtime(program.report(vec![err], "Interpreter")))
+}
+
+/// run each test of a program in a fresh program, printing a line for each and a summary
+fn test(args: &Test, passes: &Passes) -> Result<(), Failure> {
+    let program = Program::read(&args.path)?;
+    let scanner = program.scan(passes)?;
+    let parser = program.parse(&scanner, passes)?;
+    program.typecheck(&parser, passes)?;
+    let limits = Limits {
+        call_depth: Some(args.max_call_depth),
+        timeout: args.timeout,
+        ..Limits::default()
+    };
+
+    let mut outcomes = Vec::new();
+    for name in testing::discover(&parser) {
+        let outcome = if args.backend == Backend::Interpreter {
+            let interpret = || testing::interpret(&program.source, &program.path, &name, limits);
+            passes.time("interpret", || on_interpreter_stack(interpret))
+        } else {
+            let harness = Program {
+                path: program.path.clone(),
+                source: testing::harness(&program.source, &name),
+            };
+            let scanner = harness.scan(passes)?;
+            let parser = harness.parse(&scanner, passes)?;
+            let compiled = Compiled::new(args.backend, &parser, args.overflow, passes);
+            let output = passes.time("compile and run", || {
+                compiled.toolchain(args.cc.clone()).output(
+                    &compiled.source,
+                    compiled.file_name,
+                    compiled.cc_args,
+                )
+            })?;
+            let failure = (!output.status.success()).then(|| {
+                format!(
+                    "{}Error: program exited with {}",
+                    String::from_utf8_lossy(&output.stderr),
+                    output.status
+                )
+            });
+            Outcome {
+                name,
+                output: String::from_utf8_lossy(&output.stdout).into_owned(),
+                failure,
             }
-            std::fs::remove_file("./out.ll")?;
         };
+        outcomes.push(outcome);
     }
 
-    if args.interpret {
-        let mut interpreter = Interpreter::from(&parser);
-        println!("\nInterpreter output:\n");
+    print!("{}", testing::report(&outcomes));
+    let failed = outcomes.iter().filter(|outcome| !outcome.passed()).count();
+    if failed == 0 {
+        Ok(())
+    } else {
+        Err(Failure::Runtime(format!(
+            "Error: {failed} of {} tests failed",
+            outcomes.len()
+        )))
+    }
+}
+
+fn check(args: &Source, passes: &Passes) -> Result<(), Failure> {
+    let program = Program::read(&args.path)?;
+    let scanner = program.scan(passes)?;
+    let parser = program.parse(&scanner, passes)?;
+    program.typecheck(&parser, passes)
+}
 
-        if let Err(err) = interpreter.interpret() {
-            let error_report =
-                WabbitErrorReporter::new(vec![err], args.path, source, "Interpreter");
-            return Err(error_report.into());
+/// compile a program with LLVM, writing the output of the `build` subcommand
+fn build(args: Build, passes: &Passes) -> Result<(), Failure> {
+    let output = match args.output {
+        Some(output) => output,
+        None if args.path == Path::new("-") => {
+            PathBuf::from("out").with_extension(args.emit.extension())
         }
+        None => PathBuf::from(args.path.file_stem().unwrap_or_default())
+            .with_extension(args.emit.extension()),
+    };
+    if output == args.path {
+        return Err(Failure::Io(format!(
+            "output would overwrite {}",
+            args.path.display()
+        )));
     }
 
+    let program = Program::read(&args.path)?;
+    let scanner = program.scan(passes)?;
+    let parser = program.parse(&scanner, passes)?;
+    program.typecheck(&parser, passes)?;
+
+    let mut codegen = CodegenLLVM::from(&parser);
+    codegen.target = args.llvm_target;
+    codegen.overflow = args.overflow;
+    let llvm = passes.time("codegen", || codegen.llvm_codegen());
+
+    let mut toolchain = Toolchain::new(args.cc);
+    toolchain.opt_level = args.opt_level;
+    passes.time("compile", || {
+        toolchain.build_llvm(&llvm, args.emit, &output)
+    })?;
+    Ok(())
+}
+
+fn fmt(args: &Source, passes: &Passes) -> Result<(), Failure> {
+    let program = Program::read(&args.path)?;
+    let scanner = program.scan(passes)?;
+    let parser = program.parse(&scanner, passes)?;
+
+    for stmt in parser.borrow_statements() {
+        println!("{stmt}");
+    }
+    Ok(())
+}
+
+fn dump(args: &Dump, passes: &Passes) -> Result<(), Failure> {
+    let program = Program::read(&args.path)?;
+    let scanner = program.scan(passes)?;
+
+    if args.stage == Stage::Tokens {
+        println!("{:#?}", scanner.borrow_tokens());
+        return Ok(());
+    }
+
+    let parser = program.parse(&scanner, passes)?;
+
+    if args.stage == Stage::Ast {
+        println!("{:#?}", parser.borrow_statements());
+        return Ok(());
+    }
+
+    // code generation assumes the program typechecks
+    program.typecheck(&parser, passes)?;
+
+    let output = passes.time("codegen", || match args.stage {
+        Stage::Tokens | Stage::Ast => unreachable!("printed above"),
+        Stage::Ir => format!("{}\n", Lowering::from(&parser).lower()).into_bytes(),
+        Stage::Llvm => {
+            let mut codegen = CodegenLLVM::from(&parser);
+            codegen.target = args.llvm_target;
+            codegen.overflow = args.overflow;
+            format!("{}\n", codegen.llvm_codegen()).into_bytes()
+        }
+        Stage::Asm => format!("{}\n", CodegenAsm::from(&parser).asm_codegen()).into_bytes(),
+        Stage::C => format!("{}\n", CodegenC::from(&parser).c_codegen()).into_bytes(),
+        Stage::Wat => format!("{}\n", CodegenWasm::from(&parser).wasm_codegen()).into_bytes(),
+        Stage::Wasm => CodegenWasm::from(&parser).wasm_codegen().encode(),
+    });
+    std::io::stdout().write_all(&output)?;
     Ok(())
 }
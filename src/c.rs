@@ -0,0 +1,1032 @@
+//! generate C source
+//!
+//! the generated code is C99, and every operation that C leaves undefined or unspecified goes
+//! through a helper or temporaries that give it Wabbit's meaning
+
+use crate::ast::{Expr, Stmt};
+use crate::environment::{Environment, VarStore};
+use crate::operators::{BinaryOp, Builtin, LogicalOp, LoopControl, UnaryOp};
+use crate::parser::Parser;
+use crate::typechecker::Typechecker;
+use crate::types::Type;
+use crate::WabbitType;
+use std::collections::HashMap;
+
+/// headers and helpers for the generated code, formatting values the same way as the interpreter
+const RUNTIME: &str = r#"#include <inttypes.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+/* print the shortest digits that read back as the same value, without an exponent, where a
+   float32 only has to read back as the same float32 */
+static inline void wabbit_print_decimal(double value, bool single) {
+    char buffer[32];
+    int precision, exponent, head;
+    if (value != value) {
+        printf("NaN\n");
+        return;
+    }
+    if (value - value != 0) {
+        printf("%f\n", value);
+        return;
+    }
+    for (precision = 0;; precision++) {
+        snprintf(buffer, sizeof buffer, "%.*e", precision, value);
+        double parsed = single ? (double)strtof(buffer, NULL) : strtod(buffer, NULL);
+        if (precision == 16 || parsed == value) {
+            break;
+        }
+    }
+    exponent = atoi(strchr(buffer, 'e') + 1);
+    if (exponent < precision) {
+        printf("%.*f\n", precision - exponent, value);
+        return;
+    }
+    /* an integer, so the digits are followed by zeros instead of the exact binary value */
+    head = buffer[0] == '-' ? 2 : 1;
+    printf("%.*s%.*s%.*d\n", head, buffer, precision, buffer + head + 1, exponent - precision, 0);
+}
+
+static inline void wabbit_print_float(double value) {
+    wabbit_print_decimal(value, false);
+}
+
+static inline void wabbit_print_float32(float value) {
+    wabbit_print_decimal(value, true);
+}
+
+/* reads exit with status 1 if the input is exhausted or invalid */
+static inline int32_t wabbit_read_int(void) {
+    int value;
+    if (scanf("%d", &value) != 1) {
+        exit(1);
+    }
+    return value;
+}
+
+static inline double wabbit_read_float(void) {
+    double value;
+    if (scanf("%lf", &value) != 1) {
+        exit(1);
+    }
+    return value;
+}
+
+static inline uint8_t wabbit_read_char(void) {
+    int value = getchar();
+    if (value == EOF) {
+        exit(1);
+    }
+    return (uint8_t)value;
+}
+
+/* saturating conversion, like Rust's `as` */
+static inline int32_t wabbit_float_to_int(double value) {
+    if (value != value) {
+        return 0;
+    }
+    if (value >= 2147483647.0) {
+        return INT32_MAX;
+    }
+    if (value <= -2147483648.0) {
+        return INT32_MIN;
+    }
+    return (int32_t)value;
+}
+
+static inline int64_t wabbit_float_to_int64(double value) {
+    if (value != value) {
+        return 0;
+    }
+    if (value >= 9223372036854775807.0) {
+        return INT64_MAX;
+    }
+    if (value <= -9223372036854775808.0) {
+        return INT64_MIN;
+    }
+    return (int64_t)value;
+}
+
+static inline uint8_t wabbit_float_to_uint8(double value) {
+    if (value != value || value <= 0) {
+        return 0;
+    }
+    if (value >= 255) {
+        return UINT8_MAX;
+    }
+    return (uint8_t)value;
+}
+
+/* errors exit with status 1, as in the LLVM backend */
+static inline void wabbit_fail(const char *message) {
+    fprintf(stderr, "error: %s\n", message);
+    exit(1);
+}
+
+/* a function value points to the code to call, which is passed the function value itself to
+   read its captured variables from the fields that follow */
+typedef struct wabbit_closure {
+    void (*code)(void);
+} wabbit_closure;
+"#;
+
+/// the runtime's arithmetic helpers for a signed int type, `int` or `int64`
+///
+/// signed overflow is undefined in C, so values wrap around by doing the arithmetic unsigned,
+/// and a shift by a negative amount or by at least the width of the type is an error
+fn signed_runtime(dtype: &Type) -> String {
+    let t = dtype.c_type();
+    let u = match dtype {
+        Type::Int => "uint32_t",
+        _ => "uint64_t",
+    };
+    format!(
+        r#"
+static inline {t} wabbit_add_{dtype}({t} a, {t} b) {{
+    return ({t})(({u})a + ({u})b);
+}}
+
+static inline {t} wabbit_sub_{dtype}({t} a, {t} b) {{
+    return ({t})(({u})a - ({u})b);
+}}
+
+static inline {t} wabbit_mul_{dtype}({t} a, {t} b) {{
+    return ({t})(({u})a * ({u})b);
+}}
+
+static inline {t} wabbit_neg_{dtype}({t} a) {{
+    return ({t})(0 - ({u})a);
+}}
+
+/* the minimum value divided by -1 wraps around to itself, leaving no remainder */
+static inline {t} wabbit_div_{dtype}({t} a, {t} b) {{
+    if (b == 0) {{
+        wabbit_fail("division by zero");
+    }}
+    return b == -1 ? wabbit_neg_{dtype}(a) : a / b;
+}}
+
+static inline {t} wabbit_rem_{dtype}({t} a, {t} b) {{
+    if (b == 0) {{
+        wabbit_fail("division by zero");
+    }}
+    return b == -1 ? 0 : a % b;
+}}
+
+static inline void wabbit_check_shift_{dtype}({t} amount) {{
+    if (amount < 0 || amount > {max_shift}) {{
+        wabbit_fail("invalid shift amount");
+    }}
+}}
+
+/* a left shift of a negative value is undefined, so the bits are shifted unsigned */
+static inline {t} wabbit_shl_{dtype}({t} a, {t} b) {{
+    wabbit_check_shift_{dtype}(b);
+    return ({t})(({u})a << b);
+}}
+
+/* a right shift of a negative value is implementation defined, so its complement is shifted */
+static inline {t} wabbit_shr_{dtype}({t} a, {t} b) {{
+    wabbit_check_shift_{dtype}(b);
+    return a < 0 ? ~(~a >> b) : a >> b;
+}}
+"#,
+        max_shift = dtype.bits() - 1
+    )
+}
+
+/// the runtime's arithmetic helpers for `uint8`, which is promoted to int, so results are
+/// truncated to wrap around
+const UINT8_RUNTIME: &str = r#"
+static inline uint8_t wabbit_add_uint8(uint8_t a, uint8_t b) {
+    return (uint8_t)(a + b);
+}
+
+static inline uint8_t wabbit_sub_uint8(uint8_t a, uint8_t b) {
+    return (uint8_t)(a - b);
+}
+
+static inline uint8_t wabbit_mul_uint8(uint8_t a, uint8_t b) {
+    return (uint8_t)(a * b);
+}
+
+static inline uint8_t wabbit_div_uint8(uint8_t a, uint8_t b) {
+    if (b == 0) {
+        wabbit_fail("division by zero");
+    }
+    return a / b;
+}
+
+static inline uint8_t wabbit_rem_uint8(uint8_t a, uint8_t b) {
+    if (b == 0) {
+        wabbit_fail("division by zero");
+    }
+    return a % b;
+}
+
+static inline void wabbit_check_shift_uint8(uint8_t amount) {
+    if (amount > 7) {
+        wabbit_fail("invalid shift amount");
+    }
+}
+
+static inline uint8_t wabbit_shl_uint8(uint8_t a, uint8_t b) {
+    wabbit_check_shift_uint8(b);
+    return (uint8_t)(a << b);
+}
+
+static inline uint8_t wabbit_shr_uint8(uint8_t a, uint8_t b) {
+    wabbit_check_shift_uint8(b);
+    return a >> b;
+}
+"#;
+
+/// the runtime and its helpers for each int type
+fn runtime() -> String {
+    let int = signed_runtime(&Type::Int);
+    let int64 = signed_runtime(&Type::Int64);
+    format!("{RUNTIME}{int}{int64}{UINT8_RUNTIME}")
+}
+
+/// Struct for generating C99 source code
+pub struct CodegenC<'a> {
+    /// a typechecker
+    analyze: Typechecker<'a>,
+    /// used to generate unique names for shadowed variables
+    counter: usize,
+    /// map of Wabbit variable names to C names
+    var_names: Environment<'a, String>,
+    /// declarations of global variables
+    globals: Vec<String>,
+    /// prototypes of all functions, so they can be called before they are defined
+    prototypes: Vec<String>,
+    /// function definitions
+    functions: Vec<String>,
+    /// lines of the function currently being generated
+    lines: Vec<String>,
+    /// declarations of temporaries in the function currently being generated
+    temps: Vec<String>,
+    /// indentation level of the current line
+    indent: usize,
+    /// named functions used as values, which have a wrapper taking the function value
+    function_values: Vec<String>,
+}
+
+impl<'a> From<&'a Parser<'a>> for CodegenC<'a> {
+    fn from(parser: &'a Parser) -> Self {
+        Self {
+            analyze: Typechecker::from(parser),
+            counter: 0,
+            var_names: Environment::new(),
+            globals: Vec::new(),
+            prototypes: Vec::new(),
+            functions: Vec::new(),
+            lines: Vec::new(),
+            temps: Vec::new(),
+            indent: 1,
+            function_values: Vec::new(),
+        }
+    }
+}
+
+impl Type {
+    /// transform a Wabbit type into a C type
+    pub fn c_type(&self) -> &'static str {
+        match self {
+            Type::Int => "int32_t",
+            Type::Int64 => "int64_t",
+            Type::Float => "double",
+            Type::Float32 => "float",
+            Type::Bool => "bool",
+            Type::Char | Type::Uint8 => "uint8_t",
+            Type::Func(_) => "wabbit_closure *",
+        }
+    }
+}
+
+impl WabbitType {
+    /// transform a Wabbit value into a C literal
+    pub fn c_value(&self) -> String {
+        match self {
+            WabbitType::Int(val) => val.to_string(),
+            WabbitType::Bool(val) => val.to_string(),
+            WabbitType::Int64(val) => format!("INT64_C({val})"),
+            WabbitType::Uint8(val) => val.to_string(),
+            WabbitType::Float(val) => format!("{val:?}"),
+            WabbitType::Float32(val) => format!("{val:?}f"),
+            WabbitType::Char('\n') => "'\\n'".to_string(),
+            WabbitType::Char(val @ ' '..='~') if !matches!(*val, '\'' | '\\') => {
+                format!("'{val}'")
+            }
+            WabbitType::Char(val) => (*val as u8).to_string(),
+            WabbitType::TypeHolder(..) | WabbitType::Func(..) => panic!("typecheck failure"),
+        }
+    }
+}
+
+/// whether evaluating an expression calls a function, which could have side effects
+///
+/// C leaves the order operands are evaluated in unspecified, so the earlier operands of such an
+/// expression are first stored in temporaries, sequenced with the comma operator
+fn has_call(e: &Expr) -> bool {
+    match e {
+        Expr::Call { .. } | Expr::CallValue { .. } => true,
+        Expr::Grouping { e, .. } => has_call(e),
+        Expr::Unary { operand, .. } => has_call(operand),
+        Expr::Binary { lhs, rhs, .. } | Expr::Logical { lhs, rhs, .. } => {
+            has_call(lhs) || has_call(rhs)
+        }
+        Expr::TypeConversion { params, .. } => params.iter().any(has_call),
+        Expr::Literal { .. }
+        | Expr::VarName { .. }
+        | Expr::TypeName { .. }
+        | Expr::Function { .. } => false,
+    }
+}
+
+/// the name of the runtime helper for an int operator, which can wrap around or fail
+fn int_helper(op: &BinaryOp) -> Option<&'static str> {
+    match op {
+        BinaryOp::Plus => Some("add"),
+        BinaryOp::Minus => Some("sub"),
+        BinaryOp::Times => Some("mul"),
+        BinaryOp::Divide => Some("div"),
+        BinaryOp::Modulo => Some("rem"),
+        BinaryOp::ShiftLeft => Some("shl"),
+        BinaryOp::ShiftRight => Some("shr"),
+        _ => None,
+    }
+}
+
+/// the precedence-free operator of a binary expression
+fn binary_op(op: &BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Plus => "+",
+        BinaryOp::Minus => "-",
+        BinaryOp::Times => "*",
+        BinaryOp::Divide => "/",
+        BinaryOp::Modulo => "%",
+        BinaryOp::BitAnd => "&",
+        BinaryOp::BitOr => "|",
+        BinaryOp::BitXor => "^",
+        BinaryOp::ShiftLeft => "<<",
+        BinaryOp::ShiftRight => ">>",
+        BinaryOp::Less => "<",
+        BinaryOp::LessEqual => "<=",
+        BinaryOp::Greater => ">",
+        BinaryOp::GreaterEqual => ">=",
+        BinaryOp::EqualEqual => "==",
+        BinaryOp::NotEqual => "!=",
+    }
+}
+
+impl<'a> CodegenC<'a> {
+    /// transform AST into C source
+    pub fn c_codegen(&mut self) -> String {
+        for stmt in self.analyze.statements {
+            self.c_stmt(stmt);
+        }
+        self.line("return 0;");
+
+        let body = self.function_body();
+        let main = format!("int main(void) {{\n{body}\n}}\n");
+        let combine = [
+            runtime(),
+            self.globals.join("\n"),
+            self.prototypes.join("\n"),
+            self.functions.join("\n"),
+            main,
+        ];
+        combine
+            .into_iter()
+            .filter(|section| !section.is_empty())
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    /// the lines of the current function, after declarations of its temporaries
+    fn function_body(&mut self) -> String {
+        let temps = std::mem::take(&mut self.temps);
+        temps
+            .into_iter()
+            .map(|temp| format!("    {temp}"))
+            .chain(std::mem::take(&mut self.lines))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    /// declare a temporary in the current function, returning its name
+    fn temp(&mut self, dtype: &Type) -> String {
+        self.counter += 1;
+        let name = format!("tmp{}", self.counter);
+        self.temps.push(format!("{} {name};", dtype.c_type()));
+        name
+    }
+
+    /// add an indented line to the current function
+    fn line(&mut self, line: &str) {
+        self.lines
+            .push(format!("{}{line}", "    ".repeat(self.indent)));
+    }
+
+    /// enter child environment for variable names and types
+    fn enter_child(&mut self) {
+        self.analyze.env.enter_child();
+        self.var_names.enter_child();
+    }
+
+    /// exit child environment for variable names and types
+    fn exit_child_unwrap(&mut self) {
+        self.analyze.env.exit_child_unwrap();
+        self.var_names.exit_child_unwrap();
+    }
+
+    /// define a new variable, returning its C name
+    ///
+    /// names are prefixed with `wb_` so they cannot clash with C keywords or the standard library,
+    /// and a name that shadows a visible one gets a numeric suffix, since in C the new name would
+    /// already be in scope in its own initializer
+    fn define_var(&mut self, name: &'a String, dtype: Type) -> String {
+        let c_name = if self.var_names.get(name).is_some() {
+            self.counter += 1;
+            format!("wb_{name}_{}", self.counter)
+        } else {
+            format!("wb_{name}")
+        };
+        self.analyze.env.define_init(name, dtype);
+        self.var_names.define_init(name, c_name.clone());
+        c_name
+    }
+
+    /// generate a variable definition, globals are declared at file scope and assigned in `main`
+    fn c_define(&mut self, name: &'a String, dtype: &Type, value: Option<String>) {
+        let global = self.analyze.env.in_global_scope();
+        let c_name = self.define_var(name, dtype.clone());
+        let c_type = dtype.c_type();
+
+        if global {
+            self.globals.push(format!("static {c_type} {c_name};"));
+            if let Some(value) = value {
+                self.line(&format!("{c_name} = {value};"));
+            }
+        } else {
+            let value = value.unwrap_or_else(|| "0".to_string());
+            self.line(&format!("{c_type} {c_name} = {value};"));
+        }
+    }
+
+    /// generate a block of statements inside braces, `header` is the text before the brace
+    fn c_block(&mut self, header: &str, stmt: &'a Stmt) {
+        self.line(&format!("{header}{{"));
+        self.indent += 1;
+        self.enter_child();
+        if let Stmt::Block { statements, .. } = stmt {
+            for stmt in statements {
+                self.c_stmt(stmt);
+            }
+        } else {
+            self.c_stmt(stmt);
+        }
+        self.exit_child_unwrap();
+        self.indent -= 1;
+        self.line("}");
+    }
+
+    /// generate a C assignment expression
+    fn c_assign(&mut self, name: &'a String, op: Option<&BinaryOp>, value: &'a Expr) -> String {
+        let dtype = self.analyze.expr_type(value).unwrap();
+        let c_name = self.var_names.get(name).unwrap().clone_store();
+        let value = match op {
+            Some(op) => {
+                let rhs = self.c_operand(value);
+                self.c_binary(&c_name, op, &rhs, &dtype, has_call(value))
+            }
+            None => self.c_expr(value),
+        };
+        self.analyze.env.assign(name, dtype);
+        format!("{c_name} = {value}")
+    }
+
+    /// generate C for a single statement
+    fn c_stmt(&mut self, stmt: &'a Stmt) {
+        match stmt {
+            Stmt::Print { value, .. } => {
+                let dtype = self.analyze.expr_type(value).unwrap();
+                let value = self.c_expr(value);
+                let print = match dtype {
+                    Type::Int | Type::Uint8 => format!("printf(\"%d\\n\", {value});"),
+                    Type::Int64 => format!("printf(\"%\" PRId64 \"\\n\", {value});"),
+                    Type::Float => format!("wabbit_print_float({value});"),
+                    Type::Float32 => format!("wabbit_print_float32({value});"),
+                    Type::Bool => format!("printf(\"%s\\n\", {value} ? \"true\" : \"false\");"),
+                    Type::Char => format!("printf(\"%c\", {value});"),
+                    Type::Func(_) => panic!("typecheck failure"),
+                };
+                self.line(&print);
+            }
+            Stmt::VarDef {
+                name,
+                maybe_type,
+                maybe_value,
+                ..
+            } => {
+                let dtype = if let Some(t) = maybe_type {
+                    t.clone()
+                } else {
+                    self.analyze
+                        .expr_type(maybe_value.as_ref().unwrap())
+                        .unwrap()
+                };
+                let value = maybe_value.as_ref().map(|value| self.c_expr(value));
+                self.c_define(name, &dtype, value);
+            }
+            Stmt::ConstDef { name, value, .. } => {
+                let dtype = self.analyze.expr_type(value).expect("typechecking failure");
+                let value = self.c_expr(value);
+                self.c_define(name, &dtype, Some(value));
+                self.analyze.constants.insert(name, dtype);
+            }
+            Stmt::Assign {
+                name, op, value, ..
+            } => {
+                let assign = self.c_assign(name, op.as_ref(), value);
+                self.line(&format!("{assign};"));
+            }
+            Stmt::Expr(e) => {
+                let e = self.c_expr(e);
+                self.line(&format!("{e};"));
+            }
+            Stmt::Block { .. } => self.c_block("", stmt),
+            Stmt::If {
+                condition,
+                then_block,
+                maybe_else_block,
+                ..
+            } => {
+                let condition = self.c_expr(condition);
+                self.c_block(&format!("if ({condition}) "), then_block);
+                if let Some(else_block) = maybe_else_block {
+                    self.c_block("else ", else_block);
+                }
+            }
+            Stmt::Match {
+                value,
+                arms,
+                maybe_default,
+                ..
+            } => {
+                // an if chain rather than a C switch, so `break` still leaves the enclosing loop
+                let dtype = self.analyze.expr_type(value).unwrap();
+                let value = self.c_expr(value);
+                let temp = self.temp(&dtype);
+                self.line(&format!("{temp} = {value};"));
+                let mut otherwise = "";
+                for (pattern, body) in arms {
+                    let pattern = self.c_expr(pattern);
+                    self.c_block(&format!("{otherwise}if ({temp} == {pattern}) "), body);
+                    otherwise = "else ";
+                }
+                if let Some(default) = maybe_default {
+                    self.c_block(otherwise, default);
+                }
+            }
+            Stmt::Assert { value, text, .. } => {
+                let value = self.c_expr(value);
+                let message = format!("error: assertion failed: {text}\n");
+                self.line(&format!(
+                    "if (!({value})) {{ fputs({message:?}, stderr); exit(1); }}"
+                ));
+            }
+            Stmt::While {
+                condition, body, ..
+            } => {
+                let condition = self.c_expr(condition);
+                self.analyze.loop_depth += 1;
+                self.c_block(&format!("while ({condition}) "), body);
+                self.analyze.loop_depth -= 1;
+            }
+            Stmt::For {
+                init,
+                condition,
+                step,
+                body,
+                ..
+            } => {
+                // the loop variable is defined in a block around the loop
+                self.line("{");
+                self.indent += 1;
+                self.enter_child();
+                self.c_stmt(init);
+                let condition = self.c_expr(condition);
+                let Stmt::Assign {
+                    name, op, value, ..
+                } = &**step
+                else {
+                    panic!("typecheck failure")
+                };
+                let step = self.c_assign(name, op.as_ref(), value);
+                self.analyze.loop_depth += 1;
+                self.c_block(&format!("for (; {condition}; {step}) "), body);
+                self.analyze.loop_depth -= 1;
+                self.exit_child_unwrap();
+                self.indent -= 1;
+                self.line("}");
+            }
+            Stmt::LoopControl { control, .. } => match control {
+                LoopControl::Break => self.line("break;"),
+                LoopControl::Continue => self.line("continue;"),
+            },
+            Stmt::Return { value, .. } => {
+                let value = self.c_expr(value);
+                self.line(&format!("return {value};"));
+            }
+            Stmt::FuncDef {
+                def_name,
+                def_params,
+                return_type,
+                body,
+                ..
+            } => {
+                let mut param_names: HashMap<&String, VarStore<String>> = HashMap::new();
+                let mut param_env: HashMap<&String, VarStore<Type>> = HashMap::new();
+                let mut params = Vec::new();
+
+                for (name, dtype) in def_params {
+                    let c_name = format!("wb_{name}");
+                    params.push(format!("{} {c_name}", dtype.c_type()));
+                    param_names.insert(name, VarStore::Init(c_name));
+                    param_env.insert(name, VarStore::Init(dtype.clone()));
+                }
+
+                let params = if params.is_empty() {
+                    "void".to_string()
+                } else {
+                    params.join(", ")
+                };
+                let signature = format!("static {} wb_{def_name}({params})", return_type.c_type());
+                self.prototypes.push(format!("{signature};"));
+
+                // generate the body on its own, then go back to `main`
+                let main_lines = std::mem::take(&mut self.lines);
+                let main_temps = std::mem::take(&mut self.temps);
+
+                self.analyze.functions.insert(def_name, stmt);
+                self.analyze.env.enter_child_fn(param_env);
+                self.var_names.enter_child_fn(param_names);
+                self.analyze.call_depth += 1;
+
+                if let Stmt::Block { statements, .. } = &**body {
+                    for stmt in statements {
+                        self.c_stmt(stmt);
+                    }
+                } else {
+                    self.c_stmt(body);
+                }
+
+                self.analyze.env.exit_child_unwrap();
+                self.var_names.exit_child_unwrap();
+                self.analyze.call_depth -= 1;
+
+                let body = self.function_body();
+                self.lines = main_lines;
+                self.temps = main_temps;
+                self.functions.push(format!("{signature} {{\n{body}\n}}\n"));
+            }
+        }
+    }
+
+    /// generate the arguments of a call, returning assignments of the arguments to temporaries
+    /// when one of them calls a function, so they are evaluated in order
+    fn c_args(&mut self, params: &'a [Expr]) -> (Vec<String>, Vec<String>) {
+        let sequenced = params.iter().any(has_call);
+        let mut stores = Vec::new();
+        let mut args = Vec::new();
+        for (i, param) in params.iter().enumerate() {
+            let arg = self.c_expr(param);
+            if sequenced && i + 1 < params.len() {
+                let dtype = self.analyze.expr_type(param).unwrap();
+                let temp = self.temp(&dtype);
+                stores.push(format!("{temp} = {arg}, "));
+                args.push(temp);
+            } else {
+                args.push(arg);
+            }
+        }
+        (stores, args)
+    }
+
+    /// generate a call through a function value, which is stored in a temporary first if it
+    /// has to be evaluated before the arguments
+    fn c_call_value(
+        &mut self,
+        callee: String,
+        dtype: &Type,
+        params: &'a [Expr],
+        sequenced: bool,
+    ) -> String {
+        let Type::Func(sig) = dtype else {
+            panic!("typecheck failure")
+        };
+        let (callee, mut stores) = if sequenced {
+            let temp = self.temp(dtype);
+            let store = format!("{temp} = {callee}, ");
+            (temp, vec![store])
+        } else {
+            (callee, Vec::new())
+        };
+        let (arg_stores, args) = self.c_args(params);
+        stores.extend(arg_stores);
+
+        let param_types: Vec<&str> = std::iter::once(dtype.c_type())
+            .chain(sig.params.iter().map(Type::c_type))
+            .collect();
+        let code = format!(
+            "(({} (*)({})){callee}->code)",
+            sig.ret.c_type(),
+            param_types.join(", ")
+        );
+        let args: Vec<String> = std::iter::once(callee).chain(args).collect();
+        let call = format!("{code}({})", args.join(", "));
+        if stores.is_empty() {
+            call
+        } else {
+            format!("({}{call})", stores.concat())
+        }
+    }
+
+    /// generate a function value for a named function, through a wrapper that ignores the
+    /// function value it is passed
+    fn c_named_function_value(&mut self, name: &String, dtype: Type) -> String {
+        let Type::Func(sig) = dtype else {
+            panic!("typecheck failure")
+        };
+        if !self.function_values.contains(name) {
+            self.function_values.push(name.clone());
+            let mut params = vec!["wabbit_closure *wabbit_env".to_string()];
+            let mut args = Vec::new();
+            for (i, dtype) in sig.params.iter().enumerate() {
+                params.push(format!("{} wabbit_arg{i}", dtype.c_type()));
+                args.push(format!("wabbit_arg{i}"));
+            }
+            self.functions.push(format!(
+                "static {} wabbit_value_{name}({}) {{\n    (void)wabbit_env;\n    \
+                 return wb_{name}({});\n}}\n",
+                sig.ret.c_type(),
+                params.join(", "),
+                args.join(", ")
+            ));
+            self.functions.push(format!(
+                "static wabbit_closure wabbit_closure_{name} = \
+                 {{(void (*)(void))wabbit_value_{name}}};\n"
+            ));
+        }
+        format!("&wabbit_closure_{name}")
+    }
+
+    /// generate a function expression as a function of its own, with a struct holding copies
+    /// of the variables it captures, returning a call that creates the function value
+    fn c_function(
+        &mut self,
+        e: &'a Expr,
+        params: &'a [(String, Type)],
+        return_type: &Type,
+        body: &'a Stmt,
+        id: usize,
+    ) -> String {
+        let dtype = self.analyze.expr_type(e).unwrap();
+        let captures = self.analyze.captures(params, body, id).unwrap();
+        self.counter += 1;
+        let n = self.counter;
+
+        let mut scope_names: HashMap<&String, VarStore<String>> = HashMap::new();
+        let mut scope_env: HashMap<&String, VarStore<Type>> = HashMap::new();
+        let mut c_params = vec![format!("{} wabbit_env", dtype.c_type())];
+        for (name, dtype) in params {
+            let c_name = format!("wb_{name}");
+            c_params.push(format!("{} {c_name}", dtype.c_type()));
+            scope_names.insert(name, VarStore::Init(c_name));
+            scope_env.insert(name, VarStore::Init(dtype.clone()));
+        }
+
+        let mut fields = vec!["    void (*code)(void);".to_string()];
+        let mut copies = vec!["(void)wabbit_env;".to_string()];
+        let mut new_params = Vec::new();
+        let mut new_stores = Vec::new();
+        for (name, dtype) in &captures {
+            let c_name = format!("wb_{name}");
+            let c_type = dtype.c_type();
+            fields.push(format!("    {c_type} {c_name};"));
+            copies.push(format!(
+                "{c_type} {c_name} = ((struct wabbit_env_{n} *)wabbit_env)->{c_name};"
+            ));
+            new_params.push(format!("{c_type} {c_name}"));
+            new_stores.push(format!("    env->{c_name} = {c_name};\n"));
+            scope_names.insert(name, VarStore::Init(c_name));
+            scope_env.insert(name, VarStore::Captured(dtype.clone()));
+        }
+
+        let signature = format!(
+            "static {} wabbit_func_{n}({})",
+            return_type.c_type(),
+            c_params.join(", ")
+        );
+        let new_params = if new_params.is_empty() {
+            "void".to_string()
+        } else {
+            new_params.join(", ")
+        };
+        let new_signature = format!("static wabbit_closure *wabbit_new_func_{n}({new_params})");
+        self.prototypes.push(format!("{signature};"));
+        self.prototypes.push(format!("{new_signature};"));
+
+        // generate the body on its own, then go back to the enclosing function
+        let outer_lines = std::mem::take(&mut self.lines);
+        let outer_temps = std::mem::take(&mut self.temps);
+        let outer_indent = std::mem::replace(&mut self.indent, 1);
+        let env_locals = self.analyze.env.enter_call(scope_env);
+        let name_locals = self.var_names.enter_call(scope_names);
+        let loop_depth = std::mem::take(&mut self.analyze.loop_depth);
+        self.analyze.call_depth += 1;
+
+        for copy in copies {
+            self.line(&copy);
+        }
+        if let Stmt::Block { statements, .. } = body {
+            for stmt in statements {
+                self.c_stmt(stmt);
+            }
+        } else {
+            self.c_stmt(body);
+        }
+
+        self.analyze.call_depth -= 1;
+        self.analyze.loop_depth = loop_depth;
+        self.var_names.exit_call(name_locals);
+        self.analyze.env.exit_call(env_locals);
+
+        let body = self.function_body();
+        self.lines = outer_lines;
+        self.temps = outer_temps;
+        self.indent = outer_indent;
+        self.functions.push(format!(
+            "struct wabbit_env_{n} {{\n{}\n}};\n",
+            fields.join("\n")
+        ));
+        self.functions.push(format!("{signature} {{\n{body}\n}}\n"));
+        self.functions.push(format!(
+            "{new_signature} {{\n    struct wabbit_env_{n} *env = malloc(sizeof *env);\n    \
+             env->code = (void (*)(void))wabbit_func_{n};\n{}    \
+             return (wabbit_closure *)env;\n}}\n",
+            new_stores.concat()
+        ));
+
+        // the captured variables are read when the function value is created
+        let captured: Vec<String> = captures
+            .iter()
+            .map(|(name, _)| self.var_names.get(name).unwrap().clone_store())
+            .collect();
+        format!("wabbit_new_func_{n}({})", captured.join(", "))
+    }
+
+    /// generate C for an operand of a larger expression, adding parentheses where needed
+    fn c_operand(&mut self, e: &'a Expr) -> String {
+        let mut inner = e;
+        while let Expr::Grouping { e, .. } = inner {
+            inner = e;
+        }
+        let c = self.c_expr(inner);
+        match inner {
+            Expr::Binary { .. } | Expr::Logical { .. } | Expr::Unary { .. } => format!("({c})"),
+            _ => c,
+        }
+    }
+
+    /// generate C for a single expression
+    fn c_expr(&mut self, e: &'a Expr) -> String {
+        match e {
+            Expr::Literal { value, .. } => value.c_value(),
+            Expr::Grouping { e, .. } => self.c_expr(e),
+            Expr::VarName { name, .. } => {
+                if let Some(c_name) = self.var_names.get(name) {
+                    c_name.clone_store()
+                } else {
+                    let dtype = self.analyze.expr_type(e).unwrap();
+                    self.c_named_function_value(name, dtype)
+                }
+            }
+            Expr::CallValue { callee, params, .. } => {
+                let dtype = self.analyze.expr_type(callee).unwrap();
+                let callee = self.c_expr(callee);
+                self.c_call_value(callee, &dtype, params, true)
+            }
+            Expr::Function {
+                params,
+                return_type,
+                body,
+                id,
+            } => self.c_function(e, params, return_type, body, *id),
+            Expr::Call { name, params, .. } => {
+                if let Some(builtin) = Builtin::from_name(name) {
+                    return match builtin {
+                        Builtin::Int => "wabbit_read_int()",
+                        Builtin::Float => "wabbit_read_float()",
+                        Builtin::Char => "wabbit_read_char()",
+                    }
+                    .to_string();
+                }
+                if let Some(c_name) = self.var_names.get(name) {
+                    // a call through a variable holding a function value
+                    let dtype = match self.analyze.constants.get(name) {
+                        Some(dtype) => dtype.clone(),
+                        None => self.analyze.env.get(name).unwrap().clone_store(),
+                    };
+                    let sequenced = params.iter().any(has_call);
+                    return self.c_call_value(c_name.clone_store(), &dtype, params, sequenced);
+                }
+                let (stores, args) = self.c_args(params);
+                let call = format!("wb_{name}({})", args.join(", "));
+                if stores.is_empty() {
+                    call
+                } else {
+                    format!("({}{call})", stores.concat())
+                }
+            }
+            Expr::TypeConversion { dtype, params, .. } => {
+                // there should be a single parameter
+                let from = self.analyze.expr_type(&params[0]).unwrap();
+                let value = self.c_operand(&params[0]);
+
+                match (dtype, from.is_float()) {
+                    _ if *dtype == from => value,
+                    (Type::Int, true) => format!("wabbit_float_to_int({value})"),
+                    (Type::Int64, true) => format!("wabbit_float_to_int64({value})"),
+                    (Type::Uint8, true) => format!("wabbit_float_to_uint8({value})"),
+                    // only exactly 1 is true, as in the interpreter
+                    (Type::Bool, _) => format!("({value} == 1)"),
+                    // integers wrap, as in the interpreter
+                    _ => format!("({}){value}", dtype.c_type()),
+                }
+            }
+            Expr::TypeName { .. } => panic!("typecheck failure"),
+            Expr::Logical { lhs, op, rhs, .. } => {
+                let lhs = self.c_operand(lhs);
+                let rhs = self.c_operand(rhs);
+                let op = match op {
+                    LogicalOp::LogicalAnd => "&&",
+                    LogicalOp::LogicalOr => "||",
+                };
+                format!("{lhs} {op} {rhs}")
+            }
+            Expr::Unary { op, operand, .. } => {
+                let dtype = self.analyze.expr_type(operand).unwrap();
+                let operand = self.c_operand(operand);
+                if let (UnaryOp::Minus, Type::Int | Type::Int64) = (op, &dtype) {
+                    return format!("wabbit_neg_{dtype}({operand})");
+                }
+                let op = match op {
+                    UnaryOp::Plus => "+",
+                    UnaryOp::Minus => "-",
+                    UnaryOp::LogicalNot => "!",
+                };
+                format!("{op}{operand}")
+            }
+            Expr::Binary { lhs, op, rhs, .. } => {
+                let sequenced = has_call(lhs) || has_call(rhs);
+                let dtype = self.analyze.expr_type(lhs).unwrap();
+                let lhs = self.c_operand(lhs);
+                let rhs = self.c_operand(rhs);
+                self.c_binary(&lhs, op, &rhs, &dtype, sequenced)
+            }
+        }
+    }
+
+    /// combine generated operands, storing the left one first if the right one has a call
+    fn c_binary(
+        &mut self,
+        lhs: &str,
+        op: &BinaryOp,
+        rhs: &str,
+        dtype: &Type,
+        sequenced: bool,
+    ) -> String {
+        let binary = |lhs: &str| match (int_helper(op), dtype) {
+            (Some(helper), _) if dtype.is_integer() => {
+                format!("wabbit_{helper}_{dtype}({lhs}, {rhs})")
+            }
+            // uint8s are promoted to int, so bitwise results are converted back
+            (None, Type::Uint8) if !op.is_comparison() => {
+                format!("(uint8_t)({lhs} {} {rhs})", binary_op(op))
+            }
+            _ => format!("{lhs} {} {rhs}", binary_op(op)),
+        };
+        if sequenced {
+            let temp = self.temp(dtype);
+            format!("({temp} = {lhs}, {})", binary(&temp))
+        } else {
+            binary(lhs)
+        }
+    }
+}
@@ -0,0 +1,275 @@
+//! record which statements and branches of interpreted programs ran
+//!
+//! the interpreter records into a [`Coverage`], which is then mapped to lines as a [`Report`]
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+use crate::ast::Stmt;
+use crate::debugger::line_of;
+use crate::parser::Parser;
+
+/// which statements and branches of an interpreted program ran, see
+/// [`Interpreter::with_coverage`]
+///
+/// runs of the same program add up, so one `Coverage` can measure a whole test suite
+///
+/// [`Interpreter::with_coverage`]: crate::Interpreter::with_coverage
+#[derive(Debug, Clone, Default)]
+pub struct Coverage {
+    /// how many times each statement ran, by id, where a block only counts the calls of the
+    /// function it is the body of
+    statements: HashMap<usize, u64>,
+    /// how many times each outcome of a branch was taken, by statement id: which way each `if`,
+    /// `while` and `for` condition went and which arm each `match` took
+    branches: HashMap<usize, Vec<u64>>,
+}
+
+impl Coverage {
+    pub(crate) fn statement(&mut self, id: usize) {
+        *self.statements.entry(id).or_default() += 1;
+    }
+
+    pub(crate) fn branch(&mut self, id: usize, taken: bool) {
+        self.outcome(id, 2, usize::from(!taken));
+    }
+
+    /// record that a branch with `outcomes` ways to go took the one at index `taken`
+    pub(crate) fn outcome(&mut self, id: usize, outcomes: usize, taken: usize) {
+        self.branches.entry(id).or_insert_with(|| vec![0; outcomes])[taken] += 1;
+    }
+
+    /// how many times each outcome of the branch `id` was taken, zero if it never ran
+    fn taken(&self, id: usize, outcomes: usize) -> Vec<u64> {
+        self.branches
+            .get(&id)
+            .cloned()
+            .unwrap_or_else(|| vec![0; outcomes])
+    }
+
+    /// map what ran to the lines of the program that `parser` parsed from `source`
+    ///
+    /// this walks the whole program, so that statements that never ran are reported too
+    pub fn report(&self, parser: &Parser, source: &str) -> Report {
+        let mut report = Report::default();
+        let ranges = parser.borrow_ranges();
+        let mut stack: Vec<&Stmt> = parser.borrow_statements().iter().rev().collect();
+        while let Some(stmt) = stack.pop() {
+            let id = stmt.id();
+            let line = line_of(source, ranges[&id].0);
+            let count = self.statements.get(&id).copied().unwrap_or_default();
+            if !matches!(stmt, Stmt::Block { .. }) {
+                let hits = report.lines.entry(line).or_default();
+                *hits = (*hits).max(count);
+            }
+            match stmt {
+                Stmt::FuncDef { def_name, body, .. } => {
+                    let calls = self.statements.get(&body.id()).copied().unwrap_or_default();
+                    report.functions.push((line, def_name.clone(), calls));
+                    stack.push(body);
+                }
+                Stmt::If {
+                    then_block,
+                    maybe_else_block,
+                    ..
+                } => {
+                    report.branches.push(Branch {
+                        line,
+                        kind: BranchKind::Condition,
+                        ran: count > 0,
+                        taken: self.taken(id, 2),
+                    });
+                    stack.extend(maybe_else_block.as_deref());
+                    stack.push(then_block);
+                }
+                Stmt::While { body, .. } | Stmt::For { body, .. } => {
+                    report.branches.push(Branch {
+                        line,
+                        kind: BranchKind::Condition,
+                        ran: count > 0,
+                        taken: self.taken(id, 2),
+                    });
+                    stack.push(body);
+                }
+                Stmt::Match {
+                    arms,
+                    maybe_default,
+                    ..
+                } => {
+                    report.branches.push(Branch {
+                        line,
+                        kind: BranchKind::Match,
+                        ran: count > 0,
+                        taken: self.taken(id, arms.len() + 1),
+                    });
+                    stack.extend(maybe_default.as_deref());
+                    stack.extend(arms.iter().rev().map(|(_, body)| body));
+                }
+                Stmt::Block { statements, .. } => stack.extend(statements.iter().rev()),
+                _ => (),
+            }
+        }
+        report.branches.sort_by_key(|branch| branch.line);
+        report.functions.sort();
+        report.lines_of_source = source.lines().map(str::to_string).collect();
+        report
+    }
+}
+
+/// what a branch chooses between
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BranchKind {
+    /// an `if`, `while` or `for` condition, whose outcomes are true then false
+    Condition,
+    /// a `match`, whose outcomes are its arms then the default
+    Match,
+}
+
+/// a branch and how many times each of its outcomes was taken
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Branch {
+    pub line: usize,
+    pub kind: BranchKind,
+    /// whether the branch was reached at all
+    pub ran: bool,
+    pub taken: Vec<u64>,
+}
+
+/// coverage by line, in the formats that coverage tools read
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    /// how many times the statements starting on each line ran, for lines with statements
+    ///
+    /// blocks are left out, so a line is covered if any other statement starting on it ran
+    pub lines: BTreeMap<usize, u64>,
+    /// by line
+    pub branches: Vec<Branch>,
+    /// the line, name and number of calls of each function, by line
+    pub functions: Vec<(usize, String, u64)>,
+    lines_of_source: Vec<String>,
+}
+
+/// `covered/total (percent)`
+#[allow(clippy::cast_precision_loss)]
+fn ratio(covered: usize, total: usize) -> String {
+    if total == 0 {
+        return "0/0".to_string();
+    }
+    let percent = covered as f64 * 100.0 / total as f64;
+    format!("{covered}/{total} ({percent:.1}%)")
+}
+
+impl Report {
+    /// lines with statements, and those that ran
+    pub fn line_counts(&self) -> (usize, usize) {
+        let covered = self.lines.values().filter(|&&hits| hits > 0).count();
+        (covered, self.lines.len())
+    }
+
+    /// branch outcomes, two for each condition and one for each arm and default of a `match`, and
+    /// those that were taken
+    pub fn branch_counts(&self) -> (usize, usize) {
+        let covered = self
+            .branches
+            .iter()
+            .flat_map(|branch| &branch.taken)
+            .filter(|&&taken| taken > 0)
+            .count();
+        let total = self.branches.iter().map(|branch| branch.taken.len()).sum();
+        (covered, total)
+    }
+
+    /// an LCOV tracefile for the program at `path`
+    pub fn lcov(&self, path: &str) -> String {
+        let mut lcov = format!("TN:\nSF:{path}\n");
+        for (line, name, _) in &self.functions {
+            writeln!(lcov, "FN:{line},{name}").unwrap();
+        }
+        for (_, name, calls) in &self.functions {
+            writeln!(lcov, "FNDA:{calls},{name}").unwrap();
+        }
+        let called = self.functions.iter().filter(|(.., calls)| *calls > 0);
+        writeln!(lcov, "FNF:{}", self.functions.len()).unwrap();
+        writeln!(lcov, "FNH:{}", called.count()).unwrap();
+
+        for (block, branch) in self.branches.iter().enumerate() {
+            for (number, taken) in branch.taken.iter().enumerate() {
+                // `-` marks a branch whose condition never ran
+                let taken = if branch.ran {
+                    taken.to_string()
+                } else {
+                    "-".to_string()
+                };
+                writeln!(lcov, "BRDA:{},{block},{number},{taken}", branch.line).unwrap();
+            }
+        }
+        let (covered, total) = self.branch_counts();
+        writeln!(lcov, "BRF:{total}\nBRH:{covered}").unwrap();
+
+        for (line, hits) in &self.lines {
+            writeln!(lcov, "DA:{line},{hits}").unwrap();
+        }
+        let (covered, total) = self.line_counts();
+        writeln!(lcov, "LF:{total}\nLH:{covered}\nend_of_record").unwrap();
+        lcov
+    }
+
+    /// a line of the source, without indentation
+    fn text(&self, line: usize) -> &str {
+        self.lines_of_source
+            .get(line - 1)
+            .map_or("", |text| text.trim())
+    }
+
+    /// totals for lines, branches and functions, followed by the lines that never ran in red and
+    /// the conditions that only went one way and `match` arms that never ran in yellow
+    pub fn summary(&self) -> String {
+        let (lines, total_lines) = self.line_counts();
+        let (branches, total_branches) = self.branch_counts();
+        let called = self.functions.iter().filter(|(.., calls)| *calls > 0);
+        let mut summary = format!(
+            "lines: {}\nbranches: {}\nfunctions: {}\n",
+            ratio(lines, total_lines),
+            ratio(branches, total_branches),
+            ratio(called.count(), self.functions.len())
+        );
+        for (line, _) in self.lines.iter().filter(|(_, &hits)| hits == 0) {
+            writeln!(summary, "\x1b[31m{line:>5} | {}\x1b[0m", self.text(*line)).unwrap();
+        }
+        // branches that ran but never took some of their outcomes
+        for branch in &self.branches {
+            let missed: Vec<usize> = (0..branch.taken.len())
+                .filter(|&i| branch.taken[i] == 0)
+                .collect();
+            if !branch.ran || missed.is_empty() {
+                continue;
+            }
+            let missed = match branch.kind {
+                BranchKind::Condition if missed == [0] => "never true".to_string(),
+                BranchKind::Condition => "never false".to_string(),
+                BranchKind::Match => {
+                    let arms = branch.taken.len() - 1;
+                    let names: Vec<String> = missed
+                        .iter()
+                        .map(|&i| {
+                            if i == arms {
+                                "default".to_string()
+                            } else {
+                                format!("arm {}", i + 1)
+                            }
+                        })
+                        .collect();
+                    format!("{} never taken", names.join(", "))
+                }
+            };
+            let text = self.text(branch.line);
+            writeln!(
+                summary,
+                "\x1b[33m{:>5} | {text} ({missed})\x1b[0m",
+                branch.line
+            )
+            .unwrap();
+        }
+        summary
+    }
+}
@@ -0,0 +1,399 @@
+//! serve the Debug Adapter Protocol, for debugging in an editor
+//!
+//! a session runs the program with the interpreter and a [`Debugger`] whose frontend answers the
+//! client's requests while the program is paused
+
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+
+use crate::debugger::{line_of, Debugger, Frontend, Paused, Reason, Resume, Variable};
+use crate::error::WabbitError;
+use crate::host::{Limits, Output, Reader};
+use crate::interpreter::Interpreter;
+use crate::parser::Parser;
+use crate::scanner::Scanner;
+use crate::typechecker::Typechecker;
+use crate::WabbitType;
+
+/// the only thread, since Wabbit has no concurrency
+const THREAD: i64 = 1;
+
+/// variable references for the scopes of the innermost frame
+const LOCALS: i64 = 1;
+const GLOBALS: i64 = 2;
+const CONSTANTS: i64 = 3;
+
+/// reads requests and writes responses and events, each JSON after a `Content-Length` header
+struct Connection<R, W> {
+    reader: R,
+    writer: W,
+    /// the sequence number of the last message sent
+    seq: i64,
+    /// whether the client has disconnected
+    closed: bool,
+}
+
+impl<R: BufRead, W: Write> Connection<R, W> {
+    /// the next message, or `None` once the client disconnects
+    fn receive(&mut self) -> io::Result<Option<Value>> {
+        if self.closed {
+            return Ok(None);
+        }
+        let mut length = None;
+        loop {
+            let mut header = String::new();
+            if self.reader.read_line(&mut header)? == 0 {
+                self.closed = true;
+                return Ok(None);
+            }
+            match header.trim().split_once(':') {
+                Some(("Content-Length", value)) => length = value.trim().parse().ok(),
+                None if length.is_some() => break,
+                _ => (),
+            }
+        }
+        let mut body = vec![0; length.unwrap_or_default()];
+        self.reader.read_exact(&mut body)?;
+        let message: Value = serde_json::from_slice(&body)?;
+        if message["command"] == "disconnect" {
+            self.closed = true;
+        }
+        Ok(Some(message))
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        let body = message.to_string();
+        write!(self.writer, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+        self.writer.flush()
+    }
+
+    fn respond(&mut self, request: &Value, body: Value) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": true,
+        });
+        response["body"] = body;
+        self.send(response)
+    }
+
+    fn fail(&mut self, request: &Value, message: &str) -> io::Result<()> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": false,
+            "message": message,
+        }))
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        let mut message = json!({ "type": "event", "event": event });
+        message["body"] = body;
+        self.send(message)
+    }
+
+    /// answer a request that can be made at any time, or acknowledge one that is not supported
+    fn respond_other(&mut self, request: &Value) -> io::Result<()> {
+        match request["command"].as_str() {
+            Some("threads") => self.respond(
+                request,
+                json!({ "threads": [{ "id": THREAD, "name": "main" }] }),
+            ),
+            _ => self.respond(request, json!({})),
+        }
+    }
+
+    /// the lines of a `setBreakpoints` request, which replace any set before
+    fn set_breakpoints(&mut self, request: &Value) -> io::Result<BTreeSet<usize>> {
+        let lines: BTreeSet<usize> = request["arguments"]["breakpoints"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|breakpoint| breakpoint["line"].as_u64())
+            .filter_map(|line| usize::try_from(line).ok())
+            .collect();
+        let breakpoints: Vec<Value> = lines
+            .iter()
+            .map(|line| json!({ "verified": true, "line": line }))
+            .collect();
+        self.respond(request, json!({ "breakpoints": breakpoints }))?;
+        Ok(lines)
+    }
+
+    /// report an error in the program
+    fn error(&mut self, path: &str, source: &str, error: &WabbitError) -> io::Result<()> {
+        let line = line_of(source, error.range.0);
+        let output = format!("{path}:{line}: {}\n", error.text());
+        self.event("output", json!({ "category": "stderr", "output": output }))
+    }
+}
+
+/// sends printed values as `output` events, since stdout carries the protocol
+struct Events<'c, R, W>(&'c RefCell<Connection<R, W>>);
+
+impl<R: BufRead, W: Write> Output for Events<'_, R, W> {
+    fn print(&mut self, value: &WabbitType) {
+        let output = json!({ "category": "stdout", "output": value.output() });
+        // a closed connection stops the program at its next pause
+        self.0.borrow_mut().event("output", output).ok();
+    }
+}
+
+/// answers requests while the program is paused
+struct Adapter<'c, R, W> {
+    connection: &'c RefCell<Connection<R, W>>,
+    /// the path of the program, for stack frames
+    path: String,
+}
+
+fn variables(variables: &[Variable]) -> Vec<Value> {
+    variables
+        .iter()
+        .map(|variable| {
+            let value = variable
+                .value
+                .as_ref()
+                .map_or("<uninitialized>".to_string(), WabbitType::to_string);
+            json!({ "name": variable.name, "value": value, "variablesReference": 0 })
+        })
+        .collect()
+}
+
+impl<R: BufRead, W: Write> Adapter<'_, R, W> {
+    fn pause(&mut self, paused: &mut Paused, reason: Reason) -> io::Result<Resume> {
+        let mut connection = self.connection.borrow_mut();
+        connection.event(
+            "stopped",
+            json!({ "reason": reason.to_string(), "threadId": THREAD, "allThreadsStopped": true }),
+        )?;
+
+        while let Some(request) = connection.receive()? {
+            let arguments = &request["arguments"];
+            let resume = match request["command"].as_str().unwrap_or_default() {
+                "continue" => Resume::Continue,
+                "next" => Resume::StepOver,
+                "stepIn" => Resume::StepInto,
+                "stepOut" => Resume::StepOut,
+                "disconnect" | "terminate" => Resume::Stop,
+                "setBreakpoints" => {
+                    *paused.breakpoints = connection.set_breakpoints(&request)?;
+                    continue;
+                }
+                "stackTrace" => {
+                    let frames: Vec<Value> = paused
+                        .stack()
+                        .iter()
+                        .enumerate()
+                        .map(|(id, frame)| {
+                            json!({
+                                "id": id,
+                                "name": frame.name,
+                                "line": frame.line,
+                                "column": 1,
+                                "source": { "path": self.path },
+                            })
+                        })
+                        .collect();
+                    let body = json!({ "stackFrames": frames, "totalFrames": frames.len() });
+                    connection.respond(&request, body)?;
+                    continue;
+                }
+                "scopes" => {
+                    let scope = |name, reference| json!({ "name": name, "variablesReference": reference, "expensive": false });
+                    let mut scopes = vec![scope("Globals", GLOBALS), scope("Constants", CONSTANTS)];
+                    // the locals of callers are not visible
+                    if arguments["frameId"].as_u64() == Some(0) {
+                        scopes.insert(0, scope("Locals", LOCALS));
+                    }
+                    connection.respond(&request, json!({ "scopes": scopes }))?;
+                    continue;
+                }
+                "variables" => {
+                    let mut scopes = paused.scopes();
+                    let globals = scopes.pop().unwrap_or_default();
+                    let body = match arguments["variablesReference"].as_i64() {
+                        Some(LOCALS) => {
+                            // inner scopes come first, and shadow outer ones
+                            let mut seen = BTreeSet::new();
+                            let locals: Vec<Variable> = scopes
+                                .into_iter()
+                                .flatten()
+                                .filter(|variable| seen.insert(variable.name.clone()))
+                                .collect();
+                            variables(&locals)
+                        }
+                        Some(GLOBALS) => variables(&globals),
+                        Some(CONSTANTS) => variables(&paused.constants()),
+                        _ => Vec::new(),
+                    };
+                    connection.respond(&request, json!({ "variables": body }))?;
+                    continue;
+                }
+                "evaluate" => {
+                    let expression = arguments["expression"].as_str().unwrap_or_default();
+                    match paused.evaluate(expression) {
+                        Ok(value) => connection.respond(
+                            &request,
+                            json!({ "result": value.to_string(), "variablesReference": 0 }),
+                        )?,
+                        Err(err) => connection.fail(&request, &err)?,
+                    }
+                    continue;
+                }
+                _ => {
+                    connection.respond_other(&request)?;
+                    continue;
+                }
+            };
+            connection.respond(&request, json!({ "allThreadsContinued": true }))?;
+            return Ok(resume);
+        }
+        Ok(Resume::Stop)
+    }
+}
+
+impl<R: BufRead, W: Write> Frontend for Adapter<'_, R, W> {
+    fn paused(&mut self, paused: &mut Paused, reason: Reason) -> Resume {
+        self.pause(paused, reason).unwrap_or(Resume::Stop)
+    }
+}
+
+/// run a program with the debugger attached, returning its exit code
+///
+/// the program has no input
+fn debug<R: BufRead, W: Write>(
+    connection: &RefCell<Connection<R, W>>,
+    program: &Path,
+    stop_on_entry: bool,
+    breakpoints: BTreeSet<usize>,
+    limits: Limits,
+) -> io::Result<i32> {
+    let path = program.display().to_string();
+    let source = match std::fs::read_to_string(program) {
+        Ok(source) => source,
+        Err(error) => {
+            let output = format!("{path}: {error}\n");
+            let body = json!({ "category": "stderr", "output": output });
+            connection.borrow_mut().event("output", body)?;
+            return Ok(1);
+        }
+    };
+
+    let mut scanner = Scanner::new(&source);
+    if let Err(errs) = scanner.scan() {
+        connection.borrow_mut().error(&path, &source, &errs[0])?;
+        return Ok(1);
+    }
+    let mut parser = Parser::from(&scanner);
+    let compiled = parser
+        .parse()
+        .and_then(|()| Typechecker::from(&parser).typecheck());
+    if let Err(error) = compiled {
+        connection.borrow_mut().error(&path, &source, &error)?;
+        return Ok(1);
+    }
+
+    let mut output = Events(connection);
+    let mut input = Reader::from("");
+    let adapter = Adapter {
+        connection,
+        path: path.clone(),
+    };
+    let mut debugger = Debugger::new(&source, adapter);
+    debugger.breakpoints = breakpoints;
+    debugger.stop_on_entry = stop_on_entry;
+    let result = Interpreter::from(&parser)
+        .with_output(&mut output)
+        .with_input(&mut input)
+        .with_limits(limits)
+        .with_debugger(&mut debugger)
+        .interpret();
+    match result {
+        Ok(()) => Ok(0),
+        Err(_) if debugger.stopped() => Ok(0),
+        Err(error) => {
+            connection.borrow_mut().error(&path, &source, &error)?;
+            Ok(1)
+        }
+    }
+}
+
+/// serve one debugging session, reading requests from `reader` and writing to `writer`
+///
+/// the program to debug is the `program` argument of the `launch` request, or `path` if it has
+/// none. It runs once the client has configured breakpoints between `initialize` and
+/// `configurationDone`
+pub fn serve<R: BufRead, W: Write>(
+    reader: R,
+    writer: W,
+    path: Option<&Path>,
+    limits: Limits,
+) -> io::Result<()> {
+    let connection = RefCell::new(Connection {
+        reader,
+        writer,
+        seq: 0,
+        closed: false,
+    });
+    let mut launch = None;
+    let mut configured = false;
+    let mut breakpoints = BTreeSet::new();
+
+    // the client launches the program and configures it, in either order
+    while launch.is_none() || !configured {
+        let mut connection = connection.borrow_mut();
+        let Some(request) = connection.receive()? else {
+            return Ok(());
+        };
+        match request["command"].as_str().unwrap_or_default() {
+            "initialize" => {
+                let capabilities = json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsEvaluateForHovers": true,
+                });
+                connection.respond(&request, capabilities)?;
+                connection.event("initialized", json!({}))?;
+            }
+            "launch" => {
+                let arguments = &request["arguments"];
+                let program = arguments["program"]
+                    .as_str()
+                    .map(PathBuf::from)
+                    .or_else(|| path.map(Path::to_path_buf));
+                let stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+                match program {
+                    Some(program) => {
+                        connection.respond(&request, json!({}))?;
+                        launch = Some((program, stop_on_entry));
+                    }
+                    None => connection.fail(&request, "no program to debug")?,
+                }
+            }
+            "setBreakpoints" => breakpoints = connection.set_breakpoints(&request)?,
+            "configurationDone" => {
+                connection.respond(&request, json!({}))?;
+                configured = true;
+            }
+            "disconnect" => return connection.respond(&request, json!({})),
+            _ => connection.respond_other(&request)?,
+        }
+    }
+    let (program, stop_on_entry) = launch.unwrap_or_default();
+    let exit_code = debug(&connection, &program, stop_on_entry, breakpoints, limits)?;
+
+    let mut connection = connection.borrow_mut();
+    connection.event("exited", json!({ "exitCode": exit_code }))?;
+    connection.event("terminated", json!({}))?;
+    while let Some(request) = connection.receive()? {
+        connection.respond_other(&request)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,396 @@
+//! pause interpreted programs at breakpoints and steps to inspect them
+//!
+//! a [`Debugger`] decides where to pause, and while the program is paused a [`Frontend`]
+//! inspects it through [`Paused`] until it says how to resume
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+use crate::ast::Stmt;
+use crate::environment::VarStore;
+use crate::error::{msg, Msg, RangeReporter, Result};
+use crate::host::{Limits, Reader};
+use crate::interpreter::Interpreter;
+use crate::parser::Parser;
+use crate::scanner::Scanner;
+use crate::types::WabbitType;
+
+/// the statements a watch expression may run, so that a runaway function call cannot hang the
+/// debugger
+const WATCH_FUEL: u64 = 100_000;
+
+/// why the program paused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    /// before the first statement
+    Entry,
+    Breakpoint,
+    Step,
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Entry => write!(f, "entry"),
+            Self::Breakpoint => write!(f, "breakpoint"),
+            Self::Step => write!(f, "step"),
+        }
+    }
+}
+
+/// how to resume a paused program
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    /// run until a breakpoint
+    Continue,
+    /// pause at the next statement, including in a function that is called
+    StepInto,
+    /// pause at the next statement in this function or its callers
+    StepOver,
+    /// pause once this function returns
+    StepOut,
+    /// end the program with an error
+    Stop,
+}
+
+/// a function being called, or `<main>` for the top level
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub name: String,
+    /// the line being run, counting from 1
+    pub line: usize,
+}
+
+/// a variable or constant
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variable {
+    pub name: String,
+    /// `None` if not yet assigned
+    pub value: Option<WabbitType>,
+}
+
+impl fmt::Display for Variable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.value {
+            Some(value) => write!(f, "{} = {value}", self.name),
+            None => write!(f, "{} = <uninitialized>", self.name),
+        }
+    }
+}
+
+/// decides what to do while a program is paused, such as reading commands from a user
+pub trait Frontend {
+    fn paused(&mut self, paused: &mut Paused, reason: Reason) -> Resume;
+}
+
+/// the line that a character index falls on, counting from 1
+pub(crate) fn line_of(source: &str, index: usize) -> usize {
+    source.chars().take(index).filter(|&c| c == '\n').count() + 1
+}
+
+/// pauses an interpreted program at breakpoints and steps, see [`Interpreter::with_debugger`]
+pub struct Debugger<F: ?Sized> {
+    /// the source of the program, for line numbers
+    source: String,
+    /// lines to pause at, counting from 1, compared with the line each statement starts on
+    pub breakpoints: BTreeSet<usize>,
+    /// whether to pause before the first statement
+    pub stop_on_entry: bool,
+    /// how the program was last resumed and the call depth at the time, or `None` before the
+    /// first statement
+    resumed: Option<(Resume, usize)>,
+    /// what the program is paused with
+    pub frontend: F,
+}
+
+impl<F: Frontend> Debugger<F> {
+    pub fn new(source: &str, frontend: F) -> Self {
+        Self {
+            source: source.to_string(),
+            breakpoints: BTreeSet::new(),
+            stop_on_entry: false,
+            resumed: None,
+            frontend,
+        }
+    }
+}
+
+impl<F: Frontend + ?Sized> Debugger<F> {
+    /// whether the frontend stopped the program
+    pub fn stopped(&self) -> bool {
+        matches!(self.resumed, Some((Resume::Stop, _)))
+    }
+
+    /// pause before `stmt` if it is at a breakpoint or ends a step
+    ///
+    /// the interpreter calls this before each statement other than a block, and for each loop
+    /// again before its condition is checked
+    pub(crate) fn statement<'a>(
+        &mut self,
+        interpreter: &Interpreter<'a>,
+        stmt: &'a Stmt,
+    ) -> Result<()> {
+        let depth = interpreter.analyze.call_depth;
+        let line = line_of(&self.source, interpreter.analyze.ranges[&stmt.id()].0);
+        let reason = match self.resumed {
+            None if self.stop_on_entry => Some(Reason::Entry),
+            Some((Resume::StepInto, _)) => Some(Reason::Step),
+            Some((Resume::StepOver, from)) if depth <= from => Some(Reason::Step),
+            Some((Resume::StepOut, from)) if depth < from => Some(Reason::Step),
+            _ => None,
+        }
+        .or_else(|| {
+            self.breakpoints
+                .contains(&line)
+                .then_some(Reason::Breakpoint)
+        });
+
+        let Some(reason) = reason else {
+            self.resumed.get_or_insert((Resume::Continue, depth));
+            return Ok(());
+        };
+        let mut paused = Paused {
+            interpreter,
+            source: &self.source,
+            line,
+            breakpoints: &mut self.breakpoints,
+        };
+        let resume = self.frontend.paused(&mut paused, reason);
+        self.resumed = Some((resume, depth));
+        if resume == Resume::Stop {
+            msg!(Msg::DebuggerStop, (interpreter.analyze.ranges, stmt.id()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// a paused program, for a frontend to inspect
+pub struct Paused<'p, 'a> {
+    interpreter: &'p Interpreter<'a>,
+    source: &'p str,
+    /// the line of the statement about to run, counting from 1
+    pub line: usize,
+    /// lines to pause at, which may be changed while paused
+    pub breakpoints: &'p mut BTreeSet<usize>,
+}
+
+impl Paused<'_, '_> {
+    /// the source of the program
+    pub fn source(&self) -> &str {
+        self.source
+    }
+
+    /// the functions being called, innermost first and ending with `<main>`
+    pub fn stack(&self) -> Vec<Frame> {
+        let mut frames = Vec::new();
+        let mut line = self.line;
+        for (name, call) in self.interpreter.calls.iter().rev() {
+            frames.push(Frame {
+                name: (*name).to_string(),
+                line,
+            });
+            line = line_of(self.source, self.interpreter.analyze.ranges[call].0);
+        }
+        frames.push(Frame {
+            name: "<main>".to_string(),
+            line,
+        });
+        frames
+    }
+
+    /// the variables in scope, innermost scope first and ending with the globals
+    ///
+    /// in a function, the scopes of its callers are not visible
+    pub fn scopes(&self) -> Vec<Vec<Variable>> {
+        self.interpreter
+            .analyze
+            .env
+            .scopes()
+            .into_iter()
+            .map(|scope| {
+                let sorted: BTreeMap<_, _> = scope.iter().collect();
+                sorted
+                    .into_iter()
+                    .map(|(name, store)| Variable {
+                        name: (*name).clone(),
+                        value: match store {
+                            VarStore::Init(value) | VarStore::Captured(value) => {
+                                Some(value.clone())
+                            }
+                            VarStore::UnInit(_) => None,
+                        },
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    pub fn constants(&self) -> Vec<Variable> {
+        let sorted: BTreeMap<_, _> = self.interpreter.analyze.constants.iter().collect();
+        sorted
+            .into_iter()
+            .map(|(name, value)| Variable {
+                name: (*name).clone(),
+                value: Some(value.clone()),
+            })
+            .collect()
+    }
+
+    /// evaluate an expression where the program is paused, as for a watch expression
+    ///
+    /// this runs in a copy of the program's state, so functions it calls cannot change variables,
+    /// and anything they print or read is discarded
+    pub fn evaluate(&self, expression: &str) -> std::result::Result<WabbitType, String> {
+        // the scanner expects source to end with a newline
+        let source = format!("{expression}\n");
+        let mut scanner = Scanner::new(&source);
+        scanner.scan().map_err(|errs| errs[0].text().to_string())?;
+        let mut parser = Parser::from(&scanner);
+        let e = parser
+            .parse_expression()
+            .map_err(|err| err.text().to_string())?;
+
+        // the ids of the expression overlap those of the program, but only the labels of errors
+        // are reported
+        let mut ranges = parser.borrow_ranges().clone();
+        for (id, range) in self.interpreter.analyze.ranges {
+            ranges.entry(*id).or_insert(*range);
+        }
+        let statements = Vec::new();
+        let mut output = Vec::new();
+        let mut input = Reader::from("");
+        let mut watch = Interpreter::new(&statements, &ranges)
+            .with_output(&mut output)
+            .with_input(&mut input)
+            .with_limits(Limits {
+                fuel: Some(WATCH_FUEL),
+                ..self.interpreter.limits
+            });
+        let analyze = &self.interpreter.analyze;
+        watch.analyze.env = analyze.env.clone();
+        watch.analyze.constants.clone_from(&analyze.constants);
+        watch.analyze.functions.clone_from(&analyze.functions);
+        watch.analyze.natives = analyze.natives;
+        watch.analyze.call_depth = analyze.call_depth;
+        watch.definitions.clone_from(&self.interpreter.definitions);
+        watch.evaluate(&e).map_err(|err| err.text().to_string())
+    }
+}
+
+const HELP: &str = "\
+commands:
+  c, continue        run until a breakpoint
+  s, step            step to the next statement, into function calls
+  n, next            step to the next statement, over function calls
+  o, out             step out of the current function
+  b, break LINE      set a breakpoint
+  d, delete LINE     remove a breakpoint
+  p, print EXPR      evaluate an expression
+  w, watch EXPR      evaluate an expression each time the program pauses
+  unwatch            remove all watch expressions
+  l, locals          show variables in scope
+  consts             show constants
+  bt, backtrace      show the functions being called
+  q, quit            stop the program";
+
+/// a command line frontend, reading commands from `input` and writing to `output`
+pub struct Console<R, W> {
+    input: R,
+    output: W,
+    /// expressions to show each time the program pauses
+    watches: Vec<String>,
+}
+
+impl<R: BufRead, W: Write> Console<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Self {
+            input,
+            output,
+            watches: Vec::new(),
+        }
+    }
+
+    fn show_value(&mut self, paused: &Paused, expression: &str) -> io::Result<()> {
+        match paused.evaluate(expression) {
+            Ok(value) => writeln!(self.output, "{expression} = {value}"),
+            Err(err) => writeln!(self.output, "{expression}: {err}"),
+        }
+    }
+
+    /// show where the program paused, then read commands until one resumes it
+    fn pause(&mut self, paused: &mut Paused, reason: Reason) -> io::Result<Resume> {
+        let line = paused.source().lines().nth(paused.line - 1).unwrap_or("");
+        writeln!(self.output, "paused ({reason}) at line {}", paused.line)?;
+        writeln!(self.output, "{:>5} | {}", paused.line, line.trim_end())?;
+        for expression in self.watches.clone() {
+            self.show_value(paused, &expression)?;
+        }
+
+        loop {
+            write!(self.output, "(wdb) ")?;
+            self.output.flush()?;
+            let mut command = String::new();
+            if self.input.read_line(&mut command)? == 0 {
+                return Ok(Resume::Stop);
+            }
+            let (name, arg) = command
+                .trim()
+                .split_once(' ')
+                .map_or((command.trim(), ""), |(name, arg)| (name, arg.trim()));
+            match (name, arg.parse::<usize>()) {
+                ("c" | "continue", _) => return Ok(Resume::Continue),
+                ("s" | "step", _) => return Ok(Resume::StepInto),
+                ("n" | "next", _) => return Ok(Resume::StepOver),
+                ("o" | "out", _) => return Ok(Resume::StepOut),
+                ("q" | "quit", _) => return Ok(Resume::Stop),
+                ("b" | "break", Ok(line)) => {
+                    paused.breakpoints.insert(line);
+                    writeln!(self.output, "breakpoint at line {line}")?;
+                }
+                ("d" | "delete", Ok(line)) => {
+                    if !paused.breakpoints.remove(&line) {
+                        writeln!(self.output, "no breakpoint at line {line}")?;
+                    }
+                }
+                ("p" | "print", _) if !arg.is_empty() => self.show_value(paused, arg)?,
+                ("w" | "watch", _) if !arg.is_empty() => {
+                    self.show_value(paused, arg)?;
+                    self.watches.push(arg.to_string());
+                }
+                ("unwatch", _) => self.watches.clear(),
+                ("l" | "locals", _) => {
+                    let scopes = paused.scopes();
+                    if let Some((globals, locals)) = scopes.split_last() {
+                        for variable in locals.iter().flatten() {
+                            writeln!(self.output, "{variable}")?;
+                        }
+                        writeln!(self.output, "globals:")?;
+                        for variable in globals {
+                            writeln!(self.output, "  {variable}")?;
+                        }
+                    }
+                }
+                ("consts", _) => {
+                    for constant in paused.constants() {
+                        writeln!(self.output, "{constant}")?;
+                    }
+                }
+                ("bt" | "backtrace", _) => {
+                    for frame in paused.stack() {
+                        writeln!(self.output, "{} at line {}", frame.name, frame.line)?;
+                    }
+                }
+                ("", _) => (),
+                _ => writeln!(self.output, "{HELP}")?,
+            }
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> Frontend for Console<R, W> {
+    fn paused(&mut self, paused: &mut Paused, reason: Reason) -> Resume {
+        self.pause(paused, reason).unwrap_or(Resume::Stop)
+    }
+}
@@ -6,6 +6,8 @@ use std::collections::HashMap;
 pub enum VarStore<T> {
     UnInit(T),
     Init(T),
+    /// a copy of a variable, captured by a function expression, which cannot be assigned
+    Captured(T),
 }
 
 impl<T> VarStore<T> {
@@ -14,7 +16,7 @@ impl<T> VarStore<T> {
         T: Clone,
     {
         match self {
-            Self::Init(v) | Self::UnInit(v) => v.clone(),
+            Self::Init(v) | Self::UnInit(v) | Self::Captured(v) => v.clone(),
         }
     }
 }
@@ -57,6 +59,30 @@ where
         }
     }
 
+    /// enter a function body, which can only see the global scope, returning the caller's
+    /// local scopes for `exit_call` to restore
+    pub fn enter_call(
+        &mut self,
+        params: HashMap<&'a String, VarStore<T>>,
+    ) -> Vec<HashMap<&'a String, VarStore<T>>> {
+        let mut locals = Vec::new();
+        while let Some(parent) = self.parent.take() {
+            locals.push(std::mem::take(&mut self.values));
+            *self = *parent;
+        }
+        self.enter_child_fn(params);
+        locals
+    }
+
+    /// leave a function body entered with `enter_call`
+    pub fn exit_call(&mut self, locals: Vec<HashMap<&'a String, VarStore<T>>>) {
+        self.exit_child_unwrap();
+        for values in locals.into_iter().rev() {
+            self.enter_child();
+            self.values = values;
+        }
+    }
+
     pub fn exit_child<S>(&mut self, stmt: &S) -> Result<()>
     where
         S: RangeReporter,
@@ -107,6 +133,26 @@ where
         }
     }
 
+    /// like `get`, but only finds variables outside of the global scope
+    pub fn get_local(&self, name: &String) -> Option<VarStore<T>> {
+        let parent = self.parent.as_ref()?;
+        self.values
+            .get(name)
+            .cloned()
+            .or_else(|| parent.get_local(name))
+    }
+
+    /// the variables of each scope, innermost first
+    pub fn scopes(&self) -> Vec<&HashMap<&'a String, VarStore<T>>> {
+        let mut scopes = vec![&self.values];
+        let mut env = self;
+        while let Some(parent) = &env.parent {
+            scopes.push(&parent.values);
+            env = parent;
+        }
+        scopes
+    }
+
     pub fn top_contains(&self, name: &String) -> bool {
         self.values.contains_key(name)
     }
@@ -18,7 +18,7 @@ pub struct WabbitError {
     /// error text
     pub label: String,
     /// source code indices
-    range: (usize, usize),
+    pub(crate) range: (usize, usize),
 }
 
 impl WabbitError {
@@ -31,6 +31,18 @@ impl WabbitError {
             range,
         }
     }
+
+    /// the error text, without the terminal colors of `label`
+    pub fn text(&self) -> &str {
+        self.label
+            .trim_start_matches("\x1b[31m")
+            .trim_end_matches("\x1b[0m")
+    }
+
+    /// point the error at a different part of the source
+    pub(crate) fn at(self, range: (usize, usize)) -> Self {
+        Self { range, ..self }
+    }
 }
 
 /// struct for reporting multiple errors
@@ -151,7 +163,6 @@ pub enum Msg {
     // Scanner
     InvalidNumber,
     InvalidChar,
-    DoubleToken,
     UnexpectedChar,
 
     // Parser
@@ -187,6 +198,19 @@ pub enum Msg {
     RedeclareVar,
     RedeclareFunc,
     RedeclareConst,
+    InvalidInput,
+    OutOfFuel,
+    CallDepth,
+    Timeout,
+    IntOverflow,
+    DivideByZero,
+    DebuggerStop,
+    AssertFailed,
+    MatchType,
+    MatchDuplicate,
+    ShiftAmount,
+    NotCallable,
+    AssignCapture,
 
     // errors that are NOT user errors
     InternalErr,
@@ -199,13 +223,14 @@ impl Msg {
             // Scanner
             Msg::InvalidNumber => "invalid number: '{}'",
             Msg::InvalidChar => "invalid character",
-            Msg::DoubleToken => "character '{}' is invalid, maybe you meant to follow with '{}'?",
             Msg::UnexpectedChar => "unexpected character '{}'",
 
             // Parser
             Msg::VarDefEmpty => "variable definitions must contain either a type or expression.",
             Msg::ExpectExpr => "expected an expression",
-            Msg::ExpectTypeName => "expected a type name (int, float, bool, or char)",
+            Msg::ExpectTypeName => {
+                "expected a type name (int, int64, uint8, float, float32, bool, or char)"
+            }
             Msg::ExpectVarName => "expected a variable name",
             Msg::ParserExpect => "expected '{}'",
 
@@ -237,6 +262,19 @@ impl Msg {
             Msg::RedeclareVar => "'{}' is previously declared as a variable",
             Msg::RedeclareConst => "'{}' is previously declared as a constant",
             Msg::RedeclareFunc => "'{}' is previously declared as a function",
+            Msg::InvalidInput => "could not read a value of type '{}' from input",
+            Msg::OutOfFuel => "exceeded the limit of {} executed statements",
+            Msg::CallDepth => "exceeded the maximum call depth of {}",
+            Msg::Timeout => "exceeded the time limit of {}",
+            Msg::IntOverflow => "integer overflow in '{}'",
+            Msg::DivideByZero => "division by zero",
+            Msg::DebuggerStop => "stopped by the debugger",
+            Msg::AssertFailed => "assertion failed: {}",
+            Msg::MatchType => "match arm of type '{}' on a value of type '{}'",
+            Msg::MatchDuplicate => "duplicate match arm '{}'",
+            Msg::ShiftAmount => "shift by {}, which is not between 0 and {}",
+            Msg::NotCallable => "cannot call a value of type '{}'",
+            Msg::AssignCapture => "cannot assign to '{}', which the function captured by value",
 
             // errors that are NOT user errors
             Msg::InternalErr => "This is an internal error! {}",
@@ -7,6 +7,7 @@ impl Display for Stmt {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Stmt::Print { value, .. } => write!(f, "print {value};"),
+            Stmt::Assert { value, .. } => write!(f, "assert {value};"),
             Stmt::While {
                 condition, body, ..
             } => {
@@ -14,6 +15,19 @@ impl Display for Stmt {
                 write!(f, "{body}")?;
                 write!(f, "}}")
             }
+            Stmt::For {
+                init,
+                condition,
+                step,
+                body,
+                ..
+            } => {
+                // the step is written without its `;`
+                let step = step.to_string();
+                write!(f, "for {init}{condition};{}{{", step.trim_end_matches(';'))?;
+                write!(f, "{body}")?;
+                write!(f, "}}")
+            }
             Stmt::Block { statements, .. } => {
                 for stmt in statements {
                     write!(f, "{stmt}")?;
@@ -29,13 +43,32 @@ impl Display for Stmt {
                 write!(f, "if {condition}{{")?;
                 write!(f, "{then_block}")?;
                 write!(f, "}}")?;
-                if let Some(else_block) = maybe_else_block {
-                    write!(f, "else {{")?;
-                    write!(f, "{else_block}")?;
-                    write!(f, "}}")?;
+                match maybe_else_block.as_deref() {
+                    Some(else_if @ Stmt::If { .. }) => write!(f, "else {else_if}")?,
+                    Some(else_block) => {
+                        write!(f, "else {{")?;
+                        write!(f, "{else_block}")?;
+                        write!(f, "}}")?;
+                    }
+                    None => (),
                 }
                 Ok(())
             }
+            Stmt::Match {
+                value,
+                arms,
+                maybe_default,
+                ..
+            } => {
+                write!(f, "match {value}{{")?;
+                for (pattern, body) in arms {
+                    write!(f, "{pattern}=>{{{body}}},")?;
+                }
+                if let Some(default) = maybe_default {
+                    write!(f, "_=>{{{default}}}")?;
+                }
+                write!(f, "}}")
+            }
             Stmt::Expr(e) => {
                 write!(f, "{e};")
             }
@@ -95,9 +128,12 @@ impl Display for Stmt {
             Stmt::Return { value, .. } => {
                 write!(f, "return {value};")
             }
-            Stmt::Assign { name, value, .. } => {
-                write!(f, "{name}={value};")
-            }
+            Stmt::Assign {
+                name, op, value, ..
+            } => match op {
+                Some(op) => write!(f, "{name}{op}={value};"),
+                None => write!(f, "{name}={value};"),
+            },
         }
     }
 }
@@ -109,6 +145,9 @@ impl Display for Expr {
                 WabbitType::Char(c) if c == &'\n' => write!(f, "'\\n'"),
                 WabbitType::Char(c) => write!(f, "'{c}'"),
                 WabbitType::Float(x) => write!(f, "{x:.32}"),
+                WabbitType::Int64(x) => write!(f, "{x}i64"),
+                WabbitType::Uint8(x) => write!(f, "{x}u8"),
+                WabbitType::Float32(x) => write!(f, "{x}f32"),
                 _ => write!(f, "{value}"),
             },
             Expr::Logical { lhs, op, rhs, .. } => {
@@ -133,6 +172,38 @@ impl Display for Expr {
                 }
                 write!(f, ")")
             }
+            Expr::CallValue { callee, params, .. } => {
+                write!(f, "{callee}(")?;
+
+                let airty = params.len();
+
+                for (i, e) in params.iter().enumerate() {
+                    write!(f, "{e}")?;
+                    if i != airty - 1 {
+                        write!(f, ",")?;
+                    }
+                }
+                write!(f, ")")
+            }
+            Expr::Function {
+                params,
+                return_type,
+                body,
+                ..
+            } => {
+                write!(f, "func(")?;
+                let airty = params.len();
+
+                for (i, (name, typename)) in params.iter().enumerate() {
+                    write!(f, "{name} {typename}")?;
+                    if i != airty - 1 {
+                        write!(f, ",")?;
+                    }
+                }
+                write!(f, "){return_type}{{")?;
+                write!(f, "{body}")?;
+                write!(f, "}}")
+            }
             Expr::TypeConversion { dtype, params, .. } => {
                 write!(f, "{dtype}(")?;
 
@@ -169,6 +240,12 @@ impl Display for BinaryOp {
             Self::Minus => write!(f, "-"),
             Self::Times => write!(f, "*"),
             Self::Divide => write!(f, "/"),
+            Self::Modulo => write!(f, "%"),
+            Self::BitAnd => write!(f, "&"),
+            Self::BitOr => write!(f, "|"),
+            Self::BitXor => write!(f, "^"),
+            Self::ShiftLeft => write!(f, "<<"),
+            Self::ShiftRight => write!(f, ">>"),
             Self::Less => write!(f, "<"),
             Self::LessEqual => write!(f, "<="),
             Self::Greater => write!(f, ">"),
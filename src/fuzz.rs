@@ -0,0 +1,1120 @@
+//! generate random well-typed Wabbit programs for fuzzing
+//!
+//! the programs also run to completion without errors, so that the backends can be compared on
+//! them, and [`shrink`] makes one that fails smaller
+
+use crate::ast::{Expr, Stmt};
+use crate::operators::{BinaryOp, LogicalOp, LoopControl, UnaryOp};
+use crate::types::Type;
+use crate::WabbitType;
+
+/// bound on the magnitude of any int that is stored (variables, parameters and return values)
+///
+/// int expressions are built top down from a bound on their value, so nothing overflows, and ints
+/// are only divided by non-zero literals
+const MAX_STORED: i64 = 1000;
+
+/// bound on the magnitude of any int expression, since `int(float)` saturates at `-2^31`
+const MAX_INT: i64 = 1 << 31;
+
+/// bound on the estimated number of statements a program executes
+const MAX_COST: u64 = 5000;
+
+/// maximum depth of generated expressions
+const MAX_DEPTH: usize = 3;
+
+/// maximum nesting of blocks
+const MAX_NESTING: usize = 3;
+
+/// prefix of loop counter names
+const COUNTER: &str = "i";
+
+/// chars that can be written as a literal
+const CHARS: &[u8] = b"abcxyzABCXYZ0123456789 .,:;!?*+-=<>()[]{}#$%&@_~\n";
+
+const TYPES: [Type; 4] = [Type::Int, Type::Float, Type::Bool, Type::Char];
+
+/// a xorshift random number generator, so that programs are reproducible from a seed
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// a number in `0..n`
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    /// an int in `0..n`
+    fn int(&mut self, n: i32) -> i32 {
+        i32::try_from(self.below(n.unsigned_abs() as usize)).unwrap()
+    }
+
+    fn chance(&mut self, percent: u64) -> bool {
+        self.next() % 100 < percent
+    }
+
+    fn pick<T: Clone>(&mut self, items: &[T]) -> T {
+        items[self.below(items.len())].clone()
+    }
+}
+
+/// a variable that generated code can refer to
+#[derive(Clone)]
+struct Var {
+    name: String,
+    dtype: Type,
+    assignable: bool,
+}
+
+/// a function that generated code can call
+struct Func {
+    name: String,
+    params: Vec<Type>,
+    return_type: Type,
+    /// estimated number of statements executed by a call
+    cost: u64,
+}
+
+/// Struct for generating random, well-typed Wabbit programs
+pub struct Generator {
+    rng: Rng,
+    /// id for the next AST node
+    id: usize,
+    /// used to generate unique names
+    counter: usize,
+    /// variables in scope, the first being globals
+    scopes: Vec<Vec<Var>>,
+    /// functions defined so far
+    functions: Vec<Func>,
+    /// product of the iteration counts of the enclosing loops
+    multiplier: u64,
+    /// estimated statements executed by the code generated so far, in the current function
+    cost: u64,
+    loop_depth: usize,
+    /// return type of the function being generated
+    return_type: Option<Type>,
+    /// whether a return statement can be generated, which the typechecker only allows inside
+    /// a loop directly in a function body
+    can_return: bool,
+}
+
+/// render a program as source code with the formatter
+pub fn render(program: &[Stmt]) -> String {
+    program.iter().map(|stmt| stmt.to_string() + "\n").collect()
+}
+
+impl Generator {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: Rng::new(seed),
+            id: 0,
+            counter: 0,
+            scopes: vec![Vec::new()],
+            functions: Vec::new(),
+            multiplier: 1,
+            cost: 0,
+            loop_depth: 0,
+            return_type: None,
+            can_return: false,
+        }
+    }
+
+    /// generate a random program
+    pub fn program(&mut self) -> Vec<Stmt> {
+        let mut program = Vec::new();
+        for _ in 0..4 + self.rng.below(12) {
+            if self.functions.len() < 4 && self.rng.chance(25) {
+                program.push(self.func_def());
+            } else if self.rng.chance(15) {
+                program.push(self.const_def());
+            } else {
+                program.extend(self.stmt(0));
+            }
+        }
+        program
+    }
+
+    fn id(&mut self) -> usize {
+        self.id += 1;
+        self.id
+    }
+
+    fn fresh(&mut self, prefix: &str) -> String {
+        self.counter += 1;
+        format!("{prefix}{}", self.counter)
+    }
+
+    fn define(&mut self, name: &str, dtype: Type, assignable: bool) {
+        self.scopes.last_mut().unwrap().push(Var {
+            name: name.to_string(),
+            dtype,
+            assignable,
+        });
+    }
+
+    /// the variables that names currently refer to
+    fn visible(&self) -> Vec<Var> {
+        let mut visible: Vec<Var> = Vec::new();
+        for scope in self.scopes.iter().rev() {
+            for var in scope.iter().rev() {
+                if !visible.iter().any(|v| v.name == var.name) {
+                    visible.push(var.clone());
+                }
+            }
+        }
+        visible
+    }
+
+    fn literal(&mut self, value: WabbitType) -> Expr {
+        Expr::Literal {
+            value,
+            id: self.id(),
+        }
+    }
+
+    fn var_name(&mut self, name: &str) -> Expr {
+        Expr::VarName {
+            name: name.to_string(),
+            id: self.id(),
+        }
+    }
+
+    /// wrap an operand in parentheses, since the formatter does not add any
+    fn group(&mut self, e: Expr) -> Expr {
+        match e {
+            Expr::Literal { .. }
+            | Expr::VarName { .. }
+            | Expr::Call { .. }
+            | Expr::TypeConversion { .. }
+            | Expr::Grouping { .. } => e,
+            _ => Expr::Grouping {
+                e: Box::new(e),
+                id: self.id(),
+            },
+        }
+    }
+
+    fn binary(&mut self, lhs: Expr, op: BinaryOp, rhs: Expr) -> Expr {
+        Expr::Binary {
+            lhs: Box::new(self.group(lhs)),
+            op,
+            rhs: Box::new(self.group(rhs)),
+            id: self.id(),
+        }
+    }
+
+    fn unary(&mut self, op: UnaryOp, operand: Expr) -> Expr {
+        Expr::Unary {
+            op,
+            operand: Box::new(self.group(operand)),
+            id: self.id(),
+        }
+    }
+
+    fn convert(&mut self, dtype: Type, e: Expr) -> Expr {
+        Expr::TypeConversion {
+            dtype,
+            params: vec![e],
+            id: self.id(),
+        }
+    }
+
+    /// a random variable of a type, if there is one in scope
+    fn pick_var(&mut self, dtype: &Type) -> Option<Expr> {
+        let vars: Vec<String> = self
+            .visible()
+            .into_iter()
+            .filter(|var| var.dtype == *dtype)
+            .map(|var| var.name)
+            .collect();
+        if vars.is_empty() {
+            None
+        } else {
+            let name = vars[self.rng.below(vars.len())].clone();
+            Some(self.var_name(&name))
+        }
+    }
+
+    /// a call to a random function returning a type, if one fits in the cost budget
+    fn pick_call(&mut self, dtype: &Type, depth: usize) -> Option<Expr> {
+        let candidates: Vec<usize> = (0..self.functions.len())
+            .filter(|&i| {
+                let func = &self.functions[i];
+                func.return_type == *dtype && self.cost + self.multiplier * func.cost <= MAX_COST
+            })
+            .collect();
+        if candidates.is_empty() {
+            return None;
+        }
+        let index = candidates[self.rng.below(candidates.len())];
+        self.cost += self.multiplier * self.functions[index].cost;
+
+        let (name, params) = (
+            self.functions[index].name.clone(),
+            self.functions[index].params.clone(),
+        );
+        let params = params
+            .iter()
+            .map(|dtype| self.expr(dtype, depth + 1))
+            .collect();
+        Some(Expr::Call {
+            name,
+            params,
+            id: self.id(),
+        })
+    }
+
+    /// a random expression of a type, with ints at most `MAX_STORED`
+    fn expr(&mut self, dtype: &Type, depth: usize) -> Expr {
+        match dtype {
+            Type::Int => self.int_expr(MAX_STORED, depth),
+            Type::Float => self.float_expr(depth),
+            Type::Bool => self.bool_expr(depth),
+            Type::Char => self.char_expr(depth),
+            Type::Int64 | Type::Uint8 | Type::Float32 | Type::Func(_) => {
+                unreachable!("only the types in TYPES are generated")
+            }
+        }
+    }
+
+    /// a random int expression whose value is at most `limit` in magnitude
+    fn int_expr(&mut self, limit: i64, depth: usize) -> Expr {
+        if depth < MAX_DEPTH {
+            let stored = limit >= MAX_STORED;
+            let e = match self.rng.below(10) {
+                0 if stored => self.pick_var(&Type::Int),
+                1 if stored => self.pick_call(&Type::Int, depth),
+                2 if limit >= 2 => {
+                    let lhs = self.int_expr(limit / 2, depth + 1);
+                    let rhs = self.int_expr(limit / 2, depth + 1);
+                    let op = self.rng.pick(&[BinaryOp::Plus, BinaryOp::Minus]);
+                    Some(self.binary(lhs, op, rhs))
+                }
+                3 => {
+                    let sqrt = f64::from(u32::try_from(limit).unwrap()).sqrt() as i64;
+                    let lhs = self.int_expr(sqrt, depth + 1);
+                    let rhs = self.int_expr(sqrt, depth + 1);
+                    Some(self.binary(lhs, BinaryOp::Times, rhs))
+                }
+                4 => {
+                    let lhs = self.int_expr(limit, depth + 1);
+                    let divisor = 1 + self.rng.int(9);
+                    let rhs = self.literal(WabbitType::Int(divisor));
+                    let op = self.rng.pick(&[BinaryOp::Divide, BinaryOp::Modulo]);
+                    Some(self.binary(lhs, op, rhs))
+                }
+                5 => {
+                    let operand = self.int_expr(limit.min(MAX_INT - 1), depth + 1);
+                    let op = self.rng.pick(&[UnaryOp::Minus, UnaryOp::Plus]);
+                    Some(self.unary(op, operand))
+                }
+                6 if limit >= 255 => {
+                    let c = self.char_expr(depth + 1);
+                    Some(self.convert(Type::Int, c))
+                }
+                7 => {
+                    let b = self.bool_expr(depth + 1);
+                    Some(self.convert(Type::Int, b))
+                }
+                8 if limit >= MAX_INT => {
+                    let x = self.float_expr(depth + 1);
+                    Some(self.convert(Type::Int, x))
+                }
+                _ => None,
+            };
+            if let Some(e) = e {
+                return e;
+            }
+        }
+        let value = self.rng.int(i32::try_from(limit.min(100)).unwrap() + 1);
+        self.literal(WabbitType::Int(value))
+    }
+
+    fn float_expr(&mut self, depth: usize) -> Expr {
+        if depth < MAX_DEPTH {
+            let e = match self.rng.below(8) {
+                0 => self.pick_var(&Type::Float),
+                1 => self.pick_call(&Type::Float, depth),
+                2 | 3 => {
+                    let lhs = self.float_expr(depth + 1);
+                    let rhs = self.float_expr(depth + 1);
+                    let op = self.rng.pick(&[
+                        BinaryOp::Plus,
+                        BinaryOp::Minus,
+                        BinaryOp::Times,
+                        BinaryOp::Divide,
+                    ]);
+                    Some(self.binary(lhs, op, rhs))
+                }
+                4 => {
+                    let operand = self.float_expr(depth + 1);
+                    Some(self.unary(UnaryOp::Minus, operand))
+                }
+                5 => {
+                    let i = self.int_expr(MAX_STORED, depth + 1);
+                    Some(self.convert(Type::Float, i))
+                }
+                _ => None,
+            };
+            if let Some(e) = e {
+                return e;
+            }
+        }
+        // quarters are exact, so literals survive the formatter unchanged
+        let value = f64::from(self.rng.int(41)) / 4.0;
+        self.literal(WabbitType::Float(value))
+    }
+
+    fn bool_expr(&mut self, depth: usize) -> Expr {
+        if depth < MAX_DEPTH {
+            let e = match self.rng.below(9) {
+                0 => self.pick_var(&Type::Bool),
+                1 => self.pick_call(&Type::Bool, depth),
+                2 | 3 => {
+                    let dtype = self.rng.pick(&TYPES);
+                    let ops: &[BinaryOp] = if dtype == Type::Bool {
+                        &[BinaryOp::EqualEqual, BinaryOp::NotEqual]
+                    } else {
+                        &[
+                            BinaryOp::Less,
+                            BinaryOp::LessEqual,
+                            BinaryOp::Greater,
+                            BinaryOp::GreaterEqual,
+                            BinaryOp::EqualEqual,
+                            BinaryOp::NotEqual,
+                        ]
+                    };
+                    let op = self.rng.pick(ops);
+                    let lhs = self.expr(&dtype, depth + 1);
+                    let rhs = self.expr(&dtype, depth + 1);
+                    Some(self.binary(lhs, op, rhs))
+                }
+                4 | 5 => {
+                    let lhs = self.bool_expr(depth + 1);
+                    let rhs = self.bool_expr(depth + 1);
+                    let op = self
+                        .rng
+                        .pick(&[LogicalOp::LogicalAnd, LogicalOp::LogicalOr]);
+                    Some(Expr::Logical {
+                        lhs: Box::new(self.group(lhs)),
+                        op,
+                        rhs: Box::new(self.group(rhs)),
+                        id: self.id(),
+                    })
+                }
+                6 => {
+                    let operand = self.bool_expr(depth + 1);
+                    Some(self.unary(UnaryOp::LogicalNot, operand))
+                }
+                7 => {
+                    let i = self.int_expr(MAX_STORED, depth + 1);
+                    Some(self.convert(Type::Bool, i))
+                }
+                _ => None,
+            };
+            if let Some(e) = e {
+                return e;
+            }
+        }
+        let value = self.rng.chance(50);
+        self.literal(WabbitType::Bool(value))
+    }
+
+    fn char_expr(&mut self, depth: usize) -> Expr {
+        if depth < MAX_DEPTH {
+            let e = match self.rng.below(6) {
+                0 => self.pick_var(&Type::Char),
+                1 => self.pick_call(&Type::Char, depth),
+                // only printable ASCII, since backends print chars as bytes
+                2 => {
+                    let value = 32 + self.rng.int(95);
+                    let i = self.literal(WabbitType::Int(value));
+                    Some(self.convert(Type::Char, i))
+                }
+                _ => None,
+            };
+            if let Some(e) = e {
+                return e;
+            }
+        }
+        let value = char::from(self.rng.pick(CHARS));
+        self.literal(WabbitType::Char(value))
+    }
+
+    /// a block of statements in a new scope, optionally ending with a return
+    fn block(&mut self, nesting: usize, ends_with_return: bool) -> Stmt {
+        self.scopes.push(Vec::new());
+        let mut statements = Vec::new();
+        for _ in 0..=self.rng.below(4) {
+            statements.extend(self.stmt(nesting));
+        }
+        if ends_with_return {
+            statements.push(self.return_stmt());
+        }
+        self.scopes.pop();
+        Stmt::Block {
+            statements,
+            id: self.id(),
+        }
+    }
+
+    fn return_stmt(&mut self) -> Stmt {
+        let value = self.expr(&self.return_type.clone().unwrap(), 0);
+        Stmt::Return {
+            value,
+            id: self.id(),
+        }
+    }
+
+    /// a random statement, which is two statements for a loop and its counter
+    fn stmt(&mut self, nesting: usize) -> Vec<Stmt> {
+        self.cost += self.multiplier;
+        let in_function = self.return_type.is_some();
+        let assignable: Vec<Var> = self
+            .visible()
+            .into_iter()
+            .filter(|var| var.assignable)
+            .collect();
+
+        // a print below 25, or when the chosen statement cannot be generated here
+        match self.rng.below(100) {
+            25..=44 => return vec![self.var_def()],
+            45..=59 if !assignable.is_empty() => {
+                let var = assignable[self.rng.below(assignable.len())].clone();
+                let value = self.expr(&var.dtype, 0);
+                return vec![Stmt::Assign {
+                    name: var.name,
+                    op: None,
+                    value,
+                    id: self.id(),
+                }];
+            }
+            60..=71 if nesting < MAX_NESTING => {
+                let condition = self.bool_expr(0);
+                // only a block directly in a function body can end with a return, otherwise
+                // the typechecker requires a return on every path
+                let can_end = in_function && nesting == 0 && self.loop_depth == 0;
+                let ends_with_return = can_end && self.rng.chance(30);
+                let then_block = Box::new(self.block(nesting + 1, ends_with_return));
+                let maybe_else_block = if self.rng.chance(50) {
+                    let ends_with_return = can_end && self.rng.chance(30);
+                    Some(Box::new(self.block(nesting + 1, ends_with_return)))
+                } else {
+                    None
+                };
+                return vec![Stmt::If {
+                    condition,
+                    then_block,
+                    maybe_else_block,
+                    id: self.id(),
+                }];
+            }
+            72..=81 if nesting < MAX_NESTING => {
+                let count = 1 + self.rng.below(4) as u64;
+                if self.cost + self.multiplier * count * 4 <= MAX_COST {
+                    return self.counted_loop(nesting, count);
+                }
+            }
+            82..=87 if !self.functions.is_empty() => {
+                let dtype = self.rng.pick(&TYPES);
+                if let Some(call) = self.pick_call(&dtype, 0) {
+                    return vec![Stmt::Expr(call)];
+                }
+            }
+            88..=93 if self.loop_depth > 0 => {
+                let control = if self.rng.chance(50) {
+                    LoopControl::Break
+                } else {
+                    LoopControl::Continue
+                };
+                return vec![Stmt::LoopControl {
+                    control,
+                    id: self.id(),
+                }];
+            }
+            94..=99 if self.can_return => return vec![self.return_stmt()],
+            _ => (),
+        }
+
+        let dtype = self.rng.pick(&TYPES);
+        let value = self.expr(&dtype, 0);
+        vec![Stmt::Print {
+            value,
+            id: self.id(),
+        }]
+    }
+
+    /// `var i = 0; while i < count { i = i + 1; ... }`, or
+    /// `for var i = 0; i < count; i = i + 1 { ... }`
+    ///
+    /// the counter is fresh and incremented first (or in the step), so `continue` cannot skip it
+    fn counted_loop(&mut self, nesting: usize, count: u64) -> Vec<Stmt> {
+        let for_loop = self.rng.chance(50);
+        if for_loop {
+            // the counter is only in scope in the loop
+            self.scopes.push(Vec::new());
+        }
+        let counter = self.fresh(COUNTER);
+        let zero = self.literal(WabbitType::Int(0));
+        let def = Stmt::VarDef {
+            name: counter.clone(),
+            maybe_type: None,
+            maybe_value: Some(zero),
+            id: self.id(),
+        };
+        self.define(&counter, Type::Int, false);
+
+        let var = self.var_name(&counter);
+        let limit = self.literal(WabbitType::Int(count as i32));
+        let condition = self.binary(var, BinaryOp::Less, limit);
+
+        let var = self.var_name(&counter);
+        let one = self.literal(WabbitType::Int(1));
+        let increment = Stmt::Assign {
+            name: counter.clone(),
+            op: None,
+            value: self.binary(var, BinaryOp::Plus, one),
+            id: self.id(),
+        };
+
+        let (multiplier, can_return) = (self.multiplier, self.can_return);
+        self.multiplier *= count;
+        self.can_return |= self.return_type.is_some() && nesting == 0;
+        self.loop_depth += 1;
+
+        self.scopes.push(Vec::new());
+        let mut statements = Vec::new();
+        for _ in 0..=self.rng.below(4) {
+            statements.extend(self.stmt(nesting + 1));
+        }
+        self.scopes.pop();
+
+        self.loop_depth -= 1;
+        self.multiplier = multiplier;
+        self.can_return = can_return;
+
+        if for_loop {
+            self.scopes.pop();
+            let body = Box::new(Stmt::Block {
+                statements,
+                id: self.id(),
+            });
+            return vec![Stmt::For {
+                init: Box::new(def),
+                condition,
+                step: Box::new(increment),
+                body,
+                id: self.id(),
+            }];
+        }
+        statements.insert(0, increment);
+        let body = Box::new(Stmt::Block {
+            statements,
+            id: self.id(),
+        });
+        vec![
+            def,
+            Stmt::While {
+                condition,
+                body,
+                id: self.id(),
+            },
+        ]
+    }
+
+    /// a variable definition, sometimes shadowing a variable from an outer scope
+    fn var_def(&mut self) -> Stmt {
+        let dtype = self.rng.pick(&TYPES);
+        let value = self.expr(&dtype, 0);
+
+        let current: Vec<String> = self
+            .scopes
+            .last()
+            .unwrap()
+            .iter()
+            .map(|var| var.name.clone())
+            .collect();
+        let outer: Vec<String> = self
+            .visible()
+            .into_iter()
+            .filter(|var| var.assignable && !current.contains(&var.name))
+            .map(|var| var.name)
+            .collect();
+        let name = if !outer.is_empty() && self.rng.chance(20) {
+            outer[self.rng.below(outer.len())].clone()
+        } else {
+            self.fresh("v")
+        };
+        self.define(&name, dtype.clone(), true);
+
+        let maybe_type = if self.rng.chance(50) {
+            Some(dtype)
+        } else {
+            None
+        };
+        Stmt::VarDef {
+            name,
+            maybe_type,
+            maybe_value: Some(value),
+            id: self.id(),
+        }
+    }
+
+    fn const_def(&mut self) -> Stmt {
+        let dtype = self.rng.pick(&TYPES);
+        let value = self.expr(&dtype, 0);
+        let name = self.fresh("c");
+        self.define(&name, dtype.clone(), false);
+        let maybe_type = if self.rng.chance(50) {
+            Some(dtype)
+        } else {
+            None
+        };
+        Stmt::ConstDef {
+            name,
+            maybe_type,
+            value,
+            id: self.id(),
+        }
+    }
+
+    /// a function, which only calls the functions defined before it so that it cannot recurse
+    fn func_def(&mut self) -> Stmt {
+        let def_name = self.fresh("f");
+        let def_params: Vec<(String, Type)> = (0..self.rng.below(4))
+            .map(|i| (format!("p{i}"), self.rng.pick(&TYPES)))
+            .collect();
+        let return_type = self.rng.pick(&TYPES);
+
+        let (cost, multiplier) = (self.cost, self.multiplier);
+        self.cost = 0;
+        self.multiplier = 1;
+        self.return_type = Some(return_type.clone());
+
+        self.scopes.push(Vec::new());
+        for (name, dtype) in &def_params {
+            self.define(name, dtype.clone(), true);
+        }
+        let mut statements = Vec::new();
+        for _ in 0..=self.rng.below(5) {
+            statements.extend(self.stmt(0));
+        }
+        statements.push(self.return_stmt());
+        self.scopes.pop();
+
+        self.functions.push(Func {
+            name: def_name.clone(),
+            params: def_params.iter().map(|(_, dtype)| dtype.clone()).collect(),
+            return_type: return_type.clone(),
+            cost: self.cost.max(1),
+        });
+        self.cost = cost;
+        self.multiplier = multiplier;
+        self.return_type = None;
+
+        Stmt::FuncDef {
+            def_name,
+            def_params,
+            return_type,
+            body: Box::new(Stmt::Block {
+                statements,
+                id: self.id(),
+            }),
+            id: self.id(),
+        }
+    }
+}
+
+/// shrink a failing program, keeping smaller variants while `fails` holds for their source
+///
+/// statements and expressions are removed and simplified, relying on the typechecker to reject
+/// variants that are no longer valid. Loop conditions and counter updates are never touched, since
+/// that could make a loop run forever
+pub fn shrink(program: Vec<Stmt>, mut fails: impl FnMut(&str) -> bool) -> Vec<Stmt> {
+    let mut program = program;
+    'shrink: loop {
+        for candidate in list_variants(&program) {
+            if fails(&render(&candidate)) {
+                program = candidate;
+                continue 'shrink;
+            }
+        }
+        return program;
+    }
+}
+
+/// whether a statement increments a loop counter, which must be kept
+fn is_counter_update(stmt: &Stmt) -> bool {
+    matches!(stmt, Stmt::Assign { name, .. } if name.starts_with(COUNTER))
+}
+
+/// the statements of a block
+fn block_statements(stmt: &Stmt) -> Vec<Stmt> {
+    match stmt {
+        Stmt::Block { statements, .. } => statements.clone(),
+        _ => vec![stmt.clone()],
+    }
+}
+
+fn with_statements(block: &Stmt, statements: Vec<Stmt>) -> Stmt {
+    let id = match block {
+        Stmt::Block { id, .. } => *id,
+        _ => 0,
+    };
+    Stmt::Block { statements, id }
+}
+
+/// smaller variants of a list of statements
+fn list_variants(stmts: &[Stmt]) -> Vec<Vec<Stmt>> {
+    let mut variants = Vec::new();
+    for i in 0..stmts.len() {
+        if !is_counter_update(&stmts[i]) {
+            let mut variant = stmts.to_vec();
+            variant.remove(i);
+            variants.push(variant);
+        }
+    }
+    for i in 0..stmts.len() {
+        for replacement in stmt_variants(&stmts[i]) {
+            let mut variant = stmts[..i].to_vec();
+            variant.extend(replacement);
+            variant.extend_from_slice(&stmts[i + 1..]);
+            variants.push(variant);
+        }
+    }
+    variants
+}
+
+/// smaller variants of a statement, each of which may be several statements
+fn stmt_variants(stmt: &Stmt) -> Vec<Vec<Stmt>> {
+    match stmt {
+        Stmt::Print { value, id } => expr_variants(value)
+            .into_iter()
+            .map(|value| vec![Stmt::Print { value, id: *id }])
+            .collect(),
+        Stmt::Assert { value, text, id } => expr_variants(value)
+            .into_iter()
+            .map(|value| {
+                vec![Stmt::Assert {
+                    value,
+                    text: text.clone(),
+                    id: *id,
+                }]
+            })
+            .collect(),
+        Stmt::VarDef {
+            name,
+            maybe_type,
+            maybe_value: Some(value),
+            id,
+        } => expr_variants(value)
+            .into_iter()
+            .map(|value| {
+                vec![Stmt::VarDef {
+                    name: name.clone(),
+                    maybe_type: maybe_type.clone(),
+                    maybe_value: Some(value),
+                    id: *id,
+                }]
+            })
+            .collect(),
+        Stmt::ConstDef {
+            name,
+            maybe_type,
+            value,
+            id,
+        } => expr_variants(value)
+            .into_iter()
+            .map(|value| {
+                vec![Stmt::ConstDef {
+                    name: name.clone(),
+                    maybe_type: maybe_type.clone(),
+                    value,
+                    id: *id,
+                }]
+            })
+            .collect(),
+        Stmt::Assign {
+            name,
+            op,
+            value,
+            id,
+        } if !is_counter_update(stmt) => expr_variants(value)
+            .into_iter()
+            .map(|value| {
+                vec![Stmt::Assign {
+                    name: name.clone(),
+                    op: op.clone(),
+                    value,
+                    id: *id,
+                }]
+            })
+            .collect(),
+        Stmt::Return { value, id } => expr_variants(value)
+            .into_iter()
+            .map(|value| vec![Stmt::Return { value, id: *id }])
+            .collect(),
+        Stmt::Expr(e) => expr_variants(e)
+            .into_iter()
+            .map(|e| vec![Stmt::Expr(e)])
+            .collect(),
+        Stmt::If {
+            condition,
+            then_block,
+            maybe_else_block,
+            id,
+        } => {
+            let mut variants = vec![block_statements(then_block)];
+            if let Some(else_block) = maybe_else_block {
+                variants.push(block_statements(else_block));
+                variants.push(vec![Stmt::If {
+                    condition: condition.clone(),
+                    then_block: then_block.clone(),
+                    maybe_else_block: None,
+                    id: *id,
+                }]);
+                for statements in list_variants(&block_statements(else_block)) {
+                    variants.push(vec![Stmt::If {
+                        condition: condition.clone(),
+                        then_block: then_block.clone(),
+                        maybe_else_block: Some(Box::new(with_statements(else_block, statements))),
+                        id: *id,
+                    }]);
+                }
+            }
+            for statements in list_variants(&block_statements(then_block)) {
+                variants.push(vec![Stmt::If {
+                    condition: condition.clone(),
+                    then_block: Box::new(with_statements(then_block, statements)),
+                    maybe_else_block: maybe_else_block.clone(),
+                    id: *id,
+                }]);
+            }
+            for condition in expr_variants(condition) {
+                variants.push(vec![Stmt::If {
+                    condition,
+                    then_block: then_block.clone(),
+                    maybe_else_block: maybe_else_block.clone(),
+                    id: *id,
+                }]);
+            }
+            variants
+        }
+        Stmt::Match {
+            value,
+            arms,
+            maybe_default,
+            id,
+        } => {
+            // each arm on its own, then the match without each of its arms
+            let bodies = arms.iter().map(|(_, body)| body);
+            let mut variants: Vec<Vec<Stmt>> = bodies
+                .chain(maybe_default.as_deref())
+                .map(block_statements)
+                .collect();
+            for i in 0..arms.len() {
+                let mut fewer = arms.clone();
+                fewer.remove(i);
+                variants.push(vec![Stmt::Match {
+                    value: value.clone(),
+                    arms: fewer,
+                    maybe_default: maybe_default.clone(),
+                    id: *id,
+                }]);
+            }
+            if maybe_default.is_some() {
+                variants.push(vec![Stmt::Match {
+                    value: value.clone(),
+                    arms: arms.clone(),
+                    maybe_default: None,
+                    id: *id,
+                }]);
+            }
+            variants
+        }
+        Stmt::While {
+            condition,
+            body,
+            id,
+        } => {
+            let mut variants = vec![block_statements(body)];
+            for statements in list_variants(&block_statements(body)) {
+                variants.push(vec![Stmt::While {
+                    condition: condition.clone(),
+                    body: Box::new(with_statements(body, statements)),
+                    id: *id,
+                }]);
+            }
+            variants
+        }
+        Stmt::For {
+            init,
+            condition,
+            step,
+            body,
+            id,
+        } => {
+            let mut variants = Vec::new();
+            for statements in list_variants(&block_statements(body)) {
+                variants.push(vec![Stmt::For {
+                    init: init.clone(),
+                    condition: condition.clone(),
+                    step: step.clone(),
+                    body: Box::new(with_statements(body, statements)),
+                    id: *id,
+                }]);
+            }
+            variants
+        }
+        Stmt::FuncDef {
+            def_name,
+            def_params,
+            return_type,
+            body,
+            id,
+        } => list_variants(&block_statements(body))
+            .into_iter()
+            .map(|statements| {
+                vec![Stmt::FuncDef {
+                    def_name: def_name.clone(),
+                    def_params: def_params.clone(),
+                    return_type: return_type.clone(),
+                    body: Box::new(with_statements(body, statements)),
+                    id: *id,
+                }]
+            })
+            .collect(),
+        Stmt::Block { statements, id } => list_variants(statements)
+            .into_iter()
+            .map(|statements| {
+                vec![Stmt::Block {
+                    statements,
+                    id: *id,
+                }]
+            })
+            .collect(),
+        Stmt::VarDef { .. } | Stmt::Assign { .. } | Stmt::LoopControl { .. } => Vec::new(),
+    }
+}
+
+/// smaller variants of an expression, which may not have the same type
+fn expr_variants(e: &Expr) -> Vec<Expr> {
+    let mut variants = Vec::new();
+    if !matches!(e, Expr::Literal { .. }) {
+        for value in [
+            WabbitType::Int(0),
+            WabbitType::Float(0.0),
+            WabbitType::Bool(false),
+            WabbitType::Char('a'),
+        ] {
+            variants.push(Expr::Literal { value, id: 0 });
+        }
+    }
+
+    match e {
+        Expr::Binary { lhs, op, rhs, id } => {
+            variants.push(*lhs.clone());
+            variants.push(*rhs.clone());
+            for lhs in expr_variants(lhs) {
+                variants.push(Expr::Binary {
+                    lhs: Box::new(lhs),
+                    op: op.clone(),
+                    rhs: rhs.clone(),
+                    id: *id,
+                });
+            }
+            for rhs in expr_variants(rhs) {
+                variants.push(Expr::Binary {
+                    lhs: lhs.clone(),
+                    op: op.clone(),
+                    rhs: Box::new(rhs),
+                    id: *id,
+                });
+            }
+        }
+        Expr::Logical { lhs, op, rhs, id } => {
+            variants.push(*lhs.clone());
+            variants.push(*rhs.clone());
+            for lhs in expr_variants(lhs) {
+                variants.push(Expr::Logical {
+                    lhs: Box::new(lhs),
+                    op: op.clone(),
+                    rhs: rhs.clone(),
+                    id: *id,
+                });
+            }
+            for rhs in expr_variants(rhs) {
+                variants.push(Expr::Logical {
+                    lhs: lhs.clone(),
+                    op: op.clone(),
+                    rhs: Box::new(rhs),
+                    id: *id,
+                });
+            }
+        }
+        Expr::Unary { op, operand, id } => {
+            variants.push(*operand.clone());
+            for operand in expr_variants(operand) {
+                variants.push(Expr::Unary {
+                    op: op.clone(),
+                    operand: Box::new(operand),
+                    id: *id,
+                });
+            }
+        }
+        Expr::Grouping { e, id } => {
+            variants.push(*e.clone());
+            for e in expr_variants(e) {
+                variants.push(Expr::Grouping {
+                    e: Box::new(e),
+                    id: *id,
+                });
+            }
+        }
+        Expr::Call { name, params, id } => {
+            variants.extend(params.iter().cloned());
+            for (i, param) in params.iter().enumerate() {
+                for param in expr_variants(param) {
+                    let mut params = params.clone();
+                    params[i] = param;
+                    variants.push(Expr::Call {
+                        name: name.clone(),
+                        params,
+                        id: *id,
+                    });
+                }
+            }
+        }
+        Expr::TypeConversion { dtype, params, id } => {
+            variants.extend(params.iter().cloned());
+            for (i, param) in params.iter().enumerate() {
+                for param in expr_variants(param) {
+                    let mut params = params.clone();
+                    params[i] = param;
+                    variants.push(Expr::TypeConversion {
+                        dtype: dtype.clone(),
+                        params,
+                        id: *id,
+                    });
+                }
+            }
+        }
+        // function values are never generated
+        Expr::CallValue { .. }
+        | Expr::Function { .. }
+        | Expr::VarName { .. }
+        | Expr::TypeName { .. }
+        | Expr::Literal { .. } => (),
+    }
+    variants
+}
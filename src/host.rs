@@ -0,0 +1,273 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::BufRead;
+use std::time::Duration;
+
+use crate::error::Result;
+use crate::types::{Type, WabbitType};
+
+/// where `print` writes values
+pub trait Output {
+    fn print(&mut self, value: &WabbitType);
+}
+
+/// record values in order
+impl Output for Vec<WabbitType> {
+    fn print(&mut self, value: &WabbitType) {
+        self.push(value.clone());
+    }
+}
+
+/// record the printed text, as it would appear on stdout
+impl Output for String {
+    fn print(&mut self, value: &WabbitType) {
+        self.push_str(&value.output());
+    }
+}
+
+impl<T: Output + ?Sized> Output for &mut T {
+    fn print(&mut self, value: &WabbitType) {
+        (**self).print(value);
+    }
+}
+
+/// print to stdout, as the command line interpreter does
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stdout;
+
+impl Output for Stdout {
+    fn print(&mut self, value: &WabbitType) {
+        print!("{}", value.output());
+    }
+}
+
+/// pass each value to a function
+pub struct Callback<F>(pub F);
+
+impl<F: FnMut(&WabbitType)> Output for Callback<F> {
+    fn print(&mut self, value: &WabbitType) {
+        (self.0)(value);
+    }
+}
+
+/// where `readint()`, `readfloat()` and `readchar()` read values
+pub trait Input {
+    /// read a value of type `dtype`, or `None` if the input is exhausted or invalid
+    fn read(&mut self, dtype: Type) -> Option<WabbitType>;
+}
+
+impl<T: Input + ?Sized> Input for &mut T {
+    fn read(&mut self, dtype: Type) -> Option<WabbitType> {
+        (**self).read(dtype)
+    }
+}
+
+/// read values from text, as `scanf` does in compiled programs
+///
+/// ints and floats skip leading whitespace and read as much of a number as they can, while chars
+/// are single bytes, including whitespace
+#[derive(Debug, Clone)]
+pub struct Reader<R>(pub R);
+
+impl Reader<std::io::StdinLock<'static>> {
+    pub fn stdin() -> Self {
+        Self(std::io::stdin().lock())
+    }
+}
+
+impl<'a> From<&'a str> for Reader<&'a [u8]> {
+    fn from(input: &'a str) -> Self {
+        Self(input.as_bytes())
+    }
+}
+
+impl<R: BufRead> Reader<R> {
+    fn peek(&mut self) -> Option<u8> {
+        self.0.fill_buf().ok()?.first().copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = self.peek()?;
+        self.0.consume(1);
+        Some(byte)
+    }
+
+    /// append the next byte to `text` if it is accepted
+    fn accept(&mut self, text: &mut String, accepted: impl Fn(u8) -> bool) -> bool {
+        match self.peek() {
+            Some(byte) if accepted(byte) => {
+                text.push(char::from(byte));
+                self.0.consume(1);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// append a run of digits to `text`, returning whether there were any
+    fn digits(&mut self, text: &mut String) -> bool {
+        let start = text.len();
+        while self.accept(text, |byte| byte.is_ascii_digit()) {}
+        text.len() > start
+    }
+
+    /// the text of a number, without leading whitespace
+    fn number(&mut self, float: bool) -> Option<String> {
+        while self.peek()?.is_ascii_whitespace() {
+            self.0.consume(1);
+        }
+        let mut text = String::new();
+        self.accept(&mut text, |byte| byte == b'+' || byte == b'-');
+        let mut valid = self.digits(&mut text);
+        if float {
+            if self.accept(&mut text, |byte| byte == b'.') {
+                valid |= self.digits(&mut text);
+            }
+            if valid && self.accept(&mut text, |byte| byte == b'e' || byte == b'E') {
+                self.accept(&mut text, |byte| byte == b'+' || byte == b'-');
+                valid = self.digits(&mut text);
+            }
+        }
+        valid.then_some(text)
+    }
+}
+
+impl<R: BufRead> Input for Reader<R> {
+    fn read(&mut self, dtype: Type) -> Option<WabbitType> {
+        match dtype {
+            Type::Int => self.number(false)?.parse().ok().map(WabbitType::Int),
+            Type::Float => self.number(true)?.parse().ok().map(WabbitType::Float),
+            Type::Char => self.next().map(|byte| WabbitType::Char(char::from(byte))),
+            // only read by the builtins `readint()`, `readfloat()` and `readchar()`
+            _ => None,
+        }
+    }
+}
+
+/// limits on what a running program may use, each unlimited if `None`, as by default
+///
+/// exceeding a limit is a runtime error at the loop or call that was about to run. The interpreter
+/// recurses on the Rust stack, so without a call depth deep recursion can overflow it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Limits {
+    /// how many statements may be executed, checked at each loop iteration and call
+    pub fuel: Option<u64>,
+    /// how deeply function calls may be nested
+    pub call_depth: Option<usize>,
+    /// how long the program may run, checked with the fuel
+    ///
+    /// this needs `std::time::Instant`, which is not available in WebAssembly
+    pub timeout: Option<Duration>,
+}
+
+/// how a running program interacts with the application embedding it
+#[derive(Debug, Clone)]
+pub struct Host<O = Vec<WabbitType>, I = Reader<&'static [u8]>> {
+    /// where `print` writes values
+    pub output: O,
+    /// where values are read from
+    pub input: I,
+    /// what the program may use while running
+    pub limits: Limits,
+}
+
+/// record printed values in a `Vec`, with no input
+impl Default for Host {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+impl<O: Output> Host<O> {
+    /// a host with no input and the default limits
+    pub fn new(output: O) -> Self {
+        Self {
+            output,
+            input: Reader(&[]),
+            limits: Limits::default(),
+        }
+    }
+}
+
+impl<O: Output, I: Input> Host<O, I> {
+    /// read values from `input` instead
+    pub fn with_input<J: Input>(self, input: J) -> Host<O, J> {
+        Host {
+            output: self.output,
+            input,
+            limits: self.limits,
+        }
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+}
+
+/// a Rust function callable from Wabbit, given arguments that match its signature
+///
+/// errors are reported at the call that failed
+pub type NativeFn = fn(&[WabbitType]) -> Result<WabbitType>;
+
+/// a native function and its signature
+///
+/// it is called from Wabbit like any other function. The typechecker checks calls against the
+/// signature and the interpreter calls the Rust function directly, while the LLVM backend declares
+/// it as an external function under its own name, to be linked from an object file provided by
+/// the application (see [`Toolchain::link`](crate::native::Toolchain::link))
+#[derive(Clone)]
+pub struct Native {
+    pub params: Vec<Type>,
+    pub return_type: Type,
+    pub func: NativeFn,
+}
+
+// `NativeFn` only implements `Debug` for each lifetime of its argument, so it is left out
+impl fmt::Debug for Native {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Native")
+            .field("params", &self.params)
+            .field("return_type", &self.return_type)
+            .finish_non_exhaustive()
+    }
+}
+
+/// the native functions available to a program, by name
+#[derive(Debug, Clone, Default)]
+pub struct Natives(BTreeMap<String, Native>);
+
+/// no native functions, used unless others are given
+pub static NO_NATIVES: Natives = Natives::new();
+
+impl Natives {
+    pub const fn new() -> Self {
+        Self(BTreeMap::new())
+    }
+
+    /// add a native function, replacing any with the same name
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        params: &[Type],
+        return_type: Type,
+        func: NativeFn,
+    ) -> &mut Self {
+        let native = Native {
+            params: params.to_vec(),
+            return_type,
+            func,
+        };
+        self.0.insert(name.into(), native);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Native> {
+        self.0.get(name)
+    }
+
+    /// native functions in order of name
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Native)> {
+        self.0.iter()
+    }
+}
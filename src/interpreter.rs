@@ -1,13 +1,83 @@
 use crate::analyzer::Analyzer;
 use crate::ast::{Expr, Stmt};
+use crate::coverage::Coverage;
+use crate::debugger::{Debugger, Frontend};
 use crate::environment::VarStore;
-use crate::error::{msg, Msg, RangeReporter, Result};
-use crate::operators::{BinaryOp, LogicalOp, LoopControl, UnaryOp};
-use crate::types::{compare, equality, numeric_binary, numeric_unary, Type, WabbitType};
+use crate::error::{err, msg, Msg, RangeReporter, Result};
+use crate::host::{Input, Limits, Output, Reader, Stdout};
+use crate::operators::{BinaryOp, Builtin, LogicalOp, LoopControl, UnaryOp};
+use crate::parser::Parser;
+use crate::profile::Profile;
+use crate::trace::Trace;
+use crate::types::{
+    checked_int, compare, equality, int_binary, numeric_binary, Closure, Type, WabbitType, INTEGER,
+    NUMERIC, ORDERED, SIGNED,
+};
 use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Instant;
 
 /// struct for interpreting Wabbit AST
-pub type Interpreter<'a> = Analyzer<'a, WabbitType>;
+pub struct Interpreter<'a> {
+    /// variables, constants and functions, and the values printed
+    pub analyze: Analyzer<'a, WabbitType>,
+    /// what function values run, by the id of the function expression or named function
+    pub(crate) definitions: HashMap<usize, Rc<Definition<'a>>>,
+    /// where print statements write, or stdout if `None`
+    pub(crate) output: Option<&'a mut dyn Output>,
+    /// where built-in functions read values, or stdin if `None`
+    pub(crate) input: Option<&'a mut dyn Input>,
+    /// limits on running a program
+    pub(crate) limits: Limits,
+    /// number of statements executed
+    pub(crate) steps: u64,
+    /// when the program started running, if it has a timeout
+    pub(crate) started: Option<Instant>,
+    /// pauses the program to inspect it, if attached
+    pub(crate) debugger: Option<&'a mut Debugger<dyn Frontend + 'a>>,
+    /// records the statements and branches that run, if attached
+    pub(crate) coverage: Option<&'a mut Coverage>,
+    /// counts the statements run by each function and loop, if attached
+    pub(crate) profile: Option<&'a mut Profile>,
+    /// writes each statement as it runs, if attached
+    pub(crate) trace: Option<&'a mut Trace<dyn Write + 'a>>,
+    /// the functions being called and the ids of their calls, outermost first
+    pub(crate) calls: Vec<(&'a str, usize)>,
+}
+
+/// what a function value runs: a function expression, or a named function
+pub(crate) struct Definition<'a> {
+    /// the function's name, or `None` for a function expression
+    pub(crate) name: Option<&'a String>,
+    pub(crate) params: &'a [(String, Type)],
+    pub(crate) return_type: Type,
+    pub(crate) body: &'a Stmt,
+    /// the variables it uses from the enclosing function, whose values each closure holds
+    pub(crate) captured: Vec<&'a String>,
+    /// the id of the definition, for errors
+    pub(crate) id: usize,
+}
+
+// the attached sinks and tools are trait objects without `Debug`, so only the state is shown
+impl fmt::Debug for Interpreter<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Interpreter")
+            .field("analyze", &self.analyze)
+            .field("limits", &self.limits)
+            .field("steps", &self.steps)
+            .field("calls", &self.calls)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a> From<&'a Parser<'a>> for Interpreter<'a> {
+    fn from(parser: &'a Parser) -> Self {
+        Self::new(parser.borrow_statements(), parser.borrow_ranges())
+    }
+}
 
 #[derive(Debug)]
 pub enum Signal {
@@ -18,74 +88,307 @@ pub enum Signal {
 }
 
 impl<'a> Interpreter<'a> {
+    pub fn new(statements: &'a Vec<Stmt>, ranges: &'a HashMap<usize, (usize, usize)>) -> Self {
+        Self {
+            analyze: Analyzer::new(statements, ranges),
+            definitions: HashMap::new(),
+            output: None,
+            input: None,
+            limits: Limits::default(),
+            steps: 0,
+            started: None,
+            debugger: None,
+            coverage: None,
+            profile: None,
+            trace: None,
+            calls: Vec::new(),
+        }
+    }
+
+    /// write printed values to `output` instead of stdout
+    pub fn with_output(mut self, output: &'a mut dyn Output) -> Self {
+        self.output = Some(output);
+        self
+    }
+
+    /// read values for built-in functions from `input` instead of stdin
+    pub fn with_input(mut self, input: &'a mut dyn Input) -> Self {
+        self.input = Some(input);
+        self
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// pause at breakpoints and steps, see [`Debugger`]
+    pub fn with_debugger(mut self, debugger: &'a mut Debugger<dyn Frontend + 'a>) -> Self {
+        self.debugger = Some(debugger);
+        self
+    }
+
+    /// record the statements and branches that run in `coverage`
+    pub fn with_coverage(mut self, coverage: &'a mut Coverage) -> Self {
+        self.coverage = Some(coverage);
+        self
+    }
+
+    /// count the statements run by each function and loop in `profile`
+    pub fn with_profile(mut self, profile: &'a mut Profile) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+
+    /// write each statement to `trace` as it runs
+    pub fn with_trace(mut self, trace: &'a mut Trace<dyn Write + 'a>) -> Self {
+        self.trace = Some(trace);
+        self
+    }
+
+    /// let an attached debugger pause before `stmt` runs
+    fn debug_statement(&mut self, stmt: &'a Stmt) -> Result<()> {
+        match self.debugger.take() {
+            Some(debugger) => {
+                let result = debugger.statement(self, stmt);
+                self.debugger = Some(debugger);
+                result
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// write a variable's new value to an attached trace
+    fn trace_assign(&mut self, name: &str, value: &WabbitType) {
+        if let Some(trace) = self.trace.as_deref_mut() {
+            trace.assign(name, value, self.analyze.call_depth);
+        }
+    }
+
+    /// tell an attached profile that a loop has ended
+    fn exit_loop(&mut self) {
+        if let Some(profile) = self.profile.as_deref_mut() {
+            profile.exit_loop(self.steps);
+        }
+    }
+
+    /// check the fuel and timeout before a loop iteration or call, reporting errors at `id`
+    fn check_limits(&self, id: usize) -> Result<()> {
+        match (self.limits.fuel, self.limits.timeout, self.started) {
+            (Some(fuel), ..) if self.steps > fuel => {
+                msg!(Msg::OutOfFuel, (self.analyze.ranges, id), fuel)
+            }
+            (_, Some(timeout), Some(started)) if started.elapsed() >= timeout => {
+                msg!(
+                    Msg::Timeout,
+                    (self.analyze.ranges, id),
+                    format!("{timeout:?}")
+                )
+            }
+            _ => Ok(()),
+        }
+    }
+
     /// interpret an expression and confirm it is a boolean at runtime
-    fn typecheck_bool(&mut self, e: &Expr, id: usize) -> Result<bool> {
+    fn typecheck_bool(&mut self, e: &'a Expr, id: usize) -> Result<bool> {
         let eval = self.evaluate(e)?;
         match eval {
             WabbitType::Bool(val) => Ok(val),
             _ => {
-                msg!(Msg::ExpectType, (self.ranges, id), "bool")
+                msg!(Msg::ExpectType, (self.analyze.ranges, id), "bool")
             }
         }
     }
 
+    /// evaluate the condition of an `if`, `while` or `for`, recording which way it went for
+    /// coverage
+    fn branch(&mut self, condition: &'a Expr, id: usize) -> Result<bool> {
+        let taken = self.typecheck_bool(condition, id)?;
+        if let Some(coverage) = self.coverage.as_deref_mut() {
+            coverage.branch(id, taken);
+        }
+        Ok(taken)
+    }
+
     /// interpret all statements
     pub fn interpret(&mut self) -> Result<()> {
-        for statement in self.statements {
-            self.run_stmt(statement)?;
+        self.steps = 0;
+        self.calls.clear();
+        self.started = self.limits.timeout.map(|_| Instant::now());
+        if let Some(profile) = self.profile.as_deref_mut() {
+            profile.start();
+        }
+        let statements = self.analyze.statements;
+        let result = statements
+            .iter()
+            .try_for_each(|statement| self.run_stmt(statement).map(|_| ()));
+        if let Some(profile) = self.profile.as_deref_mut() {
+            profile.finish(self.steps);
+        }
+        result
+    }
+
+    /// the value of a variable or constant, which must be initialized
+    fn variable(&self, name: &String, id: usize) -> Result<WabbitType> {
+        if let Some(constant) = self.analyze.constants.get(name) {
+            Ok(constant.clone())
+        } else if let Some(VarStore::Init(var) | VarStore::Captured(var)) =
+            self.analyze.env.get(name)
+        {
+            Ok(var)
+        } else if let Some(VarStore::UnInit(_)) = self.analyze.env.get(name) {
+            msg!(Msg::AccessUninit, (self.analyze.ranges, id), name)
+        } else {
+            msg!(Msg::VarUndefined, (self.analyze.ranges, id))
+        }
+    }
+
+    /// shared by binary expressions and compound assignment
+    fn binary(
+        &self,
+        eval_lhs: WabbitType,
+        op: &BinaryOp,
+        eval_rhs: WabbitType,
+        id: usize,
+    ) -> Result<WabbitType> {
+        if !(eval_lhs.dtype() == eval_rhs.dtype()) {
+            return msg!(Msg::TypeMatch, (self.analyze.ranges, id));
+        }
+        match op {
+            BinaryOp::Plus => {
+                numeric_binary!(eval_lhs, eval_rhs, (self.analyze.ranges, id), +, checked_add)
+            }
+            BinaryOp::Minus => {
+                numeric_binary!(eval_lhs, eval_rhs, (self.analyze.ranges, id), -, checked_sub)
+            }
+            BinaryOp::Times => {
+                numeric_binary!(eval_lhs, eval_rhs, (self.analyze.ranges, id), *, checked_mul)
+            }
+            BinaryOp::Divide => {
+                numeric_binary!(eval_lhs, eval_rhs, (self.analyze.ranges, id), /, checked_div)
+            }
+            // unlike the other arithmetic, only defined on integers
+            BinaryOp::Modulo if eval_lhs.dtype().is_integer() => {
+                numeric_binary!(eval_lhs, eval_rhs, (self.analyze.ranges, id), %, checked_rem)
+            }
+            BinaryOp::Modulo => msg!(Msg::ExpectType, (self.analyze.ranges, id), INTEGER),
+            BinaryOp::BitAnd => int_binary!(eval_lhs, eval_rhs, (self.analyze.ranges, id), &),
+            BinaryOp::BitOr => int_binary!(eval_lhs, eval_rhs, (self.analyze.ranges, id), |),
+            BinaryOp::BitXor => int_binary!(eval_lhs, eval_rhs, (self.analyze.ranges, id), ^),
+            BinaryOp::ShiftLeft | BinaryOp::ShiftRight => {
+                // bits shifted out on the left are dropped, and `>>` keeps the sign
+                let amount = match eval_rhs {
+                    WabbitType::Int(b) => i64::from(b),
+                    WabbitType::Int64(b) => b,
+                    WabbitType::Uint8(b) => i64::from(b),
+                    _ => return msg!(Msg::ExpectType, (self.analyze.ranges, id), INTEGER),
+                };
+                let bits = eval_lhs.dtype().bits();
+                if !(0..i64::from(bits)).contains(&amount) {
+                    return msg!(
+                        Msg::ShiftAmount,
+                        (self.analyze.ranges, id),
+                        amount,
+                        bits - 1
+                    );
+                }
+                let left = *op == BinaryOp::ShiftLeft;
+                let amount = amount as u32;
+                Ok(match eval_lhs {
+                    WabbitType::Int(a) if left => WabbitType::Int(a << amount),
+                    WabbitType::Int(a) => WabbitType::Int(a >> amount),
+                    WabbitType::Int64(a) if left => WabbitType::Int64(a << amount),
+                    WabbitType::Int64(a) => WabbitType::Int64(a >> amount),
+                    WabbitType::Uint8(a) if left => WabbitType::Uint8(a << amount),
+                    WabbitType::Uint8(a) => WabbitType::Uint8(a >> amount),
+                    _ => return msg!(Msg::ExpectType, (self.analyze.ranges, id), INTEGER),
+                })
+            }
+            BinaryOp::Less => compare!(eval_lhs, eval_rhs, (self.analyze.ranges, id), <),
+            BinaryOp::LessEqual => compare!(eval_lhs, eval_rhs, (self.analyze.ranges, id), <=),
+            BinaryOp::Greater => compare!(eval_lhs, eval_rhs, (self.analyze.ranges, id), >),
+            BinaryOp::GreaterEqual => compare!(eval_lhs, eval_rhs, (self.analyze.ranges, id), >=),
+            BinaryOp::EqualEqual => equality!(eval_lhs, eval_rhs, (self.analyze.ranges, id), ==),
+            BinaryOp::NotEqual => equality!(eval_lhs, eval_rhs, (self.analyze.ranges, id), !=),
         }
-        Ok(())
     }
 
     /// interpret a single statement
     fn run_stmt(&mut self, stmt: &'a Stmt) -> Result<Signal> {
+        self.steps += 1;
+        if let Some(coverage) = self.coverage.as_deref_mut() {
+            coverage.statement(stmt.id());
+        }
+        if !matches!(stmt, Stmt::Block { .. }) {
+            if let Some(trace) = self.trace.as_deref_mut() {
+                trace.statement(self.analyze.ranges[&stmt.id()], self.analyze.call_depth);
+            }
+            self.debug_statement(stmt)?;
+        }
         match stmt {
             Stmt::FuncDef {
                 ref def_name, id, ..
             } => {
-                if !self.env.in_global_scope() {
-                    msg!(Msg::FuncDefScope, (self.ranges, *id))
+                if !self.analyze.env.in_global_scope() {
+                    msg!(Msg::FuncDefScope, (self.analyze.ranges, *id))
                 } else {
-                    self.check_constant(def_name, *id)?;
-                    self.check_env(def_name, *id)?;
-                    self.check_function(def_name, *id)?;
-                    self.functions.insert(def_name, stmt);
+                    self.analyze.check_constant(def_name, *id)?;
+                    self.analyze.check_env(def_name, *id)?;
+                    self.analyze.check_function(def_name, *id)?;
+                    self.analyze.functions.insert(def_name, stmt);
                     Ok(Signal::Unit)
                 }
             }
             Stmt::Return { value, id } => {
                 let value = self.evaluate(value)?;
-                if self.call_depth > 0 {
+                if self.analyze.call_depth > 0 {
                     Ok(Signal::Return(value))
                 } else {
-                    msg!(Msg::ReturnScope, (self.ranges, *id))
+                    msg!(Msg::ReturnScope, (self.analyze.ranges, *id))
                 }
             }
             Stmt::Expr(e) => {
                 self.evaluate(e)?;
                 Ok(Signal::Unit)
             }
-            Stmt::Assign { name, value, id } => {
-                self.check_constant(name, *id)?;
-                self.check_function(name, *id)?;
+            Stmt::Assign {
+                name,
+                op,
+                value,
+                id,
+            } => {
+                self.analyze.check_constant(name, *id)?;
+                self.analyze.check_function(name, *id)?;
 
-                let e = self.evaluate(value)?;
+                let e = match op {
+                    // the variable is read before the value, as in `name = name op value`
+                    Some(op) => {
+                        let current = self.variable(name, *id)?;
+                        let rhs = self.evaluate(value)?;
+                        self.binary(current, op, rhs, *id)?
+                    }
+                    None => self.evaluate(value)?,
+                };
 
-                match self.env.get(name) {
+                match self.analyze.env.get(name) {
                     Some(VarStore::Init(value) | VarStore::UnInit(value)) => {
                         if value.dtype() == e.dtype() {
-                            self.env.assign(name, e);
+                            self.trace_assign(name, &e);
+                            self.analyze.env.assign(name, e);
                         } else {
                             return msg!(
                                 Msg::AssignRetype,
-                                (self.ranges, *id),
+                                (self.analyze.ranges, *id),
                                 name,
                                 value.dtype(),
                                 e.dtype()
                             );
                         }
                     }
-                    None => return msg!(Msg::AssignUndefined, (self.ranges, *id)),
+                    Some(VarStore::Captured(_)) => {
+                        return msg!(Msg::AssignCapture, (self.analyze.ranges, *id), name);
+                    }
+                    None => return msg!(Msg::AssignUndefined, (self.analyze.ranges, *id)),
                 };
                 Ok(Signal::Unit)
             }
@@ -94,29 +397,77 @@ impl<'a> Interpreter<'a> {
                 body,
                 id,
             } => {
-                while self.typecheck_bool(condition, *id)? {
-                    self.loop_depth += 1;
-                    self.env.enter_child();
+                if let Some(profile) = self.profile.as_deref_mut() {
+                    profile.enter_loop(*id, "while", self.analyze.ranges[id].0, self.steps);
+                }
+                while self.branch(condition, *id)? {
+                    self.check_limits(*id)?;
+                    if let Some(profile) = self.profile.as_deref_mut() {
+                        profile.iteration(*id);
+                    }
+                    self.analyze.loop_depth += 1;
+                    self.analyze.env.enter_child();
                     let signal = self.run_stmt(body)?;
                     match signal {
-                        Signal::Unit => (),
+                        Signal::Unit | Signal::Continue => (),
                         Signal::Return(_) => {
-                            self.env.exit_child(&(self.ranges, *id))?;
-                            self.loop_depth -= 1;
+                            self.analyze.env.exit_child(&(self.analyze.ranges, *id))?;
+                            self.analyze.loop_depth -= 1;
+                            self.exit_loop();
                             return Ok(signal);
                         }
                         Signal::Break => {
-                            self.env.exit_child(&(self.ranges, *id))?;
-                            self.loop_depth -= 1;
+                            self.analyze.env.exit_child(&(self.analyze.ranges, *id))?;
+                            self.analyze.loop_depth -= 1;
                             break;
                         }
-                        Signal::Continue => {
-                            continue;
+                    }
+                    self.analyze.loop_depth -= 1;
+                    self.analyze.env.exit_child(&(self.analyze.ranges, *id))?;
+                    // pause again before the condition is checked
+                    self.debug_statement(stmt)?;
+                }
+                self.exit_loop();
+                Ok(Signal::Unit)
+            }
+            Stmt::For {
+                init,
+                condition,
+                step,
+                body,
+                id,
+            } => {
+                if let Some(profile) = self.profile.as_deref_mut() {
+                    profile.enter_loop(*id, "for", self.analyze.ranges[id].0, self.steps);
+                }
+                self.analyze.env.enter_child();
+                self.run_stmt(init)?;
+                while self.branch(condition, *id)? {
+                    self.check_limits(*id)?;
+                    if let Some(profile) = self.profile.as_deref_mut() {
+                        profile.iteration(*id);
+                    }
+                    self.analyze.loop_depth += 1;
+                    self.analyze.env.enter_child();
+                    let signal = self.run_stmt(body)?;
+                    self.analyze.env.exit_child(&(self.analyze.ranges, *id))?;
+                    self.analyze.loop_depth -= 1;
+                    match signal {
+                        Signal::Unit | Signal::Continue => (),
+                        Signal::Return(_) => {
+                            self.analyze.env.exit_child(&(self.analyze.ranges, *id))?;
+                            self.exit_loop();
+                            return Ok(signal);
                         }
+                        Signal::Break => break,
                     }
-                    self.loop_depth -= 1;
-                    self.env.exit_child(&(self.ranges, *id))?;
+                    // `continue` still runs the step
+                    self.run_stmt(step)?;
+                    // pause again before the condition is checked
+                    self.debug_statement(stmt)?;
                 }
+                self.analyze.env.exit_child(&(self.analyze.ranges, *id))?;
+                self.exit_loop();
                 Ok(Signal::Unit)
             }
             Stmt::ConstDef {
@@ -125,19 +476,20 @@ impl<'a> Interpreter<'a> {
                 value,
                 id,
             } => {
-                self.check_constant(name, *id)?;
-                self.check_env(name, *id)?;
-                self.check_function(name, *id)?;
+                self.analyze.check_constant(name, *id)?;
+                self.analyze.check_env(name, *id)?;
+                self.analyze.check_function(name, *id)?;
 
-                if !self.env.in_global_scope() {
-                    msg!(Msg::ConstScope, (self.ranges, *id))
+                if !self.analyze.env.in_global_scope() {
+                    msg!(Msg::ConstScope, (self.analyze.ranges, *id))
                 } else {
                     let value = self.evaluate(value)?;
-                    if maybe_type.is_none() || (*maybe_type).unwrap() == value.dtype() {
-                        self.constants.insert(name, value);
+                    if maybe_type.is_none() || maybe_type.as_ref() == Some(&value.dtype()) {
+                        self.trace_assign(name, &value);
+                        self.analyze.constants.insert(name, value);
                         Ok(Signal::Unit)
                     } else {
-                        msg!(Msg::InitType, (self.ranges, *id))
+                        msg!(Msg::InitType, (self.analyze.ranges, *id))
                     }
                 }
             }
@@ -147,9 +499,9 @@ impl<'a> Interpreter<'a> {
                 maybe_value,
                 id,
             } => {
-                self.check_constant(name, *id)?;
-                self.check_env(name, *id)?;
-                self.check_function(name, *id)?;
+                self.analyze.check_constant(name, *id)?;
+                self.analyze.check_env(name, *id)?;
+                self.analyze.check_function(name, *id)?;
 
                 match (maybe_type, maybe_value) {
                     (Some(typename), Some(value)) => {
@@ -157,23 +509,26 @@ impl<'a> Interpreter<'a> {
                         let value = self.evaluate(value)?;
 
                         if typename == &value.dtype() {
-                            self.env.define_init(name, value);
+                            self.trace_assign(name, &value);
+                            self.analyze.env.define_init(name, value);
                         } else {
-                            return msg!(Msg::InitType, (self.ranges, *id));
+                            return msg!(Msg::InitType, (self.analyze.ranges, *id));
                         }
                     }
                     (None, Some(value)) => {
                         let value = self.evaluate(value)?;
-                        self.env.define_init(name, value);
+                        self.trace_assign(name, &value);
+                        self.analyze.env.define_init(name, value);
                     }
                     (Some(typename), None) => {
-                        self.env
-                            .define_uninit(name, WabbitType::TypeHolder(*typename));
+                        self.analyze
+                            .env
+                            .define_uninit(name, WabbitType::TypeHolder(typename.clone()));
                     }
                     (None, None) => {
                         return msg!(
                             Msg::InternalErr,
-                            (self.ranges, *id),
+                            (self.analyze.ranges, *id),
                             "Parser allowed variable definition without type or initial value"
                         );
                     }
@@ -186,9 +541,9 @@ impl<'a> Interpreter<'a> {
                 maybe_else_block,
                 id,
             } => {
-                let condition = self.typecheck_bool(condition, *id)?;
+                let condition = self.branch(condition, *id)?;
 
-                self.env.enter_child();
+                self.analyze.env.enter_child();
 
                 let signal = if condition {
                     self.run_stmt(then_block)?
@@ -198,7 +553,35 @@ impl<'a> Interpreter<'a> {
                     Signal::Unit
                 };
 
-                self.env.exit_child(&(self.ranges, *id))?;
+                self.analyze.env.exit_child(&(self.analyze.ranges, *id))?;
+                Ok(signal)
+            }
+            Stmt::Match {
+                value,
+                arms,
+                maybe_default,
+                id,
+            } => {
+                let value = self.evaluate(value)?;
+                let mut maybe_body = maybe_default.as_deref();
+                let mut taken = arms.len();
+                for (i, (pattern, body)) in arms.iter().enumerate() {
+                    if self.evaluate(pattern)? == value {
+                        maybe_body = Some(body);
+                        taken = i;
+                        break;
+                    }
+                }
+                if let Some(coverage) = self.coverage.as_deref_mut() {
+                    coverage.outcome(*id, arms.len() + 1, taken);
+                }
+
+                self.analyze.env.enter_child();
+                let signal = match maybe_body {
+                    Some(body) => self.run_stmt(body)?,
+                    None => Signal::Unit,
+                };
+                self.analyze.env.exit_child(&(self.analyze.ranges, *id))?;
                 Ok(signal)
             }
             Stmt::Block { statements, .. } => {
@@ -212,52 +595,225 @@ impl<'a> Interpreter<'a> {
                 Ok(Signal::Unit)
             }
             Stmt::LoopControl { control, id } => {
-                if self.loop_depth > 0 {
+                if self.analyze.loop_depth > 0 {
                     match control {
                         LoopControl::Break => Ok(Signal::Break),
                         LoopControl::Continue => Ok(Signal::Continue),
                     }
                 } else {
-                    msg!(Msg::LoopReq, (self.ranges, *id))
+                    msg!(Msg::LoopReq, (self.analyze.ranges, *id))
+                }
+            }
+            Stmt::Assert { value, text, id } => {
+                if self.typecheck_bool(value, *id)? {
+                    Ok(Signal::Unit)
+                } else {
+                    msg!(Msg::AssertFailed, (self.analyze.ranges, *id), text)
                 }
             }
-            Stmt::Print { value, .. } => {
+            Stmt::Print { value, id } => {
                 let value = self.evaluate(value)?;
-                match value {
-                    WabbitType::Char(_) => print!("{value}"),
-                    _ => println!("{value}"),
-                };
-                self.output.push(value);
+                if let WabbitType::Func(..) = value {
+                    return msg!(
+                        Msg::ExpectType,
+                        (self.analyze.ranges, *id),
+                        format!("{ORDERED}, bool")
+                    );
+                }
+                match &mut self.output {
+                    Some(output) => output.print(&value),
+                    None => Stdout.print(&value),
+                }
+                self.analyze.output.push(value);
                 Ok(Signal::Unit)
             }
         }
     }
 
+    /// a named function, as a definition without captured variables
+    fn named_definition(&mut self, def: &'a Stmt) -> Result<Rc<Definition<'a>>> {
+        let Stmt::FuncDef {
+            def_name,
+            def_params,
+            return_type,
+            box body,
+            id,
+        } = def
+        else {
+            return msg!(
+                Msg::InternalErr,
+                (self.analyze.ranges, def.id()),
+                "Interpreter stored a function that is not a definition"
+            );
+        };
+        Ok(self.definition(*id, || Definition {
+            name: Some(def_name),
+            params: def_params,
+            return_type: return_type.clone(),
+            body,
+            captured: Vec::new(),
+            id: *id,
+        }))
+    }
+
+    /// the definition with an id, made the first time it is used
+    fn definition(
+        &mut self,
+        id: usize,
+        make: impl FnOnce() -> Definition<'a>,
+    ) -> Rc<Definition<'a>> {
+        Rc::clone(
+            self.definitions
+                .entry(id)
+                .or_insert_with(|| Rc::new(make())),
+        )
+    }
+
+    /// a function value that runs a definition with the values of its captured variables
+    fn function_value(definition: &Definition<'a>, captures: Vec<WabbitType>) -> WabbitType {
+        let param_types = definition.params.iter().map(|(_, t)| t.clone()).collect();
+        let Type::Func(sig) = Type::func(param_types, definition.return_type.clone()) else {
+            unreachable!("function types are `Type::Func`")
+        };
+        let closure = Closure {
+            def: definition.id,
+            captures,
+        };
+        WabbitType::Func(sig, Arc::new(closure))
+    }
+
+    /// call a function value, which is `name` in the call stack unless it is a named function
+    fn call_value(
+        &mut self,
+        callee: WabbitType,
+        name: &'a str,
+        call_params: &'a [Expr],
+        call_id: usize,
+    ) -> Result<WabbitType> {
+        let WabbitType::Func(_, closure) = callee else {
+            return msg!(
+                Msg::NotCallable,
+                (self.analyze.ranges, call_id),
+                callee.dtype()
+            );
+        };
+        let definition = Rc::clone(&self.definitions[&closure.def]);
+        let name = definition.name.map_or(name, String::as_str);
+        self.call(&definition, &closure.captures, name, call_params, call_id)
+    }
+
+    /// call a function, with errors in the arguments and return value reported at its definition
+    fn call(
+        &mut self,
+        definition: &Definition<'a>,
+        captures: &[WabbitType],
+        name: &'a str,
+        call_params: &'a [Expr],
+        call_id: usize,
+    ) -> Result<WabbitType> {
+        let Definition {
+            params: def_params,
+            return_type,
+            body,
+            captured,
+            id,
+            ..
+        } = definition;
+
+        // first check airty
+        let call_airty = call_params.len();
+        let def_airty = def_params.len();
+
+        if def_airty != call_airty {
+            return msg!(
+                Msg::FuncAirty,
+                (self.analyze.ranges, *id),
+                name,
+                def_airty,
+                call_airty
+            );
+        }
+
+        // next check that all parameters have the correct type
+        // if they do, we evaluate them and add to the child environment
+        let mut evaluated_params: HashMap<&String, VarStore<WabbitType>> = HashMap::new();
+
+        for (call_expr, (def_name, def_type)) in std::iter::zip(call_params, *def_params) {
+            let call_expr_eval = self.evaluate(call_expr)?;
+
+            if def_type != &call_expr_eval.dtype() {
+                return msg!(
+                    Msg::ParamType,
+                    (self.analyze.ranges, *id),
+                    &def_name,
+                    def_type,
+                    call_expr_eval.dtype()
+                );
+            }
+            evaluated_params.insert(def_name, VarStore::Init(call_expr_eval));
+        }
+
+        if def_params.len() != evaluated_params.len() {
+            return msg!(Msg::DupArgs, (self.analyze.ranges, *id));
+        };
+
+        match self.limits.call_depth {
+            Some(max) if self.analyze.call_depth >= max => {
+                return msg!(Msg::CallDepth, (self.analyze.ranges, call_id), max);
+            }
+            _ => self.check_limits(call_id)?,
+        }
+
+        // functions see globals, not the scopes of their caller, along with the variables they
+        // captured
+        let captures = captures
+            .iter()
+            .map(|value| VarStore::Captured(value.clone()));
+        evaluated_params.extend(std::iter::zip(captured.iter().copied(), captures));
+        let locals = self.analyze.env.enter_call(evaluated_params);
+        self.analyze.call_depth += 1;
+        self.calls.push((name, call_id));
+        if let Some(profile) = self.profile.as_deref_mut() {
+            profile.enter(name, self.steps);
+        }
+
+        let signal = self.run_stmt(body)?;
+
+        self.analyze.env.exit_call(locals);
+        self.analyze.call_depth -= 1;
+        self.calls.pop();
+        if let Some(profile) = self.profile.as_deref_mut() {
+            profile.exit(self.steps);
+        }
+
+        if let Signal::Return(call_return) = signal {
+            if return_type != &call_return.dtype() {
+                msg!(
+                    Msg::ReturnType,
+                    (self.analyze.ranges, *id),
+                    name,
+                    return_type,
+                    call_return.dtype()
+                )
+            } else {
+                Ok(call_return)
+            }
+        } else {
+            msg!(Msg::NoReturn, (self.analyze.ranges, *id))
+        }
+    }
+
     /// interpret a single expression
     #[allow(unused_parens)]
-    fn evaluate(&mut self, e: &Expr) -> Result<WabbitType> {
+    pub(crate) fn evaluate(&mut self, e: &'a Expr) -> Result<WabbitType> {
         match e {
             Expr::TypeConversion { dtype, params, id } => {
                 if let [to_convert] = params.as_slice() {
-                    let eval = self.evaluate(to_convert)?;
-                    match (dtype, &eval) {
-                        (Type::Int, WabbitType::Char(c)) => Ok(WabbitType::Int(*c as i32)),
-                        (Type::Int, WabbitType::Bool(b)) => Ok(WabbitType::Int(*b as i32)),
-                        (Type::Int, WabbitType::Float(f)) => Ok(WabbitType::Int(*f as i32)),
-                        (Type::Float, WabbitType::Int(i)) => Ok(WabbitType::Float(*i as f64)),
-                        (Type::Char, WabbitType::Int(i)) => {
-                            Ok(WabbitType::Char((*i as u8) as char))
-                        }
-                        (Type::Bool, WabbitType::Int(i)) => Ok(WabbitType::Bool(*i == 1)),
-                        // note sure if in the spec, but this seems reasonable...
-                        (Type::Bool, WabbitType::Bool(_))
-                        | (Type::Int, WabbitType::Int(_))
-                        | (Type::Float, WabbitType::Float(_))
-                        | (Type::Char, WabbitType::Char(_)) => Ok(eval),
-                        _ => msg!(Msg::TypeConvert, (self.ranges, *id)),
-                    }
+                    self.evaluate(to_convert)?
+                        .convert(dtype)
+                        .ok_or_else(|| err!(Msg::TypeConvert, (self.analyze.ranges, *id)))
                 } else {
-                    msg!(Msg::ConvertAirty, (self.ranges, *id))
+                    msg!(Msg::ConvertAirty, (self.analyze.ranges, *id))
                 }
             }
             Expr::Call {
@@ -265,84 +821,50 @@ impl<'a> Interpreter<'a> {
                 params: call_params,
                 id,
             } => {
-                let func_lookup = self.functions.get(call_name);
+                let func_lookup = self.analyze.functions.get(call_name).copied();
 
-                if let Some(Stmt::FuncDef {
-                    def_params,
-                    return_type,
-                    box body,
-                    id,
-                    ..
-                }) = func_lookup
-                {
-                    // first check airty
-                    let call_airty = call_params.len();
-                    let def_airty = def_params.len();
-
-                    if def_airty != call_airty {
+                if self.analyze.is_variable(call_name) {
+                    let callee = self.variable(call_name, *id)?;
+                    self.call_value(callee, call_name, call_params, *id)
+                } else if let Some(def) = func_lookup {
+                    let definition = self.named_definition(def)?;
+                    self.call(&definition, &[], call_name, call_params, *id)
+                } else if let Some(builtin) = Builtin::from_name(call_name) {
+                    self.analyze
+                        .check_builtin_args(call_name, call_params, *id)?;
+                    let dtype = builtin.return_type();
+                    let value = match &mut self.input {
+                        Some(input) => input.read(dtype.clone()),
+                        None => Reader::stdin().read(dtype.clone()),
+                    };
+                    value.ok_or_else(|| err!(Msg::InvalidInput, (self.analyze.ranges, *id), dtype))
+                } else if let Some(native) = self.analyze.natives.get(call_name) {
+                    let args = call_params
+                        .iter()
+                        .map(|param| self.evaluate(param))
+                        .collect::<Result<Vec<WabbitType>>>()?;
+                    let arg_types: Vec<Type> = args.iter().map(WabbitType::dtype).collect();
+                    self.analyze
+                        .check_native_args(call_name, native, &arg_types, *id)?;
+
+                    let range = (self.analyze.ranges, *id).extract_range();
+                    let value = (native.func)(&args).map_err(|e| e.at(range))?;
+                    if value.dtype() != native.return_type {
                         msg!(
-                            Msg::FuncAirty,
-                            (self.ranges, *id),
-                            call_name,
-                            def_airty,
-                            call_airty
+                            Msg::ReturnType,
+                            (self.analyze.ranges, *id),
+                            &call_name,
+                            native.return_type,
+                            value.dtype()
                         )
                     } else {
-                        // next check that all parameters have the correct type
-                        // if they do, we evaluate them and add to the child environment
-                        let mut evaluated_params: HashMap<&String, VarStore<WabbitType>> =
-                            HashMap::new();
-
-                        for (call_expr, (def_name, def_type)) in
-                            std::iter::zip(call_params, def_params)
-                        {
-                            let call_expr_eval = self.evaluate(call_expr)?;
-
-                            if def_type != &call_expr_eval.dtype() {
-                                return msg!(
-                                    Msg::ParamType,
-                                    (self.ranges, *id),
-                                    &def_name,
-                                    def_type,
-                                    call_expr_eval.dtype()
-                                );
-                            }
-                            evaluated_params.insert(def_name, VarStore::Init(call_expr_eval));
-                        }
-
-                        if def_params.len() != evaluated_params.len() {
-                            return msg!(Msg::DupArgs, (self.ranges, *id));
-                        };
-
-                        self.env.enter_child_fn(evaluated_params);
-                        self.call_depth += 1;
-
-                        let signal = self.run_stmt(body)?;
-
-                        self.env.exit_child(&(self.ranges, *id))?;
-                        self.call_depth -= 1;
-
-                        if let Signal::Return(call_return) = signal {
-                            if return_type != &call_return.dtype() {
-                                msg!(
-                                    Msg::ReturnType,
-                                    (self.ranges, *id),
-                                    &call_name,
-                                    return_type,
-                                    call_return.dtype()
-                                )
-                            } else {
-                                Ok(call_return)
-                            }
-                        } else {
-                            msg!(Msg::NoReturn, (self.ranges, *id))
-                        }
+                        Ok(value)
                     }
                 } else {
-                    msg!(Msg::FuncUndefined, (self.ranges, *id))
+                    msg!(Msg::FuncUndefined, (self.analyze.ranges, *id))
                 }
             }
-            Expr::TypeName { id, .. } => msg!(Msg::TypeEval, (self.ranges, *id)),
+            Expr::TypeName { id, .. } => msg!(Msg::TypeEval, (self.analyze.ranges, *id)),
             Expr::Logical { lhs, op, rhs, id } => {
                 let eval_lhs = self.typecheck_bool(lhs, *id)?;
                 match op {
@@ -364,67 +886,84 @@ impl<'a> Interpreter<'a> {
                     }
                 }
             }
-            Expr::VarName { name, id } => {
-                if let Some(constant) = self.constants.get(name) {
-                    Ok(*constant)
-                } else if let Some(VarStore::Init(var)) = self.env.get(name) {
-                    Ok(var)
-                } else if let Some(VarStore::UnInit(_)) = self.env.get(name) {
-                    msg!(Msg::AccessUninit, (self.ranges, *id), name)
-                } else {
-                    msg!(Msg::VarUndefined, (self.ranges, *id))
-                }
+            Expr::CallValue { callee, params, id } => {
+                let callee = self.evaluate(callee)?;
+                self.call_value(callee, "<func>", params, *id)
             }
+            Expr::Function {
+                params,
+                return_type,
+                body,
+                id,
+            } => {
+                // scopes are lexical, so every evaluation captures the same names
+                let (captured, captures) = self
+                    .analyze
+                    .captures(params, body, *id)?
+                    .into_iter()
+                    .unzip();
+                let definition = self.definition(*id, || Definition {
+                    name: None,
+                    params,
+                    return_type: return_type.clone(),
+                    body,
+                    captured,
+                    id: *id,
+                });
+                Ok(Self::function_value(&definition, captures))
+            }
+            Expr::VarName { name, id } => match self.analyze.functions.get(name).copied() {
+                // a named function used as a value
+                Some(def) if !self.analyze.is_variable(name) => {
+                    let definition = self.named_definition(def)?;
+                    Ok(Self::function_value(&definition, Vec::new()))
+                }
+                _ => self.variable(name, *id),
+            },
             Expr::Grouping { e, .. } => Ok(self.evaluate(e)?),
             Expr::Unary { op, operand, id } => {
                 let eval_operand = self.evaluate(operand)?;
                 match op {
                     UnaryOp::LogicalNot => {
-                        let raw_operand = self.typecheck_bool(operand, *id)?;
-                        Ok(WabbitType::Bool(!raw_operand))
+                        if let WabbitType::Bool(val) = eval_operand {
+                            Ok(WabbitType::Bool(!val))
+                        } else {
+                            msg!(Msg::ExpectType, (self.analyze.ranges, *id), "bool")
+                        }
                     }
-                    UnaryOp::Plus => numeric_unary!(eval_operand, (self.ranges, *id), (|a| a)),
-                    UnaryOp::Minus => numeric_unary!(eval_operand, (self.ranges, *id), (|a| -a)),
+                    UnaryOp::Plus if eval_operand.dtype().is_numeric() => Ok(eval_operand),
+                    UnaryOp::Plus => msg!(Msg::ExpectType, (self.analyze.ranges, *id), NUMERIC),
+                    UnaryOp::Minus => match eval_operand {
+                        WabbitType::Int(a) => {
+                            a.checked_neg().map(WabbitType::Int).ok_or_else(|| {
+                                err!(
+                                    Msg::IntOverflow,
+                                    (self.analyze.ranges, *id),
+                                    format!("-({a})")
+                                )
+                            })
+                        }
+                        WabbitType::Int64(a) => {
+                            a.checked_neg().map(WabbitType::Int64).ok_or_else(|| {
+                                err!(
+                                    Msg::IntOverflow,
+                                    (self.analyze.ranges, *id),
+                                    format!("-({a})")
+                                )
+                            })
+                        }
+                        WabbitType::Float(a) => Ok(WabbitType::Float(-a)),
+                        WabbitType::Float32(a) => Ok(WabbitType::Float32(-a)),
+                        _ => msg!(Msg::ExpectType, (self.analyze.ranges, *id), SIGNED),
+                    },
                 }
             }
             Expr::Binary { lhs, op, rhs, id } => {
                 let eval_lhs = self.evaluate(lhs)?;
                 let eval_rhs = self.evaluate(rhs)?;
-
-                if !(eval_lhs.dtype() == eval_rhs.dtype()) {
-                    msg!(Msg::TypeMatch, (self.ranges, *id))
-                } else {
-                    match op {
-                        BinaryOp::Plus => {
-                            numeric_binary!(eval_lhs, eval_rhs, (self.ranges, *id), +)
-                        }
-                        BinaryOp::Minus => {
-                            numeric_binary!(eval_lhs, eval_rhs, (self.ranges, *id), -)
-                        }
-                        BinaryOp::Times => {
-                            numeric_binary!(eval_lhs, eval_rhs, (self.ranges, *id), *)
-                        }
-                        BinaryOp::Divide => {
-                            numeric_binary!(eval_lhs, eval_rhs, (self.ranges, *id), /)
-                        }
-                        BinaryOp::Less => compare!(eval_lhs, eval_rhs, (self.ranges, *id), <),
-                        BinaryOp::LessEqual => {
-                            compare!(eval_lhs, eval_rhs, (self.ranges, *id), <=)
-                        }
-                        BinaryOp::Greater => compare!(eval_lhs, eval_rhs, (self.ranges, *id), >),
-                        BinaryOp::GreaterEqual => {
-                            compare!(eval_lhs, eval_rhs, (self.ranges, *id), >=)
-                        }
-                        BinaryOp::EqualEqual => {
-                            equality!(eval_lhs, eval_rhs, (self.ranges, *id), ==)
-                        }
-                        BinaryOp::NotEqual => {
-                            equality!(eval_lhs, eval_rhs, (self.ranges, *id), !=)
-                        }
-                    }
-                }
+                self.binary(eval_lhs, op, eval_rhs, *id)
             }
-            Expr::Literal { value, .. } => Ok(*value),
+            Expr::Literal { value, .. } => Ok(value.clone()),
         }
     }
 }
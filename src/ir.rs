@@ -0,0 +1,1034 @@
+//! intermediate representation between AST and code generation
+//!
+//! a typed, three-address IR in SSA form. A [`Function`] is made of basic blocks, each holding a
+//! list of instructions on virtual registers and ending in a single terminator
+
+use crate::operators::{BinaryOp, UnaryOp};
+use crate::types::Type;
+use crate::WabbitType;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+
+/// a virtual register, unique within a function and assigned exactly once
+///
+/// Wabbit variables are not registers: they live in stack slots (`Alloca`) or globals and are
+/// accessed with `Load` and `Store`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Reg(pub usize);
+
+/// an instruction operand
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    /// the result of an instruction, or a function parameter
+    Reg(Reg),
+    /// a constant value
+    Const(WabbitType),
+    /// the address of a global variable
+    Global(String),
+}
+
+/// a single (non-terminating) instruction
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instr {
+    /// reserve a stack slot, `dest` holds its address
+    Alloca { dest: Reg, dtype: Type },
+    /// read a value of type `dtype` from an address
+    Load {
+        dest: Reg,
+        dtype: Type,
+        ptr: Operand,
+    },
+    /// write a value of type `dtype` to an address
+    Store {
+        dtype: Type,
+        value: Operand,
+        ptr: Operand,
+    },
+    /// arithmetic or comparison, where `dtype` is the type of both operands
+    Binary {
+        dest: Reg,
+        op: BinaryOp,
+        dtype: Type,
+        lhs: Operand,
+        rhs: Operand,
+    },
+    /// negation or logical not (unary plus never reaches the IR)
+    Unary {
+        dest: Reg,
+        op: UnaryOp,
+        dtype: Type,
+        operand: Operand,
+    },
+    /// a type conversion, with the same semantics as the interpreter
+    Convert {
+        dest: Reg,
+        from: Type,
+        to: Type,
+        value: Operand,
+    },
+    /// call a Wabbit function
+    Call {
+        dest: Reg,
+        ret: Type,
+        func: String,
+        args: Vec<(Type, Operand)>,
+    },
+    /// create a function value of type `dtype`, holding `func` and copies of the values it
+    /// captures, where `func` takes the function value as an extra first parameter
+    Closure {
+        dest: Reg,
+        dtype: Type,
+        func: String,
+        captures: Vec<(Type, Operand)>,
+    },
+    /// read a captured value from the function value passed as the first parameter
+    Capture {
+        dest: Reg,
+        dtype: Type,
+        index: usize,
+    },
+    /// call a function value
+    CallValue {
+        dest: Reg,
+        ret: Type,
+        callee: Operand,
+        args: Vec<(Type, Operand)>,
+    },
+    /// print a value
+    Print { dtype: Type, value: Operand },
+    /// read a value of type `dtype` (int, float or char) from input
+    Read { dest: Reg, dtype: Type },
+    /// select a value based on the predecessor block, only for expressions that merge control
+    /// flow (`&&` and `||`)
+    Phi {
+        dest: Reg,
+        dtype: Type,
+        incoming: Vec<(Operand, String)>,
+    },
+}
+
+/// the last instruction of a basic block
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator {
+    /// unconditional jump
+    Jump(String),
+    /// conditional jump on a boolean
+    Branch {
+        cond: Operand,
+        then: String,
+        otherwise: String,
+    },
+    /// jump to the label of the case equal to an int or char, or to `default`
+    Switch {
+        dtype: Type,
+        value: Operand,
+        cases: Vec<(WabbitType, String)>,
+        default: String,
+    },
+    /// return a value from the current function
+    Return(Type, Operand),
+    /// control never reaches this point
+    Unreachable,
+    /// stop the program with an error, writing the message to stderr
+    Fail(String),
+}
+
+/// a straight-line sequence of instructions
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub label: String,
+    pub instrs: Vec<Instr>,
+    /// only `None` while the block is being built
+    pub term: Option<Terminator>,
+}
+
+/// a function, where the first block is the entry point
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<(Reg, Type)>,
+    pub return_type: Type,
+    /// the types of the values read by `Capture`, for the function of a function expression
+    pub captures: Vec<Type>,
+    pub blocks: Vec<Block>,
+}
+
+/// a global variable, zero initialized
+#[derive(Debug, Clone, PartialEq)]
+pub struct Global {
+    pub name: String,
+    pub dtype: Type,
+}
+
+/// a function defined outside the program, called by its own name
+#[derive(Debug, Clone, PartialEq)]
+pub struct Extern {
+    pub name: String,
+    pub params: Vec<Type>,
+    pub return_type: Type,
+}
+
+/// a whole program, where top-level statements are lowered into a function named `main`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Module {
+    pub globals: Vec<Global>,
+    pub externs: Vec<Extern>,
+    pub functions: Vec<Function>,
+}
+
+/// name of the function holding top-level statements
+pub const MAIN: &str = "main";
+
+/// symbol for a Wabbit function in generated code, prefixed so it cannot clash with libc
+pub fn function_symbol(name: &str) -> String {
+    if name == MAIN {
+        name.to_string()
+    } else {
+        format!("wb_fn_{name}")
+    }
+}
+
+/// symbol for a Wabbit global variable in generated code
+pub fn global_symbol(name: &str) -> String {
+    format!("wb_var_{name}")
+}
+
+impl Block {
+    pub fn new(label: &str) -> Self {
+        Self {
+            label: label.to_string(),
+            instrs: Vec::new(),
+            term: None,
+        }
+    }
+
+    /// labels of the blocks this block may jump to
+    pub fn successors(&self) -> Vec<&String> {
+        match &self.term {
+            Some(Terminator::Jump(label)) => vec![label],
+            Some(Terminator::Branch {
+                then, otherwise, ..
+            }) => vec![then, otherwise],
+            Some(Terminator::Switch { cases, default, .. }) => cases
+                .iter()
+                .map(|(_, label)| label)
+                .chain([default])
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Instr {
+    /// the register defined by this instruction, if any
+    pub fn dest(&self) -> Option<Reg> {
+        match self {
+            Instr::Alloca { dest, .. }
+            | Instr::Load { dest, .. }
+            | Instr::Binary { dest, .. }
+            | Instr::Unary { dest, .. }
+            | Instr::Convert { dest, .. }
+            | Instr::Call { dest, .. }
+            | Instr::Closure { dest, .. }
+            | Instr::Capture { dest, .. }
+            | Instr::CallValue { dest, .. }
+            | Instr::Read { dest, .. }
+            | Instr::Phi { dest, .. } => Some(*dest),
+            Instr::Store { .. } | Instr::Print { .. } => None,
+        }
+    }
+
+    /// the operands read by this instruction
+    pub fn operands(&self) -> Vec<&Operand> {
+        match self {
+            Instr::Alloca { .. } | Instr::Read { .. } | Instr::Capture { .. } => Vec::new(),
+            Instr::Load { ptr, .. } => vec![ptr],
+            Instr::Store { value, ptr, .. } => vec![value, ptr],
+            Instr::Binary { lhs, rhs, .. } => vec![lhs, rhs],
+            Instr::Unary { operand, .. } => vec![operand],
+            Instr::Convert { value, .. } | Instr::Print { value, .. } => vec![value],
+            Instr::Call { args, .. } | Instr::Closure { captures: args, .. } => {
+                args.iter().map(|(_, arg)| arg).collect()
+            }
+            Instr::CallValue { callee, args, .. } => std::iter::once(callee)
+                .chain(args.iter().map(|(_, arg)| arg))
+                .collect(),
+            Instr::Phi { incoming, .. } => incoming.iter().map(|(value, _)| value).collect(),
+        }
+    }
+}
+
+impl Terminator {
+    /// the operands read by this terminator
+    pub fn operands(&self) -> Vec<&Operand> {
+        match self {
+            Terminator::Branch { cond, .. } => vec![cond],
+            Terminator::Switch { value, .. } | Terminator::Return(_, value) => vec![value],
+            Terminator::Jump(_) | Terminator::Unreachable | Terminator::Fail(_) => Vec::new(),
+        }
+    }
+}
+
+impl Function {
+    /// map from block labels to the labels of their predecessors
+    pub fn predecessors(&self) -> HashMap<&String, Vec<&String>> {
+        let mut preds: HashMap<&String, Vec<&String>> =
+            self.blocks.iter().map(|b| (&b.label, Vec::new())).collect();
+        for block in &self.blocks {
+            for succ in block.successors() {
+                if let Some(list) = preds.get_mut(succ) {
+                    list.push(&block.label);
+                }
+            }
+        }
+        preds
+    }
+}
+
+// textual form of the IR
+
+/// short mnemonic for a binary operator
+pub fn binary_mnemonic(op: &BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Plus => "add",
+        BinaryOp::Minus => "sub",
+        BinaryOp::Times => "mul",
+        BinaryOp::Divide => "div",
+        BinaryOp::Modulo => "rem",
+        BinaryOp::BitAnd => "and",
+        BinaryOp::BitOr => "or",
+        BinaryOp::BitXor => "xor",
+        BinaryOp::ShiftLeft => "shl",
+        BinaryOp::ShiftRight => "shr",
+        BinaryOp::Less => "lt",
+        BinaryOp::LessEqual => "le",
+        BinaryOp::Greater => "gt",
+        BinaryOp::GreaterEqual => "ge",
+        BinaryOp::EqualEqual => "eq",
+        BinaryOp::NotEqual => "ne",
+    }
+}
+
+impl Display for Reg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Operand::Reg(reg) => write!(f, "{reg}"),
+            Operand::Global(name) => write!(f, "@{name}"),
+            Operand::Const(value) => match value {
+                WabbitType::Char('\n') => write!(f, "'\\n'"),
+                WabbitType::Char(c) => write!(f, "'{c}'"),
+                WabbitType::Float(x) => write!(f, "{x:?}"),
+                WabbitType::Float32(x) => write!(f, "{x:?}"),
+                _ => write!(f, "{value}"),
+            },
+        }
+    }
+}
+
+impl Display for Instr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Instr::Alloca { dest, dtype } => write!(f, "{dest} = alloca {dtype}"),
+            Instr::Load { dest, dtype, ptr } => write!(f, "{dest} = load {dtype} {ptr}"),
+            Instr::Store { dtype, value, ptr } => write!(f, "store {dtype} {value}, {ptr}"),
+            Instr::Binary {
+                dest,
+                op,
+                dtype,
+                lhs,
+                rhs,
+            } => {
+                let op = binary_mnemonic(op);
+                write!(f, "{dest} = {op} {dtype} {lhs}, {rhs}")
+            }
+            Instr::Unary {
+                dest,
+                op,
+                dtype,
+                operand,
+            } => {
+                let op = match op {
+                    UnaryOp::Plus => "plus",
+                    UnaryOp::Minus => "neg",
+                    UnaryOp::LogicalNot => "not",
+                };
+                write!(f, "{dest} = {op} {dtype} {operand}")
+            }
+            Instr::Convert {
+                dest,
+                from,
+                to,
+                value,
+            } => write!(f, "{dest} = convert {from} {value} to {to}"),
+            Instr::Call {
+                dest,
+                ret,
+                func,
+                args,
+            } => {
+                let args = args
+                    .iter()
+                    .map(|(dtype, arg)| format!("{dtype} {arg}"))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                write!(f, "{dest} = call {ret} @{func}({args})")
+            }
+            Instr::Closure {
+                dest,
+                dtype,
+                func,
+                captures,
+            } => {
+                let captures = captures
+                    .iter()
+                    .map(|(dtype, value)| format!("{dtype} {value}"))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                write!(f, "{dest} = closure {dtype} @{func}({captures})")
+            }
+            Instr::Capture { dest, dtype, index } => write!(f, "{dest} = capture {dtype} {index}"),
+            Instr::CallValue {
+                dest,
+                ret,
+                callee,
+                args,
+            } => {
+                let args = args
+                    .iter()
+                    .map(|(dtype, arg)| format!("{dtype} {arg}"))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                write!(f, "{dest} = call {ret} {callee}({args})")
+            }
+            Instr::Print { dtype, value } => write!(f, "print {dtype} {value}"),
+            Instr::Read { dest, dtype } => write!(f, "{dest} = read {dtype}"),
+            Instr::Phi {
+                dest,
+                dtype,
+                incoming,
+            } => {
+                let incoming = incoming
+                    .iter()
+                    .map(|(value, label)| format!("[{value}, {label}]"))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                write!(f, "{dest} = phi {dtype} {incoming}")
+            }
+        }
+    }
+}
+
+impl Display for Terminator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Terminator::Jump(label) => write!(f, "jump {label}"),
+            Terminator::Branch {
+                cond,
+                then,
+                otherwise,
+            } => write!(f, "branch {cond}, {then}, {otherwise}"),
+            Terminator::Switch {
+                dtype,
+                value,
+                cases,
+                default,
+            } => {
+                let cases = cases
+                    .iter()
+                    .map(|(case, label)| format!("{}: {label}", Operand::Const(case.clone())))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                write!(f, "switch {dtype} {value}, {default} [{cases}]")
+            }
+            Terminator::Return(dtype, value) => write!(f, "ret {dtype} {value}"),
+            Terminator::Unreachable => write!(f, "unreachable"),
+            Terminator::Fail(message) => write!(f, "fail {message:?}"),
+        }
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let params = self
+            .params
+            .iter()
+            .map(|(reg, dtype)| format!("{reg} {dtype}"))
+            .collect::<Vec<String>>()
+            .join(", ");
+        write!(f, "func @{}({params}) {}", self.name, self.return_type)?;
+        if !self.captures.is_empty() {
+            let captures: Vec<String> = self.captures.iter().map(ToString::to_string).collect();
+            write!(f, " captures({})", captures.join(", "))?;
+        }
+        writeln!(f, " {{")?;
+        for block in &self.blocks {
+            writeln!(f, "{}:", block.label)?;
+            for instr in &block.instrs {
+                writeln!(f, "    {instr}")?;
+            }
+            match &block.term {
+                Some(term) => writeln!(f, "    {term}")?,
+                None => writeln!(f, "    <missing terminator>")?,
+            }
+        }
+        write!(f, "}}")
+    }
+}
+
+impl Display for Module {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for global in &self.globals {
+            writeln!(f, "global @{} {}", global.name, global.dtype)?;
+        }
+        for ext in &self.externs {
+            let params = ext
+                .params
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<String>>()
+                .join(", ");
+            writeln!(f, "declare {} @{}({params})", ext.return_type, ext.name)?;
+        }
+        for func in &self.functions {
+            writeln!(f)?;
+            writeln!(f, "{func}")?;
+        }
+        Ok(())
+    }
+}
+
+// verification
+
+/// a violation of the IR's invariants, found by [`Module::verify`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerifyError {
+    /// the function containing the error
+    pub function: String,
+    /// description of the error
+    pub message: String,
+}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "in @{}: {}", self.function, self.message)
+    }
+}
+
+/// the type held by a register
+#[derive(Debug, Clone, PartialEq)]
+enum RegType {
+    Value(Type),
+    Address(Type),
+}
+
+/// state for verifying a single function
+struct FunctionVerifier<'m> {
+    module: &'m Module,
+    func: &'m Function,
+    /// types of all registers in the function
+    reg_types: HashMap<Reg, RegType>,
+    /// block index and instruction index of each register definition, params are at `(0, 0)`
+    defs: HashMap<Reg, (usize, usize)>,
+    /// map from block labels to block indices
+    blocks: HashMap<&'m String, usize>,
+    /// dominator sets, by block index
+    dominators: Vec<HashSet<usize>>,
+    errors: Vec<VerifyError>,
+}
+
+impl Module {
+    /// check the structural, SSA and type invariants of the IR
+    pub fn verify(&self) -> Result<(), Vec<VerifyError>> {
+        let mut errors = Vec::new();
+        let mut names = HashSet::new();
+
+        for func in &self.functions {
+            if !names.insert(&func.name) {
+                errors.push(VerifyError {
+                    function: func.name.clone(),
+                    message: "function defined more than once".to_string(),
+                });
+            }
+            errors.extend(FunctionVerifier::new(self, func).verify());
+        }
+        for ext in &self.externs {
+            if !names.insert(&ext.name) {
+                errors.push(VerifyError {
+                    function: ext.name.clone(),
+                    message: "function defined more than once".to_string(),
+                });
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// look up a function by name
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.iter().find(|func| func.name == name)
+    }
+
+    /// look up an external function by name
+    pub fn extern_fn(&self, name: &str) -> Option<&Extern> {
+        self.externs.iter().find(|ext| ext.name == name)
+    }
+
+    /// the parameter and return types of a function or external function
+    pub fn signature(&self, name: &str) -> Option<(Vec<Type>, Type)> {
+        if let Some(func) = self.function(name) {
+            let params = func.params.iter().map(|(_, dtype)| dtype.clone()).collect();
+            Some((params, func.return_type.clone()))
+        } else {
+            self.extern_fn(name)
+                .map(|ext| (ext.params.clone(), ext.return_type.clone()))
+        }
+    }
+
+    /// look up a global by name
+    pub fn global(&self, name: &str) -> Option<&Global> {
+        self.globals.iter().find(|global| global.name == name)
+    }
+}
+
+impl<'m> FunctionVerifier<'m> {
+    fn new(module: &'m Module, func: &'m Function) -> Self {
+        Self {
+            module,
+            func,
+            reg_types: HashMap::new(),
+            defs: HashMap::new(),
+            blocks: HashMap::new(),
+            dominators: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    fn error(&mut self, message: String) {
+        self.errors.push(VerifyError {
+            function: self.func.name.clone(),
+            message,
+        });
+    }
+
+    fn verify(mut self) -> Vec<VerifyError> {
+        if self.func.blocks.is_empty() {
+            self.error("function has no blocks".to_string());
+            return self.errors;
+        }
+
+        self.check_structure();
+        self.collect_definitions();
+
+        if self.errors.is_empty() {
+            self.compute_dominators();
+            self.check_instructions();
+        }
+        self.errors
+    }
+
+    /// labels are unique, every block is terminated, and jumps target existing blocks
+    fn check_structure(&mut self) {
+        let func = self.func;
+        for (i, block) in func.blocks.iter().enumerate() {
+            if self.blocks.insert(&block.label, i).is_some() {
+                self.error(format!("duplicate block label '{}'", block.label));
+            }
+        }
+
+        for block in &func.blocks {
+            if block.term.is_none() {
+                self.error(format!("block '{}' has no terminator", block.label));
+            }
+            for succ in block.successors() {
+                if !self.blocks.contains_key(succ) {
+                    self.error(format!(
+                        "block '{}' jumps to undefined block '{succ}'",
+                        block.label
+                    ));
+                }
+            }
+            let first_non_phi = block
+                .instrs
+                .iter()
+                .position(|instr| !matches!(instr, Instr::Phi { .. }))
+                .unwrap_or(block.instrs.len());
+            if block.instrs[first_non_phi..]
+                .iter()
+                .any(|instr| matches!(instr, Instr::Phi { .. }))
+            {
+                self.error(format!(
+                    "block '{}' has a phi after a non-phi instruction",
+                    block.label
+                ));
+            }
+        }
+
+        if let Some(preds) = self.func.predecessors().get(&self.func.blocks[0].label) {
+            if !preds.is_empty() {
+                self.error("the entry block cannot be jumped to".to_string());
+            }
+        }
+    }
+
+    /// every register is defined exactly once
+    fn collect_definitions(&mut self) {
+        let func = self.func;
+        for (reg, dtype) in &func.params {
+            self.define(*reg, RegType::Value(dtype.clone()), (0, 0));
+        }
+        for (b, block) in func.blocks.iter().enumerate() {
+            for (i, instr) in block.instrs.iter().enumerate() {
+                let reg_type = match instr {
+                    Instr::Alloca { dtype, .. } => RegType::Address(dtype.clone()),
+                    Instr::Load { dtype, .. }
+                    | Instr::Unary { dtype, .. }
+                    | Instr::Read { dtype, .. }
+                    | Instr::Closure { dtype, .. }
+                    | Instr::Capture { dtype, .. }
+                    | Instr::Phi { dtype, .. } => RegType::Value(dtype.clone()),
+                    Instr::Binary { op, dtype, .. } => RegType::Value(if op.is_comparison() {
+                        Type::Bool
+                    } else {
+                        dtype.clone()
+                    }),
+                    Instr::Convert { to, .. } => RegType::Value(to.clone()),
+                    Instr::Call { ret, .. } | Instr::CallValue { ret, .. } => {
+                        RegType::Value(ret.clone())
+                    }
+                    Instr::Store { .. } | Instr::Print { .. } => continue,
+                };
+                if let Some(dest) = instr.dest() {
+                    // instructions are numbered from 1, so params come first
+                    self.define(dest, reg_type, (b, i + 1));
+                }
+            }
+        }
+    }
+
+    fn define(&mut self, reg: Reg, reg_type: RegType, at: (usize, usize)) {
+        if self.defs.insert(reg, at).is_some() {
+            self.error(format!("register {reg} is defined more than once"));
+        }
+        self.reg_types.insert(reg, reg_type);
+    }
+
+    /// iterative dataflow computation of dominator sets
+    fn compute_dominators(&mut self) {
+        let n = self.func.blocks.len();
+        let preds = self.func.predecessors();
+        let preds: Vec<Vec<usize>> = self
+            .func
+            .blocks
+            .iter()
+            .map(|block| preds[&block.label].iter().map(|p| self.blocks[p]).collect())
+            .collect();
+
+        let all: HashSet<usize> = (0..n).collect();
+        let mut dom: Vec<HashSet<usize>> = vec![all; n];
+        dom[0] = HashSet::from([0]);
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for b in 1..n {
+                // blocks without predecessors are unreachable, and (as in LLVM) dominance
+                // is not checked in them, so they keep the full set
+                let mut iter = preds[b].iter();
+                let Some(first) = iter.next() else {
+                    continue;
+                };
+                let mut new: HashSet<usize> = iter.fold(dom[*first].clone(), |acc, p| {
+                    acc.intersection(&dom[*p]).copied().collect()
+                });
+                new.insert(b);
+                if new != dom[b] {
+                    dom[b] = new;
+                    changed = true;
+                }
+            }
+        }
+        self.dominators = dom;
+    }
+
+    /// does the definition of `reg` dominate position `at` (block index, instruction index)?
+    fn dominates(&self, reg: Reg, at: (usize, usize)) -> bool {
+        match self.defs.get(&reg) {
+            Some(&(def_block, def_index)) => {
+                if def_block == at.0 {
+                    def_index < at.1
+                } else {
+                    self.dominators[at.0].contains(&def_block)
+                }
+            }
+            None => false,
+        }
+    }
+
+    /// the type of a value operand, checking that it is defined and available at `at`
+    fn value_type(&mut self, operand: &Operand, at: (usize, usize)) -> Option<Type> {
+        match operand {
+            Operand::Const(value) => Some(value.dtype()),
+            Operand::Global(name) => {
+                self.error(format!("global @{name} used as a value"));
+                None
+            }
+            Operand::Reg(reg) => match self.reg_types.get(reg).cloned() {
+                None => {
+                    self.error(format!("register {reg} is never defined"));
+                    None
+                }
+                Some(RegType::Address(_)) => {
+                    self.error(format!("address {reg} used as a value"));
+                    None
+                }
+                Some(RegType::Value(dtype)) => {
+                    if !self.dominates(*reg, at) {
+                        let label = &self.func.blocks[at.0].label;
+                        self.error(format!(
+                            "use of {reg} in block '{label}' is not dominated by its definition"
+                        ));
+                    }
+                    Some(dtype)
+                }
+            },
+        }
+    }
+
+    /// the pointee type of an address operand
+    fn address_type(&mut self, operand: &Operand, at: (usize, usize)) -> Option<Type> {
+        match operand {
+            Operand::Global(name) => {
+                if let Some(global) = self.module.global(name) {
+                    Some(global.dtype.clone())
+                } else {
+                    self.error(format!("undefined global @{name}"));
+                    None
+                }
+            }
+            Operand::Reg(reg) => {
+                if let Some(RegType::Address(dtype)) = self.reg_types.get(reg).cloned() {
+                    if !self.dominates(*reg, at) {
+                        self.error(format!("use of {reg} is not dominated by its definition"));
+                    }
+                    Some(dtype)
+                } else {
+                    self.error(format!("{reg} is not an address"));
+                    None
+                }
+            }
+            Operand::Const(_) => {
+                self.error(format!("constant {operand} used as an address"));
+                None
+            }
+        }
+    }
+
+    /// check that an operand is a value of the expected type
+    fn expect_value(&mut self, operand: &Operand, expected: &Type, at: (usize, usize)) {
+        if let Some(found) = self.value_type(operand, at) {
+            if found != *expected {
+                self.error(format!(
+                    "expected {operand} to have type {expected}, found {found}"
+                ));
+            }
+        }
+    }
+
+    /// a function value of type `dtype` can be made from `name` and the captured values
+    fn check_closure(&mut self, dtype: &Type, name: &String, captures: &[(Type, Operand)]) {
+        let Type::Func(sig) = dtype else {
+            self.error(format!(
+                "closure of @{name} has type {dtype}, which is not a function"
+            ));
+            return;
+        };
+        let Some(func) = self.module.function(name) else {
+            self.error(format!("closure of undefined function @{name}"));
+            return;
+        };
+        let params: Vec<Type> = func.params.iter().map(|(_, t)| t.clone()).collect();
+        let captured: Vec<Type> = captures.iter().map(|(t, _)| t.clone()).collect();
+        if params.first() != Some(dtype)
+            || params[1..] != sig.params
+            || func.return_type != sig.ret
+            || func.captures != captured
+        {
+            self.error(format!("closure of @{name} does not match its type"));
+        }
+    }
+
+    fn check_instructions(&mut self) {
+        let func = self.func;
+        let preds = func.predecessors();
+
+        for (b, block) in func.blocks.iter().enumerate() {
+            for (i, instr) in block.instrs.iter().enumerate() {
+                let at = (b, i + 1);
+                match instr {
+                    Instr::Alloca { .. } => (),
+                    Instr::Read { dtype, .. } => {
+                        if *dtype == Type::Bool {
+                            self.error(format!("invalid type for '{instr}'"));
+                        }
+                    }
+                    Instr::Load { dtype, ptr, .. } => {
+                        if let Some(found) = self.address_type(ptr, at) {
+                            if found != *dtype {
+                                self.error(format!(
+                                    "load of {dtype} from {ptr}, which holds {found}"
+                                ));
+                            }
+                        }
+                    }
+                    Instr::Store { dtype, value, ptr } => {
+                        self.expect_value(value, dtype, at);
+                        if let Some(found) = self.address_type(ptr, at) {
+                            if found != *dtype {
+                                self.error(format!(
+                                    "store of {dtype} to {ptr}, which holds {found}"
+                                ));
+                            }
+                        }
+                    }
+                    Instr::Binary {
+                        dtype, lhs, rhs, ..
+                    } => {
+                        self.expect_value(lhs, dtype, at);
+                        self.expect_value(rhs, dtype, at);
+                    }
+                    Instr::Unary {
+                        op, dtype, operand, ..
+                    } => {
+                        self.expect_value(operand, dtype, at);
+                        let valid = match op {
+                            UnaryOp::LogicalNot => *dtype == Type::Bool,
+                            UnaryOp::Plus => dtype.is_numeric(),
+                            UnaryOp::Minus => dtype.is_numeric() && *dtype != Type::Uint8,
+                        };
+                        if !valid {
+                            self.error(format!("invalid operand type for '{instr}'"));
+                        }
+                    }
+                    Instr::Convert { from, value, .. } | Instr::Print { dtype: from, value } => {
+                        self.expect_value(value, from, at);
+                    }
+                    Instr::Call {
+                        ret,
+                        func: name,
+                        args,
+                        ..
+                    } => {
+                        for (dtype, arg) in args {
+                            self.expect_value(arg, dtype, at);
+                        }
+                        match self.module.signature(name) {
+                            Some((expected, return_type)) => {
+                                let found: Vec<Type> =
+                                    args.iter().map(|(t, _)| t.clone()).collect();
+                                if expected != found || return_type != *ret {
+                                    self.error(format!(
+                                        "call to @{name} does not match its signature"
+                                    ));
+                                }
+                            }
+                            None => self.error(format!("call to undefined function @{name}")),
+                        }
+                    }
+                    Instr::Closure {
+                        dtype,
+                        func: name,
+                        captures,
+                        ..
+                    } => {
+                        for (dtype, value) in captures {
+                            self.expect_value(value, dtype, at);
+                        }
+                        self.check_closure(dtype, name, captures);
+                    }
+                    Instr::Capture { dtype, index, .. } => {
+                        if func.captures.get(*index) != Some(dtype) {
+                            self.error(format!("'{instr}' does not match the captured values"));
+                        }
+                    }
+                    Instr::CallValue {
+                        ret, callee, args, ..
+                    } => {
+                        for (dtype, arg) in args {
+                            self.expect_value(arg, dtype, at);
+                        }
+                        let params = args.iter().map(|(t, _)| t.clone()).collect();
+                        self.expect_value(callee, &Type::func(params, ret.clone()), at);
+                    }
+                    Instr::Phi {
+                        dtype, incoming, ..
+                    } => {
+                        let mut from: Vec<&String> =
+                            incoming.iter().map(|(_, label)| label).collect();
+                        let mut expected = preds[&block.label].clone();
+                        from.sort();
+                        expected.sort();
+                        if from != expected {
+                            self.error(format!(
+                                "phi in block '{}' does not match its predecessors",
+                                block.label
+                            ));
+                        }
+                        for (value, label) in incoming {
+                            // a phi operand must be available at the end of its predecessor
+                            if let Some(&pred) = self.blocks.get(label) {
+                                let end = (pred, func.blocks[pred].instrs.len() + 1);
+                                self.expect_value(value, dtype, end);
+                            }
+                        }
+                    }
+                }
+            }
+
+            let at = (b, block.instrs.len() + 1);
+            match &block.term {
+                Some(Terminator::Branch { cond, .. }) => self.expect_value(cond, &Type::Bool, at),
+                Some(Terminator::Switch {
+                    dtype,
+                    value,
+                    cases,
+                    ..
+                }) => {
+                    self.expect_value(value, dtype, at);
+                    if ![Type::Int, Type::Char].contains(dtype) {
+                        self.error(format!("switch on {dtype}, which is not int or char"));
+                    }
+                    let mut seen = Vec::new();
+                    for (case, _) in cases {
+                        if case.dtype() != *dtype || seen.contains(&case) {
+                            self.error(format!(
+                                "invalid case {} of a switch",
+                                Operand::Const(case.clone())
+                            ));
+                        }
+                        seen.push(case);
+                    }
+                }
+                Some(Terminator::Return(dtype, value)) => {
+                    self.expect_value(value, dtype, at);
+                    if *dtype != func.return_type {
+                        self.error(format!(
+                            "returns {dtype}, but is declared to return {}",
+                            func.return_type
+                        ));
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+}
@@ -1,11 +1,20 @@
 //! This is my implementation of Wabbit, written as part of David Beazley's excellent class on compilers.
 //!
-//! This crate provides a Wabbit interpreter, a typechecker, a compiler that uses LLVM IR,
-//! and an export of the interpreter to `WebAssembly`.
+//! This crate provides a Wabbit interpreter, a typechecker, compilers to LLVM IR, x86-64
+//! assembly, C and `WebAssembly`, native builds of the generated code, and an export of the
+//! interpreter to `WebAssembly`.
 //!
 //! The below re-exports are roughly in the order that source code is processed (branching
 //! on if you choose to interpret or compile).
 //!
+//! The code generators ([`Lowering`], [`CodegenLLVM`], [`CodegenAsm`], [`CodegenC`] and
+//! [`CodegenWasm`]) assume the program has already been typechecked, and panic rather than
+//! return an error when it has not: every such error would be the typechecker's, and it keeps
+//! them simpler. A bug in the typechecker may therefore show up as a panic, or as wrong code, in
+//! a backend. The entry points that start from source, [`program::compile`] and the `wabbit-rs`
+//! commands, enforce this by typechecking first; a generator made directly from a [`Parser`]
+//! needs [`Typechecker::typecheck`] to have succeeded.
+//!
 //! See [`wabbit_rs`](../wabbit_rs/index.html) for command-line usage
 
 #![feature(box_syntax)]
@@ -28,20 +37,38 @@
 
 /// typecheck or interpret Wabbit AST
 pub mod analyzer;
+pub mod asm;
+pub mod c;
+pub mod coverage;
+pub mod dap;
+pub mod debugger;
 /// error reporting
 pub mod error;
 /// a code minimizer
 pub mod formatter;
+pub mod fuzz;
+/// input, output, functions and limits provided by the application embedding Wabbit
+pub mod host;
 /// interpret Wabbit AST
 pub mod interpreter;
+pub mod ir;
 /// generate LLVM IR
 pub mod llvm;
+/// lower Wabbit AST into the intermediate representation
+pub mod lower;
+pub mod native;
 /// parse Wabbit tokens
 pub mod parser;
+pub mod profile;
+pub mod program;
 /// scan Wabbit source code
 pub mod scanner;
+pub mod testing;
+/// write each statement of interpreted programs as it runs
+pub mod trace;
 /// interpret Wabbit AST
 pub mod typechecker;
+pub mod wasm;
 
 /// types for Wabbit AST
 mod ast;
@@ -67,8 +94,19 @@ pub use crate::typechecker::Typechecker;
 
 pub use crate::interpreter::Interpreter;
 
+pub use crate::lower::Lowering;
+
 pub use crate::llvm::CodegenLLVM;
 
+pub use crate::asm::CodegenAsm;
+
+pub use crate::c::CodegenC;
+
+pub use crate::wasm::CodegenWasm;
+
+pub use crate::host::Host;
+pub use crate::program::{compile, compile_with_natives, Program};
+
 pub use crate::error::*;
 pub use crate::types::{Type, WabbitType};
 
@@ -1,126 +1,462 @@
-use crate::ast::{Expr, Stmt};
-use crate::environment::Environment;
-use crate::environment::VarStore;
-use crate::operators::{BinaryOp, LogicalOp, LoopControl, UnaryOp};
+use crate::host::Natives;
+use crate::ir::{function_symbol, global_symbol, Function, Instr, Module, Operand, Terminator};
+use crate::lower::Lowering;
+use crate::operators::{BinaryOp, UnaryOp};
 use crate::parser::Parser;
-use crate::typechecker::Typechecker;
 use crate::types::Type;
 use crate::WabbitType;
-use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+/// the Wabbit runtime, using only C standard library functions so that it is target independent
+///
+/// this formats values the same way as the interpreter, see [`WabbitType::output`]. Floats are
+/// printed with the shortest `%.*e` digits that read back as the same value, then written out
+/// with `%.*f` or, for integers, as digits followed by zeros. Values are read with `scanf` and
+/// `getchar`, exiting with status 1 if the input is exhausted or invalid. Integer overflow and
+/// division by zero write an error to `stderr` and also exit with status 1, see [`Overflow`], as do
+/// failed assertions.
+const RUNTIME: &str = r#"
+@.str.int = private unnamed_addr constant [4 x i8] c"%d\0A\00"
+@.str.int64 = private unnamed_addr constant [6 x i8] c"%lld\0A\00"
+@.str.true = private unnamed_addr constant [6 x i8] c"true\0A\00"
+@.str.false = private unnamed_addr constant [7 x i8] c"false\0A\00"
+@.str.nan = private unnamed_addr constant [5 x i8] c"NaN\0A\00"
+@.str.inf = private unnamed_addr constant [4 x i8] c"%f\0A\00"
+@.str.exp = private unnamed_addr constant [5 x i8] c"%.*e\00"
+@.str.fixed = private unnamed_addr constant [6 x i8] c"%.*f\0A\00"
+@.str.digits = private unnamed_addr constant [14 x i8] c"%.*s%.*s%.*d\0A\00"
+@.str.scan_int = private unnamed_addr constant [3 x i8] c"%d\00"
+@.str.scan_float = private unnamed_addr constant [4 x i8] c"%lf\00"
+@.str.overflow = private unnamed_addr constant [25 x i8] c"error: integer overflow\0A\00"
+@.str.div_zero = private unnamed_addr constant [25 x i8] c"error: division by zero\0A\00"
+@.str.shift = private unnamed_addr constant [29 x i8] c"error: invalid shift amount\0A\00"
+
+; the C library's `stderr` stream, under the name glibc and musl give it
+@stderr = external global ptr
+
+declare i32 @printf(ptr, ...)
+declare i32 @putchar(i32)
+declare i32 @snprintf(ptr, i64, ptr, ...)
+declare double @strtod(ptr, ptr)
+declare float @strtof(ptr, ptr)
+declare ptr @strchr(ptr, i32)
+declare i32 @atoi(ptr)
+declare i32 @scanf(ptr, ...)
+declare i32 @getchar()
+declare void @exit(i32)
+declare i32 @fputs(ptr, ptr)
+declare ptr @malloc(i64)
+
+define void @_print_int(i32 %x) {
+  call i32 (ptr, ...) @printf(ptr @.str.int, i32 %x)
+  ret void
+}
 
-// NOTE
-// in this module, unlike all others, I prefer panicing to raising an Error
-// rationale:
-//   a) all errors will be caused by the typechecker
-//   b) it makes things look cleaner, and this is a "toy" implementation!!
-//
-// This may mean in some cases that a failure in the typechecker causes a silent failure
+define void @_print_int64(i64 %x) {
+  call i32 (ptr, ...) @printf(ptr @.str.int64, i64 %x)
+  ret void
+}
 
-#[derive(PartialEq)]
-enum Signal {
-    Return,
-    Unit,
-    Continue,
-    Break,
+define void @_print_uint8(i8 %x) {
+  %wide = zext i8 %x to i32
+  call void @_print_int(i32 %wide)
+  ret void
 }
 
-const RUNTIME: &str = r#"
-@.str = private unnamed_addr constant [9 x i8] c"Out: %i\0A\00", align 1
-@.str.1 = private unnamed_addr constant [10 x i8] c"Out: %lf\0A\00", align 1
-@.str.2 = private unnamed_addr constant [11 x i8] c"Out: true\0A\00", align 1
-@.str.3 = private unnamed_addr constant [12 x i8] c"Out: false\0A\00", align 1
-@.str.4 = private unnamed_addr constant [3 x i8] c"%c\00", align 1
-@stdout = external local_unnamed_addr global ptr, align 8
-
-; Function Attrs: nounwind sspstrong uwtable
-define void @_print_int(i32 noundef %0) local_unnamed_addr #0 {
-  %2 = tail call i32 (i32, ptr, ...) @__printf_chk(i32 noundef 1, ptr noundef nonnull @.str, i32 noundef %0) #3
+define void @_print_float(double %x) {
+  call void @_print_decimal(double %x, i1 false)
   ret void
 }
 
-declare i32 @__printf_chk(i32 noundef, ptr noundef, ...) local_unnamed_addr #1
+define void @_print_float32(float %x) {
+  %wide = fpext float %x to double
+  call void @_print_decimal(double %wide, i1 true)
+  ret void
+}
 
-; Function Attrs: nounwind sspstrong uwtable
-define void @_print_float(double noundef %0) local_unnamed_addr #0 {
-  %2 = tail call i32 (i32, ptr, ...) @__printf_chk(i32 noundef 1, ptr noundef nonnull @.str.1, double noundef %0) #3
+; a float32 is printed with the fewest digits that read back as the same float32
+define void @_print_decimal(double %x, i1 %single) {
+entry:
+  %buf = alloca [32 x i8]
+  %nan = fcmp uno double %x, %x
+  br i1 %nan, label %print_nan, label %check_inf
+print_nan:
+  call i32 (ptr, ...) @printf(ptr @.str.nan)
+  ret void
+check_inf:
+  %diff = fsub double %x, %x
+  %inf = fcmp uno double %diff, %diff
+  br i1 %inf, label %print_inf, label %loop
+print_inf:
+  call i32 (ptr, ...) @printf(ptr @.str.inf, double %x)
+  ret void
+loop:
+  %p = phi i32 [ 0, %check_inf ], [ %next, %retry ]
+  call i32 (ptr, i64, ptr, ...) @snprintf(ptr %buf, i64 32, ptr @.str.exp, i32 %p, double %x)
+  %last = icmp eq i32 %p, 16
+  br i1 %last, label %found, label %compare
+compare:
+  %parsed = call double @strtod(ptr %buf, ptr null)
+  %same_double = fcmp oeq double %parsed, %x
+  %parsed_float = call float @strtof(ptr %buf, ptr null)
+  %x_float = fptrunc double %x to float
+  %same_float = fcmp oeq float %parsed_float, %x_float
+  %same = select i1 %single, i1 %same_float, i1 %same_double
+  br i1 %same, label %found, label %retry
+retry:
+  %next = add i32 %p, 1
+  br label %loop
+found:
+  %e = call ptr @strchr(ptr %buf, i32 101)
+  %exp_str = getelementptr i8, ptr %e, i64 1
+  %exp = call i32 @atoi(ptr %exp_str)
+  %fixed = icmp slt i32 %exp, %p
+  br i1 %fixed, label %print_fixed, label %print_digits
+print_fixed:
+  %decimals = sub i32 %p, %exp
+  call i32 (ptr, ...) @printf(ptr @.str.fixed, i32 %decimals, double %x)
+  ret void
+print_digits:
+  %first = load i8, ptr %buf
+  %neg = icmp eq i8 %first, 45
+  %head = select i1 %neg, i32 2, i32 1
+  %head_offset = zext i32 %head to i64
+  %dot = getelementptr i8, ptr %buf, i64 %head_offset
+  %frac = getelementptr i8, ptr %dot, i64 1
+  %zeros = sub i32 %exp, %p
+  call i32 (ptr, ...) @printf(ptr @.str.digits, i32 %head, ptr %buf, i32 %p, ptr %frac, i32 %zeros, i32 0)
   ret void
 }
 
-; Function Attrs: nounwind sspstrong uwtable
-define void @_print_bool(i32 noundef %0) local_unnamed_addr #0 {
-  %2 = icmp eq i32 %0, 0
-  %3 = select i1 %2, ptr @.str.3, ptr @.str.2
-  %4 = tail call i32 (i32, ptr, ...) @__printf_chk(i32 noundef 1, ptr noundef nonnull %3) #3
+define void @_print_bool(i1 %x) {
+  %str = select i1 %x, ptr @.str.true, ptr @.str.false
+  call i32 (ptr, ...) @printf(ptr %str)
   ret void
 }
 
-; Function Attrs: nounwind sspstrong uwtable
-define void @_print_char(i8 noundef signext %0) local_unnamed_addr #0 {
-  %2 = sext i8 %0 to i32
-  %3 = tail call i32 (i32, ptr, ...) @__printf_chk(i32 noundef 1, ptr noundef nonnull @.str.4, i32 noundef %2) #3
-  %4 = load ptr, ptr @stdout, align 8, !tbaa !4
-  %5 = tail call i32 @fflush(ptr noundef %4)
+define void @_print_char(i8 %x) {
+  %c = zext i8 %x to i32
+  call i32 @putchar(i32 %c)
   ret void
 }
 
-; Function Attrs: nofree nounwind
-declare noundef i32 @fflush(ptr nocapture noundef) local_unnamed_addr #2
+define i32 @_read_int() {
+entry:
+  %x = alloca i32
+  %n = call i32 (ptr, ...) @scanf(ptr @.str.scan_int, ptr %x)
+  %ok = icmp eq i32 %n, 1
+  br i1 %ok, label %done, label %fail
+fail:
+  call void @exit(i32 1)
+  unreachable
+done:
+  %value = load i32, ptr %x
+  ret i32 %value
+}
+
+define double @_read_float() {
+entry:
+  %x = alloca double
+  %n = call i32 (ptr, ...) @scanf(ptr @.str.scan_float, ptr %x)
+  %ok = icmp eq i32 %n, 1
+  br i1 %ok, label %done, label %fail
+fail:
+  call void @exit(i32 1)
+  unreachable
+done:
+  %value = load double, ptr %x
+  ret double %value
+}
 
-attributes #0 = { nounwind sspstrong uwtable "frame-pointer"="none" "min-legal-vector-width"="0" "no-trapping-math"="true" "stack-protector-buffer-size"="4" "target-cpu"="x86-64" "target-features"="+cx8,+fxsr,+mmx,+sse,+sse2,+x87" "tune-cpu"="generic" }
-attributes #1 = { "frame-pointer"="none" "no-trapping-math"="true" "stack-protector-buffer-size"="4" "target-cpu"="x86-64" "target-features"="+cx8,+fxsr,+mmx,+sse,+sse2,+x87" "tune-cpu"="generic" }
-attributes #2 = { nofree nounwind "frame-pointer"="none" "no-trapping-math"="true" "stack-protector-buffer-size"="4" "target-cpu"="x86-64" "target-features"="+cx8,+fxsr,+mmx,+sse,+sse2,+x87" "tune-cpu"="generic" }
-attributes #3 = { nounwind }
+define void @_fail(ptr %msg) {
+  %stderr = load ptr, ptr @stderr
+  call i32 @fputs(ptr %msg, ptr %stderr)
+  call void @exit(i32 1)
+  unreachable
+}
 
-!llvm.module.flags = !{!0, !1, !2}
-!llvm.ident = !{!3}
+define void @_check_overflow(i1 %overflow) {
+entry:
+  br i1 %overflow, label %fail, label %ok
+fail:
+  call void @_fail(ptr @.str.overflow)
+  unreachable
+ok:
+  ret void
+}
 
-!0 = !{i32 1, !"wchar_size", i32 4}
-!1 = !{i32 7, !"PIC Level", i32 2}
-!2 = !{i32 7, !"uwtable", i32 2}
-!3 = !{!"clang version 15.0.7"}
-!4 = !{!5, !5, i64 0}
-!5 = !{!"any pointer", !6, i64 0}
-!6 = !{!"omnipotent char", !7, i64 0}
-!7 = !{!"Simple C/C++ TBAA"}
+define i8 @_read_char() {
+entry:
+  %c = call i32 @getchar()
+  %eof = icmp slt i32 %c, 0
+  br i1 %eof, label %fail, label %done
+fail:
+  call void @exit(i32 1)
+  unreachable
+done:
+  %value = trunc i32 %c to i8
+  ret i8 %value
+}
 "#;
 
+/// the runtime's division and shift helpers for a signed int type, `int` or `int64`
+///
+/// the minimum value divided by -1 overflows, and shifting by a negative amount or by at least
+/// the width of the type is an error in any mode
+fn signed_runtime(dtype: &Type) -> String {
+    let t = dtype.llvm_type();
+    let bits = dtype.bits();
+    let min = -(1i128 << (bits - 1));
+    format!(
+        r"
+define i1 @_check_div_{dtype}({t} %a, {t} %b) {{
+entry:
+  %zero = icmp eq {t} %b, 0
+  br i1 %zero, label %fail, label %ok
+fail:
+  call void @_fail(ptr @.str.div_zero)
+  unreachable
+ok:
+  %min = icmp eq {t} %a, {min}
+  %minus_one = icmp eq {t} %b, -1
+  %overflow = and i1 %min, %minus_one
+  ret i1 %overflow
+}}
+
+define {t} @_div_{dtype}({t} %a, {t} %b) {{
+  %overflow = call i1 @_check_div_{dtype}({t} %a, {t} %b)
+  call void @_check_overflow(i1 %overflow)
+  %q = sdiv {t} %a, %b
+  ret {t} %q
+}}
+
+define {t} @_div_{dtype}_wrap({t} %a, {t} %b) {{
+  %overflow = call i1 @_check_div_{dtype}({t} %a, {t} %b)
+  ; the minimum value divided by 1 is the wrapped result
+  %divisor = select i1 %overflow, {t} 1, {t} %b
+  %q = sdiv {t} %a, %divisor
+  ret {t} %q
+}}
+
+define {t} @_rem_{dtype}({t} %a, {t} %b) {{
+  %overflow = call i1 @_check_div_{dtype}({t} %a, {t} %b)
+  call void @_check_overflow(i1 %overflow)
+  %r = srem {t} %a, %b
+  ret {t} %r
+}}
+
+define {t} @_rem_{dtype}_wrap({t} %a, {t} %b) {{
+  %overflow = call i1 @_check_div_{dtype}({t} %a, {t} %b)
+  %divisor = select i1 %overflow, {t} 1, {t} %b
+  %r = srem {t} %a, %divisor
+  ret {t} %r
+}}
+
+define void @_check_shift_{dtype}({t} %b) {{
+entry:
+  %invalid = icmp ugt {t} %b, {max_shift}
+  br i1 %invalid, label %fail, label %ok
+fail:
+  call void @_fail(ptr @.str.shift)
+  unreachable
+ok:
+  ret void
+}}
+
+define {t} @_shl_{dtype}({t} %a, {t} %b) {{
+  call void @_check_shift_{dtype}({t} %b)
+  %r = shl {t} %a, %b
+  ret {t} %r
+}}
+
+define {t} @_shr_{dtype}({t} %a, {t} %b) {{
+  call void @_check_shift_{dtype}({t} %b)
+  %r = ashr {t} %a, %b
+  ret {t} %r
+}}
+",
+        max_shift = bits - 1
+    )
+}
+
+/// the runtime's division and shift helpers for `uint8`, which can only fail on zero or on a
+/// shift by 8 or more
+const UINT8_RUNTIME: &str = r"
+define void @_check_div_uint8(i8 %b) {
+entry:
+  %zero = icmp eq i8 %b, 0
+  br i1 %zero, label %fail, label %ok
+fail:
+  call void @_fail(ptr @.str.div_zero)
+  unreachable
+ok:
+  ret void
+}
+
+define i8 @_div_uint8(i8 %a, i8 %b) {
+  call void @_check_div_uint8(i8 %b)
+  %q = udiv i8 %a, %b
+  ret i8 %q
+}
+
+define i8 @_rem_uint8(i8 %a, i8 %b) {
+  call void @_check_div_uint8(i8 %b)
+  %r = urem i8 %a, %b
+  ret i8 %r
+}
+
+define void @_check_shift_uint8(i8 %b) {
+entry:
+  %invalid = icmp ugt i8 %b, 7
+  br i1 %invalid, label %fail, label %ok
+fail:
+  call void @_fail(ptr @.str.shift)
+  unreachable
+ok:
+  ret void
+}
+
+define i8 @_shl_uint8(i8 %a, i8 %b) {
+  call void @_check_shift_uint8(i8 %b)
+  %r = shl i8 %a, %b
+  ret i8 %r
+}
+
+define i8 @_shr_uint8(i8 %a, i8 %b) {
+  call void @_check_shift_uint8(i8 %b)
+  %r = lshr i8 %a, %b
+  ret i8 %r
+}
+";
+
+/// the runtime and its helpers for each int type
+fn runtime_helpers() -> String {
+    let int = signed_runtime(&Type::Int);
+    let int64 = signed_runtime(&Type::Int64);
+    format!("{RUNTIME}{int}{int64}{UINT8_RUNTIME}")
+}
+
+/// the platform that generated LLVM IR is compiled for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Target {
+    /// no target triple, so that clang uses the host
+    #[default]
+    Generic,
+    X86_64Linux,
+    Aarch64Linux,
+}
+
+impl Target {
+    /// the LLVM target triple, if any
+    pub fn triple(&self) -> Option<&'static str> {
+        match self {
+            Self::Generic => None,
+            Self::X86_64Linux => Some("x86_64-unknown-linux-gnu"),
+            Self::Aarch64Linux => Some("aarch64-unknown-linux-gnu"),
+        }
+    }
+
+    /// the runtime for this target, preceded by its target triple
+    pub fn runtime(&self) -> String {
+        match self.triple() {
+            Some(triple) => format!("target triple = \"{triple}\"\n{}", runtime_helpers()),
+            None => runtime_helpers(),
+        }
+    }
+}
+
+impl std::str::FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "generic" => Ok(Self::Generic),
+            "x86_64" | "x86_64-unknown-linux-gnu" => Ok(Self::X86_64Linux),
+            "aarch64" | "aarch64-unknown-linux-gnu" => Ok(Self::Aarch64Linux),
+            _ => Err(format!(
+                "unknown target `{s}`, expected one of generic, x86_64, aarch64"
+            )),
+        }
+    }
+}
+
+/// how generated code handles int overflow
+///
+/// either way, division by zero is an error, as it is in the interpreter
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    /// exit with an error, as the interpreter does, at the cost of a check for each operation
+    #[default]
+    Trap,
+    /// wrap around in two's complement
+    Wrap,
+}
+
+impl std::str::FromStr for Overflow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "trap" => Ok(Self::Trap),
+            "wrap" => Ok(Self::Wrap),
+            _ => Err(format!(
+                "unknown overflow `{s}`, expected one of trap, wrap"
+            )),
+        }
+    }
+}
+
+/// declarations for LLVM intrinsics used by the generated code
+const INTRINSICS: &str = "declare i32 @llvm.fptosi.sat.i32.f64(double)
+declare {i32, i1} @llvm.sadd.with.overflow.i32(i32, i32)
+declare {i32, i1} @llvm.ssub.with.overflow.i32(i32, i32)
+declare {i32, i1} @llvm.smul.with.overflow.i32(i32, i32)
+declare i32 @llvm.fptosi.sat.i32.f32(float)
+declare i64 @llvm.fptosi.sat.i64.f64(double)
+declare i64 @llvm.fptosi.sat.i64.f32(float)
+declare i8 @llvm.fptoui.sat.i8.f64(double)
+declare i8 @llvm.fptoui.sat.i8.f32(float)
+declare {i64, i1} @llvm.sadd.with.overflow.i64(i64, i64)
+declare {i64, i1} @llvm.ssub.with.overflow.i64(i64, i64)
+declare {i64, i1} @llvm.smul.with.overflow.i64(i64, i64)
+declare {i8, i1} @llvm.uadd.with.overflow.i8(i8, i8)
+declare {i8, i1} @llvm.usub.with.overflow.i8(i8, i8)
+declare {i8, i1} @llvm.umul.with.overflow.i8(i8, i8)";
+
+/// Struct for generating LLVM IR, by way of the crate's own IR (see [`crate::ir`])
 pub struct CodegenLLVM<'a> {
-    /// a typechecker
-    analyze: Typechecker<'a>,
-    /// used to generate unique names
-    counter: usize,
-    /// stack of labels that a continue statement could jump to
-    continue_labels: Vec<String>,
-    /// stack of labels that a break statement could jump to
-    break_labels: Vec<String>,
-    /// map of Wabbit variable names to LLVM variable names
-    var_names: Environment<'a, String>,
-    /// set of LLVM globals, for any variable defined in global scope
-    global_vars: HashSet<String>,
-    /// raw LLVM strings in the global space
-    globals: Vec<String>,
-    /// raw LLVM strings in the main function
-    main: Vec<String>,
-    /// raw LLVM strings for functions
-    func_llvm: Vec<String>,
+    /// lowers the AST into IR
+    lowering: Lowering<'a>,
+    /// the platform to generate code for
+    pub target: Target,
+    /// how int arithmetic handles overflow
+    pub overflow: Overflow,
 }
 
 impl<'a> From<&'a Parser<'a>> for CodegenLLVM<'a> {
     fn from(parser: &'a Parser) -> Self {
         Self {
-            analyze: Typechecker::from(parser),
-            counter: 0,
-            continue_labels: Vec::new(),
-            break_labels: Vec::new(),
-            var_names: Environment::new(),
-            globals: Vec::new(),
-            main: Vec::new(),
-            func_llvm: Vec::new(),
-            global_vars: HashSet::new(),
+            lowering: Lowering::from(parser),
+            target: Target::default(),
+            overflow: Overflow::default(),
         }
     }
 }
 
+/// the contents of an LLVM string constant, with quotes, backslashes and unprintable bytes
+/// written as `\XX`
+fn llvm_string(bytes: &[u8]) -> String {
+    let mut string = String::new();
+    for &byte in bytes {
+        if (byte.is_ascii_graphic() || byte == b' ') && !matches!(byte, b'"' | b'\\') {
+            string.push(char::from(byte));
+        } else {
+            write!(string, "\\{byte:02X}").unwrap();
+        }
+    }
+    string
+}
+
 // some implementations to convert values/types to LLVM
 
 impl WabbitType {
@@ -128,8 +464,12 @@ impl WabbitType {
     pub fn llvm_value(&self) -> String {
         match self {
             WabbitType::Int(val) => val.to_string(),
+            WabbitType::Int64(val) => val.to_string(),
+            WabbitType::Uint8(val) => i8::from_ne_bytes([*val]).to_string(),
             WabbitType::Bool(val) => val.to_string(),
             WabbitType::Char(val) => (*val as i8).to_string(),
+            // float constants are written as the double with the same value
+            WabbitType::Float32(val) => WabbitType::Float(f64::from(*val)).llvm_value(),
             // there's likely some edge cases, but this is fine by me!
             WabbitType::Float(val) => {
                 let bytes = val.to_be_bytes();
@@ -139,7 +479,7 @@ impl WabbitType {
                 );
                 hex
             }
-            WabbitType::TypeHolder(..) => panic!("typecheck failure"),
+            WabbitType::Func(..) | WabbitType::TypeHolder(..) => panic!("typecheck failure"),
         }
     }
 }
@@ -148,8 +488,9 @@ impl Type {
     /// transform a type into an LLVM string for zero
     pub fn global_init(&self) -> String {
         match self {
-            Type::Int | Type::Bool | Type::Char => "0".to_string(),
-            Type::Float => "0x0".to_string(),
+            Type::Float | Type::Float32 => "0x0".to_string(),
+            Type::Func(_) => "null".to_string(),
+            _ => "0".to_string(),
         }
     }
 
@@ -157,551 +498,501 @@ impl Type {
     pub fn llvm_type(&self) -> String {
         match self {
             Type::Int => "i32".to_string(),
+            Type::Int64 => "i64".to_string(),
             Type::Float => "double".to_string(),
+            Type::Float32 => "float".to_string(),
             Type::Bool => "i1".to_string(),
-            Type::Char => "i8".to_string(),
+            Type::Char | Type::Uint8 => "i8".to_string(),
+            // a function value points to its function followed by the values it captured
+            Type::Func(_) => "ptr".to_string(),
         }
     }
 }
 
-impl<'a> CodegenLLVM<'a> {
-    /// transform AST into LLVM IR
-    pub fn llvm_codegen(&mut self) -> String {
-        for stmt in self.analyze.statements {
-            self.llvm_stmt(stmt);
-        }
-        let combine = vec![
-            "target triple = \"x86_64-unknown-linux-gnu\"".to_string(),
-            RUNTIME.to_string(),
-            self.func_llvm.join("\n"),
-            self.globals.join("\n"),
-            "define void @main() \n{\nentry:".to_string(),
-            self.main.join("\n"),
-            "\tret void\n}".to_string(),
-        ];
-        combine.join("\n")
-    }
-
-    // misc utilities for generating unique names
-    // for simpicity, they all share the counter
+/// the LLVM struct type of a function value, which holds its function and captured values
+fn closure_type(captures: &[Type]) -> String {
+    let fields: Vec<String> = std::iter::once("ptr".to_string())
+        .chain(captures.iter().map(Type::llvm_type))
+        .collect();
+    format!("{{{}}}", fields.join(", "))
+}
 
-    /// generate a unique label name
-    fn label_name(&mut self, prefix: &str) -> String {
-        self.counter += 1;
-        format!("{}_{}", prefix, self.counter)
+impl Operand {
+    /// transform an IR operand into an LLVM string
+    pub fn llvm_operand(&self) -> String {
+        match self {
+            Operand::Reg(reg) => format!("%.{}", reg.0),
+            Operand::Const(value) => value.llvm_value(),
+            Operand::Global(name) => format!("@{}", global_symbol(name)),
+        }
     }
+}
 
-    /// generate a unique variable name
-    fn tmp_name(&mut self) -> String {
-        self.counter += 1;
-        format!("%.{}", self.counter)
+impl<'a> CodegenLLVM<'a> {
+    /// allow calls to native functions, declared as external functions to be linked separately
+    pub fn with_natives(mut self, natives: &'a Natives) -> Self {
+        self.lowering = self.lowering.with_natives(natives);
+        self
     }
 
-    /// get the current variable name
-    fn tmp_no_inc(&self) -> String {
-        format!("%.{}", self.counter)
+    /// transform AST into LLVM IR
+    pub fn llvm_codegen(&mut self) -> String {
+        let module = self.lowering.lower();
+        Self::llvm_module(&module, self.target, self.overflow)
     }
 
-    // determines if we are currently build a function call
-    // and gives a (mutable) reference to the corresponding Vec
-
-    /// a reference to either the LLVM main or global space
-    fn loc(&mut self) -> &mut Vec<String> {
-        if self.analyze.call_depth > 0 {
-            &mut self.func_llvm
-        } else {
-            &mut self.main
+    /// transform a module of the crate's IR into LLVM IR for a target
+    pub fn llvm_module(module: &Module, target: Target, overflow: Overflow) -> String {
+        let globals = module
+            .globals
+            .iter()
+            .map(|global| {
+                let llvm_type = global.dtype.llvm_type();
+                let init = global.dtype.global_init();
+                format!(
+                    "@{} = global {llvm_type} {init}",
+                    global_symbol(&global.name)
+                )
+            })
+            .collect::<Vec<String>>();
+
+        let externs = module
+            .externs
+            .iter()
+            .map(|ext| {
+                let params = ext
+                    .params
+                    .iter()
+                    .map(Type::llvm_type)
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                format!(
+                    "declare {} @{}({params})",
+                    ext.return_type.llvm_type(),
+                    ext.name
+                )
+            })
+            .collect::<Vec<String>>();
+
+        // the messages of `fail` terminators, each written once as a constant
+        let mut messages: Vec<&str> = Vec::new();
+        for block in module.functions.iter().flat_map(|func| &func.blocks) {
+            if let Some(Terminator::Fail(message)) = &block.term {
+                if !messages.contains(&message.as_str()) {
+                    messages.push(message);
+                }
+            }
         }
+        let constants = messages
+            .iter()
+            .enumerate()
+            .map(|(i, message)| {
+                let bytes = format!("error: {message}\n\0").into_bytes();
+                format!(
+                    "@.str.fail.{i} = private unnamed_addr constant [{} x i8] c\"{}\"",
+                    bytes.len(),
+                    llvm_string(&bytes)
+                )
+            })
+            .collect::<Vec<String>>();
+
+        let functions = module
+            .functions
+            .iter()
+            .map(|func| Self::llvm_function(func, module, overflow, &messages))
+            .collect::<Vec<String>>();
+
+        let combine = [
+            target.runtime(),
+            INTRINSICS.to_string(),
+            externs.join("\n"),
+            globals.join("\n"),
+            constants.join("\n"),
+            functions.join("\n"),
+        ];
+        combine.join("\n")
     }
 
-    // utilities to use both the type and name environment at the same time
-    /// enter child environment for variable names and types
-    fn enter_child(&mut self) {
-        self.analyze.env.enter_child();
-        self.var_names.enter_child();
-    }
+    /// generate LLVM for a single function
+    fn llvm_function(
+        func: &Function,
+        module: &Module,
+        overflow: Overflow,
+        messages: &[&str],
+    ) -> String {
+        let return_type = func.return_type.llvm_type();
+        let name = function_symbol(&func.name);
+        let args = func
+            .params
+            .iter()
+            .map(|(reg, dtype)| {
+                format!(
+                    "{} {}",
+                    dtype.llvm_type(),
+                    Operand::Reg(*reg).llvm_operand()
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        let mut lines = vec![format!("\ndefine {return_type} @{name}({args})\n{{")];
+
+        for block in &func.blocks {
+            lines.push(format!("{}:", block.label));
+            for instr in &block.instrs {
+                lines.push(Self::llvm_instr(instr, func, module, overflow));
+            }
+            let term = block.term.as_ref().expect("IR block without a terminator");
+            lines.push(Self::llvm_terminator(term, messages));
+        }
 
-    /// exit child environment for variable names and types
-    fn exit_child_unwrap(&mut self) {
-        self.analyze.env.exit_child_unwrap();
-        self.var_names.exit_child_unwrap();
+        lines.push("}".to_string());
+        lines.join("\n")
     }
 
     /// utility for generating binary instructions
-    fn binary_ops(t: Type, op: &BinaryOp) -> String {
-        let s = match (t, op) {
-            (Type::Int, BinaryOp::Plus) => "add",
-            (Type::Int, BinaryOp::Minus) => "sub",
-            (Type::Int, BinaryOp::Times) => "mul",
-            (Type::Int, BinaryOp::Divide) => "sdiv",
-            (Type::Float, BinaryOp::Plus) => "fadd",
-            (Type::Float, BinaryOp::Minus) => "fsub",
-            (Type::Float, BinaryOp::Times) => "fmul",
-            (Type::Float, BinaryOp::Divide) => "fdiv",
-            (Type::Bool | Type::Int | Type::Char, BinaryOp::EqualEqual) => "icmp eq",
-            (Type::Bool | Type::Int | Type::Char, BinaryOp::NotEqual) => "icmp ne",
-            (Type::Float, BinaryOp::EqualEqual) => "fcmp oeq",
-            (Type::Float, BinaryOp::NotEqual) => "fcmp one",
-            (Type::Int | Type::Char, BinaryOp::Less) => "icmp slt",
-            (Type::Int | Type::Char, BinaryOp::LessEqual) => "icmp sle",
-            (Type::Int | Type::Char, BinaryOp::Greater) => "icmp sgt",
-            (Type::Int | Type::Char, BinaryOp::GreaterEqual) => "icmp sge",
-            (Type::Float, BinaryOp::Less) => "fcmp olt",
-            (Type::Float, BinaryOp::LessEqual) => "fcmp ole",
-            (Type::Float, BinaryOp::Greater) => "fcmp ogt",
-            (Type::Float, BinaryOp::GreaterEqual) => "fcmp oge",
+    fn binary_ops(t: &Type, op: &BinaryOp) -> String {
+        // uint8 is the only unsigned type
+        let unsigned = *t == Type::Uint8;
+        let s = match (t.is_float(), op) {
+            (false, BinaryOp::Plus) => "add",
+            (false, BinaryOp::Minus) => "sub",
+            (false, BinaryOp::Times) => "mul",
+            (false, BinaryOp::Divide) if unsigned => "udiv",
+            (false, BinaryOp::Divide) => "sdiv",
+            (false, BinaryOp::BitAnd) => "and",
+            (false, BinaryOp::BitOr) => "or",
+            (false, BinaryOp::BitXor) => "xor",
+            (true, BinaryOp::Plus) => "fadd",
+            (true, BinaryOp::Minus) => "fsub",
+            (true, BinaryOp::Times) => "fmul",
+            (true, BinaryOp::Divide) => "fdiv",
+            (false, BinaryOp::EqualEqual) => "icmp eq",
+            (false, BinaryOp::NotEqual) => "icmp ne",
+            (true, BinaryOp::EqualEqual) => "fcmp oeq",
+            (true, BinaryOp::NotEqual) => "fcmp une",
+            (false, BinaryOp::Less) if unsigned => "icmp ult",
+            (false, BinaryOp::LessEqual) if unsigned => "icmp ule",
+            (false, BinaryOp::Greater) if unsigned => "icmp ugt",
+            (false, BinaryOp::GreaterEqual) if unsigned => "icmp uge",
+            (false, BinaryOp::Less) => "icmp slt",
+            (false, BinaryOp::LessEqual) => "icmp sle",
+            (false, BinaryOp::Greater) => "icmp sgt",
+            (false, BinaryOp::GreaterEqual) => "icmp sge",
+            (true, BinaryOp::Less) => "fcmp olt",
+            (true, BinaryOp::LessEqual) => "fcmp ole",
+            (true, BinaryOp::Greater) => "fcmp ogt",
+            (true, BinaryOp::GreaterEqual) => "fcmp oge",
             _ => panic!("typecheck failure"),
         };
         s.to_string()
     }
 
-    /// generate LLVM for a single statement
-    fn llvm_stmt(&mut self, stmt: &'a Stmt) -> Signal {
-        match stmt {
-            Stmt::LoopControl { control, .. } => match control {
-                LoopControl::Break => Signal::Break,
-                LoopControl::Continue => Signal::Continue,
-            },
-            Stmt::While {
-                condition, body, ..
-            } => {
-                // we make a label for the test, which we will return to until the condition is met
-                let test_label = self.label_name("while_cond");
-                let after_label = self.label_name("after_while");
-                let body_label = self.label_name("while_body");
-
-                self.break_labels.push(after_label.clone());
-                self.continue_labels.push(test_label.clone());
-
-                // first the test label, check the condition
-                // we either jump to the body, or to the after
-                self.loc().push(format!("\tbr label %{test_label}"));
-                self.loc().push(format!("{test_label}:"));
-                let cond_compile = self.llvm_expr(condition);
-                self.loc().push(format!(
-                    "\tbr i1 {cond_compile}, label %{body_label}, label %{after_label}"
-                ));
-
-                self.analyze.loop_depth += 1;
-                self.enter_child();
-                // now create the body, at the end of which we go back to the test
-                self.loc().push(format!("{body_label}:"));
-                self.llvm_stmt(body);
-                self.loc().push(format!("\tbr label %{test_label}"));
-                self.analyze.loop_depth -= 1;
-                self.exit_child_unwrap();
-
-                // and lastly, the rest of the program
-                self.loc().push(format!("{after_label}:"));
-                Signal::Unit
-            }
-            Stmt::If {
-                condition,
-                then_block,
-                maybe_else_block,
-                ..
-            } => {
-                let cond_compile = self.llvm_expr(condition);
-
-                // label for then_block
-                let after_label = self.label_name("after_if");
-                let then_label = self.label_name("then");
-
-                // I assume that return signals match up
-                let mut has_return = false;
-
-                if let Some(else_block) = maybe_else_block {
-                    let else_label = self.label_name("else");
-
-                    // the comparison, jumping to either then/else
-                    self.loc().push(format!(
-                        "\tbr i1 {cond_compile}, label %{then_label}, label %{else_label}"
-                    ));
+    /// generate LLVM for int arithmetic that exits on overflow, using an intrinsic that returns
+    /// the wrapped result and whether it overflowed
+    fn checked_int(dest: &str, op: &str, dtype: &Type, lhs: &str, rhs: &str) -> String {
+        let t = dtype.llvm_type();
+        let sign = if *dtype == Type::Uint8 { "u" } else { "s" };
+        let intrinsic = format!("@llvm.{sign}{op}.with.overflow.{t}");
+        [
+            format!("\t{dest}.checked = call {{{t}, i1}} {intrinsic}({t} {lhs}, {t} {rhs})"),
+            format!("\t{dest} = extractvalue {{{t}, i1}} {dest}.checked, 0"),
+            format!("\t{dest}.overflow = extractvalue {{{t}, i1}} {dest}.checked, 1"),
+            format!("\tcall void @_check_overflow(i1 {dest}.overflow)"),
+        ]
+        .join("\n")
+    }
 
-                    // both blocks, after which they both go to the after label
-                    self.loc().push(format!("{then_label}:"));
-                    self.enter_child();
-                    let then_signal = self.llvm_stmt(then_block);
+    /// generate LLVM for a conversion between two different types
+    fn convert(from: &Type, to: &Type, value: &str) -> String {
+        let from_type = from.llvm_type();
+        let to_type = to.llvm_type();
+        let signed = matches!(from, Type::Int | Type::Int64);
+        if *to == Type::Bool {
+            // only exactly 1 is true, as in the interpreter
+            format!("icmp eq {from_type} {value}, 1")
+        } else if from.is_float() && to.is_float() {
+            let cast = if to.bits() > from.bits() {
+                "fpext"
+            } else {
+                "fptrunc"
+            };
+            format!("{cast} {from_type} {value} to {to_type}")
+        } else if from.is_float() {
+            // saturating, which matches the interpreter's `as`
+            let sat = if *to == Type::Uint8 {
+                "fptoui"
+            } else {
+                "fptosi"
+            };
+            let bits = from.bits();
+            format!("call {to_type} @llvm.{sat}.sat.{to_type}.f{bits}({from_type} {value})")
+        } else if to.is_float() {
+            let cast = if signed { "sitofp" } else { "uitofp" };
+            format!("{cast} {from_type} {value} to {to_type}")
+        } else {
+            let cast = match from.bits().cmp(&to.bits()) {
+                std::cmp::Ordering::Less if signed => "sext",
+                std::cmp::Ordering::Less => "zext",
+                std::cmp::Ordering::Greater => "trunc",
+                // chars and uint8s have the same bits
+                std::cmp::Ordering::Equal => "bitcast",
+            };
+            format!("{cast} {from_type} {value} to {to_type}")
+        }
+    }
 
-                    if then_signal == Signal::Return {
-                        has_return = true;
+    /// generate LLVM for a single instruction
+    fn llvm_instr(instr: &Instr, func: &Function, module: &Module, overflow: Overflow) -> String {
+        match instr {
+            Instr::Alloca { dest, dtype } => {
+                let dest = Operand::Reg(*dest).llvm_operand();
+                format!("\t{dest} = alloca {}", dtype.llvm_type())
+            }
+            Instr::Load { dest, dtype, ptr } => {
+                let dest = Operand::Reg(*dest).llvm_operand();
+                let ptr = ptr.llvm_operand();
+                format!("\t{dest} = load {}, ptr {ptr}", dtype.llvm_type())
+            }
+            Instr::Store { dtype, value, ptr } => {
+                let value = value.llvm_operand();
+                let ptr = ptr.llvm_operand();
+                format!("\tstore {} {value}, ptr {ptr}", dtype.llvm_type())
+            }
+            Instr::Binary {
+                dest,
+                op,
+                dtype,
+                lhs,
+                rhs,
+            } => {
+                let dest = Operand::Reg(*dest).llvm_operand();
+                let lhs = lhs.llvm_operand();
+                let rhs = rhs.llvm_operand();
+                let t = dtype.llvm_type();
+                match (op, overflow) {
+                    (BinaryOp::Divide | BinaryOp::Modulo, _) if dtype.is_integer() => {
+                        let name = if *op == BinaryOp::Divide {
+                            "div"
+                        } else {
+                            "rem"
+                        };
+                        // uint8 division can't overflow
+                        let wrap = if overflow == Overflow::Wrap && *dtype != Type::Uint8 {
+                            "_wrap"
+                        } else {
+                            ""
+                        };
+                        format!("\t{dest} = call {t} @_{name}_{dtype}{wrap}({t} {lhs}, {t} {rhs})")
                     }
-
-                    self.exit_child_unwrap();
-
-                    if !has_return {
-                        self.loc().push(format!("\tbr label %{after_label}"));
+                    (BinaryOp::ShiftLeft | BinaryOp::ShiftRight, _) => {
+                        let name = if *op == BinaryOp::ShiftLeft {
+                            "shl"
+                        } else {
+                            "shr"
+                        };
+                        format!("\t{dest} = call {t} @_{name}_{dtype}({t} {lhs}, {t} {rhs})")
                     }
-
-                    self.loc().push(format!("{else_label}:"));
-                    self.enter_child();
-                    self.llvm_stmt(else_block);
-
-                    if !has_return {
-                        self.loc().push(format!("\tbr label %{after_label}"));
+                    (BinaryOp::Plus | BinaryOp::Minus | BinaryOp::Times, Overflow::Trap)
+                        if dtype.is_integer() =>
+                    {
+                        let op = Self::binary_ops(dtype, op);
+                        Self::checked_int(&dest, &op, dtype, &lhs, &rhs)
+                    }
+                    _ => {
+                        let op = Self::binary_ops(dtype, op);
+                        format!("\t{dest} = {op} {t} {lhs}, {rhs}")
                     }
-
-                    self.exit_child_unwrap();
-                } else {
-                    // the comparison, jumping to after if false since no else
-                    self.loc().push(format!(
-                        "\tbr i1 {cond_compile}, label %{then_label}, label %{after_label}"
-                    ));
-
-                    // just the then block
-                    self.loc().push(format!("{then_label}:"));
-                    self.enter_child();
-                    self.llvm_stmt(then_block);
-                    self.exit_child_unwrap();
-                    self.loc().push(format!("\tbr label %{after_label}"));
                 }
-                if !has_return {
-                    self.loc().push(format!("{after_label}:"));
+            }
+            Instr::Unary {
+                dest,
+                op,
+                dtype,
+                operand,
+            } => {
+                let dest = Operand::Reg(*dest).llvm_operand();
+                let operand = operand.llvm_operand();
+                let t = dtype.llvm_type();
+                match (dtype, op) {
+                    (Type::Int | Type::Int64, UnaryOp::Minus) => match overflow {
+                        Overflow::Trap => Self::checked_int(&dest, "sub", dtype, "0", &operand),
+                        Overflow::Wrap => format!("\t{dest} = sub {t} 0, {operand}"),
+                    },
+                    (Type::Float | Type::Float32, UnaryOp::Minus) => {
+                        format!("\t{dest} = fneg {t} {operand}")
+                    }
+                    (Type::Bool, UnaryOp::LogicalNot) => {
+                        format!("\t{dest} = xor i1 {operand}, true")
+                    }
+                    _ => panic!("typecheck failure"),
                 }
-                Signal::Unit
             }
-            Stmt::Expr(e) => {
-                self.llvm_expr(e);
-                Signal::Unit
+            Instr::Convert {
+                dest,
+                from,
+                to,
+                value,
+            } => {
+                let dest = Operand::Reg(*dest).llvm_operand();
+                let value = value.llvm_operand();
+                format!("\t{dest} = {}", Self::convert(from, to, &value))
             }
-            Stmt::Assign { name, value, .. } => {
-                let name_lookup = self.var_names.get(name).unwrap().clone_store();
-                let dtype = self.analyze.expr_type(value).unwrap();
-                let llvm_type = dtype.llvm_type();
-                let compiled_value = self.llvm_expr(value);
-                self.analyze.env.assign(name, dtype);
-
-                if self.global_vars.contains(&name_lookup) {
-                    self.loc().push(format!(
-                        "\tstore {llvm_type} {compiled_value}, {llvm_type}* @{name}"
-                    ));
+            Instr::Call {
+                dest,
+                ret,
+                func,
+                args,
+            } => {
+                let dest = Operand::Reg(*dest).llvm_operand();
+                let args = args
+                    .iter()
+                    .map(|(dtype, arg)| format!("{} {}", dtype.llvm_type(), arg.llvm_operand()))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                // external functions keep their own name, to be linked from elsewhere
+                let symbol = if module.extern_fn(func).is_some() {
+                    func.clone()
                 } else {
-                    // in case it was previously only declared
-                    self.analyze.env.assign(name, dtype);
-                    let name = self.var_names.get(name).unwrap().clone_store();
-                    let llvm_type = dtype.llvm_type();
-                    self.loc().push(format!(
-                        "\tstore {llvm_type} {compiled_value}, {llvm_type}* {name}"
-                    ));
-                }
-                Signal::Unit
+                    function_symbol(func)
+                };
+                format!("\t{dest} = call {} @{symbol}({args})", ret.llvm_type())
             }
-            Stmt::FuncDef {
-                def_name,
-                def_params,
-                return_type,
-                body,
+            Instr::Closure {
+                dest,
+                func: name,
+                captures,
                 ..
             } => {
-                let return_type = return_type.llvm_type();
-                let mut param_names: HashMap<&String, VarStore<String>> = HashMap::new();
-                let mut param_types: HashMap<&String, VarStore<Type>> = HashMap::new();
-                let mut args = Vec::new();
-                let mut queue = Vec::new();
-
-                for (name, dtype) in def_params {
-                    let tmp_name = self.tmp_name();
-                    let llvm_type = dtype.llvm_type();
-
-                    args.push(format!("{llvm_type} {tmp_name}"));
-                    queue.push(format!("\t%{name} = alloca {llvm_type}"));
-                    queue.push(format!(
-                        "\tstore {llvm_type} {tmp_name}, {llvm_type}* %{name}"
+                let dest = Operand::Reg(*dest).llvm_operand();
+                let captured: Vec<Type> = captures.iter().map(|(dtype, _)| dtype.clone()).collect();
+                let struct_type = closure_type(&captured);
+                // the size of the struct is the address of the second one in an array at null
+                let mut lines = vec![
+                    format!("	{dest}.size = getelementptr {struct_type}, ptr null, i32 1"),
+                    format!("	{dest}.bytes = ptrtoint ptr {dest}.size to i64"),
+                    format!("	{dest} = call ptr @malloc(i64 {dest}.bytes)"),
+                    format!("	store ptr @{}, ptr {dest}", function_symbol(name)),
+                ];
+                for (i, (dtype, value)) in captures.iter().enumerate() {
+                    let field = i + 1;
+                    lines.push(format!(
+                        "	{dest}.{field} = getelementptr {struct_type}, ptr {dest}, i32 0, i32 {field}"
+                    ));
+                    lines.push(format!(
+                        "	store {} {}, ptr {dest}.{field}",
+                        dtype.llvm_type(),
+                        value.llvm_operand()
                     ));
-
-                    param_names.insert(name, VarStore::Init(format!("%{name}")));
-                    param_types.insert(name, VarStore::Init(*dtype));
-                }
-
-                let args = args.join(", ");
-
-                self.func_llvm
-                    .push(format!("\ndefine {return_type} @{def_name} ({args})\n{{"));
-
-                for item in queue {
-                    self.func_llvm.push(item);
-                }
-
-                self.analyze.functions.insert(def_name, stmt);
-                self.analyze.env.enter_child_fn(param_types);
-                self.var_names.enter_child_fn(param_names);
-                self.analyze.call_depth += 1;
-
-                self.llvm_stmt(body);
-
-                self.analyze.env.exit_child_unwrap();
-                self.var_names.exit_child_unwrap();
-                self.analyze.call_depth -= 1;
-                self.func_llvm.push("}\n".to_string());
-
-                Signal::Unit
-            }
-            Stmt::Block { statements, .. } => {
-                let mut signal = Signal::Unit;
-
-                for stmt in statements {
-                    signal = self.llvm_stmt(stmt);
-                    match signal {
-                        Signal::Unit => (),
-                        Signal::Return => {
-                            break;
-                        }
-                        Signal::Break | Signal::Continue => {
-                            let label = if signal == Signal::Break {
-                                self.break_labels.pop().unwrap()
-                            } else {
-                                self.continue_labels.pop().unwrap()
-                            };
-                            let jmp = format!("\tbr label %{label}");
-                            self.loc().push(jmp);
-                            break;
-                        }
-                    }
                 }
-                // if we got to the end of a loop w/o break/continue, pop off those labels
-                if self.analyze.loop_depth > self.break_labels.iter().len() {
-                    self.break_labels.pop();
-                };
-                if self.analyze.loop_depth > self.continue_labels.iter().len() {
-                    self.continue_labels.pop();
-                };
-                signal
+                lines.join("\n")
             }
-            Stmt::Return { value, .. } => {
-                let value_compile = self.llvm_expr(value);
-                let llvm_type = self.analyze.expr_type(value).unwrap().llvm_type();
-                self.loc()
-                    .push(format!("\tret {llvm_type} {value_compile}"));
-                Signal::Return
+            Instr::Capture { dest, dtype, index } => {
+                let dest = Operand::Reg(*dest).llvm_operand();
+                let struct_type = closure_type(&func.captures);
+                let field = index + 1;
+                [
+                    format!(
+                        "	{dest}.ptr = getelementptr {struct_type}, ptr %.0, i32 0, i32 {field}"
+                    ),
+                    format!("	{dest} = load {}, ptr {dest}.ptr", dtype.llvm_type()),
+                ]
+                .join("\n")
             }
-            Stmt::VarDef {
-                name,
-                maybe_type,
-                maybe_value,
-                ..
+            Instr::CallValue {
+                dest,
+                ret,
+                callee,
+                args,
             } => {
-                // first, determine the type
-                let dtype = if let Some(t) = maybe_type {
-                    *t
-                } else {
-                    self.analyze
-                        .expr_type(&maybe_value.clone().unwrap())
-                        .unwrap()
-                };
-
-                let llvm_type = dtype.llvm_type();
-                self.analyze.env.define_init(name, dtype);
-                let tmp_name = self.tmp_name();
-
-                // the definition varies depending on the scope
-                if self.analyze.env.in_global_scope() {
-                    let fmt_name = format!("@{name}");
-                    let init = dtype.global_init();
-                    self.globals
-                        .push(format!("{fmt_name} = global {llvm_type} {init}"));
-                    self.global_vars.insert(fmt_name.clone());
-                    self.var_names.define_init(name, fmt_name);
-                } else {
-                    self.loc()
-                        .push(format!("\t{tmp_name} = alloca {llvm_type}"));
-                    self.var_names.define_init(name, tmp_name.clone());
-                };
-
-                if let Some(value) = maybe_value {
-                    let compiled_value = self.llvm_expr(value);
-                    self.analyze.env.define_init(name, dtype);
-
-                    if self.analyze.env.in_global_scope() {
-                        self.loc().push(format!(
-                            "\tstore {llvm_type} {compiled_value}, {llvm_type}* @{name}"
-                        ));
-                    } else {
-                        self.loc().push(format!(
-                            "\tstore {llvm_type} {compiled_value}, {llvm_type}* {tmp_name}",
-                        ));
-                    }
-                }
-
-                Signal::Unit
+                let dest = Operand::Reg(*dest).llvm_operand();
+                let callee = callee.llvm_operand();
+                // the function value is passed first, so it can read its captured values
+                let args = std::iter::once(format!("ptr {callee}"))
+                    .chain(args.iter().map(|(dtype, arg)| {
+                        format!("{} {}", dtype.llvm_type(), arg.llvm_operand())
+                    }))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                [
+                    format!("	{dest}.code = load ptr, ptr {callee}"),
+                    format!("	{dest} = call {} {dest}.code({args})", ret.llvm_type()),
+                ]
+                .join("\n")
             }
-            Stmt::ConstDef { name, value, .. } => {
-                let dtype = self.analyze.expr_type(value).expect("typechecking failure");
-                let llvm_type = dtype.llvm_type();
-                let compiled_value = self.llvm_expr(value);
-                let init = dtype.global_init();
-                self.globals
-                    .push(format!("@{name} = global {llvm_type} {init}"));
-                self.global_vars.insert(name.to_string());
-                self.analyze.env.define_init(name, dtype);
-                self.var_names.define_init(name, format!("@{name}"));
-                self.analyze.constants.insert(name, dtype);
-                self.loc().push(format!(
-                    "\tstore {llvm_type} {compiled_value}, {llvm_type}* @{name}"
-                ));
-                Signal::Unit
+            Instr::Print { dtype, value } => {
+                let value = value.llvm_operand();
+                match dtype {
+                    Type::Int => format!("\tcall void @_print_int(i32 {value})"),
+                    Type::Int64 => format!("\tcall void @_print_int64(i64 {value})"),
+                    Type::Uint8 => format!("\tcall void @_print_uint8(i8 {value})"),
+                    Type::Float => format!("\tcall void @_print_float(double {value})"),
+                    Type::Float32 => format!("\tcall void @_print_float32(float {value})"),
+                    Type::Char => format!("\tcall void @_print_char(i8 {value})"),
+                    Type::Bool => format!("\tcall void @_print_bool(i1 {value})"),
+                    Type::Func(_) => panic!("typecheck failure"),
+                }
             }
-            Stmt::Print { value, .. } => {
-                let dtype = self.analyze.expr_type(value).unwrap();
-                let print_arg = self.llvm_expr(value);
-                let print = match dtype {
-                    Type::Int => format!("\tcall void @_print_int(i32 {print_arg})"),
-                    Type::Float => format!("\tcall void @_print_float(double {print_arg})"),
-                    Type::Char => format!("\tcall void @_print_char(i8 {print_arg})"),
-                    Type::Bool => format!("\tcall void @_print_bool(i1 {print_arg})"),
+            Instr::Read { dest, dtype } => {
+                let dest = Operand::Reg(*dest).llvm_operand();
+                let runtime = match dtype {
+                    Type::Int => "_read_int",
+                    Type::Float => "_read_float",
+                    Type::Char => "_read_char",
+                    _ => panic!("typecheck failure"),
                 };
-                self.loc().push(print);
-                Signal::Unit
+                format!("\t{dest} = call {} @{runtime}()", dtype.llvm_type())
+            }
+            Instr::Phi {
+                dest,
+                dtype,
+                incoming,
+            } => {
+                let dest = Operand::Reg(*dest).llvm_operand();
+                let incoming = incoming
+                    .iter()
+                    .map(|(value, label)| format!("[ {}, %{label} ]", value.llvm_operand()))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                format!("\t{dest} = phi {} {incoming}", dtype.llvm_type())
             }
         }
     }
 
-    /// generate LLVM for a single expression
-    fn llvm_expr(&mut self, e: &Expr) -> String {
-        match e {
-            Expr::Literal { value, .. } => value.llvm_value(),
-            Expr::Call { name, params, .. } => {
-                let func = self.analyze.functions.get(name).unwrap();
-                let mut param_str = Vec::new();
-
-                if let Stmt::FuncDef {
-                    def_params,
-                    return_type,
-                    ..
-                } = func
-                {
-                    let llvm_return_type = return_type.llvm_type();
-
-                    for (call_e, (_, param_type)) in std::iter::zip(params, def_params) {
-                        let pname = self.llvm_expr(call_e);
-                        let ptype = param_type.llvm_type();
-                        param_str.push(format!("{ptype} {pname}"));
-                    }
-
-                    let tmp_name = self.tmp_name();
-                    let param_str = param_str.join(", ");
-                    self.loc().push(format!(
-                        "\t{tmp_name} = call {llvm_return_type} @{name}({param_str})"
-                    ));
-                    tmp_name
-                } else {
-                    panic!("typecheck failure")
-                }
-            }
-            Expr::VarName { name, .. } => {
-                let dtype = self.analyze.expr_type(e).unwrap();
-                let name = self.var_names.get(name).unwrap().clone_store();
-                let tmp_name = self.tmp_name();
-                let llvm_type = dtype.llvm_type();
-
-                self.loc().push(format!(
-                    "\t{tmp_name} = load {llvm_type}, {llvm_type}* {name}"
-                ));
-                tmp_name
-            }
-            Expr::TypeConversion { dtype, params, .. } => {
-                // there should be a single parameter
-                let mut tmp_name = self.tmp_name();
-                let param_compile = self.llvm_expr(&params[0]);
-                let param_type = self.analyze.expr_type(&params[0]).unwrap();
-
-                if dtype == &param_type {
-                    tmp_name = param_compile;
-                } else {
-                    let ins = match (dtype, param_type) {
-                        (Type::Int, Type::Char) => {
-                            format!("\t{tmp_name} = zext i8 {param_compile} to i32")
-                        }
-                        (Type::Int, Type::Bool) => {
-                            format!("\t{tmp_name} = zext i1 {param_compile} to i32")
-                        }
-                        (Type::Int, Type::Float) => {
-                            format!("\t{tmp_name} = fptosi double {param_compile} to i32")
-                        }
-                        (Type::Float, Type::Int) => {
-                            format!("\t{tmp_name} = sitofp i32 {param_compile} to double")
-                        }
-                        (Type::Char, Type::Int) => {
-                            format!("\t{tmp_name} = trunc i32 {param_compile} to i8")
-                        }
-                        (Type::Bool, Type::Int) => {
-                            format!("\t{tmp_name} = trunc i32 {param_compile} to i1")
-                        }
-                        _ => panic!("typecheck failure"),
-                    };
-                    self.loc().push(ins);
-                }
-                tmp_name
-            }
-            Expr::TypeName { .. } => panic!("typecheck failure"),
-            Expr::Logical { lhs, op, rhs, .. } => {
-                let lhs_compile = self.llvm_expr(lhs);
-
-                let rhs_label = self.label_name("rhs_logical");
-                let after_label = self.label_name("after_logical");
-
-                let ins = match op {
-                    LogicalOp::LogicalOr => {
-                        format!("\tbr i1 {lhs_compile}, label %{after_label}, label %{rhs_label}")
-                    }
-                    LogicalOp::LogicalAnd => {
-                        format!("\tbr i1 {lhs_compile}, label %{rhs_label}, label %{after_label}")
-                    }
-                };
-
-                self.loc().push(ins);
-
-                self.loc().push(format!("{rhs_label}:"));
-                let rhs_compile = self.llvm_expr(rhs);
-                self.loc().push(format!("\tbr label %{after_label}"));
-
-                self.loc().push(format!("{after_label}:"));
-                rhs_compile
+    /// generate LLVM for a block terminator
+    fn llvm_terminator(term: &Terminator, messages: &[&str]) -> String {
+        match term {
+            Terminator::Jump(label) => format!("\tbr label %{label}"),
+            Terminator::Branch {
+                cond,
+                then,
+                otherwise,
+            } => {
+                let cond = cond.llvm_operand();
+                format!("\tbr i1 {cond}, label %{then}, label %{otherwise}")
             }
-            Expr::Grouping { e, .. } => {
-                self.llvm_expr(e);
-                self.tmp_no_inc()
+            Terminator::Switch {
+                dtype,
+                value,
+                cases,
+                default,
+            } => {
+                let dtype = dtype.llvm_type();
+                let cases = cases
+                    .iter()
+                    .map(|(case, label)| format!("{dtype} {}, label %{label}", case.llvm_value()))
+                    .collect::<Vec<String>>()
+                    .join(" ");
+                let value = value.llvm_operand();
+                format!("\tswitch {dtype} {value}, label %{default} [{cases}]")
             }
-            Expr::Unary { op, operand, .. } => {
-                let operand_compile = self.llvm_expr(operand);
-                let operand_type = self.analyze.expr_type(e).unwrap();
-
-                let tmp_name = if op == &UnaryOp::Plus {
-                    self.tmp_no_inc()
-                } else {
-                    self.tmp_name()
-                };
-
-                // Unary Plus is a noop
-                if op != &UnaryOp::Plus {
-                    let ins = match (operand_type, op) {
-                        (Type::Int, UnaryOp::Minus) => {
-                            format!("\t{tmp_name} = mul i32 {operand_compile}, -1")
-                        }
-                        (Type::Float, UnaryOp::Minus) => {
-                            format!("\t{tmp_name} = fneg double {operand_compile}")
-                        }
-                        (Type::Bool, UnaryOp::LogicalNot) => {
-                            format!("\t{tmp_name} = icmp eq i1 {operand_compile}, 0")
-                        }
-                        _ => panic!("typecheck failure"),
-                    };
-                    self.loc().push(ins);
-                }
-
-                tmp_name
+            Terminator::Return(dtype, value) => {
+                format!("\tret {} {}", dtype.llvm_type(), value.llvm_operand())
             }
-            Expr::Binary { lhs, op, rhs, .. } => {
-                let lhs_compile = self.llvm_expr(lhs);
-                let rhs_compile = self.llvm_expr(rhs);
-                let dtype = self.analyze.expr_type(lhs).unwrap();
-                let llvm_type = dtype.llvm_type();
-                let tmp_name = self.tmp_name();
-                let op = Self::binary_ops(dtype, op);
-
-                self.loc().push(format!(
-                    "\t{tmp_name} = {op} {llvm_type} {lhs_compile}, {rhs_compile}"
-                ));
-                tmp_name
+            Terminator::Unreachable => "\tunreachable".to_string(),
+            Terminator::Fail(message) => {
+                let i = messages.iter().position(|m| m == message).unwrap();
+                format!("\tcall void @_fail(ptr @.str.fail.{i})\n\tunreachable")
             }
         }
     }
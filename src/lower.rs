@@ -0,0 +1,859 @@
+use crate::ast::{Expr, Stmt};
+use crate::environment::{Environment, VarStore};
+use crate::host::Natives;
+use crate::ir::{Block, Extern, Function, Global, Instr, Module, Operand, Reg, Terminator, MAIN};
+use crate::operators::{BinaryOp, Builtin, LogicalOp, LoopControl, UnaryOp};
+use crate::parser::Parser;
+use crate::typechecker::Typechecker;
+use crate::types::Type;
+use crate::WabbitType;
+use std::collections::HashMap;
+
+/// a function under construction
+struct FunctionBuilder {
+    func: Function,
+    /// index of the block that instructions are appended to
+    current: usize,
+    /// next unused register number
+    next_reg: usize,
+}
+
+impl FunctionBuilder {
+    fn new(name: &str, params: &[Type], return_type: Type) -> Self {
+        let params: Vec<(Reg, Type)> = params
+            .iter()
+            .enumerate()
+            .map(|(i, dtype)| (Reg(i), dtype.clone()))
+            .collect();
+        let next_reg = params.len();
+        Self {
+            func: Function {
+                name: name.to_string(),
+                params,
+                return_type,
+                captures: Vec::new(),
+                blocks: vec![Block::new("entry")],
+            },
+            current: 0,
+            next_reg,
+        }
+    }
+
+    fn reg(&mut self) -> Reg {
+        self.next_reg += 1;
+        Reg(self.next_reg - 1)
+    }
+
+    fn block(&mut self) -> &mut Block {
+        &mut self.func.blocks[self.current]
+    }
+
+    fn label(&self) -> String {
+        self.func.blocks[self.current].label.clone()
+    }
+
+    fn push(&mut self, instr: Instr) {
+        self.block().instrs.push(instr);
+    }
+
+    /// allocas are hoisted into the entry block, so loops do not grow the stack
+    fn alloca(&mut self, dtype: Type) -> Reg {
+        let dest = self.reg();
+        let entry = &mut self.func.blocks[0].instrs;
+        let position = entry
+            .iter()
+            .position(|instr| !matches!(instr, Instr::Alloca { .. }))
+            .unwrap_or(entry.len());
+        entry.insert(position, Instr::Alloca { dest, dtype });
+        dest
+    }
+
+    fn terminated(&self) -> bool {
+        self.func.blocks[self.current].term.is_some()
+    }
+
+    fn terminate(&mut self, term: Terminator) {
+        if !self.terminated() {
+            self.block().term = Some(term);
+        }
+    }
+
+    /// start a new block, falling through from the current one if it is unterminated
+    fn start_block(&mut self, label: &str) {
+        self.terminate(Terminator::Jump(label.to_string()));
+        self.func.blocks.push(Block::new(label));
+        self.current = self.func.blocks.len() - 1;
+    }
+}
+
+/// whether the cases of a match are dense enough for a switch, which LLVM can turn into a jump
+/// table, rather than a chain of comparisons
+fn is_dense(cases: &[(WabbitType, String)]) -> bool {
+    let values: Vec<i64> = cases
+        .iter()
+        .map(|(case, _)| match case {
+            WabbitType::Int(n) => i64::from(*n),
+            WabbitType::Char(c) => i64::from(u32::from(*c)),
+            _ => panic!("typechecking failure"),
+        })
+        .collect();
+    match (values.iter().min(), values.iter().max()) {
+        (Some(min), Some(max)) => {
+            matches!(usize::try_from(max - min), Ok(span) if span < 2 * values.len())
+        }
+        _ => false,
+    }
+}
+
+/// Struct for lowering Wabbit AST into the intermediate representation of [`crate::ir`]
+pub struct Lowering<'a> {
+    /// a typechecker
+    analyze: Typechecker<'a>,
+    /// used to generate unique labels
+    counter: usize,
+    /// stack of (continue, break) labels for the enclosing loops
+    loop_labels: Vec<(String, String)>,
+    /// map of Wabbit variable names to their addresses
+    var_names: Environment<'a, Operand>,
+    /// the module being built
+    module: Module,
+    /// stack of functions being built, with `main` at the bottom
+    builders: Vec<FunctionBuilder>,
+}
+
+impl<'a> From<&'a Parser<'a>> for Lowering<'a> {
+    fn from(parser: &'a Parser) -> Self {
+        Self {
+            analyze: Typechecker::from(parser),
+            counter: 0,
+            loop_labels: Vec::new(),
+            var_names: Environment::new(),
+            module: Module::default(),
+            builders: vec![FunctionBuilder::new(MAIN, &[], Type::Int)],
+        }
+    }
+}
+
+impl<'a> Lowering<'a> {
+    /// allow calls to native functions, which are lowered to calls of external functions
+    pub fn with_natives(mut self, natives: &'a Natives) -> Self {
+        self.analyze.natives = natives;
+        self
+    }
+
+    /// lower all statements into a module
+    pub fn lower(&mut self) -> Module {
+        for stmt in self.analyze.statements {
+            self.lower_stmt(stmt);
+        }
+        let mut main = self
+            .builders
+            .pop()
+            .expect("main function is always present");
+        main.terminate(Terminator::Return(
+            Type::Int,
+            Operand::Const(WabbitType::Int(0)),
+        ));
+        self.module.functions.push(main.func);
+        std::mem::take(&mut self.module)
+    }
+
+    /// the function currently being built
+    fn builder(&mut self) -> &mut FunctionBuilder {
+        self.builders.last_mut().unwrap()
+    }
+
+    /// generate a unique label name
+    fn label_name(&mut self, prefix: &str) -> String {
+        self.counter += 1;
+        format!("{}_{}", prefix, self.counter)
+    }
+
+    fn push(&mut self, instr: Instr) {
+        self.builder().push(instr);
+    }
+
+    /// start a block holding any code after a jump or return, which is unreachable
+    fn start_dead_block(&mut self) {
+        let label = self.label_name("dead");
+        self.builder().start_block(&label);
+    }
+
+    /// enter child environment for variable names and types
+    fn enter_child(&mut self) {
+        self.analyze.env.enter_child();
+        self.var_names.enter_child();
+    }
+
+    /// exit child environment for variable names and types
+    fn exit_child_unwrap(&mut self) {
+        self.analyze.env.exit_child_unwrap();
+        self.var_names.exit_child_unwrap();
+    }
+
+    /// define a new variable, allocating storage for it
+    fn define_var(&mut self, name: &'a String, dtype: Type) -> Operand {
+        let ptr = if self.analyze.env.in_global_scope() {
+            self.module.globals.push(Global {
+                name: name.clone(),
+                dtype: dtype.clone(),
+            });
+            Operand::Global(name.clone())
+        } else {
+            Operand::Reg(self.builder().alloca(dtype.clone()))
+        };
+        self.analyze.env.define_init(name, dtype);
+        self.var_names.define_init(name, ptr.clone());
+        ptr
+    }
+
+    /// lower a single statement
+    fn lower_stmt(&mut self, stmt: &'a Stmt) {
+        match stmt {
+            Stmt::Print { value, .. } => {
+                let dtype = self.analyze.expr_type(value).unwrap();
+                let value = self.lower_expr(value);
+                self.push(Instr::Print { dtype, value });
+            }
+            Stmt::Assert { value, text, .. } => {
+                let cond = self.lower_expr(value);
+                let ok_label = self.label_name("assert_ok");
+                let fail_label = self.label_name("assert_fail");
+                self.builder().terminate(Terminator::Branch {
+                    cond,
+                    then: ok_label.clone(),
+                    otherwise: fail_label.clone(),
+                });
+                self.builder().start_block(&fail_label);
+                self.builder()
+                    .terminate(Terminator::Fail(format!("assertion failed: {text}")));
+                self.builder().start_block(&ok_label);
+            }
+            Stmt::VarDef {
+                name,
+                maybe_type,
+                maybe_value,
+                ..
+            } => {
+                let dtype = if let Some(t) = maybe_type {
+                    t.clone()
+                } else {
+                    self.analyze
+                        .expr_type(maybe_value.as_ref().unwrap())
+                        .unwrap()
+                };
+
+                // the initial value is evaluated before the name is in scope
+                let value = maybe_value.as_ref().map(|value| self.lower_expr(value));
+                let ptr = self.define_var(name, dtype.clone());
+
+                if let Some(value) = value {
+                    self.push(Instr::Store { dtype, value, ptr });
+                }
+            }
+            Stmt::ConstDef { name, value, .. } => {
+                let dtype = self.analyze.expr_type(value).expect("typechecking failure");
+                let value = self.lower_expr(value);
+                let ptr = self.define_var(name, dtype.clone());
+                self.analyze.constants.insert(name, dtype.clone());
+                self.push(Instr::Store { dtype, value, ptr });
+            }
+            Stmt::Assign {
+                name, op, value, ..
+            } => {
+                let dtype = self.analyze.expr_type(value).unwrap();
+                let ptr = self.var_names.get(name).unwrap().clone_store();
+                let value = match op {
+                    Some(op) => {
+                        let current = self.builder().reg();
+                        self.push(Instr::Load {
+                            dest: current,
+                            dtype: dtype.clone(),
+                            ptr: ptr.clone(),
+                        });
+                        let rhs = self.lower_expr(value);
+                        let dest = self.builder().reg();
+                        self.push(Instr::Binary {
+                            dest,
+                            op: op.clone(),
+                            dtype: dtype.clone(),
+                            lhs: Operand::Reg(current),
+                            rhs,
+                        });
+                        Operand::Reg(dest)
+                    }
+                    None => self.lower_expr(value),
+                };
+                self.analyze.env.assign(name, dtype.clone());
+                self.push(Instr::Store { dtype, value, ptr });
+            }
+            Stmt::Expr(e) => {
+                self.lower_expr(e);
+            }
+            Stmt::Block { statements, .. } => {
+                for stmt in statements {
+                    self.lower_stmt(stmt);
+                }
+            }
+            Stmt::If {
+                condition,
+                then_block,
+                maybe_else_block,
+                ..
+            } => {
+                let cond = self.lower_expr(condition);
+                let then_label = self.label_name("then");
+                let else_label = self.label_name("else");
+                let after_label = self.label_name("after_if");
+
+                let otherwise = if maybe_else_block.is_some() {
+                    else_label.clone()
+                } else {
+                    after_label.clone()
+                };
+
+                self.builder().terminate(Terminator::Branch {
+                    cond,
+                    then: then_label.clone(),
+                    otherwise,
+                });
+
+                self.builder().start_block(&then_label);
+                self.enter_child();
+                self.lower_stmt(then_block);
+                self.exit_child_unwrap();
+                self.builder()
+                    .terminate(Terminator::Jump(after_label.clone()));
+
+                if let Some(else_block) = maybe_else_block {
+                    self.builder().start_block(&else_label);
+                    self.enter_child();
+                    self.lower_stmt(else_block);
+                    self.exit_child_unwrap();
+                }
+
+                self.builder().start_block(&after_label);
+            }
+            Stmt::Match {
+                value,
+                arms,
+                maybe_default,
+                ..
+            } => {
+                let dtype = self.analyze.expr_type(value).unwrap();
+                let value = self.lower_expr(value);
+                let arm_labels: Vec<String> = arms.iter().map(|_| self.label_name("arm")).collect();
+                let default_label = self.label_name("match_default");
+                let after_label = self.label_name("after_match");
+
+                let otherwise = if maybe_default.is_some() {
+                    default_label.clone()
+                } else {
+                    after_label.clone()
+                };
+                let cases: Vec<(WabbitType, String)> = std::iter::zip(arms, &arm_labels)
+                    .map(|((pattern, _), label)| match self.lower_expr(pattern) {
+                        Operand::Const(case) => (case, label.clone()),
+                        _ => panic!("typechecking failure"),
+                    })
+                    .collect();
+
+                if is_dense(&cases) {
+                    self.builder().terminate(Terminator::Switch {
+                        dtype,
+                        value,
+                        cases,
+                        default: otherwise,
+                    });
+                } else {
+                    // compare with each pattern in turn
+                    for (case, label) in cases {
+                        let dest = self.builder().reg();
+                        self.push(Instr::Binary {
+                            dest,
+                            op: BinaryOp::EqualEqual,
+                            dtype: dtype.clone(),
+                            lhs: value.clone(),
+                            rhs: Operand::Const(case),
+                        });
+                        let next_label = self.label_name("match_next");
+                        self.builder().terminate(Terminator::Branch {
+                            cond: Operand::Reg(dest),
+                            then: label,
+                            otherwise: next_label.clone(),
+                        });
+                        self.builder().start_block(&next_label);
+                    }
+                    self.builder().terminate(Terminator::Jump(otherwise));
+                }
+
+                let bodies = arms.iter().map(|(_, body)| body);
+                for (body, label) in bodies.zip(arm_labels) {
+                    self.builder().start_block(&label);
+                    self.enter_child();
+                    self.lower_stmt(body);
+                    self.exit_child_unwrap();
+                    self.builder()
+                        .terminate(Terminator::Jump(after_label.clone()));
+                }
+                if let Some(default) = maybe_default {
+                    self.builder().start_block(&default_label);
+                    self.enter_child();
+                    self.lower_stmt(default);
+                    self.exit_child_unwrap();
+                }
+
+                self.builder().start_block(&after_label);
+            }
+            Stmt::While {
+                condition, body, ..
+            } => {
+                let test_label = self.label_name("while_cond");
+                let body_label = self.label_name("while_body");
+                let after_label = self.label_name("after_while");
+
+                self.builder().start_block(&test_label);
+                let cond = self.lower_expr(condition);
+                self.builder().terminate(Terminator::Branch {
+                    cond,
+                    then: body_label.clone(),
+                    otherwise: after_label.clone(),
+                });
+
+                self.builder().start_block(&body_label);
+                self.loop_labels
+                    .push((test_label.clone(), after_label.clone()));
+                self.analyze.loop_depth += 1;
+                self.enter_child();
+                self.lower_stmt(body);
+                self.exit_child_unwrap();
+                self.analyze.loop_depth -= 1;
+                self.loop_labels.pop();
+                self.builder().terminate(Terminator::Jump(test_label));
+
+                self.builder().start_block(&after_label);
+            }
+            Stmt::For {
+                init,
+                condition,
+                step,
+                body,
+                ..
+            } => {
+                let test_label = self.label_name("for_cond");
+                let body_label = self.label_name("for_body");
+                let step_label = self.label_name("for_step");
+                let after_label = self.label_name("after_for");
+
+                self.enter_child();
+                self.lower_stmt(init);
+
+                self.builder().start_block(&test_label);
+                let cond = self.lower_expr(condition);
+                self.builder().terminate(Terminator::Branch {
+                    cond,
+                    then: body_label.clone(),
+                    otherwise: after_label.clone(),
+                });
+
+                // `continue` jumps to the step rather than the condition
+                self.builder().start_block(&body_label);
+                self.loop_labels
+                    .push((step_label.clone(), after_label.clone()));
+                self.analyze.loop_depth += 1;
+                self.enter_child();
+                self.lower_stmt(body);
+                self.exit_child_unwrap();
+                self.analyze.loop_depth -= 1;
+                self.loop_labels.pop();
+
+                self.builder().start_block(&step_label);
+                self.lower_stmt(step);
+                self.builder().terminate(Terminator::Jump(test_label));
+
+                self.builder().start_block(&after_label);
+                self.exit_child_unwrap();
+            }
+            Stmt::LoopControl { control, .. } => {
+                let (continue_label, break_label) = self.loop_labels.last().unwrap().clone();
+                let target = match control {
+                    LoopControl::Break => break_label,
+                    LoopControl::Continue => continue_label,
+                };
+                self.builder().terminate(Terminator::Jump(target));
+                self.start_dead_block();
+            }
+            Stmt::Return { value, .. } => {
+                let dtype = self.analyze.expr_type(value).unwrap();
+                let value = self.lower_expr(value);
+                self.builder().terminate(Terminator::Return(dtype, value));
+                self.start_dead_block();
+            }
+            Stmt::FuncDef {
+                def_name,
+                def_params,
+                return_type,
+                body,
+                ..
+            } => {
+                let param_types: Vec<Type> = def_params.iter().map(|(_, t)| t.clone()).collect();
+                self.builders.push(FunctionBuilder::new(
+                    def_name,
+                    &param_types,
+                    return_type.clone(),
+                ));
+
+                // parameters are copied into stack slots, so they can be assigned like any variable
+                let mut param_names: HashMap<&String, VarStore<Operand>> = HashMap::new();
+                let mut param_env: HashMap<&String, VarStore<Type>> = HashMap::new();
+
+                for (i, (name, dtype)) in def_params.iter().enumerate() {
+                    let slot = self.builder().alloca(dtype.clone());
+                    self.push(Instr::Store {
+                        dtype: dtype.clone(),
+                        value: Operand::Reg(Reg(i)),
+                        ptr: Operand::Reg(slot),
+                    });
+                    param_names.insert(name, VarStore::Init(Operand::Reg(slot)));
+                    param_env.insert(name, VarStore::Init(dtype.clone()));
+                }
+
+                self.analyze.functions.insert(def_name, stmt);
+                self.analyze.env.enter_child_fn(param_env);
+                self.var_names.enter_child_fn(param_names);
+                self.analyze.call_depth += 1;
+
+                self.lower_stmt(body);
+
+                self.analyze.env.exit_child_unwrap();
+                self.var_names.exit_child_unwrap();
+                self.analyze.call_depth -= 1;
+
+                let mut builder = self.builders.pop().unwrap();
+                builder.terminate(Terminator::Unreachable);
+                self.module.functions.push(builder.func);
+            }
+        }
+    }
+
+    /// lower the call of a function value of type `dtype`
+    fn call_value(&mut self, callee: Operand, dtype: &Type, params: &'a [Expr]) -> Operand {
+        let Type::Func(sig) = dtype else {
+            panic!("typecheck failure")
+        };
+        let args = std::iter::zip(params, &sig.params)
+            .map(|(arg, dtype)| (dtype.clone(), self.lower_expr(arg)))
+            .collect();
+        let dest = self.builder().reg();
+        self.push(Instr::CallValue {
+            dest,
+            ret: sig.ret.clone(),
+            callee,
+            args,
+        });
+        Operand::Reg(dest)
+    }
+
+    /// lower a named function used as a value, which calls the function from a wrapper that
+    /// takes the function value first, like the function of a function expression
+    fn named_function_value(&mut self, name: &String, dtype: Type) -> Operand {
+        let Type::Func(sig) = &dtype else {
+            panic!("typecheck failure")
+        };
+        let wrapper = format!("{name}.value");
+        if self.module.function(&wrapper).is_none() {
+            let params: Vec<Type> = std::iter::once(dtype.clone())
+                .chain(sig.params.clone())
+                .collect();
+            let mut builder = FunctionBuilder::new(&wrapper, &params, sig.ret.clone());
+            let args = builder.func.params[1..]
+                .iter()
+                .map(|(reg, dtype)| (dtype.clone(), Operand::Reg(*reg)))
+                .collect();
+            let dest = builder.reg();
+            builder.push(Instr::Call {
+                dest,
+                ret: sig.ret.clone(),
+                func: name.clone(),
+                args,
+            });
+            builder.terminate(Terminator::Return(sig.ret.clone(), Operand::Reg(dest)));
+            self.module.functions.push(builder.func);
+        }
+        let dest = self.builder().reg();
+        self.push(Instr::Closure {
+            dest,
+            dtype,
+            func: wrapper,
+            captures: Vec::new(),
+        });
+        Operand::Reg(dest)
+    }
+
+    /// lower a function expression into a function of its own, returning a function value
+    /// holding copies of the variables it captures
+    fn lower_function(
+        &mut self,
+        e: &'a Expr,
+        params: &'a [(String, Type)],
+        return_type: Type,
+        body: &'a Stmt,
+        id: usize,
+    ) -> Operand {
+        let dtype = self.analyze.expr_type(e).unwrap();
+        let captures = self.analyze.captures(params, body, id).unwrap();
+        self.counter += 1;
+        let name = format!("func.{}", self.counter);
+
+        let param_types: Vec<Type> = std::iter::once(dtype.clone())
+            .chain(params.iter().map(|(_, t)| t.clone()))
+            .collect();
+        let mut builder = FunctionBuilder::new(&name, &param_types, return_type);
+        builder.func.captures = captures.iter().map(|(_, dtype)| dtype.clone()).collect();
+        self.builders.push(builder);
+
+        // parameters and captured values are copied into stack slots, as in a named function
+        let mut scope_names: HashMap<&String, VarStore<Operand>> = HashMap::new();
+        let mut scope_env: HashMap<&String, VarStore<Type>> = HashMap::new();
+
+        for (i, (name, dtype)) in params.iter().enumerate() {
+            let slot = self.builder().alloca(dtype.clone());
+            self.push(Instr::Store {
+                dtype: dtype.clone(),
+                value: Operand::Reg(Reg(i + 1)),
+                ptr: Operand::Reg(slot),
+            });
+            scope_names.insert(name, VarStore::Init(Operand::Reg(slot)));
+            scope_env.insert(name, VarStore::Init(dtype.clone()));
+        }
+        for (index, (name, dtype)) in captures.iter().enumerate() {
+            let value = self.builder().reg();
+            self.push(Instr::Capture {
+                dest: value,
+                dtype: dtype.clone(),
+                index,
+            });
+            let slot = self.builder().alloca(dtype.clone());
+            self.push(Instr::Store {
+                dtype: dtype.clone(),
+                value: Operand::Reg(value),
+                ptr: Operand::Reg(slot),
+            });
+            scope_names.insert(name, VarStore::Init(Operand::Reg(slot)));
+            scope_env.insert(name, VarStore::Captured(dtype.clone()));
+        }
+
+        let env_locals = self.analyze.env.enter_call(scope_env);
+        let name_locals = self.var_names.enter_call(scope_names);
+        let loop_labels = std::mem::take(&mut self.loop_labels);
+        let loop_depth = std::mem::take(&mut self.analyze.loop_depth);
+        self.analyze.call_depth += 1;
+
+        self.lower_stmt(body);
+
+        self.analyze.call_depth -= 1;
+        self.analyze.loop_depth = loop_depth;
+        self.loop_labels = loop_labels;
+        self.var_names.exit_call(name_locals);
+        self.analyze.env.exit_call(env_locals);
+
+        let mut builder = self.builders.pop().unwrap();
+        builder.terminate(Terminator::Unreachable);
+        self.module.functions.push(builder.func);
+
+        // the captured variables are read when the function value is created
+        let captures = captures
+            .into_iter()
+            .map(|(name, dtype)| {
+                let ptr = self.var_names.get(name).unwrap().clone_store();
+                let dest = self.builder().reg();
+                self.push(Instr::Load {
+                    dest,
+                    dtype: dtype.clone(),
+                    ptr,
+                });
+                (dtype, Operand::Reg(dest))
+            })
+            .collect();
+        let dest = self.builder().reg();
+        self.push(Instr::Closure {
+            dest,
+            dtype,
+            func: name,
+            captures,
+        });
+        Operand::Reg(dest)
+    }
+
+    /// lower a single expression, returning the operand holding its value
+    fn lower_expr(&mut self, e: &'a Expr) -> Operand {
+        match e {
+            Expr::Literal { value, .. } => Operand::Const(value.clone()),
+            Expr::Grouping { e, .. } => self.lower_expr(e),
+            Expr::VarName { name, .. } => {
+                let dtype = self.analyze.expr_type(e).unwrap();
+                let Some(ptr) = self.var_names.get(name) else {
+                    return self.named_function_value(name, dtype);
+                };
+                let dest = self.builder().reg();
+                self.push(Instr::Load {
+                    dest,
+                    dtype,
+                    ptr: ptr.clone_store(),
+                });
+                Operand::Reg(dest)
+            }
+            Expr::CallValue { callee, params, .. } => {
+                let dtype = self.analyze.expr_type(callee).unwrap();
+                let callee = self.lower_expr(callee);
+                self.call_value(callee, &dtype, params)
+            }
+            Expr::Function {
+                params,
+                return_type,
+                body,
+                id,
+            } => self.lower_function(e, params, return_type.clone(), body, *id),
+            Expr::Call { name, params, .. } => {
+                if let Some(builtin) = Builtin::from_name(name) {
+                    let dest = self.builder().reg();
+                    let dtype = builtin.return_type();
+                    self.push(Instr::Read { dest, dtype });
+                    return Operand::Reg(dest);
+                }
+                if let Some(ptr) = self.var_names.get(name) {
+                    // a call through a variable holding a function value
+                    let dtype = match self.analyze.constants.get(name) {
+                        Some(dtype) => dtype.clone(),
+                        None => self.analyze.env.get(name).unwrap().clone_store(),
+                    };
+                    let callee = self.builder().reg();
+                    self.push(Instr::Load {
+                        dest: callee,
+                        dtype: dtype.clone(),
+                        ptr: ptr.clone_store(),
+                    });
+                    return self.call_value(Operand::Reg(callee), &dtype, params);
+                }
+
+                let (param_types, return_type) = if let Some(Stmt::FuncDef {
+                    def_params,
+                    return_type,
+                    ..
+                }) = self.analyze.functions.get(name).copied()
+                {
+                    let params = def_params.iter().map(|(_, dtype)| dtype.clone()).collect();
+                    (params, return_type.clone())
+                } else {
+                    let native = self.analyze.natives.get(name).expect("typecheck failure");
+                    if self.module.extern_fn(name).is_none() {
+                        self.module.externs.push(Extern {
+                            name: name.clone(),
+                            params: native.params.clone(),
+                            return_type: native.return_type.clone(),
+                        });
+                    }
+                    (native.params.clone(), native.return_type.clone())
+                };
+
+                let args = std::iter::zip(params, param_types)
+                    .map(|(arg, dtype)| (dtype, self.lower_expr(arg)))
+                    .collect();
+                let dest = self.builder().reg();
+                self.push(Instr::Call {
+                    dest,
+                    ret: return_type,
+                    func: name.clone(),
+                    args,
+                });
+                Operand::Reg(dest)
+            }
+            Expr::TypeConversion { dtype, params, .. } => {
+                // there should be a single parameter
+                let from = self.analyze.expr_type(&params[0]).unwrap();
+                let value = self.lower_expr(&params[0]);
+
+                if *dtype == from {
+                    value
+                } else {
+                    let dest = self.builder().reg();
+                    self.push(Instr::Convert {
+                        dest,
+                        from,
+                        to: dtype.clone(),
+                        value,
+                    });
+                    Operand::Reg(dest)
+                }
+            }
+            Expr::TypeName { .. } => panic!("typecheck failure"),
+            Expr::Logical { lhs, op, rhs, .. } => {
+                // the left-hand side decides if the right-hand side is evaluated at all,
+                // and a phi merges the two possible results
+                let lhs = self.lower_expr(lhs);
+                let lhs_label = self.builder().label();
+
+                let rhs_label = self.label_name("rhs_logical");
+                let after_label = self.label_name("after_logical");
+
+                let (then, otherwise, short_circuit) = match op {
+                    LogicalOp::LogicalOr => (after_label.clone(), rhs_label.clone(), true),
+                    LogicalOp::LogicalAnd => (rhs_label.clone(), after_label.clone(), false),
+                };
+                self.builder().terminate(Terminator::Branch {
+                    cond: lhs,
+                    then,
+                    otherwise,
+                });
+
+                self.builder().start_block(&rhs_label);
+                let rhs = self.lower_expr(rhs);
+                let rhs_label = self.builder().label();
+
+                self.builder().start_block(&after_label);
+                let dest = self.builder().reg();
+                self.push(Instr::Phi {
+                    dest,
+                    dtype: Type::Bool,
+                    incoming: vec![
+                        (Operand::Const(WabbitType::Bool(short_circuit)), lhs_label),
+                        (rhs, rhs_label),
+                    ],
+                });
+                Operand::Reg(dest)
+            }
+            Expr::Unary { op, operand, .. } => {
+                let dtype = self.analyze.expr_type(operand).unwrap();
+                let operand = self.lower_expr(operand);
+
+                // unary plus is a noop
+                if op == &UnaryOp::Plus {
+                    operand
+                } else {
+                    let dest = self.builder().reg();
+                    self.push(Instr::Unary {
+                        dest,
+                        op: op.clone(),
+                        dtype,
+                        operand,
+                    });
+                    Operand::Reg(dest)
+                }
+            }
+            Expr::Binary { lhs, op, rhs, .. } => {
+                let dtype = self.analyze.expr_type(lhs).unwrap();
+                let lhs = self.lower_expr(lhs);
+                let rhs = self.lower_expr(rhs);
+                let dest = self.builder().reg();
+                self.push(Instr::Binary {
+                    dest,
+                    op: op.clone(),
+                    dtype,
+                    lhs,
+                    rhs,
+                });
+                Operand::Reg(dest)
+            }
+        }
+    }
+}
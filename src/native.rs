@@ -0,0 +1,265 @@
+//! compile generated code into native executables
+//!
+//! generated code (LLVM IR, assembly or C) is compiled by running a C compiler driver, see
+//! [`Toolchain`]
+
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Output};
+
+/// what a build writes to its output path
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Emit {
+    /// LLVM IR, written directly without a compiler
+    LlvmIr,
+    /// native assembly
+    Asm,
+    /// an object file
+    Obj,
+    /// a linked executable
+    #[default]
+    Exe,
+}
+
+impl Emit {
+    /// the compiler flags that stop at this stage
+    fn flags(self) -> &'static [&'static str] {
+        match self {
+            Self::LlvmIr => &["-S", "-emit-llvm"],
+            Self::Asm => &["-S"],
+            Self::Obj => &["-c"],
+            Self::Exe => &[],
+        }
+    }
+
+    /// the usual file extension for this output
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::LlvmIr => "ll",
+            Self::Asm => "s",
+            Self::Obj => "o",
+            Self::Exe => "",
+        }
+    }
+}
+
+impl std::str::FromStr for Emit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "llvm-ir" => Ok(Self::LlvmIr),
+            "asm" => Ok(Self::Asm),
+            "obj" => Ok(Self::Obj),
+            "exe" => Ok(Self::Exe),
+            _ => Err(format!(
+                "unknown output `{s}`, expected one of llvm-ir, asm, obj, exe"
+            )),
+        }
+    }
+}
+
+/// errors from building or running native code
+#[derive(Debug)]
+pub enum BuildError {
+    Io(std::io::Error),
+    /// an output file could not be written
+    Write(PathBuf, std::io::Error),
+    /// an external command could not be found
+    NotFound(String),
+    /// an external command exited unsuccessfully
+    Command {
+        command: String,
+        status: ExitStatus,
+        stderr: String,
+    },
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{e}"),
+            Self::Write(path, e) => write!(f, "cannot write {}: {e}", path.display()),
+            Self::NotFound(program) => write!(f, "`{program}` not found"),
+            Self::Command {
+                command,
+                status,
+                stderr,
+            } => {
+                write!(f, "`{command}` failed ({status})")?;
+                if !stderr.is_empty() {
+                    write!(f, ":\n{}", stderr.trim_end())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+impl From<std::io::Error> for BuildError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// a uniquely named temporary directory, removed with its contents when dropped
+///
+/// intermediate files are written to one, so that concurrent builds do not collide and nothing is
+/// left behind in the working directory
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// create a directory with a random name in the system temporary directory, readable only
+    /// by the current user
+    pub fn new() -> std::io::Result<Self> {
+        let mut builder = std::fs::DirBuilder::new();
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        let mut attempts = 0;
+        loop {
+            // `RandomState` keys are seeded from the OS, so hashing nothing gives a random name
+            let suffix = RandomState::new().build_hasher().finish();
+            let path = std::env::temp_dir().join(format!("wabbit-{suffix:016x}"));
+            match builder.create(&path) {
+                Ok(()) => return Ok(Self(path)),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists && attempts < 16 => {
+                    attempts += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        // best effort, since there is no way to report the error
+        let _: std::io::Result<()> = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// a C compiler driver and the options passed to it
+#[derive(Debug, Clone)]
+pub struct Toolchain {
+    /// path to the compiler, which must be clang to compile LLVM IR
+    pub cc: PathBuf,
+    /// optimization level, passed as `-O<level>`
+    pub opt_level: String,
+    /// object files or libraries linked into executables, such as those defining native functions
+    pub link: Vec<PathBuf>,
+}
+
+impl Toolchain {
+    pub fn new(cc: impl Into<PathBuf>) -> Self {
+        Self {
+            cc: cc.into(),
+            opt_level: "0".to_string(),
+            link: Vec::new(),
+        }
+    }
+
+    /// compile a source file (`.ll`, `.s` or `.c`) to `output`
+    pub fn compile(
+        &self,
+        source: &Path,
+        emit: Emit,
+        output: &Path,
+        args: &[&str],
+    ) -> Result<(), BuildError> {
+        let mut command = Command::new(&self.cc);
+        command.arg(format!("-O{}", self.opt_level));
+        command.args(emit.flags()).args(args);
+        command.arg(source).arg("-o").arg(output);
+        if emit == Emit::Exe {
+            command.args(&self.link);
+        }
+        check(&mut command)
+    }
+
+    /// build LLVM IR into `output`, only running the compiler if something other than IR is
+    /// wanted
+    pub fn build_llvm(&self, llvm: &str, emit: Emit, output: &Path) -> Result<(), BuildError> {
+        if emit == Emit::LlvmIr {
+            std::fs::write(output, llvm).map_err(|e| BuildError::Write(output.into(), e))?;
+            return Ok(());
+        }
+        let dir = TempDir::new()?;
+        let source = dir.path().join("out.ll");
+        std::fs::write(&source, llvm)?;
+        self.compile(&source, emit, output, &[])
+    }
+
+    /// compile a source file named `file_name` into an executable and run it, returning how it
+    /// exited
+    pub fn run(
+        &self,
+        source: &str,
+        file_name: &str,
+        args: &[&str],
+    ) -> Result<ExitStatus, BuildError> {
+        let dir = TempDir::new()?;
+        let binary = self.executable(&dir, source, file_name, args)?;
+        Ok(Command::new(binary).status()?)
+    }
+
+    /// like [`run`](Self::run), but capturing what the executable writes to stdout and stderr
+    pub fn output(
+        &self,
+        source: &str,
+        file_name: &str,
+        args: &[&str],
+    ) -> Result<Output, BuildError> {
+        let dir = TempDir::new()?;
+        let binary = self.executable(&dir, source, file_name, args)?;
+        Ok(Command::new(binary).output()?)
+    }
+
+    /// compile a source file named `file_name` into an executable in `dir`, returning its path
+    fn executable(
+        &self,
+        dir: &TempDir,
+        source: &str,
+        file_name: &str,
+        args: &[&str],
+    ) -> Result<PathBuf, BuildError> {
+        let path = dir.path().join(file_name);
+        let binary = dir.path().join("out");
+        std::fs::write(&path, source)?;
+        self.compile(&path, Emit::Exe, &binary, args)?;
+        Ok(binary)
+    }
+}
+
+/// run a command, turning an unsuccessful exit into an error
+fn check(command: &mut Command) -> Result<(), BuildError> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = match command.stdout(std::process::Stdio::inherit()).output() {
+        Ok(output) => output,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(BuildError::NotFound(program))
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if output.status.success() {
+        Ok(())
+    } else {
+        let args = command
+            .get_args()
+            .map(std::ffi::OsStr::to_string_lossy)
+            .collect::<Vec<_>>()
+            .join(" ");
+        Err(BuildError::Command {
+            command: format!("{program} {args}").trim_end().to_string(),
+            status: output.status,
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        })
+    }
+}
@@ -1,5 +1,6 @@
 use crate::error::{msg, Msg, RangeReporter, WabbitError};
 use crate::tokens::Token;
+use crate::types::Type;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum BinaryOp {
@@ -7,6 +8,12 @@ pub enum BinaryOp {
     Minus,
     Times,
     Divide,
+    Modulo,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
     Less,
     LessEqual,
     Greater,
@@ -20,10 +27,16 @@ impl TryFrom<Token> for BinaryOp {
 
     fn try_from(token: Token) -> Result<Self, Self::Error> {
         match token.lexeme.as_str() {
-            "+" => Ok(BinaryOp::Plus),
-            "-" => Ok(BinaryOp::Minus),
-            "*" => Ok(BinaryOp::Times),
-            "/" => Ok(BinaryOp::Divide),
+            "+" | "+=" => Ok(BinaryOp::Plus),
+            "-" | "-=" => Ok(BinaryOp::Minus),
+            "*" | "*=" => Ok(BinaryOp::Times),
+            "/" | "/=" => Ok(BinaryOp::Divide),
+            "%" | "%=" => Ok(BinaryOp::Modulo),
+            "&" => Ok(BinaryOp::BitAnd),
+            "|" => Ok(BinaryOp::BitOr),
+            "^" => Ok(BinaryOp::BitXor),
+            "<<" => Ok(BinaryOp::ShiftLeft),
+            ">>" => Ok(BinaryOp::ShiftRight),
             "<" => Ok(BinaryOp::Less),
             "<=" => Ok(BinaryOp::LessEqual),
             ">" => Ok(BinaryOp::Greater),
@@ -39,6 +52,21 @@ impl TryFrom<Token> for BinaryOp {
     }
 }
 
+impl BinaryOp {
+    /// whether the operator compares its operands, giving a bool
+    pub fn is_comparison(&self) -> bool {
+        matches!(
+            self,
+            Self::Less
+                | Self::LessEqual
+                | Self::Greater
+                | Self::GreaterEqual
+                | Self::EqualEqual
+                | Self::NotEqual
+        )
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum UnaryOp {
     Plus,
@@ -106,3 +134,33 @@ impl TryFrom<Token> for LoopControl {
         }
     }
 }
+
+/// functions built into Wabbit, called like functions defined in a program
+///
+/// these are `readint()`, `readfloat()` and `readchar()`, named here by the type they read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Builtin {
+    Int,
+    Float,
+    Char,
+}
+
+impl Builtin {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "readint" => Some(Self::Int),
+            "readfloat" => Some(Self::Float),
+            "readchar" => Some(Self::Char),
+            _ => None,
+        }
+    }
+
+    /// the type of value returned, which is the type read from input
+    pub fn return_type(self) -> Type {
+        match self {
+            Self::Int => Type::Int,
+            Self::Float => Type::Float,
+            Self::Char => Type::Char,
+        }
+    }
+}
@@ -1,10 +1,24 @@
 use crate::ast::{Expr, Stmt};
 use crate::error::{msg, Msg, RangeReporter, Result};
+use crate::operators::BinaryOp;
 use crate::scanner::Scanner;
 use crate::tokens::{Token, TokenType};
-use crate::types::Type;
+use crate::types::{Type, WabbitType};
 use std::collections::HashMap;
 
+/// the tokens of assignments: `=` and the compound assignments
+const ASSIGN: [TokenType; 6] = [
+    TokenType::Assign,
+    TokenType::PlusAssign,
+    TokenType::MinusAssign,
+    TokenType::TimesAssign,
+    TokenType::DivideAssign,
+    TokenType::ModuloAssign,
+];
+
+/// the parameters, return type and body of a function definition or expression
+type Function = (Vec<(String, Type)>, Type, Box<Stmt>);
+
 /// Struct for transforming tokens into a vector of statements (AST)
 
 #[derive(Debug)]
@@ -76,6 +90,11 @@ impl<'a> Parser<'a> {
         &self.ranges
     }
 
+    /// take the statements and their source ranges, releasing the borrowed tokens
+    pub fn into_parts(self) -> (Vec<Stmt>, HashMap<usize, (usize, usize)>) {
+        (self.statements, self.ranges)
+    }
+
     /// parse all statements
     pub fn parse(&mut self) -> Result<()> {
         while !self.is_end() {
@@ -85,6 +104,16 @@ impl<'a> Parser<'a> {
         Ok(())
     }
 
+    /// parse a single expression that uses all tokens, such as a debugger's watch expression
+    pub fn parse_expression(&mut self) -> Result<Expr> {
+        let e = self.expression()?;
+        if self.is_end() {
+            Ok(e)
+        } else {
+            msg!(Msg::ParserExpect, self, "end of expression")
+        }
+    }
+
     /// parse a single statement
     ///
     /// This is the entry point for the mutually recursive private functions found below.
@@ -94,11 +123,14 @@ impl<'a> Parser<'a> {
             TokenType::Var => self.vardef(),
             TokenType::Name => self.stmt_name(),
             TokenType::Const => self.constdef(),
-            TokenType::Func => self.funcdef(),
+            TokenType::Func if self.check(TokenType::Name) => self.funcdef(),
             TokenType::Continue | TokenType::Break => self.loop_control(),
             TokenType::While => self.while_stmt(),
+            TokenType::For => self.for_stmt(),
             TokenType::If => self.if_stmt(),
+            TokenType::Match => self.match_stmt(),
             TokenType::Print => self.print_stmt(),
+            TokenType::Assert => self.assert_stmt(),
             TokenType::Return => self.return_stmt(),
             _ => {
                 self.current -= 1;
@@ -186,17 +218,37 @@ impl<'a> Parser<'a> {
 
     /// advance past and return a type, or return an error
     fn get_type(&mut self) -> Result<(Token, Type)> {
-        if self.match_any([
+        if self.match_any([TokenType::Func]) {
+            // a function type, such as `func(int, float) bool`
+            let token = self.previous();
+            self.expect('(')?;
+            let mut params = Vec::new();
+            while !self.check(TokenType::RightParen) {
+                params.push(self.get_type()?.1);
+                if !self.match_any([TokenType::Comma]) {
+                    break;
+                }
+            }
+            self.expect(')')?;
+            let (_, ret) = self.get_type()?;
+            Ok((token, Type::func(params, ret)))
+        } else if self.match_any([
             TokenType::IntegerType,
             TokenType::FloatType,
             TokenType::CharType,
             TokenType::BoolType,
+            TokenType::Int64Type,
+            TokenType::Uint8Type,
+            TokenType::Float32Type,
         ]) {
             let dtype = match self.previous().token {
                 TokenType::IntegerType => Type::Int,
                 TokenType::FloatType => Type::Float,
                 TokenType::CharType => Type::Char,
                 TokenType::BoolType => Type::Bool,
+                TokenType::Int64Type => Type::Int64,
+                TokenType::Uint8Type => Type::Uint8,
+                TokenType::Float32Type => Type::Float32,
                 _ => {
                     return msg!(
                         Msg::InternalErr,
@@ -227,7 +279,11 @@ impl<'a> Parser<'a> {
         let name = self.get_name()?;
         let mut count = 0;
 
-        let maybe_type = if let Ok((_, type_name)) = self.get_type() {
+        // a function type is an error once it has started, rather than no type
+        let maybe_type = if self.check(TokenType::Func) {
+            count += 1;
+            Some(self.get_type()?.1)
+        } else if let Ok((_, type_name)) = self.get_type() {
             count += 1;
             Some(type_name)
         } else {
@@ -263,14 +319,14 @@ impl<'a> Parser<'a> {
             let e = self.expression()?;
             self.expect(';')?;
             Ok(Stmt::Expr(e))
-        } else if self.check(TokenType::Assign) {
+        } else if ASSIGN.iter().any(|t| self.check(*t)) {
             let name = self.previous();
-            self.advance();
-            let value = self.expression()?;
+            let (op, value) = self.assign_value()?;
             let last = self.expect(';')?;
             let id = self.assign_id(&name, &last);
             Ok(Stmt::Assign {
                 name: name.lexeme,
+                op,
                 value,
                 id,
             })
@@ -280,6 +336,18 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// the operator and value of an assignment, where only compound assignments have an operator
+    fn assign_value(&mut self) -> Result<(Option<BinaryOp>, Expr)> {
+        let op = if self.match_any([TokenType::Assign]) {
+            None
+        } else if self.match_any(ASSIGN) {
+            Some(self.previous().try_into()?)
+        } else {
+            return msg!(Msg::ParserExpect, self, '=');
+        };
+        Ok((op, self.expression()?))
+    }
+
     fn constdef(&mut self) -> Result<Stmt> {
         let name = self.get_name()?;
         let maybe_type = if let Ok((_, type_name)) = self.get_type() {
@@ -304,14 +372,29 @@ impl<'a> Parser<'a> {
     fn funcdef(&mut self) -> Result<Stmt> {
         let lead = self.previous();
         let def_name = self.get_name()?.lexeme;
-        let mut def_params: Vec<(String, Type)> = Vec::new();
+        let (def_params, return_type, body) = self.function()?;
+        let last = self.previous();
+        let id = self.assign_id(&lead, &last);
+
+        Ok(Stmt::FuncDef {
+            def_name,
+            def_params,
+            return_type,
+            body,
+            id,
+        })
+    }
+
+    /// parse the rest of a function definition or expression, after its name if it has one
+    fn function(&mut self) -> Result<Function> {
+        let mut params: Vec<(String, Type)> = Vec::new();
 
         self.expect('(')?;
 
         while !self.check(TokenType::RightParen) {
             let var_name = self.get_name()?.lexeme;
             let (_, type_name) = self.get_type()?;
-            def_params.push((var_name, type_name));
+            params.push((var_name, type_name));
             if self.check(TokenType::Comma) {
                 self.advance();
             }
@@ -321,16 +404,7 @@ impl<'a> Parser<'a> {
         let (_, return_type) = self.get_type()?;
         self.expect('{')?;
         let body = box self.block()?;
-        let last = self.previous();
-        let id = self.assign_id(&lead, &last);
-
-        Ok(Stmt::FuncDef {
-            def_name,
-            def_params,
-            return_type,
-            body,
-            id,
-        })
+        Ok((params, return_type, body))
     }
 
     fn loop_control(&mut self) -> Result<Stmt> {
@@ -359,6 +433,44 @@ impl<'a> Parser<'a> {
         })
     }
 
+    fn for_stmt(&mut self) -> Result<Stmt> {
+        let lead = self.previous();
+        let next = self.tokens.get(self.current + 1);
+        let assigns =
+            self.check(TokenType::Name) && matches!(next, Some(t) if ASSIGN.contains(&t.token));
+        if !(self.check(TokenType::Var) || assigns) {
+            return msg!(Msg::ParserExpect, self, "variable definition or assignment");
+        }
+        let init = box self.statement()?;
+        let condition = self.expression()?;
+        self.expect(';')?;
+
+        // the step has no `;` of its own, as the body follows it
+        let name = self.get_name()?;
+        let (op, value) = self.assign_value()?;
+        let last = self.previous();
+        let id = self.assign_id(&name, &last);
+        let step = Stmt::Assign {
+            name: name.lexeme,
+            op,
+            value,
+            id,
+        };
+
+        self.expect('{')?;
+        let body = box self.block()?;
+        let last = self.previous();
+        let id = self.assign_id(&lead, &last);
+
+        Ok(Stmt::For {
+            init,
+            condition,
+            step: box step,
+            body,
+            id,
+        })
+    }
+
     fn if_stmt(&mut self) -> Result<Stmt> {
         let lead = self.previous();
         let condition = self.expression()?;
@@ -366,8 +478,13 @@ impl<'a> Parser<'a> {
         let then_block = box self.block()?;
 
         let maybe_else_block = if self.match_any([TokenType::Else]) {
-            self.expect('{')?;
-            let else_block = box self.block()?;
+            // `else if` chains nest the following if as the else block
+            let else_block = if self.match_any([TokenType::If]) {
+                box self.if_stmt()?
+            } else {
+                self.expect('{')?;
+                box self.block()?
+            };
             Some(else_block)
         } else {
             None
@@ -384,6 +501,70 @@ impl<'a> Parser<'a> {
         })
     }
 
+    fn match_stmt(&mut self) -> Result<Stmt> {
+        let lead = self.previous();
+        let value = self.expression()?;
+        self.expect('{')?;
+
+        let mut arms = Vec::new();
+        let mut maybe_default = None;
+        while !self.check(TokenType::RightBrace) {
+            // the `_` arm matches everything, so it must come last
+            let is_default = self.check(TokenType::Name) && self.peek().lexeme == "_";
+            let pattern = if is_default {
+                self.advance();
+                None
+            } else {
+                Some(self.pattern()?)
+            };
+            if !self.match_any([TokenType::FatArrow]) {
+                return msg!(Msg::ParserExpect, self, "=>");
+            }
+            self.expect('{')?;
+            let body = self.block()?;
+            self.match_any([TokenType::Comma]);
+
+            if let Some(pattern) = pattern {
+                arms.push((pattern, body));
+            } else {
+                maybe_default = Some(box body);
+                break;
+            }
+        }
+
+        let last = self.expect('}')?;
+        let id = self.assign_id(&lead, &last);
+
+        Ok(Stmt::Match {
+            value,
+            arms,
+            maybe_default,
+            id,
+        })
+    }
+
+    /// a match pattern: a literal, or a negated integer
+    fn pattern(&mut self) -> Result<Expr> {
+        let lead = self.peek();
+        let negate = self.match_any([TokenType::Minus]);
+        let e = self.primary()?;
+        let last = self.previous();
+        match e {
+            Expr::Literal {
+                value: WabbitType::Int(n),
+                ..
+            } if negate => {
+                let id = self.assign_id(&lead, &last);
+                Ok(Expr::Literal {
+                    value: WabbitType::Int(-n),
+                    id,
+                })
+            }
+            Expr::Literal { .. } if !negate => Ok(e),
+            _ => msg!(Msg::ParserExpect, &last, "literal pattern"),
+        }
+    }
+
     fn print_stmt(&mut self) -> Result<Stmt> {
         let lead = self.previous();
         let value = self.expression()?;
@@ -392,6 +573,25 @@ impl<'a> Parser<'a> {
         Ok(Stmt::Print { value, id })
     }
 
+    fn assert_stmt(&mut self) -> Result<Stmt> {
+        let lead = self.previous();
+        let start = self.current;
+        let value = self.expression()?;
+        let tokens = &self.tokens[start..self.current];
+        let last = self.expect(';')?;
+        let id = self.assign_id(&lead, &last);
+
+        // the tokens of the expression, spaced where they were in the source
+        let mut text = String::new();
+        for (i, token) in tokens.iter().enumerate() {
+            if i > 0 && token.range.0 > tokens[i - 1].range.1 {
+                text.push(' ');
+            }
+            text.push_str(&token.lexeme);
+        }
+        Ok(Stmt::Assert { value, text, id })
+    }
+
     fn return_stmt(&mut self) -> Result<Stmt> {
         let lead = self.previous();
         let value = self.expression()?;
@@ -453,7 +653,7 @@ impl<'a> Parser<'a> {
 
     fn compare(&mut self) -> Result<Expr> {
         let lead = self.previous();
-        let mut e = self.add_or_sub()?;
+        let mut e = self.bit_or()?;
 
         while self.match_any([
             TokenType::Less,
@@ -464,7 +664,31 @@ impl<'a> Parser<'a> {
             TokenType::NotEqual,
         ]) {
             let op = self.previous();
-            let rhs = box self.add_or_sub()?;
+            let rhs = box self.bit_or()?;
+            let last = self.previous();
+            let id = self.assign_id(&lead, &last);
+            e = Expr::Binary {
+                lhs: box e,
+                op: op.try_into()?,
+                rhs,
+                id,
+            };
+        }
+        Ok(e)
+    }
+
+    /// a left associative chain of any of `ops`, between operands parsed by `operand`
+    fn binary_chain<const N: usize>(
+        &mut self,
+        ops: [TokenType; N],
+        operand: fn(&mut Self) -> Result<Expr>,
+    ) -> Result<Expr> {
+        let lead = self.previous();
+        let mut e = operand(self)?;
+
+        while self.match_any(ops) {
+            let op = self.previous();
+            let rhs = box operand(self)?;
             let last = self.previous();
             let id = self.assign_id(&lead, &last);
             e = Expr::Binary {
@@ -477,6 +701,26 @@ impl<'a> Parser<'a> {
         Ok(e)
     }
 
+    /// as in Rust, bitwise operators bind tighter than comparisons, and shifts tighter still
+    fn bit_or(&mut self) -> Result<Expr> {
+        self.binary_chain([TokenType::BitOr], Self::bit_xor)
+    }
+
+    fn bit_xor(&mut self) -> Result<Expr> {
+        self.binary_chain([TokenType::BitXor], Self::bit_and)
+    }
+
+    fn bit_and(&mut self) -> Result<Expr> {
+        self.binary_chain([TokenType::BitAnd], Self::shift)
+    }
+
+    fn shift(&mut self) -> Result<Expr> {
+        self.binary_chain(
+            [TokenType::ShiftLeft, TokenType::ShiftRight],
+            Self::add_or_sub,
+        )
+    }
+
     fn add_or_sub(&mut self) -> Result<Expr> {
         let lead = self.previous();
         let mut e = self.times_or_div()?;
@@ -500,7 +744,7 @@ impl<'a> Parser<'a> {
         let lead = self.previous();
         let mut e = self.unary()?;
 
-        while self.match_any([TokenType::Divide, TokenType::Times]) {
+        while self.match_any([TokenType::Divide, TokenType::Times, TokenType::Modulo]) {
             let op = self.previous();
             let rhs = box self.unary()?;
             let last = self.previous();
@@ -534,7 +778,7 @@ impl<'a> Parser<'a> {
 
     fn call(&mut self) -> Result<Expr> {
         let mut e = self.primary()?;
-        if self.match_any([TokenType::LeftParen]) {
+        while self.match_any([TokenType::LeftParen]) {
             let name = self.previous();
             e = self.finish_call(e, &name)?;
         }
@@ -554,11 +798,11 @@ impl<'a> Parser<'a> {
         match e {
             Expr::VarName { name, .. } => Ok(Expr::Call { name, params, id }),
             Expr::TypeName { dtype, .. } => Ok(Expr::TypeConversion { dtype, params, id }),
-            _ => msg!(
-                Msg::InternalErr,
-                self,
-                "Parser constructed call without a name/type"
-            ),
+            callee => Ok(Expr::CallValue {
+                callee: box callee,
+                params,
+                id,
+            }),
         }
     }
 
@@ -581,6 +825,17 @@ impl<'a> Parser<'a> {
                     "Scanner created a literal without a value."
                 )
             }
+        } else if self.match_any([TokenType::Func]) {
+            let lead = self.previous();
+            let (params, return_type, body) = self.function()?;
+            let last = self.previous();
+            let id = self.assign_id(&lead, &last);
+            Ok(Expr::Function {
+                params,
+                return_type,
+                body,
+                id,
+            })
         } else if let Ok((token, dtype)) = self.get_type() {
             let id = self.assign_id_single(&token);
             Ok(Expr::TypeName { id, dtype })
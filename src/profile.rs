@@ -0,0 +1,225 @@
+//! count the statements run by each function and loop of interpreted programs
+//!
+//! a profile counts statements rather than time, so it is the same on every run and every machine
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::debugger::line_of;
+
+/// the name of the top level of a program, at the root of every stack
+pub const MAIN: &str = "<main>";
+
+/// what a function did while the program ran
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FunctionStats {
+    pub calls: u64,
+    /// statements executed while the function was called, including in the functions it called
+    ///
+    /// only the outermost call of a recursive function is counted, so that nested calls are not
+    /// counted twice
+    pub inclusive: u64,
+    /// statements executed in the function itself
+    pub exclusive: u64,
+    /// the most calls of the function nested at once, above 1 for recursive functions
+    pub max_recursion: usize,
+}
+
+/// what a `while` or `for` loop did while the program ran
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LoopStats {
+    /// `while` or `for`
+    pub keyword: &'static str,
+    /// the source index of the start of the loop
+    pub start: usize,
+    pub iterations: u64,
+    /// statements executed in the loop, including in the functions it called, counting only the
+    /// outermost run of a loop in a recursive function
+    pub statements: u64,
+}
+
+/// statement counts of an interpreted program, see [`Interpreter::with_profile`]
+///
+/// [`Interpreter::with_profile`]: crate::Interpreter::with_profile
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    /// statements executed by the whole program
+    pub statements: u64,
+    /// the deepest that calls were nested
+    pub max_call_depth: usize,
+    /// by function name
+    pub functions: BTreeMap<String, FunctionStats>,
+    /// by the id of the loop statement
+    pub loops: BTreeMap<usize, LoopStats>,
+    /// statements executed in each stack of calls, named by the functions separated by `;`,
+    /// starting from `MAIN`
+    pub stacks: BTreeMap<String, u64>,
+    /// the functions being called, with the statement count when each was entered and the
+    /// length of `stack` before it
+    calls: Vec<(String, u64, usize)>,
+    /// the loops running, with the statement count when each started
+    running: Vec<(usize, u64)>,
+    /// the current stack of calls, as in `stacks`
+    stack: String,
+    /// the statement count when the last statements were charged
+    charged: u64,
+}
+
+impl Profile {
+    /// forget the previous run, before the program starts
+    pub(crate) fn start(&mut self) {
+        *self = Self {
+            stack: MAIN.to_string(),
+            ..Self::default()
+        };
+    }
+
+    /// charge the statements since the last charge to the current stack and function
+    ///
+    /// the interpreter passes its statement count, kept for the fuel limit, whenever a function
+    /// is entered or left and a loop starts, iterates or ends, so the statements in between are
+    /// charged to whatever was running
+    fn charge(&mut self, steps: u64) {
+        let count = steps - self.charged;
+        self.charged = steps;
+        if count == 0 {
+            return;
+        }
+        match self.stacks.get_mut(&self.stack) {
+            Some(total) => *total += count,
+            None => {
+                self.stacks.insert(self.stack.clone(), count);
+            }
+        }
+        if let Some((name, ..)) = self.calls.last() {
+            if let Some(stats) = self.functions.get_mut(name) {
+                stats.exclusive += count;
+            }
+        }
+    }
+
+    pub(crate) fn enter(&mut self, name: &str, steps: u64) {
+        self.charge(steps);
+        let recursion = self.calls.iter().filter(|(n, ..)| n == name).count() + 1;
+        let stats = self.functions.entry(name.to_string()).or_default();
+        stats.calls += 1;
+        stats.max_recursion = stats.max_recursion.max(recursion);
+
+        self.calls.push((name.to_string(), steps, self.stack.len()));
+        self.stack.push(';');
+        self.stack.push_str(name);
+        self.max_call_depth = self.max_call_depth.max(self.calls.len());
+    }
+
+    pub(crate) fn exit(&mut self, steps: u64) {
+        self.charge(steps);
+        if let Some((name, entered, length)) = self.calls.pop() {
+            self.stack.truncate(length);
+            if !self.calls.iter().any(|(n, ..)| *n == name) {
+                if let Some(stats) = self.functions.get_mut(&name) {
+                    stats.inclusive += steps - entered;
+                }
+            }
+        }
+    }
+
+    pub(crate) fn enter_loop(
+        &mut self,
+        id: usize,
+        keyword: &'static str,
+        start: usize,
+        steps: u64,
+    ) {
+        let stats = self.loops.entry(id).or_default();
+        stats.keyword = keyword;
+        stats.start = start;
+        self.running.push((id, steps));
+    }
+
+    pub(crate) fn iteration(&mut self, id: usize) {
+        if let Some(stats) = self.loops.get_mut(&id) {
+            stats.iterations += 1;
+        }
+    }
+
+    pub(crate) fn exit_loop(&mut self, steps: u64) {
+        if let Some((id, started)) = self.running.pop() {
+            if !self.running.iter().any(|(i, _)| *i == id) {
+                if let Some(stats) = self.loops.get_mut(&id) {
+                    stats.statements += steps - started;
+                }
+            }
+        }
+    }
+
+    /// end the loops and calls left by an error, after the program stops
+    pub(crate) fn finish(&mut self, steps: u64) {
+        while !self.running.is_empty() {
+            self.exit_loop(steps);
+        }
+        while !self.calls.is_empty() {
+            self.exit(steps);
+        }
+        self.charge(steps);
+        self.statements = steps;
+    }
+
+    /// a table of functions and loops, the most statements first, with loops found by line in
+    /// `source`
+    pub fn report(&self, source: &str) -> String {
+        let mut report = format!(
+            "{} statements executed, max call depth {}\n",
+            self.statements, self.max_call_depth
+        );
+
+        let mut functions: Vec<_> = self.functions.iter().collect();
+        functions.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.inclusive));
+        if !functions.is_empty() {
+            writeln!(
+                report,
+                "\n{:<16} {:>10} {:>12} {:>12} {:>10}",
+                "function", "calls", "inclusive", "exclusive", "recursion"
+            )
+            .unwrap();
+        }
+        for (name, stats) in functions {
+            writeln!(
+                report,
+                "{name:<16} {:>10} {:>12} {:>12} {:>10}",
+                stats.calls, stats.inclusive, stats.exclusive, stats.max_recursion
+            )
+            .unwrap();
+        }
+
+        let mut loops: Vec<_> = self.loops.values().collect();
+        loops.sort_by_key(|stats| std::cmp::Reverse(stats.statements));
+        if !loops.is_empty() {
+            writeln!(
+                report,
+                "\n{:<16} {:>10} {:>12}",
+                "loop", "iterations", "statements"
+            )
+            .unwrap();
+        }
+        for stats in loops {
+            let name = format!("{} at line {}", stats.keyword, line_of(source, stats.start));
+            writeln!(
+                report,
+                "{name:<16} {:>10} {:>12}",
+                stats.iterations, stats.statements
+            )
+            .unwrap();
+        }
+        report
+    }
+
+    /// statements executed by each stack, one per line, in the folded format read by flamegraph
+    /// tools
+    pub fn folded(&self) -> String {
+        let mut folded = String::new();
+        for (stack, count) in &self.stacks {
+            writeln!(folded, "{stack} {count}").unwrap();
+        }
+        folded
+    }
+}
@@ -0,0 +1,129 @@
+//! compile Wabbit source once and run it from Rust
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::ast::Stmt;
+use crate::error::{WabbitError, WabbitErrorReporter};
+use crate::host::{Host, Input, Natives, Output};
+use crate::interpreter::Interpreter;
+use crate::parser::Parser;
+use crate::scanner::Scanner;
+use crate::typechecker::Typechecker;
+
+/// the name given to embedded source in error reports
+const ORIGIN: &str = "<source>";
+
+/// errors that stopped a program from compiling
+#[derive(Debug, Clone)]
+pub struct Diagnostics {
+    /// the stage that found the errors: `Scanner`, `Parser` or `Typechecking`
+    pub stage: &'static str,
+    pub errors: Vec<WabbitError>,
+    source: Arc<str>,
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reporter = WabbitErrorReporter::new(
+            self.errors.clone(),
+            PathBuf::from(ORIGIN),
+            self.source.to_string(),
+            self.stage,
+        );
+        write!(f, "{reporter}")
+    }
+}
+
+impl std::error::Error for Diagnostics {}
+
+/// an error while running a compiled program
+#[derive(Debug, Clone)]
+pub struct RuntimeError {
+    pub error: WabbitError,
+    source: Arc<str>,
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reporter = WabbitErrorReporter::new(
+            vec![self.error.clone()],
+            PathBuf::from(ORIGIN),
+            self.source.to_string(),
+            "Interpreter",
+        );
+        write!(f, "{reporter}")
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+/// a typechecked Wabbit program, ready to run
+///
+/// this owns what the pipeline otherwise borrows from a scanner and parser, so it can be stored,
+/// sent between threads and run many times
+#[derive(Debug, Clone)]
+pub struct Program {
+    source: Arc<str>,
+    statements: Vec<Stmt>,
+    /// map of statements/expressions to source indices, for runtime errors
+    ranges: HashMap<usize, (usize, usize)>,
+    natives: Natives,
+}
+
+/// scan, parse and typecheck a program
+pub fn compile(source: &str) -> Result<Program, Diagnostics> {
+    compile_with_natives(source, Natives::new())
+}
+
+/// scan, parse and typecheck a program that may call the given native functions
+pub fn compile_with_natives(source: &str, natives: Natives) -> Result<Program, Diagnostics> {
+    let source: Arc<str> = Arc::from(source);
+    let fail = |stage, errors| Diagnostics {
+        stage,
+        errors,
+        source: source.clone(),
+    };
+
+    let mut scanner = Scanner::new(&source);
+    scanner.scan().map_err(|errs| fail("Scanner", errs))?;
+    let mut parser = Parser::from(&scanner);
+    parser.parse().map_err(|err| fail("Parser", vec![err]))?;
+    let mut typechecker = Typechecker::from(&parser);
+    typechecker.natives = &natives;
+    typechecker
+        .typecheck()
+        .map_err(|err| fail("Typechecking", vec![err]))?;
+
+    let (statements, ranges) = parser.into_parts();
+    Ok(Program {
+        source,
+        statements,
+        ranges,
+        natives,
+    })
+}
+
+impl Program {
+    /// the source the program was compiled from
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// run the program from the start, with fresh variables each time
+    ///
+    /// this only builds a new interpreter over the statements, which were checked in [`compile`]
+    pub fn run<O: Output, I: Input>(&self, host: &mut Host<O, I>) -> Result<(), RuntimeError> {
+        let mut interpreter = Interpreter::new(&self.statements, &self.ranges)
+            .with_output(&mut host.output)
+            .with_input(&mut host.input)
+            .with_limits(host.limits);
+        interpreter.analyze.natives = &self.natives;
+        interpreter.interpret().map_err(|error| RuntimeError {
+            error,
+            source: self.source.clone(),
+        })
+    }
+}
@@ -6,9 +6,7 @@ use std::collections::HashMap;
 lazy_static! {
     static ref TOKENS_SINGLE: HashMap<char, TokenType> = {
         let mut m = HashMap::new();
-        m.insert('+', TokenType::Plus);
-        m.insert('-', TokenType::Minus);
-        m.insert('*', TokenType::Times);
+        m.insert('^', TokenType::BitXor);
         m.insert(';', TokenType::Semicolon);
         m.insert('(', TokenType::LeftParen);
         m.insert(')', TokenType::RightParen);
@@ -17,17 +15,38 @@ lazy_static! {
         m.insert(',', TokenType::Comma);
         m
     };
-    static ref TOKENS_DOUBLE: HashMap<char, (char, Option<TokenType>, TokenType)> = {
+    /// characters that may start a token of two characters, with the token they are on their
+    /// own, and the possible second characters and their tokens
+    static ref TOKENS_DOUBLE: HashMap<char, (TokenType, Vec<(char, TokenType)>)> = {
         let mut m = HashMap::new();
-        m.insert('<', ('=', Some(TokenType::Less), TokenType::LessEqual));
+        m.insert(
+            '<',
+            (
+                TokenType::Less,
+                vec![('=', TokenType::LessEqual), ('<', TokenType::ShiftLeft)],
+            ),
+        );
         m.insert(
             '>',
-            ('=', Some(TokenType::Greater), TokenType::GreaterEqual),
+            (
+                TokenType::Greater,
+                vec![('=', TokenType::GreaterEqual), ('>', TokenType::ShiftRight)],
+            ),
+        );
+        m.insert(
+            '=',
+            (
+                TokenType::Assign,
+                vec![('=', TokenType::EqualEqual), ('>', TokenType::FatArrow)],
+            ),
         );
-        m.insert('=', ('=', Some(TokenType::Assign), TokenType::EqualEqual));
-        m.insert('!', ('=', Some(TokenType::LogicalNot), TokenType::NotEqual));
-        m.insert('&', ('&', None, TokenType::LogicalAnd));
-        m.insert('|', ('|', None, TokenType::LogicalOr));
+        m.insert('!', (TokenType::LogicalNot, vec![('=', TokenType::NotEqual)]));
+        m.insert('&', (TokenType::BitAnd, vec![('&', TokenType::LogicalAnd)]));
+        m.insert('|', (TokenType::BitOr, vec![('|', TokenType::LogicalOr)]));
+        m.insert('+', (TokenType::Plus, vec![('=', TokenType::PlusAssign)]));
+        m.insert('-', (TokenType::Minus, vec![('=', TokenType::MinusAssign)]));
+        m.insert('*', (TokenType::Times, vec![('=', TokenType::TimesAssign)]));
+        m.insert('%', (TokenType::Modulo, vec![('=', TokenType::ModuloAssign)]));
         m
     };
     static ref KEYWORDS: HashMap<&'static str, TokenType> = {
@@ -35,11 +54,14 @@ lazy_static! {
         m.insert("const", TokenType::Const);
         m.insert("var", TokenType::Var);
         m.insert("print", TokenType::Print);
+        m.insert("assert", TokenType::Assert);
         m.insert("break", TokenType::Break);
         m.insert("continue", TokenType::Continue);
         m.insert("if", TokenType::If);
         m.insert("else", TokenType::Else);
         m.insert("while", TokenType::While);
+        m.insert("for", TokenType::For);
+        m.insert("match", TokenType::Match);
         m.insert("func", TokenType::Func);
         m.insert("return", TokenType::Return);
         m.insert("true", TokenType::True);
@@ -52,6 +74,9 @@ lazy_static! {
         m.insert("float", TokenType::FloatType);
         m.insert("bool", TokenType::BoolType);
         m.insert("char", TokenType::CharType);
+        m.insert("int64", TokenType::Int64Type);
+        m.insert("uint8", TokenType::Uint8Type);
+        m.insert("float32", TokenType::Float32Type);
         m
     };
 }
@@ -195,18 +220,29 @@ impl Scanner {
             }
         }
 
+        // a suffix directly after the digits gives the type of the literal, as in `1i64`
+        let number = self.lexeme();
+        if self.source[self.current - 1].is_ascii_digit() {
+            while self.peek().is_ascii_alphanumeric() {
+                self.advance();
+            }
+        }
         let lexeme = self.lexeme();
 
-        if found_decimal {
-            match lexeme.parse::<f64>() {
-                Ok(n) => self.add_literal_token(TokenType::Float, WabbitType::Float(n)),
-                Err(_) => return msg!(Msg::InvalidNumber, self, lexeme),
-            }
-        } else {
-            match lexeme.parse::<i32>() {
-                Ok(n) => self.add_literal_token(TokenType::Integer, WabbitType::Int(n)),
-                Err(_) => return msg!(Msg::InvalidNumber, self, lexeme),
+        let value = match (&lexeme[number.len()..], found_decimal) {
+            ("", false) => number.parse().ok().map(WabbitType::Int),
+            ("", true) => number.parse().ok().map(WabbitType::Float),
+            ("i64", false) => number.parse().ok().map(WabbitType::Int64),
+            ("u8", false) => number.parse().ok().map(WabbitType::Uint8),
+            ("f32", _) => number.parse().ok().map(WabbitType::Float32),
+            _ => None,
+        };
+        match value {
+            Some(value) if value.dtype().is_float() => {
+                self.add_literal_token(TokenType::Float, value);
             }
+            Some(value) => self.add_literal_token(TokenType::Integer, value),
+            None => return msg!(Msg::InvalidNumber, self, lexeme),
         }
 
         Ok(())
@@ -225,18 +261,15 @@ impl Scanner {
             self.add_token(TokenType::Eof);
         } else if let Some(single_tt) = TOKENS_SINGLE.get(&c) {
             self.add_token(*single_tt);
-        } else if let Some((next_for_double, maybe_single, double_tt)) = TOKENS_DOUBLE.get(&c) {
+        } else if let Some((single_tt, doubles)) = TOKENS_DOUBLE.get(&c) {
             // checking for pairs of chars that make a token
-            if self.peek() == *next_for_double {
+            let next = self.peek();
+            if let Some((_, double_tt)) = doubles.iter().find(|(second, _)| *second == next) {
                 self.advance();
                 self.add_token(*double_tt);
-            } else if let Some(single_tt) = maybe_single {
-                self.add_token(*single_tt);
             } else {
-                failed = true;
-                let err = err!(Msg::DoubleToken, self, c, next_for_double);
-                errors.push(err);
-            };
+                self.add_token(*single_tt);
+            }
         } else {
             match c {
                 '/' => {
@@ -253,6 +286,9 @@ impl Scanner {
                                 break;
                             }
                         }
+                    } else if self.peek() == '=' {
+                        self.advance();
+                        self.add_token(TokenType::DivideAssign);
                     } else {
                         self.add_token(TokenType::Divide);
                     }
@@ -0,0 +1,128 @@
+//! discover and run the test functions of Wabbit programs
+//!
+//! a test is a top level function named `test_*` without parameters
+
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::ast::Stmt;
+use crate::error::WabbitErrorReporter;
+use crate::host::Limits;
+use crate::interpreter::Interpreter;
+use crate::parser::Parser;
+use crate::scanner::Scanner;
+
+/// the prefix of the names of test functions
+pub const PREFIX: &str = "test_";
+
+/// the names of the test functions of a parsed program, in source order
+pub fn discover(parser: &Parser) -> Vec<String> {
+    parser
+        .borrow_statements()
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Stmt::FuncDef {
+                def_name,
+                def_params,
+                ..
+            } if def_name.starts_with(PREFIX) && def_params.is_empty() => Some(def_name.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// the source of a program that runs `source` and then the test `name`
+///
+/// the top level of the program runs first, as it would for a real run, and every backend can run
+/// the result. The call goes after the original source, so errors in the program keep their
+/// positions
+pub fn harness(source: &str, name: &str) -> String {
+    let separator = if source.ends_with('\n') { "" } else { "\n" };
+    format!("{source}{separator}{name}();\n")
+}
+
+/// how a test ran
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome {
+    pub name: String,
+    /// what the test printed
+    pub output: String,
+    /// the error that stopped the test, if it failed
+    pub failure: Option<String>,
+}
+
+impl Outcome {
+    pub fn passed(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+/// run the test `name` of the program `source`, read from `path`, with the interpreter
+pub fn interpret(source: &str, path: &Path, name: &str, limits: Limits) -> Outcome {
+    let harness = harness(source, name);
+    let report = |errors, title| {
+        WabbitErrorReporter::new(errors, path.to_path_buf(), harness.clone(), title).to_string()
+    };
+
+    let mut output = String::new();
+    let mut scanner = Scanner::new(&harness);
+    let result = if let Err(errors) = scanner.scan() {
+        Err(report(errors, "Scanner"))
+    } else {
+        let mut parser = Parser::from(&scanner);
+        match parser.parse() {
+            Err(error) => Err(report(vec![error], "Parser")),
+            Ok(()) => Interpreter::from(&parser)
+                .with_output(&mut output)
+                .with_limits(limits)
+                .interpret()
+                .map_err(|error| report(vec![error], "Interpreter")),
+        }
+    };
+    Outcome {
+        name: name.to_string(),
+        output,
+        failure: result.err(),
+    }
+}
+
+/// a summary of the tests that ran, like cargo's: a line for each test, then the output and
+/// error of each failure, then the counts
+pub fn report(outcomes: &[Outcome]) -> String {
+    let plural = if outcomes.len() == 1 { "" } else { "s" };
+    let mut report = format!("running {} test{plural}\n", outcomes.len());
+    for outcome in outcomes {
+        let result = if outcome.passed() {
+            "ok"
+        } else {
+            "\x1b[31mFAILED\x1b[0m"
+        };
+        writeln!(report, "test {} ... {result}", outcome.name).unwrap();
+    }
+
+    let failed: Vec<&Outcome> = outcomes.iter().filter(|o| !o.passed()).collect();
+    if !failed.is_empty() {
+        report.push_str("\nfailures:\n");
+    }
+    for outcome in &failed {
+        writeln!(report, "\n---- {} ----", outcome.name).unwrap();
+        report.push_str(&outcome.output);
+        if let Some(failure) = &outcome.failure {
+            writeln!(report, "{}", failure.trim_end()).unwrap();
+        }
+    }
+
+    let result = if failed.is_empty() {
+        "ok"
+    } else {
+        "\x1b[31mFAILED\x1b[0m"
+    };
+    writeln!(
+        report,
+        "\ntest result: {result}. {} passed; {} failed",
+        outcomes.len() - failed.len(),
+        failed.len()
+    )
+    .unwrap();
+    report
+}
@@ -7,11 +7,14 @@ pub enum TokenType {
     Const,
     Var,
     Print,
+    Assert,
     Break,
     Continue,
     If,
     Else,
     While,
+    For,
+    Match,
     Func,
     Return,
     True,
@@ -26,6 +29,9 @@ pub enum TokenType {
     FloatType,
     CharType,
     BoolType,
+    Int64Type,
+    Uint8Type,
+    Float32Type,
 
     // literals
     Char,
@@ -37,6 +43,12 @@ pub enum TokenType {
     Minus,
     Times,
     Divide,
+    Modulo,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
     Less,
     LessEqual,
     Greater,
@@ -47,6 +59,12 @@ pub enum TokenType {
     LogicalOr,
     LogicalNot,
     Assign,
+    PlusAssign,
+    MinusAssign,
+    TimesAssign,
+    DivideAssign,
+    ModuloAssign,
+    FatArrow,
     Semicolon,
     LeftParen,
     RightParen,
@@ -0,0 +1,56 @@
+use std::io::Write;
+
+use crate::types::WabbitType;
+
+/// writes each statement as the interpreter executes it, see [`Interpreter::with_trace`]
+///
+/// statements are written with their span in the source, as `line:column-line:column`, and the
+/// line they start on, followed by the values of any variables they assign. Both are
+/// indented by the depth of function calls.
+///
+/// [`Interpreter::with_trace`]: crate::Interpreter::with_trace
+pub struct Trace<W: ?Sized> {
+    /// the lines of the source, with the index of their first character
+    lines: Vec<(usize, String)>,
+    /// where the trace is written
+    pub output: W,
+}
+
+impl<W: Write> Trace<W> {
+    pub fn new(source: &str, output: W) -> Self {
+        let mut lines = Vec::new();
+        let mut start = 0;
+        for line in source.split('\n') {
+            lines.push((start, line.to_string()));
+            start += line.chars().count() + 1;
+        }
+        Self { lines, output }
+    }
+}
+
+impl<W: Write + ?Sized> Trace<W> {
+    /// the line and column of a source index, counting from 1
+    fn position(&self, index: usize) -> (usize, usize) {
+        // the first line starts at 0, so is always counted
+        let line = self.lines.partition_point(|(start, _)| *start <= index);
+        (line, index - self.lines[line - 1].0 + 1)
+    }
+
+    pub(crate) fn statement(&mut self, range: (usize, usize), depth: usize) {
+        let (line, column) = self.position(range.0);
+        let (end_line, end_column) = self.position(range.1);
+        let indent = "  ".repeat(depth);
+        // like printing to stdout, tracing does not stop the program if it fails
+        writeln!(
+            self.output,
+            "{indent}{line}:{column}-{end_line}:{end_column} {}",
+            self.lines[line - 1].1.trim()
+        )
+        .ok();
+    }
+
+    pub(crate) fn assign(&mut self, name: &str, value: &WabbitType, depth: usize) {
+        let indent = "  ".repeat(depth + 1);
+        writeln!(self.output, "{indent}{name} = {value}").ok();
+    }
+}
@@ -2,8 +2,8 @@ use crate::analyzer::Analyzer;
 use crate::ast::{Expr, Stmt};
 use crate::environment::VarStore;
 use crate::error::{msg, Msg, RangeReporter, Result};
-use crate::operators::{BinaryOp, UnaryOp};
-use crate::types::Type;
+use crate::operators::{BinaryOp, Builtin, UnaryOp};
+use crate::types::{Type, INTEGER, NUMERIC, ORDERED, SIGNED};
 use std::collections::HashMap;
 
 /// struct for typechecking Wabbit AST
@@ -22,11 +22,23 @@ impl<'a> Typechecker<'a> {
     /// typecheck a single statement
     pub fn typecheck_stmt(&mut self, stmt: &'a Stmt) -> Result<Option<Type>> {
         match stmt {
-            Stmt::Assign { name, value, id } => {
+            Stmt::Assign {
+                name,
+                op,
+                value,
+                id,
+            } => {
                 self.check_constant(name, *id)?;
                 self.check_function(name, *id)?;
 
-                let new_type = self.expr_type(value)?;
+                let new_type = match op {
+                    Some(op) => {
+                        let current_type = self.variable_type(name, *id)?;
+                        let value_type = self.expr_type(value)?;
+                        self.binary_type(current_type, op, &value_type, *id)?
+                    }
+                    None => self.expr_type(value)?,
+                };
 
                 match self.env.get(name) {
                     Some(VarStore::Init(old_type) | VarStore::UnInit(old_type)) => {
@@ -42,6 +54,9 @@ impl<'a> Typechecker<'a> {
                             );
                         }
                     }
+                    Some(VarStore::Captured(_)) => {
+                        return msg!(Msg::AssignCapture, (self.ranges, *id), name);
+                    }
                     None => return msg!(Msg::AssignUndefined, (self.ranges, *id)),
                 };
                 Ok(None)
@@ -52,26 +67,47 @@ impl<'a> Typechecker<'a> {
 
                 for stmt in statements {
                     if let Some(ret_type) = self.typecheck_stmt(stmt)? {
-                        return_types.push(ret_type);
+                        return_types.push(ret_type.clone());
 
                         match stmt {
                             Stmt::While { .. }
+                            | Stmt::For { .. }
                             | Stmt::If {
                                 maybe_else_block: None,
                                 ..
+                            }
+                            | Stmt::Match {
+                                maybe_default: None,
+                                ..
                             } => (),
                             Stmt::If {
                                 then_block,
                                 maybe_else_block: Some(else_block),
                                 ..
                             } => {
-                                let ret_then = self.typecheck_stmt(then_block).unwrap();
-                                let ret_else = self.typecheck_stmt(else_block).unwrap();
+                                self.env.enter_child();
+                                let ret_then = self.typecheck_stmt(then_block)?;
+                                self.env.exit_child(&(self.ranges, *id))?;
+                                self.env.enter_child();
+                                let ret_else = self.typecheck_stmt(else_block)?;
+                                self.env.exit_child(&(self.ranges, *id))?;
 
                                 if matches!((ret_then, ret_else), (Some(_), Some(_))) {
                                     return_exclude_if_while.push(ret_type);
                                 };
                             }
+                            Stmt::Match {
+                                arms,
+                                maybe_default: Some(default),
+                                id,
+                                ..
+                            } => {
+                                // with a default arm, the match returns if every arm does
+                                let arm_returns = self.typecheck_arms(arms, Some(default), *id)?;
+                                if arm_returns.iter().all(Option::is_some) {
+                                    return_exclude_if_while.push(ret_type);
+                                }
+                            }
                             _ => return_exclude_if_while.push(ret_type),
                         }
                     }
@@ -82,7 +118,7 @@ impl<'a> Typechecker<'a> {
                 } else if return_exclude_if_while.is_empty() && self.loop_depth == 0 {
                     msg!(Msg::AltBranch, (self.ranges, *id))
                 } else if return_types.iter().all(|item| item == &return_types[0]) {
-                    Ok(Some(return_types[0]))
+                    Ok(Some(return_types[0].clone()))
                 } else {
                     msg!(Msg::ReturnDiverge, (self.ranges, *id))
                 }
@@ -108,6 +144,32 @@ impl<'a> Typechecker<'a> {
 
                 Ok(maybe_return)
             }
+            Stmt::For {
+                init,
+                condition,
+                step,
+                body,
+                id,
+            } => {
+                // the loop variable is in a scope of its own, around the body's
+                self.env.enter_child();
+                self.typecheck_stmt(init)?;
+
+                if self.expr_type(condition)? != Type::Bool {
+                    return msg!(Msg::ExpectType, (self.ranges, *id), "bool");
+                };
+
+                self.loop_depth += 1;
+                self.env.enter_child();
+                let maybe_return = self.typecheck_stmt(body)?;
+                self.env.exit_child(&(self.ranges, *id))?;
+                self.loop_depth -= 1;
+
+                self.typecheck_stmt(step)?;
+                self.env.exit_child(&(self.ranges, *id))?;
+
+                Ok(maybe_return)
+            }
             Stmt::Return { value, id } => {
                 let value_type = self.expr_type(value)?;
                 if self.call_depth > 0 {
@@ -156,6 +218,45 @@ impl<'a> Typechecker<'a> {
                     Ok(None)
                 }
             }
+            Stmt::Match {
+                value,
+                arms,
+                maybe_default,
+                id,
+            } => {
+                let value_type = self.expr_type(value)?;
+                if ![Type::Int, Type::Char].contains(&value_type) {
+                    return msg!(Msg::ExpectType, (self.ranges, *id), "int, char");
+                }
+
+                let mut patterns = Vec::new();
+                for (pattern, _) in arms {
+                    let pattern_type = self.expr_type(pattern)?;
+                    if pattern_type != value_type {
+                        return msg!(
+                            Msg::MatchType,
+                            (self.ranges, pattern.id()),
+                            pattern_type,
+                            value_type
+                        );
+                    }
+                    let text = pattern.to_string();
+                    if patterns.contains(&text) {
+                        return msg!(Msg::MatchDuplicate, (self.ranges, pattern.id()), text);
+                    }
+                    patterns.push(text);
+                }
+
+                let arm_returns = self.typecheck_arms(arms, maybe_default.as_deref(), *id)?;
+
+                // the arms that return must agree on a type
+                let mut returns = arm_returns.into_iter().flatten();
+                let maybe_return = returns.next();
+                if returns.any(|ret_type| Some(ret_type) != maybe_return) {
+                    return msg!(Msg::ReturnDiverge, (self.ranges, *id));
+                }
+                Ok(maybe_return)
+            }
             Stmt::LoopControl { id, .. } => {
                 if self.loop_depth > 0 {
                     Ok(None)
@@ -163,10 +264,23 @@ impl<'a> Typechecker<'a> {
                     msg!(Msg::LoopReq, (self.ranges, *id))
                 }
             }
-            Stmt::Print { value, .. } => {
-                self.expr_type(value)?;
+            Stmt::Print { value, id } => {
+                if let Type::Func(_) = self.expr_type(value)? {
+                    return msg!(
+                        Msg::ExpectType,
+                        (self.ranges, *id),
+                        format!("{ORDERED}, bool")
+                    );
+                }
                 Ok(None)
             }
+            Stmt::Assert { value, id, .. } => {
+                if self.expr_type(value)? == Type::Bool {
+                    Ok(None)
+                } else {
+                    msg!(Msg::ExpectType, (self.ranges, *id), "bool")
+                }
+            }
             Stmt::ConstDef {
                 name,
                 maybe_type,
@@ -181,7 +295,7 @@ impl<'a> Typechecker<'a> {
                     msg!(Msg::ConstScope, (self.ranges, *id))
                 } else {
                     let value_type = self.expr_type(value)?;
-                    if maybe_type.is_none() || (*maybe_type).unwrap() == value_type {
+                    if maybe_type.is_none() || maybe_type.as_ref() == Some(&value_type) {
                         self.constants.insert(name, value_type);
                         Ok(None)
                     } else {
@@ -205,7 +319,7 @@ impl<'a> Typechecker<'a> {
 
                     let param_types: HashMap<&String, VarStore<Type>> = def_params
                         .iter()
-                        .map(|(name, t)| (name, VarStore::Init(*t)))
+                        .map(|(name, t)| (name, VarStore::Init(t.clone())))
                         .collect();
 
                     if def_params.len() != param_types.len() {
@@ -263,7 +377,7 @@ impl<'a> Typechecker<'a> {
                         let value_type = self.expr_type(value)?;
 
                         if typename == &value_type {
-                            self.env.define_init(name, *typename);
+                            self.env.define_init(name, typename.clone());
                         } else {
                             return msg!(Msg::InitType, (self.ranges, *id));
                         }
@@ -273,7 +387,7 @@ impl<'a> Typechecker<'a> {
                         self.env.define_init(name, value_type);
                     }
                     (Some(typename), None) => {
-                        self.env.define_uninit(name, *typename);
+                        self.env.define_uninit(name, typename.clone());
                     }
                     (None, None) => {
                         return msg!(
@@ -292,8 +406,183 @@ impl<'a> Typechecker<'a> {
         }
     }
 
+    /// typecheck the blocks of a match, each in a child environment, returning what each returns
+    fn typecheck_arms(
+        &mut self,
+        arms: &'a [(Expr, Stmt)],
+        maybe_default: Option<&'a Stmt>,
+        id: usize,
+    ) -> Result<Vec<Option<Type>>> {
+        let mut arm_returns = Vec::new();
+        for body in arms.iter().map(|(_, body)| body).chain(maybe_default) {
+            self.env.enter_child();
+            arm_returns.push(self.typecheck_stmt(body)?);
+            self.env.exit_child(&(self.ranges, id))?;
+        }
+        Ok(arm_returns)
+    }
+
+    /// the type of a variable or constant, which must be initialized
+    fn variable_type(&self, name: &String, id: usize) -> Result<Type> {
+        if let Some(constant) = self.constants.get(name) {
+            Ok(constant.clone())
+        } else if let Some(VarStore::Init(var_type) | VarStore::Captured(var_type)) =
+            self.env.get(name)
+        {
+            Ok(var_type)
+        } else if let Some(VarStore::UnInit(_)) = self.env.get(name) {
+            msg!(Msg::AccessUninit, (self.ranges, id), name)
+        } else if let Some(Stmt::FuncDef {
+            def_params,
+            return_type,
+            ..
+        }) = self.functions.get(name)
+        {
+            // a named function used as a value
+            let param_types = def_params.iter().map(|(_, t)| t.clone()).collect();
+            Ok(Type::func(param_types, return_type.clone()))
+        } else {
+            msg!(Msg::VarUndefined, (self.ranges, id))
+        }
+    }
+
+    /// shared by binary expressions and compound assignment
+    fn binary_type(
+        &self,
+        lhs_type: Type,
+        op: &BinaryOp,
+        rhs_type: &Type,
+        id: usize,
+    ) -> Result<Type> {
+        if lhs_type != *rhs_type {
+            return msg!(Msg::TypeMatch, (self.ranges, id));
+        }
+        match op {
+            BinaryOp::Plus | BinaryOp::Divide | BinaryOp::Times | BinaryOp::Minus => {
+                if lhs_type.is_numeric() {
+                    Ok(lhs_type)
+                } else {
+                    msg!(Msg::ExpectType, (self.ranges, id), NUMERIC)
+                }
+            }
+            BinaryOp::Modulo
+            | BinaryOp::BitAnd
+            | BinaryOp::BitOr
+            | BinaryOp::BitXor
+            | BinaryOp::ShiftLeft
+            | BinaryOp::ShiftRight => {
+                if lhs_type.is_integer() {
+                    Ok(lhs_type)
+                } else {
+                    msg!(Msg::ExpectType, (self.ranges, id), INTEGER)
+                }
+            }
+            BinaryOp::Less | BinaryOp::LessEqual | BinaryOp::Greater | BinaryOp::GreaterEqual => {
+                if lhs_type.is_numeric() || lhs_type == Type::Char {
+                    Ok(Type::Bool)
+                } else {
+                    msg!(Msg::ExpectType, (self.ranges, id), ORDERED)
+                }
+            }
+            BinaryOp::EqualEqual | BinaryOp::NotEqual => {
+                if let Type::Func(_) = lhs_type {
+                    msg!(
+                        Msg::ExpectType,
+                        (self.ranges, id),
+                        format!("{ORDERED}, bool")
+                    )
+                } else {
+                    Ok(Type::Bool)
+                }
+            }
+        }
+    }
+
+    /// the return type of calling a function value, named `callee` for errors
+    fn call_type(
+        &mut self,
+        callee_type: &Type,
+        callee: &str,
+        params: &'a [Expr],
+        id: usize,
+    ) -> Result<Type> {
+        let Type::Func(sig) = callee_type else {
+            return msg!(Msg::NotCallable, (self.ranges, id), callee_type);
+        };
+        if sig.params.len() != params.len() {
+            return msg!(
+                Msg::FuncAirty,
+                (self.ranges, id),
+                callee,
+                sig.params.len(),
+                params.len()
+            );
+        }
+        for (i, (param_type, param)) in std::iter::zip(&sig.params, params).enumerate() {
+            let arg_type = self.expr_type(param)?;
+            if *param_type != arg_type {
+                return msg!(
+                    Msg::ParamType,
+                    (self.ranges, id),
+                    i + 1,
+                    param_type,
+                    arg_type
+                );
+            }
+        }
+        Ok(sig.ret.clone())
+    }
+
+    /// the type of a function expression, checking its body with only the global scope, its
+    /// parameters and the variables it captures in view
+    fn function_type(
+        &mut self,
+        params: &'a [(String, Type)],
+        return_type: Type,
+        body: &'a Stmt,
+        id: usize,
+    ) -> Result<Type> {
+        let mut scope: HashMap<&String, VarStore<Type>> = params
+            .iter()
+            .map(|(name, t)| (name, VarStore::Init(t.clone())))
+            .collect();
+        if params.len() != scope.len() {
+            return msg!(Msg::DupArgs, (self.ranges, id));
+        }
+        // captured variables share the scope of the parameters, whose names they can't have
+        for (name, t) in self.captures(params, body, id)? {
+            scope.insert(name, VarStore::Captured(t));
+        }
+
+        // loops around the expression can't be continued from its body
+        let locals = self.env.enter_call(scope);
+        let loop_depth = std::mem::take(&mut self.loop_depth);
+        self.call_depth += 1;
+
+        let body_type = self.typecheck_stmt(body)?;
+
+        self.call_depth -= 1;
+        self.loop_depth = loop_depth;
+        self.env.exit_call(locals);
+
+        match body_type {
+            Some(body_type) if body_type == return_type => Ok(Type::func(
+                params.iter().map(|(_, t)| t.clone()).collect(),
+                return_type,
+            )),
+            Some(body_type) => msg!(
+                Msg::ReturnType,
+                (self.ranges, id),
+                "func",
+                return_type,
+                body_type
+            ),
+            None => msg!(Msg::NoReturn, (self.ranges, id)),
+        }
+    }
+
     /// typecheck a single expression
-    pub fn expr_type(&mut self, e: &Expr) -> Result<Type> {
+    pub fn expr_type(&mut self, e: &'a Expr) -> Result<Type> {
         match e {
             Expr::Call {
                 name: call_name,
@@ -302,7 +591,10 @@ impl<'a> Typechecker<'a> {
             } => {
                 let func_lookup = self.functions.get(call_name);
 
-                if let Some(Stmt::FuncDef {
+                if self.is_variable(call_name) {
+                    let callee_type = self.variable_type(call_name, *id)?;
+                    self.call_type(&callee_type, call_name, call_params, *id)
+                } else if let Some(Stmt::FuncDef {
                     def_params,
                     return_type,
                     id,
@@ -336,35 +628,41 @@ impl<'a> Typechecker<'a> {
                             }
                         }
 
-                        Ok(*return_type)
+                        Ok(return_type.clone())
                     }
+                } else if let Some(builtin) = Builtin::from_name(call_name) {
+                    self.check_builtin_args(call_name, call_params, *id)?;
+                    Ok(builtin.return_type())
+                } else if let Some(native) = self.natives.get(call_name) {
+                    let arg_types = call_params
+                        .iter()
+                        .map(|param| self.expr_type(param))
+                        .collect::<Result<Vec<Type>>>()?;
+                    self.check_native_args(call_name, native, &arg_types, *id)?;
+                    Ok(native.return_type.clone())
                 } else {
                     msg!(Msg::FuncUndefined, (self.ranges, *id))
                 }
             }
-            Expr::VarName { name, id } => {
-                if let Some(constant) = self.constants.get(name) {
-                    Ok(*constant)
-                } else if let Some(VarStore::Init(var_type)) = self.env.get(name) {
-                    Ok(var_type)
-                } else if let Some(VarStore::UnInit(_)) = self.env.get(name) {
-                    msg!(Msg::AccessUninit, (self.ranges, *id), name)
-                } else {
-                    msg!(Msg::VarUndefined, (self.ranges, *id))
-                }
+            Expr::CallValue { callee, params, id } => {
+                let callee_type = self.expr_type(callee)?;
+                self.call_type(&callee_type, &callee_type.to_string(), params, *id)
             }
+            Expr::Function {
+                params,
+                return_type,
+                body,
+                id,
+            } => self.function_type(params, return_type.clone(), body, *id),
+            Expr::VarName { name, id } => self.variable_type(name, *id),
             Expr::TypeName { id, .. } => msg!(Msg::TypeEval, (self.ranges, *id)),
             Expr::TypeConversion { dtype, params, id } => {
                 if let [to_convert] = params.as_slice() {
                     let original_type = self.expr_type(to_convert)?;
-                    match (dtype, original_type) {
-                        (Type::Int, Type::Char | Type::Bool | Type::Float) => Ok(Type::Int),
-                        (Type::Float, Type::Int) => Ok(Type::Float),
-                        (Type::Char, Type::Int) => Ok(Type::Char),
-                        (Type::Bool, Type::Int) => Ok(Type::Bool),
-                        // note sure if in the spec, but this seems reasonable...
-                        (a, b) if a == &b => Ok(*a),
-                        _ => msg!(Msg::TypeConvert, (self.ranges, *id)),
+                    if original_type.converts_to(dtype) {
+                        Ok(dtype.clone())
+                    } else {
+                        msg!(Msg::TypeConvert, (self.ranges, *id))
                     }
                 } else {
                     msg!(Msg::ConvertAirty, (self.ranges, *id))
@@ -374,31 +672,7 @@ impl<'a> Typechecker<'a> {
             Expr::Binary { lhs, op, rhs, id } => {
                 let lhs_type = self.expr_type(lhs)?;
                 let rhs_type = self.expr_type(rhs)?;
-
-                if lhs_type != rhs_type {
-                    msg!(Msg::TypeMatch, (self.ranges, *id))
-                } else {
-                    match op {
-                        BinaryOp::Plus | BinaryOp::Divide | BinaryOp::Times | BinaryOp::Minus => {
-                            if [Type::Float, Type::Int].contains(&lhs_type) {
-                                Ok(lhs_type)
-                            } else {
-                                msg!(Msg::ExpectType, (self.ranges, *id), "int, float")
-                            }
-                        }
-                        BinaryOp::Less
-                        | BinaryOp::LessEqual
-                        | BinaryOp::Greater
-                        | BinaryOp::GreaterEqual => {
-                            if [Type::Float, Type::Int, Type::Char].contains(&lhs_type) {
-                                Ok(Type::Bool)
-                            } else {
-                                msg!(Msg::ExpectType, (self.ranges, *id), "int, float, char")
-                            }
-                        }
-                        BinaryOp::EqualEqual | BinaryOp::NotEqual => Ok(Type::Bool),
-                    }
-                }
+                self.binary_type(lhs_type, op, &rhs_type, *id)
             }
             Expr::Grouping { e, .. } => Ok(self.expr_type(e)?),
             Expr::Logical { lhs, rhs, id, .. } => {
@@ -421,11 +695,19 @@ impl<'a> Typechecker<'a> {
                             msg!(Msg::ExpectType, (self.ranges, *id), "bool")
                         }
                     }
-                    UnaryOp::Plus | UnaryOp::Minus => {
-                        if [Type::Float, Type::Int].contains(&operand_type) {
+                    UnaryOp::Plus => {
+                        if operand_type.is_numeric() {
+                            Ok(operand_type)
+                        } else {
+                            msg!(Msg::ExpectType, (self.ranges, *id), NUMERIC)
+                        }
+                    }
+                    // uint8 has no negative values
+                    UnaryOp::Minus => {
+                        if operand_type.is_numeric() && operand_type != Type::Uint8 {
                             Ok(operand_type)
                         } else {
-                            msg!(Msg::ExpectType, (self.ranges, *id), "int, float")
+                            msg!(Msg::ExpectType, (self.ranges, *id), SIGNED)
                         }
                     }
                 }
@@ -1,13 +1,26 @@
 use std::fmt::Display;
+use std::sync::Arc;
 
 /// Wabbit data types
 
-#[derive(Debug, Clone, PartialEq, Copy)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Type {
     Int,
     Char,
     Bool,
     Float,
+    Int64,
+    Uint8,
+    Float32,
+    /// a function value, such as `func(int) int`
+    Func(Arc<Signature>),
+}
+
+/// the parameter and return types of a function value
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct Signature {
+    pub params: Vec<Type>,
+    pub ret: Type,
 }
 
 impl Display for Type {
@@ -17,21 +30,82 @@ impl Display for Type {
             Type::Char => write!(f, "char"),
             Type::Bool => write!(f, "bool"),
             Type::Float => write!(f, "float"),
+            Type::Int64 => write!(f, "int64"),
+            Type::Uint8 => write!(f, "uint8"),
+            Type::Float32 => write!(f, "float32"),
+            Type::Func(sig) => {
+                let params: Vec<String> = sig.params.iter().map(Type::to_string).collect();
+                write!(f, "func({}) {}", params.join(", "), sig.ret)
+            }
         }
     }
 }
 
+impl Type {
+    /// the type of a function value with the given parameter and return types
+    pub fn func(params: Vec<Type>, ret: Type) -> Type {
+        Type::Func(Arc::new(Signature { params, ret }))
+    }
+
+    /// whether values are integers, which have checked arithmetic and the bitwise operators
+    pub fn is_integer(&self) -> bool {
+        matches!(self, Type::Int | Type::Int64 | Type::Uint8)
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, Type::Float | Type::Float32)
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        self.is_integer() || self.is_float()
+    }
+
+    /// the size of a value in bits
+    pub fn bits(&self) -> u32 {
+        match self {
+            Type::Bool => 1,
+            Type::Char | Type::Uint8 => 8,
+            Type::Int | Type::Float32 => 32,
+            Type::Float | Type::Int64 | Type::Func(_) => 64,
+        }
+    }
+
+    /// whether a type conversion such as `int64(x)` can turn a value of this type into `to`
+    ///
+    /// numbers convert to each other, integers to and from chars and bools, and any type to itself
+    pub fn converts_to(&self, to: &Type) -> bool {
+        self == to
+            || (self.is_numeric() && to.is_numeric())
+            || (self.is_integer() && matches!(to, Type::Char | Type::Bool))
+            || (to.is_integer() && matches!(self, Type::Char | Type::Bool))
+    }
+}
+
 /// a Wabbit value, including Wabbit types
 
-#[derive(Debug, Clone, PartialEq, Copy)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum WabbitType {
     Int(i32),
     Float(f64),
     Char(char),
     Bool(bool),
+    Int64(i64),
+    Uint8(u8),
+    Float32(f32),
+    /// a function value, shared by every copy of it
+    Func(Arc<Signature>, Arc<Closure>),
     TypeHolder(Type),
 }
 
+/// the definition a function value runs and the values of the variables it captured
+#[derive(Debug, PartialEq)]
+pub struct Closure {
+    /// the id of the function expression or named function, keying the interpreter's definitions
+    pub(crate) def: usize,
+    /// in the order of the definition's captured names
+    pub(crate) captures: Vec<WabbitType>,
+}
+
 impl From<char> for WabbitType {
     fn from(value: char) -> Self {
         Self::Char(value)
@@ -63,120 +137,160 @@ impl Display for WabbitType {
             Self::Int(val) => write!(f, "{val}"),
             Self::Char(val) => write!(f, "{val}"),
             Self::Float(val) => write!(f, "{val}"),
+            Self::Int64(val) => write!(f, "{val}"),
+            Self::Uint8(val) => write!(f, "{val}"),
+            Self::Float32(val) => write!(f, "{val}"),
+            Self::Func(sig, _) => write!(f, "{}", Type::Func(Arc::clone(sig))),
             Self::TypeHolder(val) => write!(f, "{val}"),
         }
     }
 }
 
 impl WabbitType {
+    /// the text that `print` outputs for a value, which the interpreter and every backend share
+    ///
+    /// values are followed by a newline, except chars which are printed on their own. Floats use
+    /// Rust's `Display`: the shortest digits that read back as the same value, never in exponent
+    /// notation, with `NaN`, `inf` and `-inf` for values that are not finite.
+    pub fn output(&self) -> String {
+        if let Self::Char(_) = self {
+            format!("{self}")
+        } else {
+            format!("{self}\n")
+        }
+    }
+
     pub fn dtype(&self) -> Type {
         match self {
             Self::Bool(_) => Type::Bool,
             Self::Int(_) => Type::Int,
             Self::Char(_) => Type::Char,
             Self::Float(_) => Type::Float,
-            Self::TypeHolder(t) => *t,
+            Self::Int64(_) => Type::Int64,
+            Self::Uint8(_) => Type::Uint8,
+            Self::Float32(_) => Type::Float32,
+            Self::Func(sig, _) => Type::Func(Arc::clone(sig)),
+            Self::TypeHolder(t) => t.clone(),
         }
     }
 
-    pub fn bool_compare(self, other: WabbitType, f: impl Fn(bool, bool) -> bool) -> WabbitType {
-        match (self, other) {
-            (WabbitType::Bool(a), WabbitType::Bool(b)) => WabbitType::Bool(f(a, b)),
-            _ => panic!("Invalid arguments to bool_compare"),
+    /// convert a value like Rust's `as`, where floats saturate when converted to integers and
+    /// integers wrap, or `None` if the conversion is not allowed
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss,
+        clippy::cast_lossless
+    )]
+    pub fn convert(self, to: &Type) -> Option<WabbitType> {
+        if self.dtype() == *to {
+            return Some(self);
+        } else if !self.dtype().converts_to(to) {
+            return None;
         }
-    }
-
-    pub fn char_compare(self, other: WabbitType, f: impl Fn(char, char) -> bool) -> WabbitType {
-        match (self, other) {
-            (WabbitType::Char(a), WabbitType::Char(b)) => WabbitType::Bool(f(a, b)),
-            _ => panic!("Invalid arguments to char_compare"),
-        }
-    }
-
-    pub fn float_compare(self, other: WabbitType, f: impl Fn(f64, f64) -> bool) -> WabbitType {
-        match (self, other) {
-            (WabbitType::Float(a), WabbitType::Float(b)) => WabbitType::Bool(f(a, b)),
-            _ => panic!("Invalid arguments to float_compare"),
-        }
-    }
-
-    pub fn int_compare(self, other: WabbitType, f: impl Fn(i32, i32) -> bool) -> WabbitType {
-        match (self, other) {
-            (WabbitType::Int(a), WabbitType::Int(b)) => WabbitType::Bool(f(a, b)),
-            _ => panic!("Invalid arguments to int_compare"),
-        }
-    }
-
-    pub fn float_binary(self, other: WabbitType, f: impl Fn(f64, f64) -> f64) -> WabbitType {
-        match (self, other) {
-            (WabbitType::Float(a), WabbitType::Float(b)) => WabbitType::Float(f(a, b)),
-            _ => panic!("Invalid arguments to float_binary"),
-        }
-    }
-
-    pub fn int_binary(self, other: WabbitType, f: impl Fn(i32, i32) -> i32) -> WabbitType {
-        match (self, other) {
-            (WabbitType::Int(a), WabbitType::Int(b)) => WabbitType::Int(f(a, b)),
-            _ => panic!("Invalid arguments to int_binary"),
+        macro_rules! cast {
+            ($value:expr) => {
+                Some(match to {
+                    Type::Int => WabbitType::Int($value as i32),
+                    Type::Int64 => WabbitType::Int64($value as i64),
+                    Type::Uint8 => WabbitType::Uint8($value as u8),
+                    Type::Float => WabbitType::Float($value as f64),
+                    Type::Float32 => WabbitType::Float32($value as f32),
+                    Type::Char => WabbitType::Char(char::from($value as u8)),
+                    Type::Bool => WabbitType::Bool($value as i64 == 1),
+                    Type::Func(_) => return None,
+                })
+            };
         }
-    }
-
-    pub fn float_unary(self, f: impl Fn(f64) -> f64) -> WabbitType {
         match self {
-            WabbitType::Float(a) => WabbitType::Float(f(a)),
-            _ => panic!("Invalid arguments to float_unary"),
+            Self::Int(value) => cast!(value),
+            Self::Int64(value) => cast!(value),
+            Self::Uint8(value) => cast!(value),
+            Self::Float(value) => cast!(value),
+            Self::Float32(value) => cast!(value),
+            Self::Char(value) => cast!(u32::from(value)),
+            Self::Bool(value) => cast!(u8::from(value)),
+            Self::Func(..) | Self::TypeHolder(_) => None,
         }
     }
+}
 
-    pub fn int_unary(self, f: impl Fn(i32) -> i32) -> WabbitType {
-        match self {
-            WabbitType::Int(a) => WabbitType::Int(f(a)),
-            _ => panic!("Invalid arguments to int_unary"),
+/// ints use a checked method, so that overflow and division by zero are errors
+macro_rules! checked_int {
+    ($variant:ident, $a:ident, $b:ident, $loc:expr, $op:tt, $checked:ident) => {
+        match $a.$checked($b) {
+            Some(value) => Ok(WabbitType::$variant(value)),
+            None if $b == 0 => msg!(Msg::DivideByZero, $loc),
+            None => msg!(
+                Msg::IntOverflow,
+                $loc,
+                format!("{} {} {}", $a, stringify!($op), $b)
+            ),
         }
-    }
+    };
 }
 
-macro_rules! numeric_unary {
-    ($op:ident, $loc:expr, $closure:tt) => {
-        match $op {
-            WabbitType::Int(_) => Ok($op.int_unary($closure)),
-            WabbitType::Float(_) => Ok($op.float_unary($closure)),
-            _ => msg!(Msg::ExpectType, $loc, "int, float"),
+macro_rules! numeric_binary {
+    ($op1:ident, $op2:ident, $loc:expr, $op:tt, $checked:ident) => {
+        match ($op1, $op2) {
+            (WabbitType::Int(a), WabbitType::Int(b)) => checked_int!(Int, a, b, $loc, $op, $checked),
+            (WabbitType::Int64(a), WabbitType::Int64(b)) => checked_int!(Int64, a, b, $loc, $op, $checked),
+            (WabbitType::Uint8(a), WabbitType::Uint8(b)) => checked_int!(Uint8, a, b, $loc, $op, $checked),
+            (WabbitType::Float(a), WabbitType::Float(b)) => Ok(WabbitType::Float(a $op b)),
+            (WabbitType::Float32(a), WabbitType::Float32(b)) => Ok(WabbitType::Float32(a $op b)),
+            _ => msg!(Msg::ExpectType, $loc, $crate::types::NUMERIC)
         }
     };
 }
 
-macro_rules! numeric_binary {
+/// bitwise operators are only defined on integers and can't fail
+macro_rules! int_binary {
     ($op1:ident, $op2:ident, $loc:expr, $op:tt) => {
-        match (&$op1, &$op2) {
-            (WabbitType::Int(_), WabbitType::Int(_)) => Ok($op1.int_binary($op2, |a, b| a $op b)),
-            (WabbitType::Float(_), WabbitType::Float(_)) => Ok($op1.float_binary($op2, |a, b| a $op b)),
-            _ => msg!(Msg::ExpectType, $loc, "int, float")
+        match ($op1, $op2) {
+            (WabbitType::Int(a), WabbitType::Int(b)) => Ok(WabbitType::Int(a $op b)),
+            (WabbitType::Int64(a), WabbitType::Int64(b)) => Ok(WabbitType::Int64(a $op b)),
+            (WabbitType::Uint8(a), WabbitType::Uint8(b)) => Ok(WabbitType::Uint8(a $op b)),
+            _ => msg!(Msg::ExpectType, $loc, $crate::types::INTEGER)
         }
     };
 }
 
 macro_rules! compare {
     ($op1:ident, $op2:ident, $loc:expr, $op:tt) => {
-        match (&$op1, &$op2) {
-            (WabbitType::Int(_), WabbitType::Int(_)) => Ok($op1.int_compare($op2, |a, b| a $op b)),
-            (WabbitType::Float(_), WabbitType::Float(_)) => Ok($op1.float_compare($op2, |a, b| a $op b)),
-            (WabbitType::Char(_), WabbitType::Char(_)) => Ok($op1.char_compare($op2, |a, b| a $op b)),
-            _ => msg!(Msg::ExpectType, $loc, "int, float, char")
+        match ($op1, $op2) {
+            (WabbitType::Int(a), WabbitType::Int(b)) => Ok(WabbitType::Bool(a $op b)),
+            (WabbitType::Int64(a), WabbitType::Int64(b)) => Ok(WabbitType::Bool(a $op b)),
+            (WabbitType::Uint8(a), WabbitType::Uint8(b)) => Ok(WabbitType::Bool(a $op b)),
+            (WabbitType::Float(a), WabbitType::Float(b)) => Ok(WabbitType::Bool(a $op b)),
+            (WabbitType::Float32(a), WabbitType::Float32(b)) => Ok(WabbitType::Bool(a $op b)),
+            (WabbitType::Char(a), WabbitType::Char(b)) => Ok(WabbitType::Bool(a $op b)),
+            _ => msg!(Msg::ExpectType, $loc, $crate::types::ORDERED)
         }
     };
 }
 
 macro_rules! equality {
     ($op1:ident, $op2:ident, $loc:expr, $op:tt) => {
-        match (&$op1, &$op2) {
-            (WabbitType::Int(_), WabbitType::Int(_)) => Ok($op1.int_compare($op2, |a, b| a $op b)),
-            (WabbitType::Float(_), WabbitType::Float(_)) => Ok($op1.float_compare($op2, |a, b| a $op b)),
-            (WabbitType::Char(_), WabbitType::Char(_)) => Ok($op1.char_compare($op2, |a, b| a $op b)),
-            (WabbitType::Bool(_), WabbitType::Bool(_)) => Ok($op1.bool_compare($op2, |a, b| a $op b)),
-            _ => msg!(Msg::ExpectType, $loc, "int, float, char, bool")
+        match ($op1, $op2) {
+            (WabbitType::Int(a), WabbitType::Int(b)) => Ok(WabbitType::Bool(a $op b)),
+            (WabbitType::Int64(a), WabbitType::Int64(b)) => Ok(WabbitType::Bool(a $op b)),
+            (WabbitType::Uint8(a), WabbitType::Uint8(b)) => Ok(WabbitType::Bool(a $op b)),
+            (WabbitType::Float(a), WabbitType::Float(b)) => Ok(WabbitType::Bool(a $op b)),
+            (WabbitType::Float32(a), WabbitType::Float32(b)) => Ok(WabbitType::Bool(a $op b)),
+            (WabbitType::Char(a), WabbitType::Char(b)) => Ok(WabbitType::Bool(a $op b)),
+            (WabbitType::Bool(a), WabbitType::Bool(b)) => Ok(WabbitType::Bool(a $op b)),
+            _ => msg!(Msg::ExpectType, $loc, format!("{}, bool", $crate::types::ORDERED))
         }
     };
 }
 
-pub(crate) use {compare, equality, numeric_binary, numeric_unary};
+/// the types of the arithmetic operators, for errors
+pub(crate) const NUMERIC: &str = "int, int64, uint8, float, float32";
+/// the types of `%` and the bitwise operators
+pub(crate) const INTEGER: &str = "int, int64, uint8";
+/// the types of `<` and the other comparisons
+pub(crate) const ORDERED: &str = "int, int64, uint8, float, float32, char";
+/// the types that can be negated
+pub(crate) const SIGNED: &str = "int, int64, float, float32";
+
+pub(crate) use {checked_int, compare, equality, int_binary, numeric_binary};
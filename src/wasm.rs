@@ -0,0 +1,1675 @@
+//! generate WebAssembly
+//!
+//! top-level statements become the exported `main` function, and printing and reading input are
+//! done through functions imported from the host, see [`IMPORTS`]
+
+use crate::ast::{Expr, Stmt};
+use crate::environment::{Environment, VarStore};
+use crate::operators::{BinaryOp, Builtin, LogicalOp, LoopControl, UnaryOp};
+use crate::parser::Parser;
+use crate::typechecker::Typechecker;
+use crate::types::Type;
+use crate::WabbitType;
+use std::collections::HashMap;
+use std::fmt::Display;
+
+/// a WebAssembly value type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValType {
+    I32,
+    I64,
+    F32,
+    F64,
+}
+
+/// the host functions imported from `env`, with their parameter and result types
+///
+/// these are always functions `0..IMPORTS.len()`. The `print` functions each take a single
+/// Wabbit value, and the `read` functions return one (the host should trap if it cannot). uint8s
+/// are printed with `print_int`.
+pub const IMPORTS: [(&str, &[ValType], Option<ValType>); 9] = [
+    ("print_int", &[ValType::I32], None),
+    ("print_float", &[ValType::F64], None),
+    ("print_bool", &[ValType::I32], None),
+    ("print_char", &[ValType::I32], None),
+    ("read_int", &[], Some(ValType::I32)),
+    ("read_float", &[], Some(ValType::F64)),
+    ("read_char", &[], Some(ValType::I32)),
+    ("print_int64", &[ValType::I64], None),
+    ("print_float32", &[ValType::F32], None),
+];
+
+/// a single WebAssembly instruction, of the subset used by Wabbit
+#[derive(Debug, Clone, PartialEq)]
+pub enum WasmInstr {
+    Unreachable,
+    Block,
+    Loop,
+    /// `if`, with the type of the value it produces
+    If(Option<ValType>),
+    Else,
+    End,
+    /// branch to the enclosing block at a relative depth
+    Br(u32),
+    BrIf(u32),
+    Return,
+    Call(u32),
+    /// `call_indirect` through table 0, with a type index
+    CallIndirect(u32),
+    Drop,
+    LocalGet(u32),
+    LocalSet(u32),
+    GlobalGet(u32),
+    GlobalSet(u32),
+    /// a load from memory, with a constant offset
+    Load(ValType, u32),
+    /// a store to memory, with a constant offset
+    Store(ValType, u32),
+    MemorySize,
+    MemoryGrow,
+    I32Const(i32),
+    F64Const(f64),
+    I64Const(i64),
+    F32Const(f32),
+    I32Eqz,
+    I32Eq,
+    I32Ne,
+    I32LtS,
+    I32GtS,
+    I32GtU,
+    I32LeS,
+    I32GeS,
+    I64Eq,
+    I64Ne,
+    I64LtS,
+    I64GtS,
+    I64LeS,
+    I64GeS,
+    F32Eq,
+    F32Ne,
+    F32Lt,
+    F32Gt,
+    F32Le,
+    F32Ge,
+    I32Add,
+    I32Sub,
+    I32Mul,
+    I32DivS,
+    I32RemS,
+    I32And,
+    I32Or,
+    I32Xor,
+    I32Shl,
+    I32ShrS,
+    I64Add,
+    I64Sub,
+    I64Mul,
+    I64DivS,
+    I64RemS,
+    I64And,
+    I64Or,
+    I64Xor,
+    I64Shl,
+    I64ShrS,
+    F32Neg,
+    F32Add,
+    F32Sub,
+    F32Mul,
+    F32Div,
+    F32Min,
+    F64Eq,
+    F64Ne,
+    F64Lt,
+    F64Gt,
+    F64Le,
+    F64Ge,
+    F64Neg,
+    F64Add,
+    F64Sub,
+    F64Mul,
+    F64Div,
+    F64Min,
+    I32WrapI64,
+    I64ExtendI32S,
+    I64ExtendI32U,
+    F32ConvertI32S,
+    F32ConvertI64S,
+    F32DemoteF64,
+    F64ConvertI32S,
+    F64ConvertI64S,
+    F64PromoteF32,
+    I32TruncSatF32S,
+    I32TruncSatF32U,
+    I32TruncSatF64S,
+    I32TruncSatF64U,
+    I64TruncSatF32S,
+    I64TruncSatF64S,
+}
+
+/// a function defined in the module
+#[derive(Debug, Clone, PartialEq)]
+pub struct WasmFunction {
+    pub name: String,
+    pub params: Vec<ValType>,
+    pub result: Option<ValType>,
+    /// locals other than the parameters
+    pub locals: Vec<ValType>,
+    pub body: Vec<WasmInstr>,
+}
+
+/// a mutable global, zero initialized
+#[derive(Debug, Clone, PartialEq)]
+pub struct WasmGlobal {
+    pub name: String,
+    pub dtype: ValType,
+}
+
+/// a whole program, where defined functions are numbered after the imports
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct WasmModule {
+    /// the types of `call_indirect`, which come before the types of the functions
+    pub types: Vec<(Vec<ValType>, Option<ValType>)>,
+    /// function indices in the table, which are the code of function values
+    pub table: Vec<u32>,
+    /// whether the module has a memory, only needed for function values
+    pub memory: bool,
+    pub globals: Vec<WasmGlobal>,
+    pub functions: Vec<WasmFunction>,
+    /// function index of the exported `main`, which holds the top-level statements
+    pub main: u32,
+    /// the global holding the remaining fuel, exported as `fuel` so that a host can tell running
+    /// out of it from other traps
+    pub fuel: Option<u32>,
+}
+
+impl Display for ValType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::I32 => write!(f, "i32"),
+            Self::I64 => write!(f, "i64"),
+            Self::F32 => write!(f, "f32"),
+            Self::F64 => write!(f, "f64"),
+        }
+    }
+}
+
+impl ValType {
+    fn encode(self) -> u8 {
+        match self {
+            Self::I32 => 0x7F,
+            Self::I64 => 0x7E,
+            Self::F32 => 0x7D,
+            Self::F64 => 0x7C,
+        }
+    }
+
+    /// the instruction pushing zero of this type
+    fn zero(self) -> WasmInstr {
+        match self {
+            Self::I32 => WasmInstr::I32Const(0),
+            Self::I64 => WasmInstr::I64Const(0),
+            Self::F32 => WasmInstr::F32Const(0.0),
+            Self::F64 => WasmInstr::F64Const(0.0),
+        }
+    }
+}
+
+impl Type {
+    /// transform a Wabbit type into a WebAssembly value type
+    ///
+    /// bools are 0/1, chars and uint8s are 0..=255 and function values are addresses
+    pub fn wasm_type(&self) -> ValType {
+        match self {
+            Type::Int | Type::Bool | Type::Char | Type::Uint8 | Type::Func(_) => ValType::I32,
+            Type::Int64 => ValType::I64,
+            Type::Float32 => ValType::F32,
+            Type::Float => ValType::F64,
+        }
+    }
+}
+
+/// append an unsigned LEB128 integer
+fn leb_u32(bytes: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+/// append a signed LEB128 integer
+fn leb_i32(bytes: &mut Vec<u8>, value: i32) {
+    leb_i64(bytes, i64::from(value));
+}
+
+/// append a signed LEB128 integer, which has the same encoding as an `i32` of the same value
+fn leb_i64(bytes: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        if done {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+/// append a length-prefixed vector
+fn encode_vec(bytes: &mut Vec<u8>, items: &[u8], count: usize) {
+    leb_u32(bytes, count as u32);
+    bytes.extend(items);
+}
+
+/// append a name
+fn encode_name(bytes: &mut Vec<u8>, name: &str) {
+    encode_vec(bytes, name.as_bytes(), name.len());
+}
+
+/// append a section, with its id and size
+fn encode_section(bytes: &mut Vec<u8>, id: u8, contents: &[u8]) {
+    bytes.push(id);
+    encode_vec(bytes, contents, contents.len());
+}
+
+/// append a function type
+fn encode_func_type(bytes: &mut Vec<u8>, params: &[ValType], result: Option<ValType>) {
+    bytes.push(0x60);
+    leb_u32(bytes, params.len() as u32);
+    bytes.extend(params.iter().map(|t| t.encode()));
+    let results: Vec<u8> = result.iter().map(|t| t.encode()).collect();
+    encode_vec(bytes, &results, results.len());
+}
+
+impl WasmInstr {
+    /// the text format name of the instruction
+    fn mnemonic(&self) -> &'static str {
+        match self {
+            Self::Unreachable => "unreachable",
+            Self::Block => "block",
+            Self::Loop => "loop",
+            Self::If(..) => "if",
+            Self::Else => "else",
+            Self::End => "end",
+            Self::Br(..) => "br",
+            Self::BrIf(..) => "br_if",
+            Self::Return => "return",
+            Self::Call(..) => "call",
+            Self::CallIndirect(..) => "call_indirect",
+            Self::Drop => "drop",
+            Self::LocalGet(..) => "local.get",
+            Self::LocalSet(..) => "local.set",
+            Self::GlobalGet(..) => "global.get",
+            Self::GlobalSet(..) => "global.set",
+            Self::Load(ValType::I32, _) => "i32.load",
+            Self::Load(ValType::I64, _) => "i64.load",
+            Self::Load(ValType::F32, _) => "f32.load",
+            Self::Load(ValType::F64, _) => "f64.load",
+            Self::Store(ValType::I32, _) => "i32.store",
+            Self::Store(ValType::I64, _) => "i64.store",
+            Self::Store(ValType::F32, _) => "f32.store",
+            Self::Store(ValType::F64, _) => "f64.store",
+            Self::MemorySize => "memory.size",
+            Self::MemoryGrow => "memory.grow",
+            Self::I32Const(..) => "i32.const",
+            Self::F64Const(..) => "f64.const",
+            Self::I64Const(..) => "i64.const",
+            Self::F32Const(..) => "f32.const",
+            Self::I32Eqz => "i32.eqz",
+            Self::I32Eq => "i32.eq",
+            Self::I32Ne => "i32.ne",
+            Self::I32LtS => "i32.lt_s",
+            Self::I32GtS => "i32.gt_s",
+            Self::I32GtU => "i32.gt_u",
+            Self::I32LeS => "i32.le_s",
+            Self::I32GeS => "i32.ge_s",
+            Self::I64Eq => "i64.eq",
+            Self::I64Ne => "i64.ne",
+            Self::I64LtS => "i64.lt_s",
+            Self::I64GtS => "i64.gt_s",
+            Self::I64LeS => "i64.le_s",
+            Self::I64GeS => "i64.ge_s",
+            Self::F32Eq => "f32.eq",
+            Self::F32Ne => "f32.ne",
+            Self::F32Lt => "f32.lt",
+            Self::F32Gt => "f32.gt",
+            Self::F32Le => "f32.le",
+            Self::F32Ge => "f32.ge",
+            Self::I32Add => "i32.add",
+            Self::I32Sub => "i32.sub",
+            Self::I32Mul => "i32.mul",
+            Self::I32DivS => "i32.div_s",
+            Self::I32RemS => "i32.rem_s",
+            Self::I32And => "i32.and",
+            Self::I32Or => "i32.or",
+            Self::I32Xor => "i32.xor",
+            Self::I32Shl => "i32.shl",
+            Self::I32ShrS => "i32.shr_s",
+            Self::I64Add => "i64.add",
+            Self::I64Sub => "i64.sub",
+            Self::I64Mul => "i64.mul",
+            Self::I64DivS => "i64.div_s",
+            Self::I64RemS => "i64.rem_s",
+            Self::I64And => "i64.and",
+            Self::I64Or => "i64.or",
+            Self::I64Xor => "i64.xor",
+            Self::I64Shl => "i64.shl",
+            Self::I64ShrS => "i64.shr_s",
+            Self::F32Neg => "f32.neg",
+            Self::F32Add => "f32.add",
+            Self::F32Sub => "f32.sub",
+            Self::F32Mul => "f32.mul",
+            Self::F32Div => "f32.div",
+            Self::F32Min => "f32.min",
+            Self::F64Eq => "f64.eq",
+            Self::F64Ne => "f64.ne",
+            Self::F64Lt => "f64.lt",
+            Self::F64Gt => "f64.gt",
+            Self::F64Le => "f64.le",
+            Self::F64Ge => "f64.ge",
+            Self::F64Neg => "f64.neg",
+            Self::F64Add => "f64.add",
+            Self::F64Sub => "f64.sub",
+            Self::F64Mul => "f64.mul",
+            Self::F64Div => "f64.div",
+            Self::F64Min => "f64.min",
+            Self::I32WrapI64 => "i32.wrap_i64",
+            Self::I64ExtendI32S => "i64.extend_i32_s",
+            Self::I64ExtendI32U => "i64.extend_i32_u",
+            Self::F32ConvertI32S => "f32.convert_i32_s",
+            Self::F32ConvertI64S => "f32.convert_i64_s",
+            Self::F32DemoteF64 => "f32.demote_f64",
+            Self::F64ConvertI32S => "f64.convert_i32_s",
+            Self::F64ConvertI64S => "f64.convert_i64_s",
+            Self::F64PromoteF32 => "f64.promote_f32",
+            Self::I32TruncSatF32S => "i32.trunc_sat_f32_s",
+            Self::I32TruncSatF32U => "i32.trunc_sat_f32_u",
+            Self::I32TruncSatF64S => "i32.trunc_sat_f64_s",
+            Self::I32TruncSatF64U => "i32.trunc_sat_f64_u",
+            Self::I64TruncSatF32S => "i64.trunc_sat_f32_s",
+            Self::I64TruncSatF64S => "i64.trunc_sat_f64_s",
+        }
+    }
+
+    /// the binary opcode of the instruction, without immediates
+    fn opcode(&self) -> &'static [u8] {
+        match self {
+            Self::Unreachable => &[0x00],
+            Self::Block => &[0x02],
+            Self::Loop => &[0x03],
+            Self::If(..) => &[0x04],
+            Self::Else => &[0x05],
+            Self::End => &[0x0B],
+            Self::Br(..) => &[0x0C],
+            Self::BrIf(..) => &[0x0D],
+            Self::Return => &[0x0F],
+            Self::Call(..) => &[0x10],
+            Self::CallIndirect(..) => &[0x11],
+            Self::Drop => &[0x1A],
+            Self::LocalGet(..) => &[0x20],
+            Self::LocalSet(..) => &[0x21],
+            Self::GlobalGet(..) => &[0x23],
+            Self::GlobalSet(..) => &[0x24],
+            Self::Load(ValType::I32, _) => &[0x28],
+            Self::Load(ValType::I64, _) => &[0x29],
+            Self::Load(ValType::F32, _) => &[0x2A],
+            Self::Load(ValType::F64, _) => &[0x2B],
+            Self::Store(ValType::I32, _) => &[0x36],
+            Self::Store(ValType::I64, _) => &[0x37],
+            Self::Store(ValType::F32, _) => &[0x38],
+            Self::Store(ValType::F64, _) => &[0x39],
+            Self::MemorySize => &[0x3F],
+            Self::MemoryGrow => &[0x40],
+            Self::I32Const(..) => &[0x41],
+            Self::I64Const(..) => &[0x42],
+            Self::F32Const(..) => &[0x43],
+            Self::F64Const(..) => &[0x44],
+            Self::I32Eqz => &[0x45],
+            Self::I32Eq => &[0x46],
+            Self::I32Ne => &[0x47],
+            Self::I32LtS => &[0x48],
+            Self::I32GtS => &[0x4A],
+            Self::I32GtU => &[0x4B],
+            Self::I32LeS => &[0x4C],
+            Self::I32GeS => &[0x4E],
+            Self::I64Eq => &[0x51],
+            Self::I64Ne => &[0x52],
+            Self::I64LtS => &[0x53],
+            Self::I64GtS => &[0x55],
+            Self::I64LeS => &[0x57],
+            Self::I64GeS => &[0x59],
+            Self::F32Eq => &[0x5B],
+            Self::F32Ne => &[0x5C],
+            Self::F32Lt => &[0x5D],
+            Self::F32Gt => &[0x5E],
+            Self::F32Le => &[0x5F],
+            Self::F32Ge => &[0x60],
+            Self::F64Eq => &[0x61],
+            Self::F64Ne => &[0x62],
+            Self::F64Lt => &[0x63],
+            Self::F64Gt => &[0x64],
+            Self::F64Le => &[0x65],
+            Self::F64Ge => &[0x66],
+            Self::I32Add => &[0x6A],
+            Self::I32Sub => &[0x6B],
+            Self::I32Mul => &[0x6C],
+            Self::I32DivS => &[0x6D],
+            Self::I32RemS => &[0x6F],
+            Self::I32And => &[0x71],
+            Self::I32Or => &[0x72],
+            Self::I32Xor => &[0x73],
+            Self::I32Shl => &[0x74],
+            Self::I32ShrS => &[0x75],
+            Self::I64Add => &[0x7C],
+            Self::I64Sub => &[0x7D],
+            Self::I64Mul => &[0x7E],
+            Self::I64DivS => &[0x7F],
+            Self::I64RemS => &[0x81],
+            Self::I64And => &[0x83],
+            Self::I64Or => &[0x84],
+            Self::I64Xor => &[0x85],
+            Self::I64Shl => &[0x86],
+            Self::I64ShrS => &[0x87],
+            Self::F32Neg => &[0x8C],
+            Self::F32Add => &[0x92],
+            Self::F32Sub => &[0x93],
+            Self::F32Mul => &[0x94],
+            Self::F32Div => &[0x95],
+            Self::F32Min => &[0x96],
+            Self::F64Neg => &[0x9A],
+            Self::F64Add => &[0xA0],
+            Self::F64Sub => &[0xA1],
+            Self::F64Mul => &[0xA2],
+            Self::F64Div => &[0xA3],
+            Self::F64Min => &[0xA4],
+            Self::I32WrapI64 => &[0xA7],
+            Self::I64ExtendI32S => &[0xAC],
+            Self::I64ExtendI32U => &[0xAD],
+            Self::F32ConvertI32S => &[0xB2],
+            Self::F32ConvertI64S => &[0xB4],
+            Self::F32DemoteF64 => &[0xB6],
+            Self::F64ConvertI32S => &[0xB7],
+            Self::F64ConvertI64S => &[0xB9],
+            Self::F64PromoteF32 => &[0xBB],
+            Self::I32TruncSatF32S => &[0xFC, 0x00],
+            Self::I32TruncSatF32U => &[0xFC, 0x01],
+            Self::I32TruncSatF64S => &[0xFC, 0x02],
+            Self::I32TruncSatF64U => &[0xFC, 0x03],
+            Self::I64TruncSatF32S => &[0xFC, 0x04],
+            Self::I64TruncSatF64S => &[0xFC, 0x06],
+        }
+    }
+
+    /// append the binary encoding of the instruction
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        bytes.extend(self.opcode());
+        match self {
+            Self::Block | Self::Loop | Self::If(None) => bytes.push(0x40),
+            Self::If(Some(t)) => bytes.push(t.encode()),
+            Self::Br(index)
+            | Self::BrIf(index)
+            | Self::Call(index)
+            | Self::LocalGet(index)
+            | Self::LocalSet(index)
+            | Self::GlobalGet(index)
+            | Self::GlobalSet(index) => leb_u32(bytes, *index),
+            Self::CallIndirect(index) => {
+                leb_u32(bytes, *index);
+                bytes.push(0x00);
+            }
+            // the alignment is the natural one, as a power of 2
+            Self::Load(t, offset) | Self::Store(t, offset) => {
+                let align = match t {
+                    ValType::I32 | ValType::F32 => 2,
+                    ValType::I64 | ValType::F64 => 3,
+                };
+                leb_u32(bytes, align);
+                leb_u32(bytes, *offset);
+            }
+            Self::MemorySize | Self::MemoryGrow => bytes.push(0x00),
+            Self::I32Const(value) => leb_i32(bytes, *value),
+            Self::I64Const(value) => leb_i64(bytes, *value),
+            Self::F32Const(value) => bytes.extend(value.to_le_bytes()),
+            Self::F64Const(value) => bytes.extend(value.to_le_bytes()),
+            _ => (),
+        }
+    }
+}
+
+impl WasmFunction {
+    fn new(name: &str, params: &[ValType], result: Option<ValType>) -> Self {
+        Self {
+            name: name.to_string(),
+            params: params.to_vec(),
+            result,
+            locals: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+}
+
+impl WasmModule {
+    /// the name of a function in the text format, by function index
+    fn function_name(&self, index: u32) -> &str {
+        let index = index as usize;
+        if index < IMPORTS.len() {
+            IMPORTS[index].0
+        } else {
+            &self.functions[index - IMPORTS.len()].name
+        }
+    }
+
+    /// the text format of a single instruction
+    fn wat_instr(&self, instr: &WasmInstr) -> String {
+        let mnemonic = instr.mnemonic();
+        match instr {
+            WasmInstr::If(Some(t)) => format!("{mnemonic} (result {t})"),
+            WasmInstr::Call(index) => format!("{mnemonic} ${}", self.function_name(*index)),
+            WasmInstr::CallIndirect(index) => format!("{mnemonic} (type {index})"),
+            WasmInstr::Load(_, offset) | WasmInstr::Store(_, offset) => {
+                format!("{mnemonic} offset={offset}")
+            }
+            WasmInstr::GlobalGet(index) | WasmInstr::GlobalSet(index) => {
+                format!("{mnemonic} ${}", self.globals[*index as usize].name)
+            }
+            WasmInstr::Br(index)
+            | WasmInstr::BrIf(index)
+            | WasmInstr::LocalGet(index)
+            | WasmInstr::LocalSet(index) => format!("{mnemonic} {index}"),
+            WasmInstr::I32Const(value) => format!("{mnemonic} {value}"),
+            WasmInstr::I64Const(value) => format!("{mnemonic} {value}"),
+            WasmInstr::F32Const(value) => format!("{mnemonic} {value:?}"),
+            WasmInstr::F64Const(value) => format!("{mnemonic} {value:?}"),
+            _ => mnemonic.to_string(),
+        }
+    }
+
+    /// encode the module in the WebAssembly binary format
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = b"\0asm".to_vec();
+        bytes.extend(1u32.to_le_bytes());
+
+        // the types of `call_indirect`, then one type per function: imports first, then
+        // definitions
+        let mut types = Vec::new();
+        for (params, result) in &self.types {
+            encode_func_type(&mut types, params, *result);
+        }
+        for (_, params, result) in IMPORTS {
+            encode_func_type(&mut types, params, result);
+        }
+        for func in &self.functions {
+            encode_func_type(&mut types, &func.params, func.result);
+        }
+        let mut section = Vec::new();
+        let count = self.types.len() + IMPORTS.len() + self.functions.len();
+        encode_vec(&mut section, &types, count);
+        encode_section(&mut bytes, 1, &section);
+
+        let mut imports = Vec::new();
+        for (i, (name, ..)) in IMPORTS.iter().enumerate() {
+            encode_name(&mut imports, "env");
+            encode_name(&mut imports, name);
+            imports.push(0x00);
+            leb_u32(&mut imports, (self.types.len() + i) as u32);
+        }
+        let mut section = Vec::new();
+        encode_vec(&mut section, &imports, IMPORTS.len());
+        encode_section(&mut bytes, 2, &section);
+
+        let mut functions = Vec::new();
+        for i in 0..self.functions.len() {
+            leb_u32(
+                &mut functions,
+                (self.types.len() + IMPORTS.len() + i) as u32,
+            );
+        }
+        let mut section = Vec::new();
+        encode_vec(&mut section, &functions, self.functions.len());
+        encode_section(&mut bytes, 3, &section);
+
+        if !self.table.is_empty() {
+            // a funcref table, with a minimum size and no maximum
+            let mut table = vec![0x70, 0x00];
+            leb_u32(&mut table, self.table.len() as u32);
+            let mut section = Vec::new();
+            encode_vec(&mut section, &table, 1);
+            encode_section(&mut bytes, 4, &section);
+        }
+
+        if self.memory {
+            // a minimum of 1 page and no maximum
+            let mut section = Vec::new();
+            encode_vec(&mut section, &[0x00, 0x01], 1);
+            encode_section(&mut bytes, 5, &section);
+        }
+
+        if !self.globals.is_empty() {
+            let mut globals = Vec::new();
+            for global in &self.globals {
+                globals.push(global.dtype.encode());
+                globals.push(0x01);
+                global.dtype.zero().encode(&mut globals);
+                WasmInstr::End.encode(&mut globals);
+            }
+            let mut section = Vec::new();
+            encode_vec(&mut section, &globals, self.globals.len());
+            encode_section(&mut bytes, 6, &section);
+        }
+
+        let mut exports = Vec::new();
+        encode_name(&mut exports, "main");
+        exports.push(0x00);
+        leb_u32(&mut exports, self.main);
+        if let Some(fuel) = self.fuel {
+            encode_name(&mut exports, "fuel");
+            exports.push(0x03);
+            leb_u32(&mut exports, fuel);
+        }
+        let mut section = Vec::new();
+        encode_vec(&mut section, &exports, 1 + usize::from(self.fuel.is_some()));
+        encode_section(&mut bytes, 7, &section);
+
+        if !self.table.is_empty() {
+            // a single active segment filling the table from 0
+            let mut elements = vec![0x00];
+            WasmInstr::I32Const(0).encode(&mut elements);
+            WasmInstr::End.encode(&mut elements);
+            leb_u32(&mut elements, self.table.len() as u32);
+            for index in &self.table {
+                leb_u32(&mut elements, *index);
+            }
+            let mut section = Vec::new();
+            encode_vec(&mut section, &elements, 1);
+            encode_section(&mut bytes, 9, &section);
+        }
+
+        let mut code = Vec::new();
+        for func in &self.functions {
+            let mut body = Vec::new();
+            leb_u32(&mut body, func.locals.len() as u32);
+            for local in &func.locals {
+                leb_u32(&mut body, 1);
+                body.push(local.encode());
+            }
+            for instr in &func.body {
+                instr.encode(&mut body);
+            }
+            WasmInstr::End.encode(&mut body);
+            encode_vec(&mut code, &body, body.len());
+        }
+        let mut section = Vec::new();
+        encode_vec(&mut section, &code, self.functions.len());
+        encode_section(&mut bytes, 10, &section);
+
+        bytes
+    }
+}
+
+// the text format, with nested blocks indented
+impl Display for WasmModule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "(module")?;
+        for (params, result) in &self.types {
+            write!(f, "  (type (func")?;
+            for param in params {
+                write!(f, " (param {param})")?;
+            }
+            if let Some(result) = result {
+                write!(f, " (result {result})")?;
+            }
+            writeln!(f, "))")?;
+        }
+        for (name, params, result) in IMPORTS {
+            write!(f, "  (import \"env\" \"{name}\" (func ${name}")?;
+            for param in params {
+                write!(f, " (param {param})")?;
+            }
+            if let Some(result) = result {
+                write!(f, " (result {result})")?;
+            }
+            writeln!(f, "))")?;
+        }
+        if !self.table.is_empty() {
+            writeln!(f, "  (table {} funcref)", self.table.len())?;
+        }
+        if self.memory {
+            writeln!(f, "  (memory 1)")?;
+        }
+        for global in &self.globals {
+            writeln!(
+                f,
+                "  (global ${} (mut {t}) ({t}.const 0))",
+                global.name,
+                t = global.dtype
+            )?;
+        }
+        for func in &self.functions {
+            write!(f, "  (func ${}", func.name)?;
+            for param in &func.params {
+                write!(f, " (param {param})")?;
+            }
+            if let Some(result) = func.result {
+                write!(f, " (result {result})")?;
+            }
+            if !func.locals.is_empty() {
+                let locals: Vec<String> = func.locals.iter().map(ToString::to_string).collect();
+                write!(f, " (local {})", locals.join(" "))?;
+            }
+            writeln!(f)?;
+
+            let mut indent = 2;
+            for instr in &func.body {
+                if matches!(instr, WasmInstr::Else | WasmInstr::End) {
+                    indent -= 1;
+                }
+                writeln!(f, "{}{}", "  ".repeat(indent), self.wat_instr(instr))?;
+                if matches!(
+                    instr,
+                    WasmInstr::Block | WasmInstr::Loop | WasmInstr::If(..) | WasmInstr::Else
+                ) {
+                    indent += 1;
+                }
+            }
+            writeln!(f, "  )")?;
+        }
+        writeln!(
+            f,
+            "  (export \"main\" (func ${}))",
+            self.function_name(self.main)
+        )?;
+        if let Some(fuel) = self.fuel {
+            writeln!(
+                f,
+                "  (export \"fuel\" (global ${}))",
+                self.globals[fuel as usize].name
+            )?;
+        }
+        if !self.table.is_empty() {
+            let names: Vec<String> = self
+                .table
+                .iter()
+                .map(|index| format!("${}", self.function_name(*index)))
+                .collect();
+            writeln!(f, "  (elem (i32.const 0) {})", names.join(" "))?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// where a Wabbit variable is stored
+#[derive(Debug, Clone, Copy)]
+enum Var {
+    Local(u32),
+    Global(u32),
+}
+
+/// Struct for generating a WebAssembly module
+///
+/// code is generated from the AST rather than the IR, since WebAssembly only has structured
+/// control flow, which maps directly onto `if` and `while`
+pub struct CodegenWasm<'a> {
+    /// a typechecker
+    analyze: Typechecker<'a>,
+    /// map of Wabbit variable names to their storage
+    var_names: Environment<'a, Var>,
+    /// map of Wabbit function names to function indices
+    function_indices: HashMap<&'a String, u32>,
+    /// the module being built
+    module: WasmModule,
+    /// the function currently being built, which is `main` at the top level
+    current: WasmFunction,
+    /// number of enclosing blocks, used to compute branch depths
+    depth: u32,
+    /// absolute depths of the (break, continue) targets of the enclosing loops
+    loops: Vec<(u32, u32)>,
+    /// functions numbered after `main`, which are reserved before they are generated
+    lifted: Vec<WasmFunction>,
+    /// function indices of the wrappers of named functions used as values
+    wrappers: HashMap<&'a String, u32>,
+    /// function index of the allocator, once it is needed
+    alloc: Option<u32>,
+    /// how many loop iterations and calls the program may run before it traps, unlimited if
+    /// `None`, as by default
+    pub fuel: Option<u64>,
+}
+
+impl<'a> From<&'a Parser<'a>> for CodegenWasm<'a> {
+    fn from(parser: &'a Parser) -> Self {
+        Self {
+            analyze: Typechecker::from(parser),
+            var_names: Environment::new(),
+            function_indices: HashMap::new(),
+            module: WasmModule::default(),
+            current: WasmFunction::new("main", &[], None),
+            depth: 0,
+            loops: Vec::new(),
+            lifted: Vec::new(),
+            wrappers: HashMap::new(),
+            alloc: None,
+            fuel: None,
+        }
+    }
+}
+
+impl<'a> CodegenWasm<'a> {
+    /// transform AST into a WebAssembly module
+    pub fn wasm_codegen(&mut self) -> WasmModule {
+        if let Some(fuel) = self.fuel {
+            self.module.globals.push(WasmGlobal {
+                name: "fuel".to_string(),
+                dtype: ValType::I64,
+            });
+            let global = (self.module.globals.len() - 1) as u32;
+            self.module.fuel = Some(global);
+            self.push(WasmInstr::I64Const(i64::try_from(fuel).unwrap_or(i64::MAX)));
+            self.push(WasmInstr::GlobalSet(global));
+        }
+
+        // functions can be called before they are defined, so they are numbered up front
+        for stmt in self.analyze.statements {
+            if let Stmt::FuncDef { def_name, .. } = stmt {
+                let index = IMPORTS.len() + self.function_indices.len();
+                self.function_indices.insert(def_name, index as u32);
+            }
+        }
+
+        for stmt in self.analyze.statements {
+            self.wasm_stmt(stmt);
+        }
+
+        self.module.main = (IMPORTS.len() + self.module.functions.len()) as u32;
+        let main = std::mem::replace(&mut self.current, WasmFunction::new("main", &[], None));
+        self.module.functions.push(main);
+        self.module.functions.append(&mut self.lifted);
+        std::mem::take(&mut self.module)
+    }
+
+    fn push(&mut self, instr: WasmInstr) {
+        self.current.body.push(instr);
+    }
+
+    /// trap if the fuel has run out and use one unit of it otherwise, at each loop iteration and
+    /// call as in the interpreter
+    fn use_fuel(&mut self) {
+        if let Some(global) = self.module.fuel {
+            self.push(WasmInstr::GlobalGet(global));
+            self.push(WasmInstr::I64Const(0));
+            self.push(WasmInstr::I64Eq);
+            self.push(WasmInstr::If(None));
+            self.push(WasmInstr::Unreachable);
+            self.push(WasmInstr::End);
+            self.push(WasmInstr::GlobalGet(global));
+            self.push(WasmInstr::I64Const(1));
+            self.push(WasmInstr::I64Sub);
+            self.push(WasmInstr::GlobalSet(global));
+        }
+    }
+
+    /// enter child environment for variable names and types
+    fn enter_child(&mut self) {
+        self.analyze.env.enter_child();
+        self.var_names.enter_child();
+    }
+
+    /// exit child environment for variable names and types
+    fn exit_child_unwrap(&mut self) {
+        self.analyze.env.exit_child_unwrap();
+        self.var_names.exit_child_unwrap();
+    }
+
+    /// define a new variable, as a global at the top level and a local otherwise
+    fn define_var(&mut self, name: &'a String, dtype: Type) -> Var {
+        let var = if self.analyze.env.in_global_scope() {
+            self.module.globals.push(WasmGlobal {
+                name: format!("wb_{name}"),
+                dtype: dtype.wasm_type(),
+            });
+            Var::Global((self.module.globals.len() - 1) as u32)
+        } else {
+            self.current.locals.push(dtype.wasm_type());
+            Var::Local((self.current.params.len() + self.current.locals.len() - 1) as u32)
+        };
+        self.analyze.env.define_init(name, dtype);
+        self.var_names.define_init(name, var);
+        var
+    }
+
+    /// load a variable onto the stack
+    fn get_var(&mut self, var: Var) {
+        match var {
+            Var::Local(index) => self.push(WasmInstr::LocalGet(index)),
+            Var::Global(index) => self.push(WasmInstr::GlobalGet(index)),
+        }
+    }
+
+    /// store the value on top of the stack into a variable
+    fn set_var(&mut self, var: Var) {
+        match var {
+            Var::Local(index) => self.push(WasmInstr::LocalSet(index)),
+            Var::Global(index) => self.push(WasmInstr::GlobalSet(index)),
+        }
+    }
+
+    /// generate a variable definition, where `has_value` means its value is on the stack
+    fn wasm_define(&mut self, name: &'a String, dtype: &Type, has_value: bool) {
+        let var = self.define_var(name, dtype.clone());
+        match (has_value, var) {
+            (true, _) => self.set_var(var),
+            // locals are reset, since a definition in a loop runs more than once
+            (false, Var::Local(_)) => {
+                self.push(dtype.wasm_type().zero());
+                self.set_var(var);
+            }
+            (false, Var::Global(_)) => (),
+        }
+    }
+
+    /// generate the statements of a block, in a new scope
+    fn wasm_block(&mut self, stmt: &'a Stmt) {
+        self.enter_child();
+        if let Stmt::Block { statements, .. } = stmt {
+            for stmt in statements {
+                self.wasm_stmt(stmt);
+            }
+        } else {
+            self.wasm_stmt(stmt);
+        }
+        self.exit_child_unwrap();
+    }
+
+    /// generate code for a single statement
+    fn wasm_stmt(&mut self, stmt: &'a Stmt) {
+        match stmt {
+            Stmt::Print { value, .. } => {
+                let dtype = self.analyze.expr_type(value).unwrap();
+                self.wasm_expr(value);
+                let import = match dtype {
+                    Type::Int | Type::Uint8 => 0,
+                    Type::Float => 1,
+                    Type::Bool => 2,
+                    Type::Char => 3,
+                    Type::Int64 => 7,
+                    Type::Float32 => 8,
+                    Type::Func(_) => panic!("typecheck failure"),
+                };
+                self.push(WasmInstr::Call(import));
+            }
+            Stmt::VarDef {
+                name,
+                maybe_type,
+                maybe_value,
+                ..
+            } => {
+                let dtype = if let Some(t) = maybe_type {
+                    t.clone()
+                } else {
+                    self.analyze
+                        .expr_type(maybe_value.as_ref().unwrap())
+                        .unwrap()
+                };
+                if let Some(value) = maybe_value {
+                    self.wasm_expr(value);
+                }
+                self.wasm_define(name, &dtype, maybe_value.is_some());
+            }
+            Stmt::ConstDef { name, value, .. } => {
+                let dtype = self.analyze.expr_type(value).expect("typechecking failure");
+                self.wasm_expr(value);
+                self.wasm_define(name, &dtype, true);
+                self.analyze.constants.insert(name, dtype);
+            }
+            Stmt::Assign {
+                name, op, value, ..
+            } => {
+                let dtype = self.analyze.expr_type(value).unwrap();
+                let var = self.var_names.get(name).unwrap().clone_store();
+                if let Some(op) = op {
+                    self.get_var(var);
+                    self.wasm_expr(value);
+                    self.wasm_binary(op, &dtype);
+                } else {
+                    self.wasm_expr(value);
+                }
+                self.analyze.env.assign(name, dtype);
+                self.set_var(var);
+            }
+            Stmt::Expr(e) => {
+                self.wasm_expr(e);
+                self.push(WasmInstr::Drop);
+            }
+            Stmt::Block { .. } => self.wasm_block(stmt),
+            Stmt::If {
+                condition,
+                then_block,
+                maybe_else_block,
+                ..
+            } => {
+                self.wasm_expr(condition);
+                self.push(WasmInstr::If(None));
+                self.depth += 1;
+                self.wasm_block(then_block);
+                if let Some(else_block) = maybe_else_block {
+                    self.push(WasmInstr::Else);
+                    self.wasm_block(else_block);
+                }
+                self.push(WasmInstr::End);
+                self.depth -= 1;
+            }
+            Stmt::Match {
+                value,
+                arms,
+                maybe_default,
+                ..
+            } => {
+                // the value is kept in a local, and each arm is an `if` in the `else` of the last
+                self.wasm_expr(value);
+                self.current.locals.push(ValType::I32);
+                let local = (self.current.params.len() + self.current.locals.len() - 1) as u32;
+                self.push(WasmInstr::LocalSet(local));
+                for (pattern, body) in arms {
+                    self.push(WasmInstr::LocalGet(local));
+                    self.wasm_expr(pattern);
+                    self.push(WasmInstr::I32Eq);
+                    self.push(WasmInstr::If(None));
+                    self.depth += 1;
+                    self.wasm_block(body);
+                    self.push(WasmInstr::Else);
+                }
+                if let Some(default) = maybe_default {
+                    self.wasm_block(default);
+                }
+                for _ in arms {
+                    self.push(WasmInstr::End);
+                    self.depth -= 1;
+                }
+            }
+            Stmt::Assert { value, .. } => {
+                // a failed assertion traps
+                self.wasm_expr(value);
+                self.push(WasmInstr::I32Eqz);
+                self.push(WasmInstr::If(None));
+                self.push(WasmInstr::Unreachable);
+                self.push(WasmInstr::End);
+            }
+            Stmt::While {
+                condition, body, ..
+            } => {
+                // block $break
+                //   loop $continue
+                //     br_if $break (i32.eqz condition)
+                //     body
+                //     br $continue
+                self.push(WasmInstr::Block);
+                self.push(WasmInstr::Loop);
+                self.depth += 2;
+                self.loops.push((self.depth - 1, self.depth));
+                self.use_fuel();
+
+                self.wasm_expr(condition);
+                self.push(WasmInstr::I32Eqz);
+                self.push(WasmInstr::BrIf(1));
+
+                self.analyze.loop_depth += 1;
+                self.wasm_block(body);
+                self.analyze.loop_depth -= 1;
+                self.push(WasmInstr::Br(0));
+
+                self.loops.pop();
+                self.depth -= 2;
+                self.push(WasmInstr::End);
+                self.push(WasmInstr::End);
+            }
+            Stmt::For {
+                init,
+                condition,
+                step,
+                body,
+                ..
+            } => {
+                // init
+                // block $break
+                //   loop
+                //     br_if $break (i32.eqz condition)
+                //     block $continue
+                //       body
+                //     end
+                //     step
+                //     br 0
+                //   end
+                // end
+                self.enter_child();
+                self.wasm_stmt(init);
+                self.push(WasmInstr::Block);
+                self.push(WasmInstr::Loop);
+                self.depth += 2;
+                self.use_fuel();
+
+                self.wasm_expr(condition);
+                self.push(WasmInstr::I32Eqz);
+                self.push(WasmInstr::BrIf(1));
+
+                self.push(WasmInstr::Block);
+                self.depth += 1;
+                self.loops.push((self.depth - 2, self.depth));
+                self.analyze.loop_depth += 1;
+                self.wasm_block(body);
+                self.analyze.loop_depth -= 1;
+                self.loops.pop();
+                self.push(WasmInstr::End);
+                self.depth -= 1;
+
+                self.wasm_stmt(step);
+                self.push(WasmInstr::Br(0));
+                self.depth -= 2;
+                self.push(WasmInstr::End);
+                self.push(WasmInstr::End);
+                self.exit_child_unwrap();
+            }
+            Stmt::LoopControl { control, .. } => {
+                let (break_depth, continue_depth) = *self.loops.last().unwrap();
+                let target = match control {
+                    LoopControl::Break => break_depth,
+                    LoopControl::Continue => continue_depth,
+                };
+                self.push(WasmInstr::Br(self.depth - target));
+            }
+            Stmt::Return { value, .. } => {
+                self.wasm_expr(value);
+                self.push(WasmInstr::Return);
+            }
+            Stmt::FuncDef {
+                def_name,
+                def_params,
+                return_type,
+                body,
+                ..
+            } => {
+                let params: Vec<ValType> = def_params.iter().map(|(_, t)| t.wasm_type()).collect();
+                let name = format!("wb_{def_name}");
+                let func = WasmFunction::new(&name, &params, Some(return_type.wasm_type()));
+                let main = std::mem::replace(&mut self.current, func);
+
+                let mut param_names: HashMap<&String, VarStore<Var>> = HashMap::new();
+                let mut param_env: HashMap<&String, VarStore<Type>> = HashMap::new();
+                for (i, (name, dtype)) in def_params.iter().enumerate() {
+                    param_names.insert(name, VarStore::Init(Var::Local(i as u32)));
+                    param_env.insert(name, VarStore::Init(dtype.clone()));
+                }
+
+                self.analyze.functions.insert(def_name, stmt);
+                self.analyze.env.enter_child_fn(param_env);
+                self.var_names.enter_child_fn(param_names);
+                self.analyze.call_depth += 1;
+                self.use_fuel();
+
+                if let Stmt::Block { statements, .. } = &**body {
+                    for stmt in statements {
+                        self.wasm_stmt(stmt);
+                    }
+                } else {
+                    self.wasm_stmt(body);
+                }
+                // falling off the end of a function is not valid Wabbit
+                self.push(WasmInstr::Unreachable);
+
+                self.analyze.env.exit_child_unwrap();
+                self.var_names.exit_child_unwrap();
+                self.analyze.call_depth -= 1;
+
+                let func = std::mem::replace(&mut self.current, main);
+                self.module.functions.push(func);
+            }
+        }
+    }
+
+    /// reserve a function numbered after `main`, returning its function index
+    fn reserve(&mut self, func: WasmFunction) -> (usize, u32) {
+        self.lifted.push(func);
+        let slot = self.lifted.len() - 1;
+        let index = IMPORTS.len() + self.function_indices.len() + 1 + slot;
+        (slot, index as u32)
+    }
+
+    /// the table index of a function, adding it to the table if needed
+    fn table_index(&mut self, index: u32) -> u32 {
+        let table = &mut self.module.table;
+        let position = table.iter().position(|i| *i == index).unwrap_or_else(|| {
+            table.push(index);
+            table.len() - 1
+        });
+        position as u32
+    }
+
+    /// the type index of the code of a function value, for `call_indirect`
+    fn type_index(&mut self, dtype: Type) -> u32 {
+        let Type::Func(sig) = dtype else {
+            panic!("typecheck failure")
+        };
+        let params: Vec<ValType> = std::iter::once(ValType::I32)
+            .chain(sig.params.iter().map(Type::wasm_type))
+            .collect();
+        let func_type = (params, Some(sig.ret.wasm_type()));
+        let types = &mut self.module.types;
+        let position = types
+            .iter()
+            .position(|t| *t == func_type)
+            .unwrap_or_else(|| {
+                types.push(func_type);
+                types.len() - 1
+            });
+        position as u32
+    }
+
+    /// allocate a function value holding a function index and the captured values, which are
+    /// loaded by `captures`, leaving its address on the stack
+    ///
+    /// a function value is an address in linear memory, holding the table index of the code to
+    /// call followed by the captured values, each in 8 bytes. The code is passed the function value
+    /// as its first parameter, and memory is allocated by bumping the `heap` global
+    fn new_function_value(&mut self, index: u32, captures: &[(Type, Var)]) {
+        let alloc = if let Some(alloc) = self.alloc {
+            alloc
+        } else {
+            // returns the old `heap`, growing the memory by a page if the new `heap` is past
+            // its end, which is enough since a function value is much smaller than a page
+            self.module.memory = true;
+            self.module.globals.push(WasmGlobal {
+                name: "heap".to_string(),
+                dtype: ValType::I32,
+            });
+            let heap = (self.module.globals.len() - 1) as u32;
+            let mut func = WasmFunction::new("alloc", &[ValType::I32], Some(ValType::I32));
+            func.body = vec![
+                WasmInstr::GlobalGet(heap),
+                WasmInstr::GlobalGet(heap),
+                WasmInstr::LocalGet(0),
+                WasmInstr::I32Add,
+                WasmInstr::GlobalSet(heap),
+                WasmInstr::GlobalGet(heap),
+                WasmInstr::MemorySize,
+                WasmInstr::I32Const(16),
+                WasmInstr::I32Shl,
+                WasmInstr::I32GtU,
+                WasmInstr::If(None),
+                WasmInstr::I32Const(1),
+                WasmInstr::MemoryGrow,
+                WasmInstr::Drop,
+                WasmInstr::End,
+            ];
+            let (_, alloc) = self.reserve(func);
+            self.alloc = Some(alloc);
+            alloc
+        };
+        self.current.locals.push(ValType::I32);
+        let local = (self.current.params.len() + self.current.locals.len() - 1) as u32;
+
+        let size = i32::try_from(8 * (captures.len() + 1)).unwrap();
+        self.push(WasmInstr::I32Const(size));
+        self.push(WasmInstr::Call(alloc));
+        self.push(WasmInstr::LocalSet(local));
+        self.push(WasmInstr::LocalGet(local));
+        let table_index = i32::try_from(self.table_index(index)).unwrap();
+        self.push(WasmInstr::I32Const(table_index));
+        self.push(WasmInstr::Store(ValType::I32, 0));
+        for (i, (dtype, var)) in captures.iter().enumerate() {
+            self.push(WasmInstr::LocalGet(local));
+            self.get_var(*var);
+            self.push(WasmInstr::Store(dtype.wasm_type(), 8 * (i as u32 + 1)));
+        }
+        self.push(WasmInstr::LocalGet(local));
+    }
+
+    /// generate a call through a function value, which is on top of the stack
+    fn wasm_call_value(&mut self, dtype: Type, params: &'a [Expr]) {
+        self.current.locals.push(ValType::I32);
+        let local = (self.current.params.len() + self.current.locals.len() - 1) as u32;
+        self.push(WasmInstr::LocalSet(local));
+        self.push(WasmInstr::LocalGet(local));
+        for param in params {
+            self.wasm_expr(param);
+        }
+        self.push(WasmInstr::LocalGet(local));
+        self.push(WasmInstr::Load(ValType::I32, 0));
+        let type_index = self.type_index(dtype);
+        self.push(WasmInstr::CallIndirect(type_index));
+    }
+
+    /// generate a function value for a named function, through a wrapper that ignores the
+    /// function value it is passed
+    fn wasm_named_function_value(&mut self, name: &'a String, dtype: Type) {
+        let index = if let Some(&index) = self.wrappers.get(name) {
+            index
+        } else {
+            let Type::Func(sig) = dtype else {
+                panic!("typecheck failure")
+            };
+            let params: Vec<ValType> = std::iter::once(ValType::I32)
+                .chain(sig.params.iter().map(Type::wasm_type))
+                .collect();
+            let wrapper = format!("wb_{name}.value");
+            let mut func = WasmFunction::new(&wrapper, &params, Some(sig.ret.wasm_type()));
+            for i in 1..params.len() {
+                func.body.push(WasmInstr::LocalGet(i as u32));
+            }
+            func.body.push(WasmInstr::Call(self.function_indices[name]));
+            let (_, index) = self.reserve(func);
+            self.wrappers.insert(name, index);
+            index
+        };
+        self.new_function_value(index, &[]);
+    }
+
+    /// generate a function expression as a function of its own, leaving a function value
+    /// holding copies of the variables it captures on the stack
+    ///
+    /// these functions, and the wrappers of named functions used as values, are numbered after
+    /// `main`
+    fn wasm_function(
+        &mut self,
+        params: &'a [(String, Type)],
+        return_type: &Type,
+        body: &'a Stmt,
+        id: usize,
+    ) {
+        let captures = self.analyze.captures(params, body, id).unwrap();
+        let param_types: Vec<ValType> = std::iter::once(ValType::I32)
+            .chain(params.iter().map(|(_, t)| t.wasm_type()))
+            .collect();
+        let name = format!("func.{}", self.lifted.len());
+        let func = WasmFunction::new(&name, &param_types, Some(return_type.wasm_type()));
+        let (slot, index) = self.reserve(func.clone());
+        let outer = std::mem::replace(&mut self.current, func);
+        let depth = std::mem::take(&mut self.depth);
+        let loops = std::mem::take(&mut self.loops);
+
+        // captured values are copied out of the function value into locals
+        let mut scope_names: HashMap<&String, VarStore<Var>> = HashMap::new();
+        let mut scope_env: HashMap<&String, VarStore<Type>> = HashMap::new();
+        for (i, (name, dtype)) in params.iter().enumerate() {
+            scope_names.insert(name, VarStore::Init(Var::Local(i as u32 + 1)));
+            scope_env.insert(name, VarStore::Init(dtype.clone()));
+        }
+        for (i, (name, dtype)) in captures.iter().enumerate() {
+            self.current.locals.push(dtype.wasm_type());
+            let local = (self.current.params.len() + self.current.locals.len() - 1) as u32;
+            self.push(WasmInstr::LocalGet(0));
+            self.push(WasmInstr::Load(dtype.wasm_type(), 8 * (i as u32 + 1)));
+            self.push(WasmInstr::LocalSet(local));
+            scope_names.insert(name, VarStore::Init(Var::Local(local)));
+            scope_env.insert(name, VarStore::Captured(dtype.clone()));
+        }
+
+        let env_locals = self.analyze.env.enter_call(scope_env);
+        let name_locals = self.var_names.enter_call(scope_names);
+        let loop_depth = std::mem::take(&mut self.analyze.loop_depth);
+        self.analyze.call_depth += 1;
+        self.use_fuel();
+
+        self.wasm_stmt(body);
+        // falling off the end of a function is not valid Wabbit
+        self.push(WasmInstr::Unreachable);
+
+        self.analyze.call_depth -= 1;
+        self.analyze.loop_depth = loop_depth;
+        self.var_names.exit_call(name_locals);
+        self.analyze.env.exit_call(env_locals);
+        self.loops = loops;
+        self.depth = depth;
+        self.lifted[slot] = std::mem::replace(&mut self.current, outer);
+
+        // the captured variables are read when the function value is created
+        let captures: Vec<(Type, Var)> = captures
+            .into_iter()
+            .map(|(name, dtype)| (dtype, self.var_names.get(name).unwrap().clone_store()))
+            .collect();
+        self.new_function_value(index, &captures);
+    }
+
+    /// generate code for a single expression, leaving its value on the stack
+    fn wasm_expr(&mut self, e: &'a Expr) {
+        match e {
+            Expr::Literal { value, .. } => {
+                let instr = match value {
+                    WabbitType::Int(val) => WasmInstr::I32Const(*val),
+                    WabbitType::Int64(val) => WasmInstr::I64Const(*val),
+                    WabbitType::Uint8(val) => WasmInstr::I32Const(i32::from(*val)),
+                    WabbitType::Float32(val) => WasmInstr::F32Const(*val),
+                    WabbitType::Bool(val) => WasmInstr::I32Const(i32::from(*val)),
+                    WabbitType::Char(val) => WasmInstr::I32Const(i32::from(*val as u8)),
+                    WabbitType::Float(val) => WasmInstr::F64Const(*val),
+                    WabbitType::TypeHolder(..) | WabbitType::Func(..) => {
+                        panic!("typecheck failure")
+                    }
+                };
+                self.push(instr);
+            }
+            Expr::Grouping { e, .. } => self.wasm_expr(e),
+            Expr::VarName { name, .. } => {
+                if let Some(var) = self.var_names.get(name) {
+                    self.get_var(var.clone_store());
+                } else {
+                    let dtype = self.analyze.expr_type(e).unwrap();
+                    self.wasm_named_function_value(name, dtype);
+                }
+            }
+            Expr::CallValue { callee, params, .. } => {
+                let dtype = self.analyze.expr_type(callee).unwrap();
+                self.wasm_expr(callee);
+                self.wasm_call_value(dtype, params);
+            }
+            Expr::Function {
+                params,
+                return_type,
+                body,
+                id,
+            } => self.wasm_function(params, return_type, body, *id),
+            Expr::Call { name, params, .. } => {
+                if let Some(var) = self.var_names.get(name) {
+                    // a call through a variable holding a function value
+                    let dtype = match self.analyze.constants.get(name) {
+                        Some(dtype) => dtype.clone(),
+                        None => self.analyze.env.get(name).unwrap().clone_store(),
+                    };
+                    self.get_var(var.clone_store());
+                    self.wasm_call_value(dtype, params);
+                    return;
+                }
+                let index = match Builtin::from_name(name) {
+                    Some(Builtin::Int) => 4,
+                    Some(Builtin::Float) => 5,
+                    Some(Builtin::Char) => 6,
+                    None => self.function_indices[name],
+                };
+                for param in params {
+                    self.wasm_expr(param);
+                }
+                self.push(WasmInstr::Call(index));
+            }
+            Expr::TypeConversion { dtype, params, .. } => {
+                // there should be a single parameter
+                let from = self.analyze.expr_type(&params[0]).unwrap();
+                self.wasm_expr(&params[0]);
+
+                for instr in Self::wasm_convert(&from, dtype) {
+                    self.push(instr);
+                }
+            }
+            Expr::TypeName { .. } => panic!("typecheck failure"),
+            Expr::Logical { lhs, op, rhs, .. } => {
+                // the right-hand side is only evaluated in one arm of an `if`
+                self.wasm_expr(lhs);
+                self.push(WasmInstr::If(Some(ValType::I32)));
+                self.depth += 1;
+                match op {
+                    LogicalOp::LogicalAnd => {
+                        self.wasm_expr(rhs);
+                        self.push(WasmInstr::Else);
+                        self.push(WasmInstr::I32Const(0));
+                    }
+                    LogicalOp::LogicalOr => {
+                        self.push(WasmInstr::I32Const(1));
+                        self.push(WasmInstr::Else);
+                        self.wasm_expr(rhs);
+                    }
+                }
+                self.depth -= 1;
+                self.push(WasmInstr::End);
+            }
+            Expr::Unary { op, operand, .. } => {
+                let dtype = self.analyze.expr_type(operand).unwrap();
+                match (dtype, op) {
+                    (_, UnaryOp::Plus) => self.wasm_expr(operand),
+                    (Type::Int, UnaryOp::Minus) => {
+                        self.push(WasmInstr::I32Const(0));
+                        self.wasm_expr(operand);
+                        self.push(WasmInstr::I32Sub);
+                    }
+                    (Type::Int64, UnaryOp::Minus) => {
+                        self.push(WasmInstr::I64Const(0));
+                        self.wasm_expr(operand);
+                        self.push(WasmInstr::I64Sub);
+                    }
+                    (Type::Float, UnaryOp::Minus) => {
+                        self.wasm_expr(operand);
+                        self.push(WasmInstr::F64Neg);
+                    }
+                    (Type::Float32, UnaryOp::Minus) => {
+                        self.wasm_expr(operand);
+                        self.push(WasmInstr::F32Neg);
+                    }
+                    (Type::Bool, UnaryOp::LogicalNot) => {
+                        self.wasm_expr(operand);
+                        self.push(WasmInstr::I32Eqz);
+                    }
+                    _ => panic!("typecheck failure"),
+                }
+            }
+            Expr::Binary { lhs, op, rhs, .. } => {
+                let dtype = self.analyze.expr_type(lhs).unwrap();
+                self.wasm_expr(lhs);
+                self.wasm_expr(rhs);
+                self.wasm_binary(op, &dtype);
+            }
+        }
+    }
+
+    /// apply a binary operator to the two values on top of the stack
+    fn wasm_binary(&mut self, op: &BinaryOp, dtype: &Type) {
+        let instr = match (dtype.wasm_type(), op) {
+            (ValType::I32, BinaryOp::Plus) => WasmInstr::I32Add,
+            (ValType::I32, BinaryOp::Minus) => WasmInstr::I32Sub,
+            (ValType::I32, BinaryOp::Times) => WasmInstr::I32Mul,
+            (ValType::I32, BinaryOp::Divide) => WasmInstr::I32DivS,
+            (ValType::I32, BinaryOp::Modulo) => WasmInstr::I32RemS,
+            (ValType::I32, BinaryOp::BitAnd) => WasmInstr::I32And,
+            (ValType::I32, BinaryOp::BitOr) => WasmInstr::I32Or,
+            (ValType::I32, BinaryOp::BitXor) => WasmInstr::I32Xor,
+            (ValType::I32, BinaryOp::ShiftLeft) => WasmInstr::I32Shl,
+            (ValType::I32, BinaryOp::ShiftRight) => WasmInstr::I32ShrS,
+            (ValType::I32, BinaryOp::EqualEqual) => WasmInstr::I32Eq,
+            (ValType::I32, BinaryOp::NotEqual) => WasmInstr::I32Ne,
+            (ValType::I32, BinaryOp::Less) => WasmInstr::I32LtS,
+            (ValType::I32, BinaryOp::LessEqual) => WasmInstr::I32LeS,
+            (ValType::I32, BinaryOp::Greater) => WasmInstr::I32GtS,
+            (ValType::I32, BinaryOp::GreaterEqual) => WasmInstr::I32GeS,
+            (ValType::I64, BinaryOp::Plus) => WasmInstr::I64Add,
+            (ValType::I64, BinaryOp::Minus) => WasmInstr::I64Sub,
+            (ValType::I64, BinaryOp::Times) => WasmInstr::I64Mul,
+            (ValType::I64, BinaryOp::Divide) => WasmInstr::I64DivS,
+            (ValType::I64, BinaryOp::Modulo) => WasmInstr::I64RemS,
+            (ValType::I64, BinaryOp::BitAnd) => WasmInstr::I64And,
+            (ValType::I64, BinaryOp::BitOr) => WasmInstr::I64Or,
+            (ValType::I64, BinaryOp::BitXor) => WasmInstr::I64Xor,
+            (ValType::I64, BinaryOp::ShiftLeft) => WasmInstr::I64Shl,
+            (ValType::I64, BinaryOp::ShiftRight) => WasmInstr::I64ShrS,
+            (ValType::I64, BinaryOp::EqualEqual) => WasmInstr::I64Eq,
+            (ValType::I64, BinaryOp::NotEqual) => WasmInstr::I64Ne,
+            (ValType::I64, BinaryOp::Less) => WasmInstr::I64LtS,
+            (ValType::I64, BinaryOp::LessEqual) => WasmInstr::I64LeS,
+            (ValType::I64, BinaryOp::Greater) => WasmInstr::I64GtS,
+            (ValType::I64, BinaryOp::GreaterEqual) => WasmInstr::I64GeS,
+            (ValType::F32, BinaryOp::Plus) => WasmInstr::F32Add,
+            (ValType::F32, BinaryOp::Minus) => WasmInstr::F32Sub,
+            (ValType::F32, BinaryOp::Times) => WasmInstr::F32Mul,
+            (ValType::F32, BinaryOp::Divide) => WasmInstr::F32Div,
+            (ValType::F32, BinaryOp::EqualEqual) => WasmInstr::F32Eq,
+            (ValType::F32, BinaryOp::NotEqual) => WasmInstr::F32Ne,
+            (ValType::F32, BinaryOp::Less) => WasmInstr::F32Lt,
+            (ValType::F32, BinaryOp::LessEqual) => WasmInstr::F32Le,
+            (ValType::F32, BinaryOp::Greater) => WasmInstr::F32Gt,
+            (ValType::F32, BinaryOp::GreaterEqual) => WasmInstr::F32Ge,
+            (ValType::F64, BinaryOp::Plus) => WasmInstr::F64Add,
+            (ValType::F64, BinaryOp::Minus) => WasmInstr::F64Sub,
+            (ValType::F64, BinaryOp::Times) => WasmInstr::F64Mul,
+            (ValType::F64, BinaryOp::Divide) => WasmInstr::F64Div,
+            (ValType::F64, BinaryOp::EqualEqual) => WasmInstr::F64Eq,
+            (ValType::F64, BinaryOp::NotEqual) => WasmInstr::F64Ne,
+            (ValType::F64, BinaryOp::Less) => WasmInstr::F64Lt,
+            (ValType::F64, BinaryOp::LessEqual) => WasmInstr::F64Le,
+            (ValType::F64, BinaryOp::Greater) => WasmInstr::F64Gt,
+            (ValType::F64, BinaryOp::GreaterEqual) => WasmInstr::F64Ge,
+            _ => panic!("typecheck failure"),
+        };
+        self.push(instr);
+        // uint8s are computed as i32s, which are nonnegative so that signed operators also work,
+        // then truncated
+        if *dtype == Type::Uint8
+            && matches!(
+                op,
+                BinaryOp::Plus | BinaryOp::Minus | BinaryOp::Times | BinaryOp::ShiftLeft
+            )
+        {
+            self.push(WasmInstr::I32Const(0xFF));
+            self.push(WasmInstr::I32And);
+        }
+    }
+
+    /// the instructions converting the value on top of the stack to another type
+    fn wasm_convert(from: &Type, to: &Type) -> Vec<WasmInstr> {
+        match (to, from) {
+            _ if to == from => vec![],
+            // only exactly 1 is true, as in the interpreter
+            (Type::Bool, Type::Int64) => vec![WasmInstr::I64Const(1), WasmInstr::I64Eq],
+            (Type::Bool, _) => vec![WasmInstr::I32Const(1), WasmInstr::I32Eq],
+            // saturating, which matches the interpreter's `as`
+            (Type::Int, Type::Float) => vec![WasmInstr::I32TruncSatF64S],
+            (Type::Int, Type::Float32) => vec![WasmInstr::I32TruncSatF32S],
+            (Type::Int64, Type::Float) => vec![WasmInstr::I64TruncSatF64S],
+            (Type::Int64, Type::Float32) => vec![WasmInstr::I64TruncSatF32S],
+            (Type::Uint8, Type::Float) => vec![
+                WasmInstr::F64Const(255.0),
+                WasmInstr::F64Min,
+                WasmInstr::I32TruncSatF64U,
+            ],
+            (Type::Uint8, Type::Float32) => vec![
+                WasmInstr::F32Const(255.0),
+                WasmInstr::F32Min,
+                WasmInstr::I32TruncSatF32U,
+            ],
+            (Type::Float, Type::Float32) => vec![WasmInstr::F64PromoteF32],
+            (Type::Float32, Type::Float) => vec![WasmInstr::F32DemoteF64],
+            (Type::Float, Type::Int64) => vec![WasmInstr::F64ConvertI64S],
+            (Type::Float32, Type::Int64) => vec![WasmInstr::F32ConvertI64S],
+            (Type::Float, _) => vec![WasmInstr::F64ConvertI32S],
+            (Type::Float32, _) => vec![WasmInstr::F32ConvertI32S],
+            (Type::Int64, Type::Int) => vec![WasmInstr::I64ExtendI32S],
+            (Type::Int64, _) => vec![WasmInstr::I64ExtendI32U],
+            (Type::Int, Type::Int64) => vec![WasmInstr::I32WrapI64],
+            // chars, bools and uint8s are already valid ints
+            (Type::Int, _) => vec![],
+            (Type::Char | Type::Uint8, Type::Int64) => vec![
+                WasmInstr::I32WrapI64,
+                WasmInstr::I32Const(0xFF),
+                WasmInstr::I32And,
+            ],
+            (Type::Char | Type::Uint8, _) => vec![WasmInstr::I32Const(0xFF), WasmInstr::I32And],
+            (Type::Func(_), _) => panic!("typecheck failure"),
+        }
+    }
+}
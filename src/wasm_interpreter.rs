@@ -1,9 +1,11 @@
 use wasm_bindgen::prelude::*;
 
-use crate::interpreter::Interpreter;
+use crate::host::{Host, Limits};
 use crate::parser::Parser;
+use crate::program::compile;
 use crate::scanner::Scanner;
 use crate::typechecker::Typechecker;
+use crate::wasm::CodegenWasm;
 
 /// entry point for WebAssembly interpreter
 ///
@@ -13,46 +15,60 @@ pub fn wasm_entry() -> Result<(), JsValue> {
     Ok(())
 }
 
+/// the statements a program may run in the browser, or the loop iterations and calls of a compiled
+/// one, so that an infinite loop ends with an error instead of freezing the page
+const PLAYGROUND_FUEL: u64 = 10_000_000;
+
 /// a Wabbit interpreter, exported to WebAssembly
 ///
 /// note that this is the entire Rust implementation of the scanner, parser and interpreter, not a
 /// use of WebAssembly as a compilation target
 #[wasm_bindgen]
 pub fn wasm_interp(source: &str) -> String {
-    let mut scanner = Scanner::new(source);
-
     // doing simplified error reporting here...
     // this also has the terminal color codes in the error strings
 
+    let program = match compile(source) {
+        Ok(program) => program,
+        Err(diagnostics) => return diagnostics.errors[0].label.clone(),
+    };
+
+    let mut host = Host::new(String::new()).with_limits(Limits {
+        fuel: Some(PLAYGROUND_FUEL),
+        ..Limits::default()
+    });
+    match program.run(&mut host) {
+        Ok(()) => host.output,
+        Err(e) => e.error.label,
+    }
+}
+
+/// compile a Wabbit program to a WebAssembly module, exported to WebAssembly
+///
+/// the module imports the functions in [`crate::wasm::IMPORTS`] from `env` and exports `main` and
+/// the remaining `fuel`, which is zero when `main` traps for running out of it, see
+/// [index.html](https://github.com/chenson2018/wabbit/blob/main/index.html)
+#[wasm_bindgen]
+pub fn wasm_compile(source: &str) -> Result<Vec<u8>, JsValue> {
+    let mut scanner = Scanner::new(source);
+
     if let Err(errs) = scanner.scan() {
-        return errs[0].label.clone();
+        return Err(errs[0].label.clone().into());
     }
 
     let mut parser = Parser::from(&scanner);
 
     if let Err(e) = parser.parse() {
-        return e.label;
+        return Err(e.label.into());
     }
 
     let mut typechecker = Typechecker::from(&parser);
 
     if let Err(e) = typechecker.typecheck() {
-        return e.label;
+        return Err(e.label.into());
     }
 
-    let mut interpreter = Interpreter::from(&parser);
-
-    if let Err(e) = interpreter.interpret() {
-        return e.label;
-    }
-
-    interpreter
-        .output
-        .iter()
-        .map(std::string::ToString::to_string)
-        .collect::<Vec<String>>()
-        .join("\n")
+    let mut codegen = CodegenWasm::from(&parser);
+    codegen.fuel = Some(PLAYGROUND_FUEL);
+    Ok(codegen.wasm_codegen().encode())
 }
-
-// See https://rustwasm.github.io/wasm-bindgen/examples/console-log.html
-// for how to log to console
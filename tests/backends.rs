@@ -0,0 +1,568 @@
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Write};
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::path::{Path, PathBuf};
+    use std::process::{Command, Stdio};
+    use wabbit::asm::CodegenAsm;
+    use wabbit::c::CodegenC;
+    use wabbit::fuzz::{self, Generator};
+    use wabbit::host::{Input, Reader};
+    use wabbit::interpreter::Interpreter;
+    use wabbit::llvm::CodegenLLVM;
+    use wabbit::parser::Parser;
+    use wabbit::scanner::Scanner;
+    use wabbit::typechecker::Typechecker;
+    use wabbit::wasm::CodegenWasm;
+    use wabbit::{Type, WabbitType};
+    use wasmi::core::{Trap, F32, F64};
+    use wasmi::{Caller, Engine, Linker, Module, Store, Value};
+
+    /// runs a program with the given input, returning its output or `None` if the tools it
+    /// needs are not installed
+    type Backend = fn(&str, &Parser, &str) -> Option<String>;
+
+    const BACKENDS: [(&str, Backend); 5] = [
+        ("interpreter", interpreter),
+        ("llvm", llvm),
+        ("asm", asm),
+        ("c", c),
+        ("wasm", wasm),
+    ];
+
+    // too slow for the interpreter, see tests/interpreter.rs
+    const SLOW: [&str; 2] = ["22_fib", "23_mandel"];
+
+    /// the output a program declares at its end, in a block comment starting with the line
+    /// `/* expected output:` and ending with a line that is only `*/`
+    fn expected_output(source: &str) -> Option<String> {
+        let (_, block) = source.rsplit_once("/* expected output:\n")?;
+        let (output, _) = block.rsplit_once("*/")?;
+        Some(output.to_string())
+    }
+
+    /// make outputs comparable, with `\n` line endings and a final newline
+    fn normalize(output: &str) -> String {
+        let mut output = output.replace("\r\n", "\n");
+        if !output.is_empty() && !output.ends_with('\n') {
+            output.push('\n');
+        }
+        output
+    }
+
+    /// whether a command can be run at all
+    fn available(program: &str) -> bool {
+        Command::new(program).arg("--version").output().is_ok()
+    }
+
+    /// a scratch directory for one backend
+    fn scratch(backend: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("wabbit-{backend}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// run a compiled program with `input` on stdin, returning its stdout
+    fn run(name: &str, binary: &Path, input: &str) -> String {
+        let mut child = Command::new(binary)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(input.as_bytes())
+            .unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(
+            output.status.success(),
+            "{name}: exited with {}",
+            output.status
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// compile a source file with `cc`, returning the executable
+    fn compile(name: &str, dir: &Path, file: &str, source: &str, args: &[&str]) -> Option<PathBuf> {
+        if !available("cc") {
+            return None;
+        }
+        let path = dir.join(file);
+        let binary = dir.join(name);
+        std::fs::write(&path, source).unwrap();
+
+        let status = Command::new("cc")
+            .args(args)
+            .arg(&path)
+            .arg("-o")
+            .arg(&binary)
+            .status()
+            .unwrap();
+        assert!(status.success(), "{name}: failed to compile {file}");
+        Some(binary)
+    }
+
+    /// compile a source file with `cc` and run it
+    fn cc(
+        name: &str,
+        dir: &Path,
+        file: &str,
+        source: &str,
+        args: &[&str],
+        input: &str,
+    ) -> Option<String> {
+        let binary = compile(name, dir, file, source, args)?;
+        Some(run(name, &binary, input))
+    }
+
+    // every runner has the `Backend` signature, though this one always runs
+    #[allow(clippy::unnecessary_wraps)]
+    fn interpreter(_name: &str, parser: &Parser, input: &str) -> Option<String> {
+        let mut output = String::new();
+        Interpreter::from(parser)
+            .with_output(&mut output)
+            .with_input(&mut Reader::from(input))
+            .interpret()
+            .unwrap();
+        Some(output)
+    }
+
+    fn llvm(name: &str, parser: &Parser, input: &str) -> Option<String> {
+        let llvm = CodegenLLVM::from(parser).llvm_codegen();
+        let dir = scratch("llvm");
+        let source = dir.join(format!("{name}.ll"));
+        let binary = dir.join(name);
+        std::fs::write(&source, llvm).unwrap();
+
+        if available("clang") {
+            let status = Command::new("clang")
+                .arg(&source)
+                .arg("-o")
+                .arg(&binary)
+                .status()
+                .unwrap();
+            assert!(status.success(), "{name}: clang failed");
+            return Some(run(name, &binary, input));
+        }
+        if !available("llc") || !available("cc") {
+            return None;
+        }
+
+        // older versions of LLVM need opaque pointers turned on
+        let object = dir.join(format!("{name}.o"));
+        let llc = |extra: &[&str]| {
+            Command::new("llc")
+                .args(extra)
+                .args(["-relocation-model=pic", "-filetype=obj"])
+                .arg(&source)
+                .arg("-o")
+                .arg(&object)
+                .output()
+                .unwrap()
+                .status
+                .success()
+        };
+        assert!(llc(&["-opaque-pointers"]) || llc(&[]), "{name}: llc failed");
+        let status = Command::new("cc")
+            .arg(&object)
+            .arg("-o")
+            .arg(&binary)
+            .status()
+            .unwrap();
+        assert!(status.success(), "{name}: failed to link");
+        Some(run(name, &binary, input))
+    }
+
+    fn asm(name: &str, parser: &Parser, input: &str) -> Option<String> {
+        if !cfg!(all(target_arch = "x86_64", target_os = "linux")) {
+            return None;
+        }
+        let asm = CodegenAsm::from(parser).asm_codegen();
+        cc(
+            name,
+            &scratch("asm"),
+            &format!("{name}.s"),
+            &asm,
+            &[],
+            input,
+        )
+    }
+
+    fn c(name: &str, parser: &Parser, input: &str) -> Option<String> {
+        let c = CodegenC::from(parser).c_codegen();
+        let dir = scratch("c");
+        cc(name, &dir, &format!("{name}.c"), &c, &["-std=c99"], input)
+    }
+
+    /// what the imported functions of a WebAssembly program print to and read from
+    struct Io {
+        output: Vec<WabbitType>,
+        input: Reader<Cursor<Vec<u8>>>,
+    }
+
+    impl Io {
+        fn read(&mut self, dtype: &Type) -> Result<WabbitType, Trap> {
+            self.input
+                .read(dtype.clone())
+                .ok_or_else(|| Trap::new(format!("could not read {dtype}")))
+        }
+    }
+
+    // every runner has the `Backend` signature, though this one always runs
+    #[allow(clippy::unnecessary_wraps)]
+    fn wasm(name: &str, parser: &Parser, input: &str) -> Option<String> {
+        let bytes = CodegenWasm::from(parser).wasm_codegen().encode();
+        let engine = Engine::default();
+        let module = Module::new(&engine, &bytes[..]).unwrap_or_else(|e| panic!("{name}: {e}"));
+        let io = Io {
+            output: Vec::new(),
+            input: Reader(Cursor::new(input.as_bytes().to_vec())),
+        };
+        let mut store = Store::new(&engine, io);
+        let linker = wasm_linker(&engine);
+
+        let instance = linker
+            .instantiate(&mut store, &module)
+            .and_then(|pre| pre.start(&mut store))
+            .unwrap();
+        let main = instance.get_typed_func::<(), ()>(&store, "main").unwrap();
+        main.call(&mut store, ())
+            .unwrap_or_else(|e| panic!("{name}: {e}"));
+        Some(
+            store
+                .into_data()
+                .output
+                .iter()
+                .map(WabbitType::output)
+                .collect(),
+        )
+    }
+
+    /// a linker providing the imports of a generated module, see `wabbit::wasm::IMPORTS`
+    fn wasm_linker(engine: &Engine) -> Linker<Io> {
+        let mut linker = <Linker<Io>>::new(engine);
+
+        linker
+            .func_wrap("env", "print_int", |mut caller: Caller<'_, Io>, x: i32| {
+                caller.data_mut().output.push(WabbitType::Int(x));
+            })
+            .unwrap();
+        linker
+            .func_wrap(
+                "env",
+                "print_float",
+                |mut caller: Caller<'_, Io>, x: F64| {
+                    caller
+                        .data_mut()
+                        .output
+                        .push(WabbitType::Float(x.to_float()));
+                },
+            )
+            .unwrap();
+        linker
+            .func_wrap("env", "print_bool", |mut caller: Caller<'_, Io>, x: i32| {
+                caller.data_mut().output.push(WabbitType::Bool(x != 0));
+            })
+            .unwrap();
+        linker
+            .func_wrap("env", "print_char", |mut caller: Caller<'_, Io>, x: i32| {
+                caller
+                    .data_mut()
+                    .output
+                    .push(WabbitType::Char(char::from(u8::try_from(x).unwrap())));
+            })
+            .unwrap();
+        linker
+            .func_wrap(
+                "env",
+                "print_int64",
+                |mut caller: Caller<'_, Io>, x: i64| {
+                    caller.data_mut().output.push(WabbitType::Int64(x));
+                },
+            )
+            .unwrap();
+        linker
+            .func_wrap(
+                "env",
+                "print_float32",
+                |mut caller: Caller<'_, Io>, x: F32| {
+                    caller
+                        .data_mut()
+                        .output
+                        .push(WabbitType::Float32(x.to_float()));
+                },
+            )
+            .unwrap();
+        linker
+            .func_wrap("env", "read_int", |mut caller: Caller<'_, Io>| match caller
+                .data_mut()
+                .read(&Type::Int)?
+            {
+                WabbitType::Int(x) => Ok(x),
+                _ => unreachable!(),
+            })
+            .unwrap();
+        linker
+            .func_wrap(
+                "env",
+                "read_float",
+                |mut caller: Caller<'_, Io>| match caller.data_mut().read(&Type::Float)? {
+                    WabbitType::Float(x) => Ok(F64::from(x)),
+                    _ => unreachable!(),
+                },
+            )
+            .unwrap();
+        linker
+            .func_wrap(
+                "env",
+                "read_char",
+                |mut caller: Caller<'_, Io>| match caller.data_mut().read(&Type::Char)? {
+                    WabbitType::Char(x) => Ok(x as i32),
+                    _ => unreachable!(),
+                },
+            )
+            .unwrap();
+        linker
+    }
+
+    /// run a program through some backends, returning a message for each that disagrees with
+    /// the program's expected output
+    fn disagreements(path: &Path, backends: &[(&str, Backend)]) -> Vec<String> {
+        let name = path.file_stem().unwrap().to_str().unwrap();
+        let source = std::fs::read_to_string(path).unwrap();
+        let expected =
+            expected_output(&source).unwrap_or_else(|| panic!("{name}: no expected output"));
+        let expected = normalize(&expected);
+
+        let mut scanner = Scanner::new(&source);
+        scanner.scan().unwrap();
+        let mut parser = Parser::from(&scanner);
+        parser.parse().unwrap();
+
+        let mut failures = Vec::new();
+        for (backend, run) in backends {
+            if let Some(output) = run(name, &parser, "") {
+                let output = normalize(&output);
+                if output != expected {
+                    failures.push(format!(
+                        "{name} ({backend}):\nexpected:\n{expected}\nfound:\n{output}"
+                    ));
+                }
+            }
+        }
+        failures
+    }
+
+    #[test]
+    fn backends_agree() {
+        let mut failures = Vec::new();
+        for file in std::fs::read_dir("./program_examples/").unwrap() {
+            let path = file.unwrap().path();
+            if SLOW.iter().any(|slow| path.ends_with(format!("{slow}.wb"))) {
+                failures.extend(disagreements(&path, &BACKENDS[1..]));
+            } else {
+                failures.extend(disagreements(&path, &BACKENDS));
+            }
+        }
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+
+    #[test]
+    #[ignore = "too slow for the interpreter"]
+    fn backends_agree_slow_interpreter() {
+        let mut failures = Vec::new();
+        for slow in SLOW {
+            let path = format!("./program_examples/{slow}.wb");
+            failures.extend(disagreements(Path::new(&path), &BACKENDS[..1]));
+        }
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+
+    #[test]
+    fn expected_output_block() {
+        let source = "print 1;\n\n/* expected output:\n1\n*/\n";
+        assert_eq!(expected_output(source), Some("1\n".to_string()));
+        assert_eq!(expected_output("print 1;\n"), None);
+        assert_eq!(normalize("a\r\nb"), "a\nb\n");
+    }
+
+    #[test]
+    fn input_builtins() {
+        let source = "var n int = readint();\n\
+                      var x float = readfloat();\n\
+                      var c char = readchar();\n\
+                      print n * 2;\n\
+                      print x + 0.5;\n\
+                      print c;\n\
+                      print readchar();\n";
+        let mut scanner = Scanner::new(source);
+        scanner.scan().unwrap();
+        let mut parser = Parser::from(&scanner);
+        parser.parse().unwrap();
+        Typechecker::from(&parser).typecheck().unwrap();
+
+        for (backend, run) in &BACKENDS {
+            if let Some(output) = run("input", &parser, " 21\n2.5xy") {
+                assert_eq!(output, "42\n3\nxy", "{backend}");
+            }
+        }
+    }
+
+    /// signed overflow, shifts by the width of the type or more and division by zero are
+    /// undefined in C, so the generated code wraps around or exits with an error instead
+    #[test]
+    fn c_int_edge_cases() {
+        let compile_c = |name: &str, source: &str| {
+            let mut scanner = Scanner::new(source);
+            scanner.scan().unwrap();
+            let mut parser = Parser::from(&scanner);
+            parser.parse().unwrap();
+            let c = CodegenC::from(&parser).c_codegen();
+            compile(name, &scratch("c"), &format!("{name}.c"), &c, &["-std=c99"])
+        };
+
+        let source = "var min = -2147483647 - 1;\n\
+                      print min - 1;\n\
+                      print -min;\n\
+                      print min / -1;\n\
+                      print min % -1;\n\
+                      print -7 >> 1;\n";
+        if let Some(binary) = compile_c("c_wrap", source) {
+            let output = run("c_wrap", &binary, "");
+            assert_eq!(output, "2147483647\n-2147483648\n-2147483648\n0\n-4\n");
+        }
+
+        let (zero, shift) = ("division by zero", "invalid shift amount");
+        for (name, source, error) in [
+            ("c_div_zero", "var z = 0;\nprint 1 / z;\n", zero),
+            ("c_rem_zero", "var z = 0u8;\nprint 1u8 % z;\n", zero),
+            ("c_shift_width", "var s = 32;\nprint 1 << s;\n", shift),
+            ("c_shift_sign", "var s = -1i64;\nprint 2i64 >> s;\n", shift),
+        ] {
+            if let Some(binary) = compile_c(name, source) {
+                let output = Command::new(binary).output().unwrap();
+                assert_eq!(output.status.code(), Some(1), "{name}");
+                let stderr = String::from_utf8(output.stderr).unwrap();
+                assert_eq!(stderr, format!("error: {error}\n"), "{name}");
+            }
+        }
+    }
+
+    #[test]
+    fn wasm_fuel() {
+        for (name, source, runs_out) in [
+            ("loop", "while true {\n}\n", true),
+            ("for", "for var i = 0; true; i += 1 {\n}\n", true),
+            ("call", "func f() int {\nreturn f();\n}\nprint f();\n", true),
+            ("finishes", "for var i = 0; i < 10; i += 1 {\n}\n", false),
+        ] {
+            let mut scanner = Scanner::new(source);
+            scanner.scan().unwrap();
+            let mut parser = Parser::from(&scanner);
+            parser.parse().unwrap();
+            let mut codegen = CodegenWasm::from(&parser);
+            codegen.fuel = Some(1000);
+            let bytes = codegen.wasm_codegen().encode();
+            let engine = Engine::default();
+            let module = Module::new(&engine, &bytes[..]).unwrap();
+            let io = Io {
+                output: Vec::new(),
+                input: Reader(Cursor::new(Vec::new())),
+            };
+            let mut store = Store::new(&engine, io);
+            let instance = wasm_linker(&engine)
+                .instantiate(&mut store, &module)
+                .and_then(|pre| pre.start(&mut store))
+                .unwrap();
+            let main = instance.get_typed_func::<(), ()>(&store, "main").unwrap();
+            let result = main.call(&mut store, ());
+            let fuel = instance.get_global(&store, "fuel").unwrap().get(&store);
+            assert_eq!(result.is_err(), runs_out, "{name}");
+            assert_eq!(matches!(fuel, Value::I64(0)), runs_out, "{name}");
+        }
+    }
+
+    /// run a stage of the pipeline, turning a panic into an error
+    fn stage<T>(f: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+        catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|panic| {
+            Err(panic
+                .downcast_ref::<String>()
+                .cloned()
+                .or_else(|| panic.downcast_ref::<&str>().map(ToString::to_string))
+                .unwrap_or_default())
+        })
+    }
+
+    /// name the stage an error comes from
+    fn fail(stage: &str) -> impl Fn(String) -> (String, String) + '_ {
+        move |e| (stage.to_string(), e)
+    }
+
+    /// run a generated program through the whole pipeline, returning the failing stage and why
+    fn check(source: &str) -> Result<(), (String, String)> {
+        let mut scanner = Scanner::new(source);
+        stage(|| scanner.scan().map_err(|errs| errs[0].label.clone())).map_err(fail("parse"))?;
+        let mut parser = Parser::from(&scanner);
+        stage(|| parser.parse().map_err(|e| e.label)).map_err(fail("parse"))?;
+
+        let formatted = fuzz::render(parser.borrow_statements());
+        if formatted != source {
+            return Err(("round trip".to_string(), formatted));
+        }
+
+        stage(|| Typechecker::from(&parser).typecheck().map_err(|e| e.label))
+            .map_err(fail("typecheck"))?;
+
+        let expected: String = stage(|| {
+            let mut output = String::new();
+            Interpreter::from(&parser)
+                .with_output(&mut output)
+                .interpret()
+                .map_err(|e| e.label)?;
+            Ok(output)
+        })
+        .map_err(fail("interpreter"))?;
+
+        for &(backend, run) in &BACKENDS[1..] {
+            let output = stage(|| Ok(run("fuzz", &parser, ""))).map_err(fail(backend))?;
+            if let Some(output) = output {
+                if output != expected {
+                    return Err((
+                        backend.to_string(),
+                        format!("expected:\n{expected}\nfound:\n{output}"),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// generate programs from a range of seeds, set with `WABBIT_FUZZ_SEED` and
+    /// `WABBIT_FUZZ_COUNT`, shrinking the first that fails
+    #[test]
+    fn fuzz() {
+        let var = |name: &str, default: u64| {
+            std::env::var(name).map_or(default, |value| value.parse().unwrap())
+        };
+        let seed = var("WABBIT_FUZZ_SEED", 0);
+        let count = var("WABBIT_FUZZ_COUNT", 25);
+
+        for seed in seed..seed + count {
+            let program = Generator::new(seed).program();
+            if let Err((failed, message)) = check(&fuzz::render(&program)) {
+                // keep the kind of failure, not just the stage
+                let first_line = |message: &str| message.lines().next().map(str::to_string);
+                let kind = first_line(&message);
+                let program = fuzz::shrink(program, |source| {
+                    matches!(check(source), Err((stage, message))
+                        if stage == failed && first_line(&message) == kind)
+                });
+                let source = fuzz::render(&program);
+                let (_, message) = check(&source).unwrap_err();
+                panic!("seed {seed}: {failed} failed on\n{source}\n{message}");
+            }
+        }
+    }
+}
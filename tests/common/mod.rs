@@ -0,0 +1,11 @@
+use wabbit::parser::Parser;
+use wabbit::scanner::Scanner;
+
+/// scan and parse `source`, which must be valid, and pass the parser to `f`
+pub fn parse<T>(source: &str, f: impl FnOnce(&Parser) -> T) -> T {
+    let mut scanner = Scanner::new(source);
+    scanner.scan().unwrap();
+    let mut parser = Parser::from(&scanner);
+    parser.parse().unwrap();
+    f(&parser)
+}
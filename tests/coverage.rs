@@ -0,0 +1,144 @@
+mod common;
+
+#[cfg(test)]
+mod test {
+    use crate::common::parse;
+    use wabbit::coverage::{Branch, BranchKind, Coverage};
+    use wabbit::interpreter::Interpreter;
+
+    const SOURCE: &str = "\
+func abs(x int) int {
+    if x < 0 {
+        return -x;
+    }
+    return x;
+}
+func unused() int {
+    return 1;
+}
+var i = 0;
+while i < 3 {
+    print abs(i);
+    i = i + 1;
+}
+";
+
+    fn measure(source: &str, runs: usize) -> wabbit::coverage::Report {
+        parse(source, |parser| {
+            let mut coverage = Coverage::default();
+            for _ in 0..runs {
+                let mut output = Vec::new();
+                Interpreter::from(parser)
+                    .with_output(&mut output)
+                    .with_coverage(&mut coverage)
+                    .interpret()
+                    .unwrap();
+            }
+            coverage.report(parser, source)
+        })
+    }
+
+    #[test]
+    fn lines_branches_functions() {
+        let report = measure(SOURCE, 1);
+        assert_eq!(report.line_counts(), (8, 10));
+        assert_eq!(report.lines[&3], 0);
+        assert_eq!(report.lines[&8], 0);
+        assert_eq!(report.lines[&12], 3);
+        assert_eq!(
+            report.branches,
+            [
+                Branch {
+                    line: 2,
+                    kind: BranchKind::Condition,
+                    ran: true,
+                    taken: vec![0, 3]
+                },
+                Branch {
+                    line: 11,
+                    kind: BranchKind::Condition,
+                    ran: true,
+                    taken: vec![3, 1]
+                },
+            ]
+        );
+        assert_eq!(report.branch_counts(), (3, 4));
+        assert_eq!(
+            report.functions,
+            [(1, "abs".to_string(), 3), (7, "unused".to_string(), 0)]
+        );
+
+        let summary = report.summary();
+        assert!(summary.starts_with("lines: 8/10 (80.0%)\nbranches: 3/4 (75.0%)\n"));
+        assert!(summary.contains("    3 | return -x;"));
+        assert!(summary.contains("    2 | if x < 0 { (never true)"));
+    }
+
+    #[test]
+    fn match_arms() {
+        let source = "\
+var i = 0;
+while i < 3 {
+    match i {
+        0 => { print 'a'; }
+        2 => { print 'c'; }
+        5 => { print 'f'; }
+    }
+    i = i + 1;
+}
+";
+        let report = measure(source, 1);
+        // one outcome for each arm, then the default
+        assert_eq!(
+            report.branches[1],
+            Branch {
+                line: 3,
+                kind: BranchKind::Match,
+                ran: true,
+                taken: vec![1, 1, 0, 1]
+            }
+        );
+        assert_eq!(report.branch_counts(), (5, 6));
+        let summary = report.summary();
+        assert!(summary.contains("    3 | match i { (arm 3 never taken)"));
+
+        let lcov = report.lcov("match.wb");
+        assert!(lcov.contains("BRDA:3,1,2,0\nBRDA:3,1,3,1\n"));
+        assert!(lcov.contains("BRF:6\nBRH:5\n"));
+    }
+
+    #[test]
+    fn runs_add_up() {
+        let report = measure(SOURCE, 2);
+        assert_eq!(report.lines[&12], 6);
+        assert_eq!(report.functions[0].2, 6);
+    }
+
+    #[test]
+    fn lcov() {
+        let lcov = measure(SOURCE, 1).lcov("abs.wb");
+        let lines: Vec<_> = lcov.lines().collect();
+        assert_eq!(lines[..2], ["TN:", "SF:abs.wb"]);
+        for record in [
+            "FN:7,unused",
+            "FNDA:3,abs",
+            "FNF:2",
+            "FNH:1",
+            "BRDA:2,0,0,0",
+            "BRDA:11,1,1,1",
+            "BRF:4",
+            "BRH:3",
+            "DA:3,0",
+            "DA:13,3",
+            "LF:10",
+            "LH:8",
+        ] {
+            assert!(lines.contains(&record), "{record}");
+        }
+        assert_eq!(lines.last(), Some(&"end_of_record"));
+
+        // branches that were never reached are marked `-`
+        let lcov = measure("if false {\n    if true { print 1; }\n}\n", 1).lcov("if.wb");
+        assert!(lcov.contains("BRDA:2,1,0,-\nBRDA:2,1,1,-\n"));
+    }
+}
@@ -0,0 +1,160 @@
+mod common;
+
+#[cfg(test)]
+mod test {
+    use crate::common::parse;
+    use serde_json::{json, Value};
+    use wabbit::dap::serve;
+    use wabbit::debugger::{Console, Debugger};
+    use wabbit::host::Limits;
+    use wabbit::interpreter::Interpreter;
+    use wabbit::WabbitType;
+
+    const SOURCE: &str = "\
+func square(x int) int {
+    var y = x * x;
+    return y;
+}
+var total = 0;
+var i = 1;
+while i <= 3 {
+    total = total + square(i);
+    i = i + 1;
+}
+print total;
+";
+
+    /// run `SOURCE` under the console frontend, returning what it printed and the program output
+    fn console(commands: &str, breakpoints: &[usize]) -> (String, Vec<WabbitType>) {
+        let mut transcript = Vec::new();
+        let mut output = Vec::new();
+        parse(SOURCE, |parser| {
+            let mut debugger =
+                Debugger::new(SOURCE, Console::new(commands.as_bytes(), &mut transcript));
+            debugger.stop_on_entry = breakpoints.is_empty();
+            debugger.breakpoints.extend(breakpoints);
+            let result = Interpreter::from(parser)
+                .with_output(&mut output)
+                .with_debugger(&mut debugger)
+                .interpret();
+            if !debugger.stopped() {
+                result.unwrap();
+            }
+        });
+        (String::from_utf8(transcript).unwrap(), output)
+    }
+
+    #[test]
+    fn breakpoints_and_inspection() {
+        let (transcript, output) = console("l\nbt\np x * 10\nc\nd 2\nc\n", &[2]);
+        assert_eq!(output, vec![WabbitType::Int(14)]);
+        assert!(transcript.starts_with("paused (breakpoint) at line 2\n"));
+        assert!(transcript.contains("x = 1\nglobals:\n  i = 1\n  total = 0\n"));
+        assert!(transcript.contains("square at line 2\n<main> at line 8\n"));
+        assert!(transcript.contains("x * 10 = 10\n"));
+        // paused a second time, then the breakpoint was removed
+        assert_eq!(transcript.matches("(breakpoint)").count(), 2);
+    }
+
+    #[test]
+    fn stepping() {
+        let (transcript, _) = console("s\ns\ns\nn\nn\ns\ns\ns\no\nq\n", &[]);
+        let lines: Vec<_> = transcript
+            .lines()
+            .filter_map(|line| line.split(" at line ").nth(1))
+            .collect();
+        assert_eq!(lines, ["1", "5", "6", "7", "8", "9", "7", "8", "2", "9"]);
+    }
+
+    #[test]
+    fn quit_and_watch() {
+        let (transcript, output) = console("w total\nn\nq\n", &[6]);
+        assert!(output.is_empty());
+        assert!(transcript.contains("total = 0\n"));
+        assert!(transcript.ends_with("(wdb) "));
+        assert!(console("p nope\nq\n", &[6])
+            .0
+            .contains("nope: undefined variable"));
+    }
+
+    /// frame requests as a client would send them
+    fn requests(requests: &[(&str, Value)]) -> Vec<u8> {
+        let mut framed = Vec::new();
+        for (seq, (command, arguments)) in requests.iter().enumerate() {
+            let body = json!({
+                "seq": seq + 1,
+                "type": "request",
+                "command": command,
+                "arguments": arguments,
+            })
+            .to_string();
+            framed.extend(format!("Content-Length: {}\r\n\r\n{body}", body.len()).bytes());
+        }
+        framed
+    }
+
+    /// the messages sent back to the client, without their headers
+    fn messages(framed: &[u8]) -> Vec<Value> {
+        let text = String::from_utf8(framed.to_vec()).unwrap();
+        text.split("Content-Length: ")
+            .filter_map(|message| message.split_once("\r\n\r\n"))
+            .map(|(_, body)| serde_json::from_str(body).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn dap_session() {
+        let path = std::env::temp_dir().join(format!("wabbit-dap-{}.wb", std::process::id()));
+        std::fs::write(&path, SOURCE).unwrap();
+        let input = requests(&[
+            ("initialize", json!({})),
+            ("launch", json!({ "program": path })),
+            (
+                "setBreakpoints",
+                json!({ "source": { "path": path }, "breakpoints": [{ "line": 2 }] }),
+            ),
+            ("configurationDone", json!({})),
+            ("stackTrace", json!({ "threadId": 1 })),
+            ("variables", json!({ "variablesReference": 1 })),
+            ("evaluate", json!({ "expression": "x + total" })),
+            (
+                "setBreakpoints",
+                json!({ "source": { "path": path }, "breakpoints": [] }),
+            ),
+            ("continue", json!({ "threadId": 1 })),
+            ("disconnect", json!({})),
+        ]);
+        let mut output = Vec::new();
+        serve(input.as_slice(), &mut output, None, Limits::default()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let messages = messages(&output);
+        let body = |command: &str| {
+            messages
+                .iter()
+                .find(|message| message["command"] == command)
+                .map(|message| message["body"].clone())
+                .unwrap()
+        };
+        let event = |event: &str| {
+            messages
+                .iter()
+                .find(|message| message["event"] == event)
+                .map(|message| message["body"].clone())
+                .unwrap()
+        };
+        assert_eq!(event("stopped")["reason"], "breakpoint");
+        let frames = &body("stackTrace")["stackFrames"];
+        assert_eq!(frames[0]["name"], "square");
+        assert_eq!(frames[0]["line"], 2);
+        assert_eq!(frames[1]["line"], 8);
+        assert_eq!(
+            body("variables")["variables"],
+            json!([{ "name": "x", "value": "1", "variablesReference": 0 }])
+        );
+        assert_eq!(body("evaluate")["result"], "1");
+        assert_eq!(event("output")["output"], "14\n");
+        assert_eq!(event("exited")["exitCode"], 0);
+        assert!(messages.iter().all(|message| message["success"] != false));
+    }
+}
@@ -8,13 +8,20 @@ mod test {
     #[allow(clippy::needless_pass_by_value)]
     fn expect_io(path: &str, expected: Vec<WabbitType>) {
         let source = std::fs::read_to_string(path).unwrap();
-        let mut scanner = Scanner::new(&source);
+        expect_output(&source, &expected);
+    }
+
+    fn expect_output(source: &str, expected: &[WabbitType]) {
+        let mut scanner = Scanner::new(source);
         scanner.scan().unwrap();
         let mut parser = Parser::from(&scanner);
         parser.parse().unwrap();
-        let mut interpreter = Interpreter::from(&parser);
-        interpreter.interpret().unwrap();
-        assert_eq!(interpreter.output, expected);
+        let mut output = Vec::new();
+        Interpreter::from(&parser)
+            .with_output(&mut output)
+            .interpret()
+            .unwrap();
+        assert_eq!(output, expected);
     }
 
     #[test]
@@ -280,4 +287,78 @@ mod test {
         ];
         expect_io("./program_examples/24_conversions.wb", expected);
     }
+
+    #[test]
+    fn printed_values_are_recorded() {
+        let source = "print 1;\nprint 'a';\n";
+        let mut scanner = Scanner::new(source);
+        scanner.scan().unwrap();
+        let mut parser = Parser::from(&scanner);
+        parser.parse().unwrap();
+        let mut output = Vec::new();
+        let mut interpreter = Interpreter::from(&parser).with_output(&mut output);
+        interpreter.interpret().unwrap();
+        let expected = [WabbitType::from(1), WabbitType::from('a')];
+        assert_eq!(interpreter.analyze.output, expected);
+        assert!(format!("{interpreter:?}").starts_with("Interpreter { analyze: Analyzer {"));
+    }
+
+    #[test]
+    fn continue_leaves_loop_scope() {
+        let source = "
+            var i = 0;
+            while i < 3 {
+                var x = i * 10;
+                i = i + 1;
+                if x == 10 {
+                    continue;
+                }
+                print x;
+            }
+            var x = 7;
+            print x;
+            ";
+        let expected = [0, 20, 7].map(WabbitType::from);
+        expect_output(source, &expected);
+    }
+
+    #[test]
+    fn callee_sees_globals_not_caller_locals() {
+        let source = "
+            var y = 1;
+            func f() int {
+                return y;
+            }
+            func g() int {
+                var y = 5;
+                return f();
+            }
+            print g();
+            ";
+        expect_output(source, &[WabbitType::from(1)]);
+
+        // without typechecking, a caller's local is undefined in the callee
+        let source =
+            "func f() int { return z; }\nfunc g() int { var z = 5; return f(); }\nprint g();\n";
+        let mut scanner = Scanner::new(source);
+        scanner.scan().unwrap();
+        let mut parser = Parser::from(&scanner);
+        parser.parse().unwrap();
+        let error = Interpreter::from(&parser).interpret().unwrap_err();
+        assert!(error.label.contains("undefined variable"));
+    }
+
+    #[test]
+    fn logical_not_evaluates_once() {
+        let source = "
+            var n = 0;
+            func t() bool {
+                n = n + 1;
+                return true;
+            }
+            print !t();
+            print n;
+            ";
+        expect_output(source, &[WabbitType::from(false), WabbitType::from(1)]);
+    }
 }
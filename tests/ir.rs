@@ -0,0 +1,153 @@
+#[cfg(test)]
+mod test {
+    use wabbit::ir::{Block, Function, Global, Instr, Module, Operand, Reg, Terminator};
+    use wabbit::lower::Lowering;
+    use wabbit::parser::Parser;
+    use wabbit::scanner::Scanner;
+    use wabbit::{Type, WabbitType};
+
+    fn lower(source: &str) -> Module {
+        let mut scanner = Scanner::new(source);
+        scanner.scan().unwrap();
+        let mut parser = Parser::from(&scanner);
+        parser.parse().unwrap();
+        Lowering::from(&parser).lower()
+    }
+
+    fn function(blocks: Vec<Block>) -> Module {
+        Module {
+            globals: vec![],
+            externs: vec![],
+            functions: vec![Function {
+                name: "f".to_string(),
+                params: vec![(Reg(0), Type::Int)],
+                return_type: Type::Int,
+                captures: vec![],
+                blocks,
+            }],
+        }
+    }
+
+    fn block(label: &str, instrs: Vec<Instr>, term: Terminator) -> Block {
+        Block {
+            label: label.to_string(),
+            instrs,
+            term: Some(term),
+        }
+    }
+
+    fn ret(reg: usize) -> Terminator {
+        Terminator::Return(Type::Int, Operand::Reg(Reg(reg)))
+    }
+
+    #[test]
+    fn lower_programs() {
+        let paths = std::fs::read_dir("./program_examples/").unwrap();
+
+        for file in paths {
+            let path = file.unwrap().path();
+            let source = std::fs::read_to_string(&path).unwrap();
+            let module = lower(&source);
+            if let Err(errs) = module.verify() {
+                panic!("{}: {errs:?}\n{module}", path.display());
+            }
+        }
+    }
+
+    #[test]
+    fn lower_shortcircuit_phi() {
+        let module = lower("var x = 1; print (x < 2) && (x > 0);\n");
+        let main = module.function("main").unwrap();
+        let phis = main
+            .blocks
+            .iter()
+            .flat_map(|block| &block.instrs)
+            .filter(|instr| matches!(instr, Instr::Phi { .. }))
+            .count();
+        assert_eq!(phis, 1);
+        assert!(module.global("x").is_some());
+        assert!(module.verify().is_ok());
+    }
+
+    #[test]
+    fn verify_valid() {
+        let module = function(vec![block("entry", vec![], ret(0))]);
+        assert!(module.verify().is_ok());
+    }
+
+    #[test]
+    fn verify_undefined_register() {
+        let module = function(vec![block("entry", vec![], ret(1))]);
+        assert!(module.verify().is_err());
+    }
+
+    #[test]
+    fn verify_double_definition() {
+        let load = |dest| Instr::Load {
+            dest: Reg(dest),
+            dtype: Type::Int,
+            ptr: Operand::Global("g".to_string()),
+        };
+        let mut module = function(vec![block("entry", vec![load(1), load(1)], ret(1))]);
+        module.globals.push(Global {
+            name: "g".to_string(),
+            dtype: Type::Int,
+        });
+        assert!(module.verify().is_err());
+    }
+
+    #[test]
+    fn verify_undefined_label() {
+        let module = function(vec![block(
+            "entry",
+            vec![],
+            Terminator::Jump("nowhere".to_string()),
+        )]);
+        assert!(module.verify().is_err());
+    }
+
+    #[test]
+    fn verify_type_mismatch() {
+        let module = function(vec![block(
+            "entry",
+            vec![],
+            Terminator::Return(Type::Float, Operand::Const(WabbitType::Float(1.0))),
+        )]);
+        assert!(module.verify().is_err());
+    }
+
+    #[test]
+    fn verify_dominance() {
+        // %1 is defined on only one path into `join`
+        let module = function(vec![
+            block(
+                "entry",
+                vec![],
+                Terminator::Branch {
+                    cond: Operand::Const(WabbitType::Bool(true)),
+                    then: "left".to_string(),
+                    otherwise: "join".to_string(),
+                },
+            ),
+            block(
+                "left",
+                vec![Instr::Convert {
+                    dest: Reg(1),
+                    from: Type::Int,
+                    to: Type::Float,
+                    value: Operand::Reg(Reg(0)),
+                }],
+                Terminator::Jump("join".to_string()),
+            ),
+            block(
+                "join",
+                vec![Instr::Print {
+                    dtype: Type::Float,
+                    value: Operand::Reg(Reg(1)),
+                }],
+                ret(0),
+            ),
+        ]);
+        assert!(module.verify().is_err());
+    }
+}
@@ -0,0 +1,269 @@
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use wabbit::host::Natives;
+    use wabbit::llvm::{CodegenLLVM, Overflow, Target};
+    use wabbit::parser::Parser;
+    use wabbit::scanner::Scanner;
+    use wabbit::{Type, WabbitType};
+
+    fn llvm(source: &str, target: Target) -> String {
+        let mut scanner = Scanner::new(source);
+        scanner.scan().unwrap();
+        let mut parser = Parser::from(&scanner);
+        parser.parse().unwrap();
+        let mut codegen = CodegenLLVM::from(&parser);
+        codegen.target = target;
+        codegen.llvm_codegen()
+    }
+
+    /// the instructions of each block of `@main`, by label
+    fn main_blocks(ir: &str) -> HashMap<&str, Vec<&str>> {
+        let body = ir.split("define i32 @main()").nth(1).unwrap();
+        let mut blocks: HashMap<&str, Vec<&str>> = HashMap::new();
+        let mut label = "";
+        for line in body.lines().skip_while(|line| !line.ends_with(':')) {
+            if line == "}" {
+                break;
+            } else if let Some(name) = line.strip_suffix(':') {
+                label = name;
+                blocks.insert(label, Vec::new());
+            } else {
+                blocks.get_mut(label).unwrap().push(line.trim());
+            }
+        }
+        blocks
+    }
+
+    /// the instruction defining each register, by register
+    fn definitions<'a>(instrs: &[&'a str]) -> HashMap<&'a str, &'a str> {
+        instrs
+            .iter()
+            .filter_map(|instr| instr.split_once(" = "))
+            .collect()
+    }
+
+    /// the registers an instruction uses, in order
+    fn registers(instr: &str) -> Vec<&str> {
+        instr
+            .split(|c: char| !(c.is_alphanumeric() || "%._".contains(c)))
+            .filter(|word| word.starts_with('%'))
+            .collect()
+    }
+
+    /// the value, default label and cases of a `switch` instruction
+    fn switch(instr: &str) -> (&str, &str, Vec<(i64, &str)>) {
+        let (head, cases) = instr
+            .strip_prefix("switch ")
+            .unwrap()
+            .split_once(" [")
+            .unwrap();
+        let (value, default) = head.split_once(", label %").unwrap();
+        let value = value.split_once(' ').unwrap().1;
+        let words: Vec<&str> = cases.trim_end_matches(']').split_whitespace().collect();
+        let cases = words
+            .chunks(4)
+            .map(|case| {
+                let value = case[1].trim_end_matches(',').parse().unwrap();
+                (value, case[3].trim_start_matches('%'))
+            })
+            .collect();
+        (value, default, cases)
+    }
+
+    #[test]
+    fn llvm_target_triple() {
+        let source = "print 1;\n";
+        assert!(!llvm(source, Target::Generic).contains("target triple"));
+        assert!(llvm(source, Target::X86_64Linux)
+            .starts_with("target triple = \"x86_64-unknown-linux-gnu\"\n"));
+        assert!(llvm(source, Target::Aarch64Linux)
+            .starts_with("target triple = \"aarch64-unknown-linux-gnu\"\n"));
+    }
+
+    #[test]
+    fn llvm_portable_runtime() {
+        let llvm = llvm("print 'a';\n", Target::Generic);
+        assert!(llvm.contains("declare i32 @printf(ptr, ...)"));
+        assert!(llvm.contains("declare i32 @putchar(i32)"));
+        assert!(llvm.contains("declare i32 @fputs(ptr, ptr)"));
+        assert!(!llvm.contains("@write"));
+        assert!(!llvm.contains("__printf_chk"));
+        assert!(!llvm.contains("target-cpu"));
+    }
+
+    #[test]
+    fn llvm_match_switch() {
+        let dense = llvm(
+            "var x = 2;\nmatch x { 1 => { print 1; } 2 => { print 2; } 4 => { print 4; } }\n",
+            Target::Generic,
+        );
+        let blocks = main_blocks(&dense);
+        let entry = &blocks["entry"];
+        let (value, after, cases) = switch(entry[entry.len() - 1]);
+        assert_eq!(
+            entry[entry.len() - 2],
+            format!("{value} = load i32, ptr @wb_var_x")
+        );
+        assert_eq!(
+            cases.iter().map(|case| case.0).collect::<Vec<_>>(),
+            [1, 2, 4]
+        );
+        // each arm prints its pattern, and without a default the switch goes past the match
+        for (pattern, label) in cases {
+            assert_eq!(
+                blocks[label],
+                [
+                    format!("call void @_print_int(i32 {pattern})"),
+                    format!("br label %{after}")
+                ]
+            );
+        }
+        assert_eq!(blocks[after], ["ret i32 0"]);
+
+        let chars = llvm(
+            "match 'b' { 'a' => { print 1; } _ => { print 2; } }\n",
+            Target::Generic,
+        );
+        let blocks = main_blocks(&chars);
+        let (value, default, cases) = switch(blocks["entry"][0]);
+        assert_eq!(value, "98");
+        assert_eq!(cases.len(), 1);
+        assert_eq!(cases[0].0, 97);
+        assert_eq!(blocks[cases[0].1][0], "call void @_print_int(i32 1)");
+        assert_eq!(blocks[default][0], "call void @_print_int(i32 2)");
+
+        // sparse patterns are compared one at a time
+        let sparse = llvm(
+            "var x = 2;\nmatch x { 1 => { print 1; } 1000 => { print 2; } }\n",
+            Target::Generic,
+        );
+        assert!(!sparse.contains("switch"));
+        let compares: Vec<&str> = main_blocks(&sparse)
+            .into_values()
+            .flatten()
+            .filter(|instr| instr.contains(" = icmp eq i32 "))
+            .collect();
+        assert_eq!(compares.len(), 2);
+        assert!(compares.iter().any(|instr| instr.ends_with(", 1000")));
+    }
+
+    #[test]
+    fn llvm_integer_operators() {
+        let ir = llvm(
+            "var x = 7;\nx %= 3;\nprint x & 6 | x ^ 1;\nprint x << 2 >> 1;\n",
+            Target::Generic,
+        );
+        let entry = &main_blocks(&ir)["entry"];
+        let defs = definitions(entry);
+        let find = |prefix: &str| {
+            let (&reg, &instr) = defs
+                .iter()
+                .find(|(_, instr)| instr.starts_with(prefix))
+                .unwrap();
+            (reg, instr)
+        };
+        let load = "load i32, ptr @wb_var_x";
+
+        // the compound assignment loads the variable first, then stores the result back
+        let (rem, rem_instr) = find("call i32 @_rem_int(");
+        assert!(rem_instr.ends_with(", i32 3)"));
+        assert_eq!(defs[registers(rem_instr)[0]], load);
+        assert!(entry.contains(&format!("store i32 {rem}, ptr @wb_var_x").as_str()));
+
+        let (_, or) = find("or i32 ");
+        let (and, xor) = (defs[registers(or)[0]], defs[registers(or)[1]]);
+        assert!(and.starts_with("and i32 ") && and.ends_with(", 6"));
+        assert!(xor.starts_with("xor i32 ") && xor.ends_with(", 1"));
+        assert_eq!(defs[registers(and)[0]], load);
+        assert_eq!(defs[registers(xor)[0]], load);
+
+        let (_, shift_right) = find("call i32 @_shr_int(");
+        assert!(shift_right.ends_with(", i32 1)"));
+        let shift_left = defs[registers(shift_right)[0]];
+        assert!(shift_left.starts_with("call i32 @_shl_int(") && shift_left.ends_with(", i32 2)"));
+        assert_eq!(defs[registers(shift_left)[0]], load);
+
+        // the runtime helpers use the signed instructions
+        assert!(ir.contains("srem i32 %a, %b"));
+        assert!(ir.contains("ashr i32 %a, %b"));
+    }
+
+    #[test]
+    fn llvm_numeric_types() {
+        let ir = llvm(
+            "var x = 5i64;\nprint x * x;\nvar y = 7u8;\nprint y + y;\nprint y < y;\n\
+             var z = 0.5f32;\nprint float(z + z);\nprint uint8(z);\n",
+            Target::Generic,
+        );
+        assert!(ir.contains("@wb_var_x = global i64 0"));
+        assert!(ir.contains("@wb_var_z = global float 0x0"));
+        assert!(ir.contains("call {i64, i1} @llvm.smul.with.overflow.i64(i64 %.0, i64 %.1)"));
+        // uint8 is unsigned
+        assert!(ir.contains("call {i8, i1} @llvm.uadd.with.overflow.i8(i8 %.3, i8 %.4)"));
+        assert!(ir.contains("icmp ult i8"));
+        assert!(ir.contains("fadd float %.9, %.10"));
+        assert!(ir.contains("fpext float %.11 to double"));
+        assert!(ir.contains("call i8 @llvm.fptoui.sat.i8.f32(float %.13)"));
+        assert!(ir.contains("call void @_print_int64(i64"));
+        assert!(ir.contains("call void @_print_uint8(i8"));
+        assert!(ir.contains("sdiv i64 %a, %b"));
+        assert!(ir.contains("lshr i8 %a, %b"));
+    }
+
+    #[test]
+    fn llvm_parse_target() {
+        assert_eq!("generic".parse(), Ok(Target::Generic));
+        assert_eq!("x86_64".parse(), Ok(Target::X86_64Linux));
+        assert_eq!(
+            "aarch64-unknown-linux-gnu".parse(),
+            Ok(Target::Aarch64Linux)
+        );
+        assert!("riscv64".parse::<Target>().is_err());
+    }
+
+    #[test]
+    fn llvm_native_extern() {
+        let mut natives = Natives::new();
+        natives.register("scale", &[Type::Int, Type::Float], Type::Float, |_| {
+            Ok(WabbitType::Float(0.0))
+        });
+
+        let mut scanner = Scanner::new("print scale(2, 1.5);\nprint scale(3, 0.5);\n");
+        scanner.scan().unwrap();
+        let mut parser = Parser::from(&scanner);
+        parser.parse().unwrap();
+        let llvm = CodegenLLVM::from(&parser)
+            .with_natives(&natives)
+            .llvm_codegen();
+
+        assert_eq!(
+            llvm.matches("declare double @scale(i32, double)").count(),
+            1
+        );
+        assert!(llvm.contains("call double @scale(i32 2, double"));
+    }
+
+    #[test]
+    fn llvm_overflow() {
+        let mut scanner = Scanner::new("var x = 1;\nprint x + 2;\nprint -x;\nprint x / 3;\n");
+        scanner.scan().unwrap();
+        let mut parser = Parser::from(&scanner);
+        parser.parse().unwrap();
+        let checked = CodegenLLVM::from(&parser).llvm_codegen();
+        assert!(checked.contains("call {i32, i1} @llvm.sadd.with.overflow.i32(i32 "));
+        assert!(checked.contains("call {i32, i1} @llvm.ssub.with.overflow.i32(i32 0, "));
+        assert!(checked.contains("call i32 @_div_int(i32 "));
+
+        let mut codegen = CodegenLLVM::from(&parser);
+        codegen.overflow = Overflow::Wrap;
+        let wrapped = codegen.llvm_codegen();
+        assert!(wrapped.contains(" = add i32 "));
+        assert!(wrapped.contains(" = sub i32 0, "));
+        assert!(wrapped.contains("call i32 @_div_int_wrap(i32 "));
+        assert!(!wrapped.contains("@llvm.sadd.with.overflow.i32(i32 "));
+
+        assert_eq!("wrap".parse(), Ok(Overflow::Wrap));
+        assert!("saturate".parse::<Overflow>().is_err());
+    }
+}
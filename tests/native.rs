@@ -0,0 +1,67 @@
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+    use wabbit::native::{BuildError, Emit, TempDir, Toolchain};
+
+    #[test]
+    fn parse_emit() {
+        assert_eq!("llvm-ir".parse(), Ok(Emit::LlvmIr));
+        assert_eq!("asm".parse(), Ok(Emit::Asm));
+        assert_eq!("obj".parse(), Ok(Emit::Obj));
+        assert_eq!("exe".parse(), Ok(Emit::Exe));
+        assert!("wasm".parse::<Emit>().is_err());
+    }
+
+    #[test]
+    fn temp_dir_removed() {
+        let first = TempDir::new().unwrap();
+        let second = TempDir::new().unwrap();
+        assert_ne!(first.path(), second.path());
+
+        let path = first.path().to_path_buf();
+        std::fs::write(path.join("out.ll"), "").unwrap();
+        drop(first);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn emit_llvm_ir_without_compiler() {
+        let dir = TempDir::new().unwrap();
+        let output = dir.path().join("out.ll");
+        let toolchain = Toolchain::new("no-such-compiler");
+        toolchain
+            .build_llvm("; module", Emit::LlvmIr, &output)
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(output).unwrap(), "; module");
+    }
+
+    #[test]
+    fn unwritable_output() {
+        let dir = TempDir::new().unwrap();
+        let output = dir.path().join("missing").join("out.ll");
+        let result = Toolchain::new("no-such-compiler").build_llvm("", Emit::LlvmIr, &output);
+        match result {
+            Err(BuildError::Write(path, _)) => assert_eq!(path, output),
+            result => panic!("expected a write error, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn compiler_failure() {
+        let dir = TempDir::new().unwrap();
+        let mut toolchain = Toolchain::new("false");
+        toolchain.opt_level = "2".to_string();
+
+        match toolchain.build_llvm("; module", Emit::Exe, &dir.path().join("out")) {
+            Err(BuildError::Command { command, .. }) => {
+                assert!(command.starts_with("false -O2 "), "{command}");
+            }
+            result => panic!("expected a command error, got {result:?}"),
+        }
+
+        let missing = Toolchain::new("no-such-compiler").build_llvm("", Emit::Obj, Path::new("x"));
+        assert!(
+            matches!(missing, Err(BuildError::NotFound(program)) if program == "no-such-compiler")
+        );
+    }
+}
@@ -76,6 +76,86 @@ mod test {
         expect_err_args("print 1\n", Msg::ParserExpect, &[";"]);
     }
 
+    #[test]
+    fn for_init() {
+        let expected = ["variable definition or assignment"];
+        expect_err_args(
+            "for print 1; true; x = 1 {}\n",
+            Msg::ParserExpect,
+            &expected,
+        );
+        expect_err_args("for x; true; x = 1 {}\n", Msg::ParserExpect, &expected);
+    }
+
+    #[test]
+    fn else_if() {
+        let mut scanner =
+            Scanner::new("if a { print 1; } else if b { print 2; } else { print 3; }\n");
+        scanner.scan().unwrap();
+        let mut parser = Parser::from(&scanner);
+        parser.parse().unwrap();
+        // the second `if` is the else block of the first
+        assert_eq!(
+            parser.borrow_statements()[0].to_string(),
+            "if a{print 1;}else if b{print 2;}else {print 3;}"
+        );
+    }
+
+    #[test]
+    fn compound_assign() {
+        let mut scanner = Scanner::new("x %= y << 2 | 1;\nfor i = 0; i < 3; i += 1 { }\n");
+        scanner.scan().unwrap();
+        let mut parser = Parser::from(&scanner);
+        parser.parse().unwrap();
+        let statements: Vec<String> = parser
+            .borrow_statements()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(statements, ["x%=y<<2|1;", "for i=0;i<3;i+=1{}"]);
+    }
+
+    #[test]
+    fn match_arms() {
+        expect_err_args("match x { 1 { } }\n", Msg::ParserExpect, &["=>"]);
+        expect_err_args(
+            "match x { y => { } }\n",
+            Msg::ParserExpect,
+            &["literal pattern"],
+        );
+        expect_err_args(
+            "match x { -'a' => { } }\n",
+            Msg::ParserExpect,
+            &["literal pattern"],
+        );
+        // the `_` arm is the last
+        expect_err_args("match x { _ => { } 1 => { } }\n", Msg::ParserExpect, &["}"]);
+    }
+
+    #[test]
+    fn functions() {
+        let source = "var f func(int, float) func() bool = g;\nprint f(1, 2)();\n\
+                      print func(x int) int { return x; }(1);\n";
+        let mut scanner = Scanner::new(source);
+        scanner.scan().unwrap();
+        let mut parser = Parser::from(&scanner);
+        parser.parse().unwrap();
+        let statements: Vec<String> = parser
+            .borrow_statements()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            statements,
+            [
+                "var f func(int, float) func() bool=g;",
+                "print f(1,2)();",
+                "print func(x int)int{return x;}(1);"
+            ]
+        );
+        expect_err("var f func(int) = g;\n", Msg::ExpectTypeName);
+    }
+
     // Just checking that it doesn't throw an error, not if it is correct
     #[test]
     fn parse_programs() {
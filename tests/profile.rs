@@ -0,0 +1,107 @@
+mod common;
+
+#[cfg(test)]
+mod test {
+    use crate::common::parse;
+    use wabbit::interpreter::Interpreter;
+    use wabbit::profile::{FunctionStats, Profile};
+    use wabbit::trace::Trace;
+
+    const SOURCE: &str = "\
+func fact(n int) int {
+    if n <= 1 {
+        return 1;
+    }
+    return n * fact(n - 1);
+}
+func twice(n int) int {
+    return fact(n) + fact(n);
+}
+var i = 0;
+while i < 2 {
+    print twice(3);
+    i = i + 1;
+}
+";
+
+    fn interpret(profile: &mut Profile, trace: Option<&mut Trace<Vec<u8>>>) {
+        let mut output = Vec::new();
+        parse(SOURCE, |parser| {
+            let mut interpreter = Interpreter::from(parser)
+                .with_output(&mut output)
+                .with_profile(profile);
+            if let Some(trace) = trace {
+                interpreter = interpreter.with_trace(trace);
+            }
+            interpreter.interpret().unwrap();
+        });
+    }
+
+    #[test]
+    fn counts() {
+        let mut profile = Profile::default();
+        interpret(&mut profile, None);
+
+        let twice = profile.functions["twice"];
+        let fact = profile.functions["fact"];
+        assert_eq!(twice.calls, 2);
+        assert_eq!(fact.calls, 12);
+        assert_eq!(fact.max_recursion, 3);
+        assert_eq!(twice.max_recursion, 1);
+        assert_eq!(profile.max_call_depth, 4);
+        // twice only runs its block and return itself, in each of its calls
+        assert_eq!(twice.exclusive, 4);
+        assert_eq!(twice.inclusive, twice.exclusive + fact.inclusive);
+        assert_eq!(fact.inclusive, fact.exclusive);
+
+        let loops: Vec<_> = profile.loops.values().collect();
+        assert_eq!(loops.len(), 1);
+        assert_eq!(loops[0].iterations, 2);
+        // all but the four top level statements
+        assert_eq!(loops[0].statements, profile.statements - 4);
+
+        // every statement is charged to exactly one stack
+        assert_eq!(profile.stacks.values().sum::<u64>(), profile.statements);
+        assert_eq!(profile.folded().lines().count(), 5);
+        assert!(profile
+            .folded()
+            .contains(&format!("<main>;twice {}\n", twice.exclusive)));
+
+        let report = profile.report(SOURCE);
+        assert!(report.contains("while at line 11"));
+        assert!(report.lines().any(|line| line.starts_with("fact")));
+    }
+
+    #[test]
+    fn profile_restarts() {
+        let mut profile = Profile::default();
+        interpret(&mut profile, None);
+        let first = profile.clone();
+        interpret(&mut profile, None);
+        assert_eq!(profile.statements, first.statements);
+        assert_eq!(profile.functions, first.functions);
+        assert_ne!(profile.functions["fact"], FunctionStats::default());
+    }
+
+    #[test]
+    fn trace() {
+        let mut trace = Trace::new(SOURCE, Vec::new());
+        interpret(&mut Profile::default(), Some(&mut trace));
+        let trace = String::from_utf8(trace.output).unwrap();
+        let lines: Vec<_> = trace.lines().take(8).collect();
+        assert_eq!(
+            lines,
+            [
+                "1:1-6:2 func fact(n int) int {",
+                "7:1-9:2 func twice(n int) int {",
+                "10:5-10:11 var i = 0;",
+                "  i = 0",
+                "11:1-14:2 while i < 2 {",
+                "12:5-12:20 print twice(3);",
+                "  8:5-8:30 return fact(n) + fact(n);",
+                "    2:5-4:6 if n <= 1 {",
+            ]
+        );
+        assert!(trace.ends_with("13:5-13:15 i = i + 1;\n  i = 2\n"));
+    }
+}
@@ -0,0 +1,334 @@
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::time::Duration;
+    use wabbit::host::{Callback, Host, Limits, Natives, Reader};
+    use wabbit::program::{compile, compile_with_natives};
+    use wabbit::{Type, WabbitError, WabbitType};
+
+    #[test]
+    fn run_repeatedly() {
+        let program = compile("var x = 2;\nprint x * 21;\nprint 'a';\n").unwrap();
+        for _ in 0..3 {
+            let mut host = Host::default();
+            program.run(&mut host).unwrap();
+            assert_eq!(
+                host.output,
+                vec![WabbitType::Int(42), WabbitType::Char('a')]
+            );
+        }
+    }
+
+    #[test]
+    fn outputs() {
+        let program = compile("print 1;\nprint 2.5;\nprint 'a';\n").unwrap();
+
+        let mut host = Host::new(String::new());
+        program.run(&mut host).unwrap();
+        assert_eq!(host.output, "1\n2.5\na");
+
+        let mut count = 0;
+        program
+            .run(&mut Host::new(Callback(|_: &WabbitType| count += 1)))
+            .unwrap();
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn compile_errors() {
+        assert_eq!(compile("print 1 +;\n").unwrap_err().stage, "Parser");
+        assert_eq!(
+            compile("print 1 + 1.0;\n").unwrap_err().stage,
+            "Typechecking"
+        );
+
+        let diagnostics = compile("print x;\n").unwrap_err();
+        assert_eq!(diagnostics.errors.len(), 1);
+        assert!(diagnostics.to_string().contains("<source>:1:7"));
+    }
+
+    #[test]
+    fn runtime_error() {
+        let program = compile("var x int;\nif false { x = 1; }\nprint x;\n").unwrap();
+        let error = program.run(&mut Host::default()).unwrap_err();
+        assert!(error.error.label.contains("uninitialized"));
+        assert!(error.to_string().contains("Interpreter"));
+    }
+
+    #[test]
+    fn for_loop() {
+        let source = "\
+var total = 0;
+for var i = 0; i < 6; i = i + 1 {
+    if i == 2 {
+        continue;
+    }
+    total = total + i;
+}
+print total;
+";
+        let program = compile(source).unwrap();
+        let mut host = Host::new(String::new());
+        program.run(&mut host).unwrap();
+        assert_eq!(host.output, "13\n");
+
+        // errors in the step point at the step
+        let program = compile("for var i = 2147483646; true; i = i + 1 {}\n").unwrap();
+        let error = program.run(&mut Host::default()).unwrap_err();
+        assert!(error.error.label.contains("'2147483647 + 1'"));
+        assert!(error.to_string().contains("<source>:1:33"));
+    }
+
+    #[test]
+    fn run_on_threads() {
+        let program = Arc::new(compile("print 1;\n").unwrap());
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let program = Arc::clone(&program);
+                std::thread::spawn(move || {
+                    let mut host = Host::new(String::new());
+                    program.run(&mut host).unwrap();
+                    host.output
+                })
+            })
+            .collect();
+        for thread in threads {
+            assert_eq!(thread.join().unwrap(), "1\n");
+        }
+    }
+
+    fn natives() -> Natives {
+        let mut natives = Natives::new();
+        natives
+            .register(
+                "hypot",
+                &[Type::Float, Type::Float],
+                Type::Float,
+                |args| match args {
+                    [WabbitType::Float(x), WabbitType::Float(y)] => {
+                        Ok(WabbitType::Float(x.hypot(*y)))
+                    }
+                    _ => unreachable!(),
+                },
+            )
+            .register("fail", &[], Type::Int, |_| {
+                Err(WabbitError::new(&"out of widgets", (0, 0)))
+            });
+        natives
+    }
+
+    #[test]
+    fn native_functions() {
+        let program = compile_with_natives("print hypot(3.0, 4.0);\n", natives()).unwrap();
+        let mut host = Host::default();
+        program.run(&mut host).unwrap();
+        assert_eq!(host.output, vec![WabbitType::Float(5.0)]);
+
+        // natives are unknown unless registered
+        assert_eq!(
+            compile("print hypot(3.0, 4.0);\n").unwrap_err().stage,
+            "Typechecking"
+        );
+    }
+
+    #[test]
+    fn native_typecheck() {
+        for source in [
+            "print hypot(3, 4);\n",
+            "print hypot(3.0);\n",
+            "var x int = hypot(3.0, 4.0);\n",
+            "func hypot(x float) float { return x; }\n",
+        ] {
+            let diagnostics = compile_with_natives(source, natives()).unwrap_err();
+            assert_eq!(diagnostics.stage, "Typechecking", "{source}");
+        }
+    }
+
+    #[test]
+    fn native_error_at_call() {
+        let program = compile_with_natives("print 1;\nprint fail();\n", natives()).unwrap();
+        let error = program.run(&mut Host::default()).unwrap_err();
+        assert!(error.error.label.contains("out of widgets"));
+        assert!(error.to_string().contains("<source>:2:11"));
+    }
+
+    #[test]
+    fn scripted_input() {
+        let program =
+            compile("print readint() + 1;\nprint readfloat();\nprint readchar();\n").unwrap();
+        let mut host = Host::new(String::new()).with_input(Reader::from("  41\n2.5x"));
+        program.run(&mut host).unwrap();
+        assert_eq!(host.output, "42\n2.5\nx");
+
+        // the input runs out before the float
+        let mut host = Host::new(String::new()).with_input(Reader::from("41 "));
+        let error = program.run(&mut host).unwrap_err();
+        assert!(error.error.label.contains("'float'"));
+        assert!(error.to_string().contains("<source>:2:16"));
+    }
+
+    #[test]
+    fn input_typecheck() {
+        for source in [
+            "print readint(1);\n",
+            "var x float = readint();\n",
+            "func readchar() char { return 'a'; }\n",
+        ] {
+            assert_eq!(
+                compile(source).unwrap_err().stage,
+                "Typechecking",
+                "{source}"
+            );
+        }
+    }
+
+    const FOREVER: &str = "var x = 0;\nwhile true {\n    x = x + 1;\n}\n";
+
+    const RECURSE: &str = "func f(n int) int {\n    return f(n + 1);\n}\nprint f(0);\n";
+
+    #[test]
+    fn fuel() {
+        let limits = Limits {
+            fuel: Some(100),
+            ..Limits::default()
+        };
+        let program = compile(FOREVER).unwrap();
+        let error = program
+            .run(&mut Host::default().with_limits(limits))
+            .unwrap_err();
+        assert!(error
+            .error
+            .label
+            .contains("limit of 100 executed statements"));
+        assert!(error.to_string().contains("<source>:2:1"));
+
+        let program = compile("var x = 0;\nwhile x < 10 {\n    x = x + 1;\n}\nprint x;\n").unwrap();
+        program
+            .run(&mut Host::default().with_limits(limits))
+            .unwrap();
+    }
+
+    #[test]
+    fn call_depth() {
+        // limits are opt in, and a call depth stops runaway recursion before the stack overflows
+        assert_eq!(Host::default().limits.call_depth, None);
+        let limits = Limits {
+            call_depth: Some(48),
+            ..Limits::default()
+        };
+        let program = compile(RECURSE).unwrap();
+        let error = program
+            .run(&mut Host::default().with_limits(limits))
+            .unwrap_err();
+        assert!(error.error.label.contains("call depth of 48"));
+        assert!(error.to_string().contains("<source>:2:13"));
+
+        let limits = Limits {
+            call_depth: Some(3),
+            ..Limits::default()
+        };
+        let program = compile("func f(n int) int {\n    if n == 0 { return 0; }\n    return f(n - 1);\n}\nprint f(3);\n").unwrap();
+        let error = program
+            .run(&mut Host::default().with_limits(limits))
+            .unwrap_err();
+        assert!(error.to_string().contains("<source>:3:13"));
+    }
+
+    #[test]
+    fn timeout() {
+        let limits = Limits {
+            timeout: Some(Duration::from_millis(50)),
+            ..Limits::default()
+        };
+        let program = compile(FOREVER).unwrap();
+        let error = program
+            .run(&mut Host::default().with_limits(limits))
+            .unwrap_err();
+        assert!(error.error.label.contains("time limit of 50ms"));
+        assert!(error.to_string().contains("<source>:2:1"));
+    }
+
+    #[test]
+    fn arithmetic_errors() {
+        for (source, label) in [
+            ("var x = 2147483647;\nprint x + 1;\n", "'2147483647 + 1'"),
+            ("var x = -2147483647;\nprint x - 2;\n", "'-2147483647 - 2'"),
+            ("var x = 65536;\nprint x * x;\n", "'65536 * 65536'"),
+            (
+                "var x = -2147483647 - 1;\nprint x / -1;\n",
+                "'-2147483648 / -1'",
+            ),
+            ("var x = -2147483647 - 1;\nprint -x;\n", "'-(-2147483648)'"),
+            ("var x = 0;\nprint 1 / x;\n", "division by zero"),
+            ("var x = 0;\nprint 1 % x;\n", "division by zero"),
+            (
+                "var x = -2147483647 - 1;\nprint x % -1;\n",
+                "'-2147483648 % -1'",
+            ),
+            ("var x = 32;\nprint 1 << x;\n", "shift by 32"),
+            ("var x = -1;\nprint 1 >> x;\n", "shift by -1"),
+            ("var x = 1;\nx /= 0;\n", "division by zero"),
+            ("var x = 255u8;\nprint x + 1u8;\n", "'255 + 1'"),
+            ("var x = 0u8;\nprint x - 1u8;\n", "'0 - 1'"),
+            ("var x = 8u8;\nprint 1u8 << x;\n", "shift by 8"),
+            (
+                "var x = 9223372036854775807i64;\nprint x + 1i64;\n",
+                "'9223372036854775807 + 1'",
+            ),
+        ] {
+            let program = compile(source).unwrap();
+            let error = program.run(&mut Host::default()).unwrap_err();
+            assert!(error.error.label.contains(label), "{source}");
+            assert!(error.to_string().contains("<source>:2:"), "{source}");
+        }
+
+        // floats follow IEEE 754
+        let program = compile("print 1.0 / 0.0;\n").unwrap();
+        let mut host = Host::new(String::new());
+        program.run(&mut host).unwrap();
+        assert_eq!(host.output, "inf\n");
+    }
+
+    #[test]
+    fn bitwise_operators() {
+        let source = "
+var x = 7;
+x += 3;
+x *= 4;
+x %= 7;
+print x;
+print -7 % 3;
+print 12 & 10 | 1;
+print 12 ^ 10;
+print 1 << 31;
+print -16 >> 2;
+print 1 + 2 << 3;
+print 6 & 3 == 2;
+";
+        let program = compile(source).unwrap();
+        let mut host = Host::new(String::new());
+        program.run(&mut host).unwrap();
+        assert_eq!(host.output, "5\n-1\n9\n6\n-2147483648\n-4\n24\ntrue\n");
+    }
+
+    #[test]
+    fn numeric_types() {
+        let source = "
+var big = 1i64 << 40i64;
+print big * 3i64;
+print uint8(-1) / 2u8;
+print 0.1f32 + 0.2f32;
+print float(0.1f32);
+print int(300.5f32);
+print uint8(-3.0);
+print char(104u8);
+";
+        let program = compile(source).unwrap();
+        let mut host = Host::new(String::new());
+        program.run(&mut host).unwrap();
+        assert_eq!(
+            host.output,
+            "3298534883328\n127\n0.3\n0.10000000149011612\n300\n0\nh"
+        );
+    }
+}
@@ -48,14 +48,45 @@ mod test {
         expect_err_args("10.a;\n", Msg::InvalidNumber, &["."]);
     }
 
+    #[test]
+    fn number_suffixes() {
+        let mut scanner = Scanner::new("1i64 + 255u8 * 1.5f32 - 2f32;\n");
+        scanner.scan().unwrap();
+        let lexemes: Vec<&str> = scanner
+            .borrow_tokens()
+            .iter()
+            .map(|token| token.lexeme.as_str())
+            .collect();
+        assert_eq!(
+            lexemes,
+            ["1i64", "+", "255u8", "*", "1.5f32", "-", "2f32", ";", "\n"]
+        );
+        expect_err_args("256u8;\n", Msg::InvalidNumber, &["256u8"]);
+        expect_err_args("1.5i64;\n", Msg::InvalidNumber, &["1.5i64"]);
+        expect_err_args("1x;\n", Msg::InvalidNumber, &["1x"]);
+    }
+
     #[test]
     fn invalid_char() {
         expect_err("'abc';\n", Msg::InvalidChar);
     }
 
     #[test]
-    fn double_token() {
-        expect_err_args("true | false;\n", Msg::DoubleToken, &["|", "|"]);
+    fn operator_tokens() {
+        let mut scanner = Scanner::new("x %= y<<2 | z>>1 & -w ^ v || u;\n");
+        scanner.scan().unwrap();
+        let lexemes: Vec<&str> = scanner
+            .borrow_tokens()
+            .iter()
+            .map(|token| token.lexeme.as_str())
+            .collect();
+        assert_eq!(
+            lexemes,
+            [
+                "x", "%=", "y", "<<", "2", "|", "z", ">>", "1", "&", "-", "w", "^", "v", "||", "u",
+                ";", "\n"
+            ]
+        );
     }
 
     #[test]
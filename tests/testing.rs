@@ -0,0 +1,98 @@
+mod common;
+
+#[cfg(test)]
+mod test {
+    use crate::common::parse;
+    use std::path::Path;
+    use wabbit::host::{Host, Limits};
+    use wabbit::llvm::CodegenLLVM;
+    use wabbit::program::compile;
+    use wabbit::testing::{self, Outcome};
+
+    const SOURCE: &str = "\
+func add(a int, b int) int {
+    return a + b;
+}
+var ready = false;
+ready = true;
+func test_add() bool {
+    assert add(1, 2) == 3;
+    assert ready;
+    return true;
+}
+func test_broken() bool {
+    print 1;
+    assert add(2,  2)==5;
+    return true;
+}
+func test_with_params(x int) bool {
+    return true;
+}
+";
+
+    #[test]
+    fn assert_statement() {
+        let program = compile("var x = 1;\nassert x == 1;\nassert x * 2 > 3;\n").unwrap();
+        let error = program.run(&mut Host::default()).unwrap_err();
+        assert!(error.error.label.contains("assertion failed: x * 2 > 3"));
+        assert!(error.to_string().contains("<source>:3:1"));
+
+        let errors = compile("assert 1 + 1;\n").unwrap_err();
+        assert_eq!(errors.stage, "Typechecking");
+    }
+
+    #[test]
+    fn discover_and_run() {
+        parse(SOURCE, |parser| {
+            assert_eq!(testing::discover(parser), ["test_add", "test_broken"]);
+        });
+
+        let path = Path::new("add.wb");
+        let passed = testing::interpret(SOURCE, path, "test_add", Limits::default());
+        assert_eq!(
+            passed,
+            Outcome {
+                name: "test_add".to_string(),
+                output: String::new(),
+                failure: None,
+            }
+        );
+        let failed = testing::interpret(SOURCE, path, "test_broken", Limits::default());
+        assert_eq!(failed.output, "1\n");
+        // the expression is written as it was spaced in the source
+        let failure = failed.failure.as_deref().unwrap();
+        assert!(failure.contains("assertion failed: add(2, 2)==5"));
+        assert!(failure.contains("add.wb:13:5"));
+
+        let report = testing::report(&[passed, failed]);
+        assert!(report.starts_with("running 2 tests\ntest test_add ... ok\n"));
+        assert!(report.contains("---- test_broken ----\n1\n"));
+        assert!(report.contains("1 passed; 1 failed"));
+    }
+
+    #[test]
+    fn harness() {
+        assert_eq!(
+            testing::harness("var x = 1;", "test_x"),
+            "var x = 1;\ntest_x();\n"
+        );
+        assert_eq!(
+            testing::harness("print 1;\n", "test_x"),
+            "print 1;\ntest_x();\n"
+        );
+    }
+
+    #[test]
+    fn llvm_assert() {
+        parse("assert 1 < 2;\nassert 1 < 2;\nassert true;\n", |parser| {
+            let llvm = CodegenLLVM::from(parser).llvm_codegen();
+            assert!(llvm.contains(
+                "@.str.fail.0 = private unnamed_addr constant [32 x i8] \
+                 c\"error: assertion failed: 1 < 2\\0A\\00\""
+            ));
+            assert!(llvm.contains("@.str.fail.1 = private unnamed_addr constant [31 x i8]"));
+            assert!(!llvm.contains("@.str.fail.2"));
+            assert!(llvm.contains("call void @_fail(ptr @.str.fail.0)\n\tunreachable"));
+        });
+    }
+}
@@ -116,6 +116,22 @@ mod test {
         expect_err("continue;\n", Msg::LoopReq);
     }
 
+    #[test]
+    fn for_loop() {
+        let source = "for var i = 0; i < 3; i = i + 1 {}\nprint i;\n";
+        expect_err(source, Msg::VarUndefined);
+        expect_err_args(
+            "for var i = 0; i; i = i + 1 {}\n",
+            Msg::ExpectType,
+            &["bool"],
+        );
+        expect_err_args(
+            "for var i = 0; i < 3; i = 1.0 {}\n",
+            Msg::AssignRetype,
+            &["i", "int", "float"],
+        );
+    }
+
     #[test]
     fn assign_retype() {
         let source = "
@@ -281,6 +297,157 @@ mod test {
         expect_err_args(source, Msg::RedeclareFunc, &["x"]);
     }
 
+    #[test]
+    fn shadow_in_branches() {
+        let source = "
+            func f(x int) int {
+                if x > 0 {
+                    var x = 1;
+                    return x;
+                } else {
+                    var x = 2;
+                    return x;
+                }
+            }
+            ";
+        let mut scanner = Scanner::new(source);
+        scanner.scan().unwrap();
+        let mut parser = Parser::from(&scanner);
+        parser.parse().unwrap();
+        let mut typechecker = Typechecker::from(&parser);
+        typechecker.typecheck().unwrap();
+
+        // a variable declared in a returning branch ends with it
+        let source = "
+            func f(b bool) int {
+                if b {
+                    var y = 1;
+                    return y;
+                } else {
+                    return 2;
+                }
+                return y;
+            }
+            ";
+        expect_err(source, Msg::VarUndefined);
+    }
+
+    #[test]
+    fn match_arms() {
+        expect_err_args("match 1.5 { _ => { } }\n", Msg::ExpectType, &["int, char"]);
+        expect_err_args(
+            "match 'a' { 'a' => { } 1 => { } }\n",
+            Msg::MatchType,
+            &["int", "char"],
+        );
+        expect_err_args(
+            "match 1 { -1 => { } 2 => { } -1 => { } }\n",
+            Msg::MatchDuplicate,
+            &["-1"],
+        );
+    }
+
+    #[test]
+    fn integer_operators() {
+        let integer = &["int, int64, uint8"];
+        expect_err_args("print 1.0 % 2.0;\n", Msg::ExpectType, integer);
+        expect_err_args("print true | false;\n", Msg::ExpectType, integer);
+        expect_err_args("print 'a' << 'b';\n", Msg::ExpectType, integer);
+        expect_err_args("var x = 1.5;\nx %= 2.0;\n", Msg::ExpectType, integer);
+        expect_err_args("var x = 1;\nx += 2.0;\n", Msg::TypeMatch, &[]);
+        expect_err_args("var x int;\nx += 1;\n", Msg::AccessUninit, &["x"]);
+    }
+
+    #[test]
+    fn numeric_types() {
+        expect_err("print 1 + 1i64;\n", Msg::TypeMatch);
+        expect_err("var x float32 = 1.5;\n", Msg::InitType);
+        expect_err("print char(1.5f32);\n", Msg::TypeConvert);
+        expect_err("print bool(2.0);\n", Msg::TypeConvert);
+        expect_err_args(
+            "print -(1u8);\n",
+            Msg::ExpectType,
+            &["int, int64, float, float32"],
+        );
+
+        let source = "
+            var x = int64(1.5f32) + int64('a') + int64(255u8);
+            var y uint8 = uint8(x) & 15u8;
+            var z = float32(x) / float32(1.5) < 2.5f32;
+            print bool(y) == z;
+            ";
+        let mut scanner = Scanner::new(source);
+        scanner.scan().unwrap();
+        let mut parser = Parser::from(&scanner);
+        parser.parse().unwrap();
+        let mut typechecker = Typechecker::from(&parser);
+        typechecker.typecheck().unwrap();
+    }
+
+    #[test]
+    fn match_returns() {
+        let source = "
+            func f(x int) int {
+                match x {
+                    1 => { return 1; }
+                    2 => { print 2; }
+                }
+            }
+            ";
+        expect_err(source, Msg::AltBranch);
+        let source = "
+            func f(x int) int {
+                match x {
+                    1 => { return 1; }
+                    _ => { return 2.0; }
+                }
+            }
+            ";
+        expect_err(source, Msg::ReturnDiverge);
+    }
+
+    #[test]
+    fn functions() {
+        let source = "
+            func f(x int) func() int {
+                return func() int {
+                    x = x + 1;
+                    return x;
+                };
+            }
+            ";
+        expect_err_args(source, Msg::AssignCapture, &["x"]);
+        expect_err_args("var x = 1;\nprint x(2);\n", Msg::NotCallable, &["int"]);
+        expect_err_args(
+            "var f = func(x int) int { return x; };\nprint f(1, 2);\n",
+            Msg::FuncAirty,
+            &["f", "1", "2"],
+        );
+        expect_err_args(
+            "var f = func(x int) int { return x; };\nprint f(1.5);\n",
+            Msg::ParamType,
+            &["1", "int", "float"],
+        );
+        expect_err(
+            "var f func(int) int = func(x float) int { return 1; };\n",
+            Msg::InitType,
+        );
+        let ordered = &["int, int64, uint8, float, float32, char, bool"];
+        let source = "func f() int { return 1; }\nprint f;\n";
+        expect_err_args(source, Msg::ExpectType, ordered);
+        let source = "func f() int { return 1; }\nprint f == f;\n";
+        expect_err_args(source, Msg::ExpectType, ordered);
+        // a variable is captured when the function is created, so it must be initialized
+        let source = "
+            func f() int {
+                var x int;
+                var g = func() int { return x; };
+                return 1;
+            }
+            ";
+        expect_err_args(source, Msg::AccessUninit, &["x"]);
+    }
+
     #[test]
     fn typecheck_programs() {
         let paths = std::fs::read_dir("./program_examples/").unwrap();